@@ -1,13 +1,21 @@
 //! Command-line argument definitions using clap
 
 use clap::{Parser, Subcommand};
-use std::net::SocketAddr;
+use shared::config::{
+    DEFAULT_CERT_VALIDITY_SECS, MAX_CERT_VALIDITY_HOURS, DEFAULT_KNOWN_PEER_MAX_AGE_SECS, DEFAULT_MULTICAST_GROUP, DEFAULT_MULTICAST_PORT, DEFAULT_REORDER_WINDOW_MS,
+    HEARTBEAT_INTERVAL, MAX_CONNECTIONS, PROTOCOL_VERSION, ROOM_KEY_ENV,
+};
+use shared::p2p::RoomKey;
+use shared::utils::{parse_multicast_group, parse_peer_target, parse_room_key};
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::OnceLock;
 
 /// DPQ Chat Client - A modern P2P chat application
 #[derive(Parser)]
 #[command(name = "dpq-chat")]
 #[command(about = "A modern terminal-based P2P chat application")]
-#[command(version = "0.1.0")]
+#[command(version = env!("CARGO_PKG_VERSION"), long_version = long_version())]
 #[command(author = "DPQ Chat Team")]
 #[command(long_about = None)]
 pub struct Cli {
@@ -15,21 +23,35 @@ pub struct Cli {
     #[arg(short, long)]
     pub verbose: bool,
 
+    /// Hide banners, separators and the startup spinner
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
     /// Configuration file path
     #[arg(short, long, value_name = "FILE")]
     pub config: Option<String>,
 
+    /// Directory holding identity files (default: $IDENTITY_DIR or ~/.dpq-chat/identities)
+    #[arg(long, global = true, value_name = "DIR")]
+    pub identity_dir: Option<PathBuf>,
+
+    /// Disable colored output (also set by the NO_COLOR environment variable)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
     /// Subcommands
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+// Parsed once at startup, so the size of the P2p variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     /// Start a P2P chat session
     P2p {
-        /// Username for the chat session
-        #[arg(short, long)]
+        /// Username for the chat session (letters, digits, '_' and '-', up to 32)
+        #[arg(short, long, value_parser = parse_username)]
         username: String,
 
         /// Port to listen on
@@ -40,14 +62,126 @@ pub enum Commands {
         #[arg(long, default_value = "127.0.0.1")]
         host: String,
 
-        /// Bootstrap peer addresses to connect to
-        #[arg(short, long)]
-        bootstrap: Vec<SocketAddr>,
+        /// Bootstrap peer addresses (host:port, host may be a DNS name) to connect to
+        #[arg(short, long, value_parser = parse_peer_target)]
+        bootstrap: Vec<String>,
+
+        /// File of peer addresses (ip:port, one per line) to connect to, re-read while running
+        #[arg(long, value_name = "PATH")]
+        peers_file: Option<PathBuf>,
 
-        /// Disable TLS encryption
+        /// Don't announce or look for peers with LAN multicast
         #[arg(long)]
+        no_multicast: bool,
+
+        /// Multicast group for LAN discovery, peers only find each other within one group
+        #[arg(long, value_name = "IP", default_value_t = DEFAULT_MULTICAST_GROUP, value_parser = parse_multicast_group)]
+        multicast_group: Ipv4Addr,
+
+        /// UDP port for LAN discovery
+        #[arg(long, value_name = "PORT", default_value_t = DEFAULT_MULTICAST_PORT, value_parser = clap::value_parser!(u16).range(1..))]
+        multicast_port: u16,
+
+        /// Advertise and find peers as an mDNS service (_dpqchat._tcp)
+        #[arg(long)]
+        mdns: bool,
+
+        /// Disable TLS encryption, sending all traffic in cleartext (needs --i-understand-this-is-insecure)
+        #[arg(long, requires = "i_understand_this_is_insecure")]
         no_tls: bool,
+
+        /// Confirm that --no-tls should really send traffic unencrypted
+        #[arg(long, requires = "no_tls")]
+        i_understand_this_is_insecure: bool,
+
+        /// Mutual TLS: bind our certificate to this user's identity and only accept peers with a bound one (asks for the identity password)
+        #[arg(long, conflicts_with = "no_tls")]
+        mutual_tls: bool,
+
+        /// Color theme for the chat UI
+        #[arg(long, default_value = "dark", value_parser = ["dark", "light", "none"])]
+        theme: String,
+
+        /// Number of messages kept in scrollback (10-100000)
+        #[arg(long, default_value_t = p2p_core::DEFAULT_HISTORY_SIZE, value_parser = parse_history_size)]
+        history_size: usize,
+
+        /// Tell peers you are away after this many seconds without input (0 = off)
+        #[arg(long, value_name = "SECS", default_value_t = 0)]
+        auto_away: u64,
+
+        /// Don't show when peers are typing
+        #[arg(long)]
+        no_typing: bool,
+
+        /// Ring the bell for incoming messages: every one, only @mentions, or never
+        #[arg(long, value_name = "MODE", default_value = "off", value_parser = ["all", "mentions", "off"])]
+        notify: String,
+
+        /// Raise a desktop notification instead of ringing the bell
+        #[arg(long)]
+        desktop_notify: bool,
+
+        /// Seconds between heartbeats sent to each peer
+        #[arg(long, value_name = "SECS", default_value_t = HEARTBEAT_INTERVAL, value_parser = clap::value_parser!(u64).range(1..))]
+        heartbeat_interval: u64,
+
+        /// Seconds between sweeps for dead peers (0 = once per heartbeat)
+        #[arg(long, value_name = "SECS", default_value_t = 0)]
+        cleanup_interval: u64,
+
+        /// Drop peers silent this many seconds, at least two heartbeats (0 = four heartbeats)
+        #[arg(long, value_name = "SECS", default_value_t = 0)]
+        dead_peer_timeout: u64,
+
+        /// Most peers connected at once, inbound and outbound together
+        #[arg(long, value_name = "N", default_value_t = MAX_CONNECTIONS as u64, value_parser = clap::value_parser!(u64).range(1..))]
+        max_connections: u64,
+
+        /// Only accept peers whose fingerprint (needs --mutual-tls), IP or ip:port is listed in this file
+        #[arg(long, value_name = "PATH")]
+        allowlist: Option<PathBuf>,
+
+        /// Milliseconds to hold a message back for an earlier one from the same sender, 0 disables
+        #[arg(long, value_name = "MS", default_value_t = DEFAULT_REORDER_WINDOW_MS)]
+        reorder_window: u64,
+
+        /// Save peers we reached to this file on exit and reconnect to them on start
+        #[arg(long, value_name = "PATH")]
+        known_peers: Option<PathBuf>,
+
+        /// Forget known peers not seen for this many hours
+        #[arg(long, value_name = "HOURS", default_value_t = DEFAULT_KNOWN_PEER_MAX_AGE_SECS / 3600)]
+        known_peers_max_age: u64,
+
+        /// Hours our TLS certificate stays valid, it is renewed before it runs out
+        #[arg(long, value_name = "HOURS", default_value_t = DEFAULT_CERT_VALIDITY_SECS / 3600, value_parser = clap::value_parser!(u64).range(1..=MAX_CERT_VALIDITY_HOURS))]
+        cert_validity: u64,
+
+        /// Only admit peers that know this passphrase, the environment variable keeps it out of the process list
+        #[arg(long, value_name = "PASSPHRASE", env = ROOM_KEY_ENV, hide_env_values = true, value_parser = parse_room_key)]
+        room_key: Option<RoomKey>,
+
+        /// Terminal UI, or JSON lines on stdin/stdout for scripts and bots
+        #[arg(long, default_value = "tui", value_parser = ["tui", "json"])]
+        output: String,
     },
+    /// Check that the listen port can be bound and bootstrap peers are reachable
+    Check {
+        /// Host to bind to
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+
+        /// Port to check, searches for a free port when omitted
+        #[arg(short, long)]
+        port: Option<u16>,
+
+        /// Bootstrap peer addresses (host:port, host may be a DNS name) to connect to
+        #[arg(short, long, value_parser = parse_peer_target)]
+        bootstrap: Vec<String>,
+    },
+    /// Run the crypto and TLS stack in-process to verify this build works
+    Selftest,
     /// Interactive menu mode (default)
     Menu,
     /// Show configuration
@@ -58,8 +192,8 @@ pub enum Commands {
     },
     /// Generate cryptographic identity
     GenerateKey {
-        /// Username for the identity
-        #[arg(short, long)]
+        /// Username for the identity (letters, digits, '_' and '-', up to 32)
+        #[arg(short, long, value_parser = parse_username)]
         username: Option<String>,
         
         /// Expiration time in days (optional)
@@ -70,6 +204,32 @@ pub enum Commands {
     List,
 }
 
+/// Details shown by `--version` for bug reports: release, commit, build date and wire protocol
+fn long_version() -> &'static str {
+    static LONG_VERSION: OnceLock<String> = OnceLock::new();
+    LONG_VERSION.get_or_init(|| {
+        format!(
+            "{}\ncommit:   {}\nbuilt:    {}\nprotocol: {}",
+            env!("CARGO_PKG_VERSION"),
+            env!("DPQ_GIT_COMMIT"),
+            env!("DPQ_BUILD_DATE"),
+            PROTOCOL_VERSION,
+        )
+    })
+}
+
+/// Parse and bounds-check `--history-size`
+fn parse_history_size(value: &str) -> Result<usize, String> {
+    let size = value.parse::<usize>().map_err(|e| e.to_string())?;
+    p2p_core::ClientOptions::validate_history_size(size)
+}
+
+/// Check a username with the rule shared by identities and the chat
+fn parse_username(value: &str) -> Result<String, String> {
+    identity_gen::validate_username(value).map_err(str::to_string)?;
+    Ok(value.to_string())
+}
+
 impl Cli {
     /// Parse command line arguments
     pub fn parse_args() -> Self {
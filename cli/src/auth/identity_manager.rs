@@ -18,17 +18,7 @@ impl IdentityManager {
         // Get username
         let username: String = Input::with_theme(&ColorfulTheme::default())
             .with_prompt("Choose a username")
-            .validate_with(|input: &String| -> Result<(), &str> {
-                if input.trim().is_empty() {
-                    Err("Username cannot be empty")
-                } else if input.len() > 32 {
-                    Err("Username must be 32 characters or less")
-                } else if input.contains(' ') {
-                    Err("Username cannot contain spaces")
-                } else {
-                    Ok(())
-                }
-            })
+            .validate_with(|input: &String| identity_gen::validate_username(input))
             .interact_text()?;
         
         // Check if username already exists
@@ -160,15 +150,14 @@ impl IdentityManager {
         encrypted_secret_key: &[u8],
     ) -> Result<(), Box<dyn std::error::Error>> {
         use base64::{Engine as _, engine::general_purpose};
-        use std::fs;
-        
+        use identity_gen::FileManager;
+
         // Get identities directory
         let identities_dir = identity_gen::FileManager::get_identities_dir()?;
         let username = &identity.username;
         
         // Create file paths
-        let pub_key_path = identities_dir.join(format!("{}.pub", username));
-        let priv_key_path = identities_dir.join(format!("{}.key", username));
+        let (pub_key_path, priv_key_path) = FileManager::get_export_paths(&identities_dir, username);
         
         // Save public key in PEM format
         let pub_key_b64 = general_purpose::STANDARD.encode(keypair.public_key_bytes());
@@ -176,28 +165,12 @@ impl IdentityManager {
             "-----BEGIN DILITHIUM2 PUBLIC KEY-----\n{}\n-----END DILITHIUM2 PUBLIC KEY-----\n",
             pub_key_b64
         );
-        fs::write(&pub_key_path, pub_key_pem)?;
-        
-        // Save encrypted private key (base64 encoded for readability)
+        FileManager::write_atomic(&pub_key_path, pub_key_pem.as_bytes(), 0o644)?; // rw-r--r--
+
+        // Save encrypted private key (base64 encoded for readability), owner only
         let priv_key_b64 = general_purpose::STANDARD.encode(encrypted_secret_key);
-        fs::write(&priv_key_path, priv_key_b64)?;
-        
-        // Set file permissions (read/write for owner only)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            
-            // Set permissions for public key file
-            let mut pub_perms = fs::metadata(&pub_key_path)?.permissions();
-            pub_perms.set_mode(0o644); // rw-r--r--
-            fs::set_permissions(&pub_key_path, pub_perms)?;
-            
-            // Set permissions for private key file (more restrictive)
-            let mut priv_perms = fs::metadata(&priv_key_path)?.permissions();
-            priv_perms.set_mode(0o600); // rw-------
-            fs::set_permissions(&priv_key_path, priv_perms)?;
-        }
-        
+        FileManager::write_atomic(&priv_key_path, priv_key_b64.as_bytes(), 0o600)?; // rw-------
+
         println!("{}", "✓ Public key exported to:".bright_green());
         println!("  {}", pub_key_path.display().to_string().bright_cyan());
         println!("{}", "✓ Private key exported to:".bright_green());
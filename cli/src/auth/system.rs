@@ -9,12 +9,16 @@ pub struct AuthSystem;
 
 impl AuthSystem {
     /// Main authentication flow - checks for keys and verifies user
-    pub async fn authenticate() -> Result<AuthenticatedUser, Box<dyn std::error::Error>> {
-        // Clear screen for clean presentation
-        print!("\x1B[2J\x1B[1;1H");
-        
-        // Show authentication header
-        Self::show_auth_header();
+    ///
+    /// With `quiet` the screen is not cleared and the header is skipped.
+    pub async fn authenticate(quiet: bool) -> Result<AuthenticatedUser, Box<dyn std::error::Error>> {
+        if !quiet {
+            // Clear screen for clean presentation
+            print!("\x1B[2J\x1B[1;1H");
+
+            // Show authentication header
+            Self::show_auth_header();
+        }
         
         // Delegate to identity verifier
         IdentityVerifier::check_and_verify_identities().await
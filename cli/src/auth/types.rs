@@ -3,6 +3,11 @@
 use identity_gen::Identity;
 
 /// Authenticated user information
+///
+/// Authentication is local: it shows that whoever is at the keyboard knows
+/// the password that decrypts this identity's secret key on this machine.
+/// No server is involved, and peers are only told the fingerprint and public
+/// key, so they can recognise or block the identity but not check the login.
 #[derive(Debug, Clone)]
 pub struct AuthenticatedUser {
     pub username: String,
@@ -2,7 +2,9 @@
 
 use colored::*;
 use dialoguer::{theme::ColorfulTheme, Select, Password};
-use identity_gen::{list_identities, load_identity, Identity, Encryption};
+use identity_gen::password::unlock_secret_key_with;
+use identity_gen::{list_identities, load_identity, Identity, IdentityError, SecretPassword, MAX_PASSWORD_ATTEMPTS};
+use shared::crypto::DilithiumKeypair;
 use std::collections::HashMap;
 use crate::auth::types::AuthenticatedUser;
 use crate::auth::identity_manager::IdentityManager;
@@ -129,62 +131,53 @@ impl IdentityVerifier {
         println!("{}", format!("Fingerprint: {}", identity.fingerprint).dimmed());
         println!();
         
-        const MAX_ATTEMPTS: u8 = 3;
-        let mut attempts = 0;
-        
-        loop {
-            attempts += 1;
-            
-            let password = Password::with_theme(&ColorfulTheme::default())
-                .with_prompt(format!("Enter password for '{}' (attempt {}/{})", username, attempts, MAX_ATTEMPTS))
-                .interact()?;
-            
-            // Try to decrypt the secret key to verify password
-            match Self::verify_password(identity, &password) {
-                Ok(true) => {
-                    println!();
-                    println!("{}", "✅ Authentication successful!".bright_green().bold());
-                    println!("{}", format!("Welcome back, {}!", username).bright_green());
-                    println!();
-                    
-                    // Wait a moment for user to see success message
-                    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-                    
-                    return Ok(AuthenticatedUser {
-                        username: username.to_string(),
-                        identity: identity.clone(),
-                    });
-                }
-                Ok(false) => {
-                    println!("{}", "❌ Invalid password".bright_red());
-                    
-                    if attempts >= MAX_ATTEMPTS {
-                        println!();
-                        println!("{}", "🚫 Maximum authentication attempts exceeded.".bright_red().bold());
-                        println!("{}", "Access denied for security reasons.".bright_red());
-                        return Err("Authentication failed".into());
-                    }
-                    
-                    println!("{}", format!("Please try again. {} attempts remaining.", MAX_ATTEMPTS - attempts).bright_yellow());
-                    println!();
-                }
-                Err(e) => {
-                    return Err(format!("Authentication error: {}", e).into());
-                }
+        // Decrypting the secret key proves the password, the key itself is wiped right away
+        let unlocked = unlock_secret_key_with(identity, MAX_PASSWORD_ATTEMPTS, |attempt, max_attempts| {
+            Self::prompt_password(username, attempt, max_attempts)
+        });
+
+        match unlocked {
+            Ok(_) => {
+                println!();
+                println!("{}", "✅ Authentication successful!".bright_green().bold());
+                println!("{}", format!("Welcome back, {}!", username).bright_green());
+                println!();
+
+                // Wait a moment for user to see success message
+                tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+                Ok(AuthenticatedUser {
+                    username: username.to_string(),
+                    identity: identity.clone(),
+                })
             }
+            Err(IdentityError::PasswordAttemptsExceeded(_)) => {
+                println!();
+                println!("{}", "🚫 Maximum authentication attempts exceeded.".bright_red().bold());
+                println!("{}", "Access denied for security reasons.".bright_red());
+                Err("Authentication failed".into())
+            }
+            Err(e) => Err(format!("Authentication error: {}", e).into()),
         }
     }
-    
-    /// Verify password by attempting to decrypt secret key
-    fn verify_password(identity: &Identity, password: &str) -> Result<bool, Box<dyn std::error::Error>> {
-        let encrypted_secret_key = identity.get_secret_key_bytes()?;
-        
-        match Encryption::decrypt_secret_key(&encrypted_secret_key, password) {
-            Ok(_) => Ok(true),
-            Err(_) => Ok(false), // Invalid password, not an error
-        }
+
+    /// Unlock an identity's Dilithium keypair, e.g. to bind TLS certificates to it
+    pub fn unlock_keypair(username: &str, identity: &Identity) -> Result<DilithiumKeypair, Box<dyn std::error::Error>> {
+        println!("{}", format!("🔐 Unlocking identity: {}", username).bright_cyan().bold());
+        let secret_key = unlock_secret_key_with(identity, MAX_PASSWORD_ATTEMPTS, |attempt, max_attempts| {
+            Self::prompt_password(username, attempt, max_attempts)
+        })?;
+        DilithiumKeypair::from_bytes(&identity.get_public_key_bytes()?, &secret_key)
     }
-    
+
+    fn prompt_password(username: &str, attempt: u32, max_attempts: u32) -> Result<SecretPassword, IdentityError> {
+        Password::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Enter password for '{}' (attempt {}/{})", username, attempt, max_attempts))
+            .interact()
+            .map(SecretPassword::new)
+            .map_err(|e| IdentityError::InvalidInput(e.to_string()))
+    }
+
     /// Check if any identities exist and handle verification
     pub async fn check_and_verify_identities() -> Result<AuthenticatedUser, Box<dyn std::error::Error>> {
         // Check if any identities exist
@@ -0,0 +1,157 @@
+//! Connectivity check command handlers
+//!
+//! Verifies that the listen address can be bound and that every bootstrap
+//! peer completes the TLS and chat handshakes and admits us, without
+//! starting the chat UI.
+
+use colored::*;
+use shared::config::{find_available_port, BUILD_VERSION, FIXED_PORT, FALLBACK_PORT_END, MAX_FRAME_LENGTH, PROTOCOL_VERSION};
+use shared::p2p::derive_peer_id;
+use shared::p2p::handshake::{check_protocol_version, exchange_handshake, frame_connection, refuse, wait_for_admission};
+use shared::tls::TlsConnection;
+use shared::{CertificateManager, P2PMessage, TlsContext};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+/// Maximum time a single probe may take before it is reported as failed
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of a single connectivity probe
+struct CheckResult {
+    name: String,
+    outcome: Result<String, String>,
+}
+
+/// Handle connectivity check command
+pub async fn handle_check_command(
+    host: String,
+    port: Option<u16>,
+    bootstrap: Vec<SocketAddr>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "🩺 Running connectivity checks...".bright_cyan().bold());
+    println!("{}", "─".repeat(60).dimmed());
+
+    let mut results = vec![check_bind(&host, port).await];
+
+    if !bootstrap.is_empty() {
+        match tls_context().await {
+            Ok(tls_context) => {
+                for peer in &bootstrap {
+                    results.push(check_peer(*peer, &tls_context).await);
+                }
+            }
+            Err(e) => results.push(report(CheckResult {
+                name: "TLS certificate".to_string(),
+                outcome: Err(e),
+            })),
+        }
+    }
+
+    let failed = results.iter().filter(|result| result.outcome.is_err()).count();
+
+    println!("{}", "─".repeat(60).dimmed());
+    if failed == 0 {
+        println!("{}", format!("✅ All {} checks passed", results.len()).bright_green().bold());
+        Ok(())
+    } else {
+        println!("{}", format!("❌ {} of {} checks failed", failed, results.len()).bright_red().bold());
+        Err(format!("{} connectivity checks failed", failed).into())
+    }
+}
+
+/// Check that the listen address can be bound
+async fn check_bind(host: &str, port: Option<u16>) -> CheckResult {
+    let outcome = match port {
+        Some(port) => bind_port(host, port).await.map(|_| format!("port {} is free", port)),
+        None => find_available_port(host)
+            .map(|port| format!("port {} is free (searched {}-{})", port, FIXED_PORT, FALLBACK_PORT_END))
+            .map_err(|e| e.to_string()),
+    };
+
+    report(CheckResult {
+        name: format!("Bind {}", host),
+        outcome,
+    })
+}
+
+/// Bind and immediately release the given port
+async fn bind_port(host: &str, port: u16) -> Result<(), String> {
+    let addr: SocketAddr = format!("{}:{}", host, port)
+        .parse()
+        .map_err(|e| format!("invalid address: {}", e))?;
+
+    match tokio::time::timeout(PROBE_TIMEOUT, TcpListener::bind(addr)).await {
+        Ok(Ok(_listener)) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("timed out after {}s", PROBE_TIMEOUT.as_secs())),
+    }
+}
+
+/// Create the TLS context used for peer probes
+async fn tls_context() -> Result<TlsContext, String> {
+    let mut cert_manager = CertificateManager::new("connectivity-check".to_string());
+    cert_manager
+        .generate_self_signed_cert()
+        .await
+        .map_err(|e| e.to_string())?;
+    TlsContext::new(&cert_manager).await.map_err(|e| e.to_string())
+}
+
+/// Connect to a bootstrap peer, handshake and check that it admits us
+///
+/// Peers that need mutual TLS or a room key, or speak another protocol
+/// version, fail here with the reason they give.
+async fn check_peer(addr: SocketAddr, tls_context: &TlsContext) -> CheckResult {
+    let started = Instant::now();
+    let probe = async {
+        let connection = TlsConnection::connect_tls(addr, tls_context.client_config.clone())
+            .await
+            .map_err(|e| e.to_string())?;
+        let tls_info = connection.get_tls_info().unwrap_or_else(|| "TLS".to_string());
+
+        let local_peer_id = derive_peer_id(b"connectivity-check");
+        let local_hello = P2PMessage::Handshake {
+            peer_id: local_peer_id.clone(),
+            username: "connectivity-check".to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            fingerprint: String::new(),
+            build_version: BUILD_VERSION.to_string(),
+            heartbeat_interval_secs: 0,
+            room_proof: String::new(),
+        };
+        let mut framed = frame_connection(connection, MAX_FRAME_LENGTH);
+        let hello = exchange_handshake(&mut framed, &local_hello, PROBE_TIMEOUT)
+            .await
+            .map_err(|e| e.to_string())?;
+        check_protocol_version(&hello.protocol_version)?;
+        wait_for_admission(&mut framed, PROBE_TIMEOUT).await?;
+        refuse(&mut framed, &local_peer_id, "Connectivity check done").await;
+
+        Ok(format!(
+            "{} admitted us over {} in {}ms",
+            hello.username,
+            tls_info,
+            started.elapsed().as_millis()
+        ))
+    };
+
+    let outcome = match tokio::time::timeout(PROBE_TIMEOUT, probe).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(format!("timed out after {}s", PROBE_TIMEOUT.as_secs())),
+    };
+
+    report(CheckResult {
+        name: format!("Peer {}", addr),
+        outcome,
+    })
+}
+
+/// Print a single check result as it completes
+fn report(result: CheckResult) -> CheckResult {
+    match &result.outcome {
+        Ok(detail) => println!("{} {:<28} {}", "PASS".bright_green().bold(), result.name, detail.dimmed()),
+        Err(reason) => println!("{} {:<28} {}", "FAIL".bright_red().bold(), result.name, reason.bright_red()),
+    }
+    result
+}
@@ -3,7 +3,7 @@
 use colored::*;
 use shared::config::{
     DEFAULT_LOG_LEVEL, FIXED_PORT, FALLBACK_PORT_START, FALLBACK_PORT_END, 
-    DEFAULT_HOST_LOCALHOST, MULTICAST_ADDR, CONNECTION_TIMEOUT, 
+    DEFAULT_HOST_LOCALHOST, DEFAULT_MULTICAST_GROUP, DEFAULT_MULTICAST_PORT, CONNECTION_TIMEOUT, 
     HEARTBEAT_INTERVAL, MAX_CONNECTIONS
 };
 
@@ -25,7 +25,7 @@ fn show_config() {
     println!("🔄 Fallback Ports: {}-{}", FALLBACK_PORT_START.to_string().bright_white(), FALLBACK_PORT_END.to_string().bright_white());
     println!("🔒 TLS: {} (Always Enabled)", "true".bright_green());
     println!("📝 Log Level: {}", DEFAULT_LOG_LEVEL.bright_white());
-    println!("🌐 Multicast: {}", MULTICAST_ADDR.bright_white());
+    println!("🌐 Multicast: {}:{}", DEFAULT_MULTICAST_GROUP.to_string().bright_white(), DEFAULT_MULTICAST_PORT.to_string().bright_white());
     println!("⏱️  Connection Timeout: {}s", CONNECTION_TIMEOUT.to_string().bright_white());
     println!("💓 Heartbeat Interval: {}s", HEARTBEAT_INTERVAL.to_string().bright_white());
     println!("👥 Max Connections: {}", MAX_CONNECTIONS.to_string().bright_white());
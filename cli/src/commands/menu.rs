@@ -5,14 +5,16 @@ use crate::ui::InteractiveMenu;
 use crate::auth::AuthSystem;
 
 /// Handle menu command (interactive mode)
-pub async fn handle_menu_command() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn handle_menu_command(quiet: bool) -> Result<(), Box<dyn std::error::Error>> {
     // Interactive menu mode with authentication
-    println!("{}", "🎯 Starting DPQ Chat...".bright_green().bold());
+    if !quiet {
+        println!("{}", "🎯 Starting DPQ Chat...".bright_green().bold());
+    }
     
     // First authenticate the user
-    let authenticated_user = AuthSystem::authenticate().await?;
+    let authenticated_user = AuthSystem::authenticate(quiet).await?;
     
     // Then show the interactive menu with authenticated user
-    let mut menu = InteractiveMenu::new_with_user(authenticated_user);
+    let mut menu = InteractiveMenu::new_with_user(authenticated_user).with_quiet(quiet);
     menu.show().await
 }
@@ -4,11 +4,16 @@
 //! for better maintainability and organization.
 
 pub mod p2p;
+pub mod check;
 pub mod config;
 pub mod identity;
 pub mod menu;
+pub mod selftest;
 
 use super::{Cli, Commands};
+use crate::auth::verification::IdentityVerifier;
+use p2p_core::{ClientOptions, NotifyMode, OutputMode, Theme};
+use shared::utils::resolve_peer_addrs;
 use std::env;
 
 /// Handle the parsed CLI command
@@ -18,18 +23,96 @@ pub async fn handle_command(cli: Cli) -> Result<(), Box<dyn std::error::Error>>
         env::set_var("LOG_LEVEL", "debug");
     }
 
+    if cli.identity_dir.is_some() {
+        identity_gen::FileManager::set_identity_dir(cli.identity_dir);
+    }
+
+    if cli.no_color {
+        crate::ui::disable_colors();
+    }
+
     match cli.command {
         Some(Commands::P2p { 
             username, 
             port, 
             host, 
             bootstrap, 
-            no_tls 
+            peers_file,
+            no_multicast,
+            multicast_group,
+            multicast_port,
+            mdns,
+            no_tls,
+            i_understand_this_is_insecure: _,
+            mutual_tls,
+            theme,
+            history_size,
+            auto_away,
+            no_typing,
+            notify,
+            desktop_notify,
+            heartbeat_interval,
+            cleanup_interval,
+            dead_peer_timeout,
+            max_connections,
+            allowlist,
+            reorder_window,
+            known_peers,
+            known_peers_max_age,
+            cert_validity,
+            room_key,
+            output,
         }) => {
-            p2p::handle_p2p_command(username, port, host, bootstrap, no_tls).await
+            // An identity for this username gives peers a fingerprint and a stable peer ID
+            let identity = identity_gen::load_identity(&username).ok();
+            let tls_identity = match (&identity, mutual_tls) {
+                (_, false) => None,
+                (Some(identity), true) => Some(IdentityVerifier::unlock_keypair(&username, identity)?),
+                (None, true) => {
+                    return Err(format!("--mutual-tls needs an identity for {}, create one with generate-key", username).into())
+                }
+            };
+            let options = ClientOptions {
+                theme: if cli.no_color { Theme::None } else { theme.parse::<Theme>()? },
+                history_size,
+                quiet: cli.quiet,
+                output: output.parse::<OutputMode>()?,
+                auto_away_secs: auto_away,
+                typing_indicators: !no_typing,
+                notify_mode: notify.parse::<NotifyMode>()?,
+                desktop_notifications: desktop_notify,
+                multicast_discovery: !no_multicast,
+                multicast_group,
+                multicast_port,
+                mdns_discovery: mdns,
+                peers_file,
+                heartbeat_interval_secs: heartbeat_interval,
+                cleanup_interval_secs: cleanup_interval,
+                dead_peer_timeout_secs: dead_peer_timeout,
+                max_connections: max_connections as usize,
+                allowlist_path: allowlist,
+                reorder_window_ms: reorder_window,
+                known_peers_path: known_peers,
+                known_peers_max_age_secs: known_peers_max_age.saturating_mul(60 * 60),
+                cert_validity_secs: cert_validity.saturating_mul(60 * 60),
+                room_key,
+                fingerprint: identity.as_ref().map(|identity| identity.fingerprint.clone()),
+                identity_public_key: identity.as_ref().and_then(|identity| identity.get_public_key_bytes().ok()),
+                tls_identity,
+                ..ClientOptions::default()
+            };
+            let bootstrap = resolve_peer_addrs(&bootstrap).await.map_err(|e| e.to_string())?;
+            p2p::handle_p2p_command(username, port, host, bootstrap, no_tls, options).await
+        }
+        Some(Commands::Check { host, port, bootstrap }) => {
+            let bootstrap = resolve_peer_addrs(&bootstrap).await.map_err(|e| e.to_string())?;
+            check::handle_check_command(host, port, bootstrap).await
+        }
+        Some(Commands::Selftest) => {
+            selftest::handle_selftest_command().await
         }
         Some(Commands::Menu) | None => {
-            menu::handle_menu_command().await
+            menu::handle_menu_command(cli.quiet).await
         }
         Some(Commands::Config { show }) => {
             config::handle_config_command(show).await
@@ -1,9 +1,12 @@
 //! P2P command handlers
 
 use colored::*;
-use std::process::Command;
+use p2p_core::{ClientOptions, OutputMode, QuitReason};
 use std::net::SocketAddr;
 
+/// Printed before a chat starts without TLS
+const CLEARTEXT_WARNING: &str = "⚠️  TLS DISABLED: all traffic, including the handshake, is sent in cleartext. Only peers also started with --no-tls can connect.";
+
 /// Handle P2P chat command
 pub async fn handle_p2p_command(
     username: String,
@@ -11,59 +14,77 @@ pub async fn handle_p2p_command(
     host: String,
     bootstrap: Vec<SocketAddr>,
     no_tls: bool,
+    options: ClientOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    println!("{}", "🚀 Starting P2P Chat Mode...".bright_cyan().bold());
-    
-    // Convert to the format expected by the existing P2P client
-    let mut args = vec![
-        "p2p-core".to_string(),
-        "-u".to_string(),
-        username,
-        "--host".to_string(),
-        host,
-    ];
-
-    if let Some(p) = port {
-        args.push("-p".to_string());
-        args.push(p.to_string());
+    // Stdout carries only events in JSON mode
+    if options.output == OutputMode::Json {
+        if no_tls {
+            eprintln!("{}", CLEARTEXT_WARNING);
+        }
+        p2p_core::run_headless_chat(username, Some(host), port, bootstrap, !no_tls, options).await?;
+        return Ok(());
     }
 
-    for peer in bootstrap {
-        args.push("-b".to_string());
-        args.push(peer.to_string());
+    if !options.quiet {
+        println!("{}", "🚀 Starting P2P Chat Mode...".bright_cyan().bold());
     }
 
-    // TLS is always enabled in hardcoded config, ignore no_tls flag
+    // Shown even with --quiet, the chat header keeps warning for the whole session
     if no_tls {
-        println!("{}", "⚠️  Warning: TLS is always enabled for security. --no-tls flag ignored.".bright_yellow());
+        println!("{}", CLEARTEXT_WARNING.bright_red().bold());
     }
 
-    // Call external p2p-core binary
-    run_chat_client(&args).await
+    run_chat_session(username, host, port, bootstrap, !no_tls, options).await?;
+    Ok(())
 }
 
-/// Run external p2p-core binary
-async fn run_chat_client(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-    println!("{}", "🚀 Launching P2P Chat Client...".bright_cyan().bold());
-    
-    let mut cmd = Command::new("cargo");
-    cmd.arg("run")
-       .arg("-p")
-       .arg("p2p-core")
-       .arg("--bin")
-       .arg("p2p-core")
-       .arg("--");
-    
-    // Add all arguments except the first one (program name)
-    for arg in args.iter().skip(1) {
-        cmd.arg(arg);
+/// Run a P2P chat session in this process and report how it ended
+///
+/// Shared by the `p2p` subcommand and the interactive menu so both go
+/// through the same `p2p_core` client. The reason is returned so the menu
+/// can offer to rejoin after an unexpected exit.
+pub async fn run_chat_session(
+    username: String,
+    host: String,
+    port: Option<u16>,
+    bootstrap: Vec<SocketAddr>,
+    enable_tls: bool,
+    options: ClientOptions,
+) -> Result<QuitReason, Box<dyn std::error::Error>> {
+    if !options.quiet {
+        println!("{}", "🚀 Launching P2P Chat Client...".bright_cyan().bold());
     }
-    
-    let status = cmd.status()?;
-    
-    if !status.success() {
-        return Err("Chat client failed to start".into());
+
+    let result = p2p_core::run_p2p_chat_with_options(
+        username,
+        Some(host),
+        port,
+        bootstrap,
+        enable_tls,
+        options,
+    ).await;
+
+    match result {
+        Ok(quit_reason) => {
+            match &quit_reason {
+                QuitReason::UserQuit => {
+                    println!("{}", "✅ Returned to main menu".bright_green());
+                }
+                QuitReason::OwnerDisconnect => {
+                    println!("{}", "⚠️  Owner disconnected, returning to menu".bright_yellow());
+                }
+                QuitReason::NetworkError => {
+                    println!("{}", "❌ Network error, returning to menu".bright_red());
+                }
+                QuitReason::StdinClosed => {
+                    println!("{}", "👋 Input closed, left the chat".bright_green());
+                }
+            }
+            Ok(quit_reason)
+        }
+        Err(e) => {
+            println!("{} {}", "❌ Error:".bright_red().bold(), format!("Chat client error: {}", e).red());
+            Err(e.into())
+        }
     }
-    
-    Ok(())
 }
@@ -0,0 +1,177 @@
+//! Self-test command handlers
+//!
+//! Runs the crypto stack end to end in-process, so a fresh build or a new
+//! machine can be checked without a second peer.
+
+use colored::*;
+use identity_gen::KeyPair;
+use shared::crypto::{DilithiumKeypair, DilithiumVerifier, KyberKeyExchangeManager};
+use shared::tls::TlsListener;
+use shared::{CertificateManager, HandshakeManager, MessageCrypto, SessionKey, TlsContext};
+use std::net::SocketAddr;
+
+/// Outcome of a single self-test check
+struct CheckResult {
+    name: String,
+    outcome: Result<String, String>,
+}
+
+/// Handle self-test command
+pub async fn handle_selftest_command() -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", "🧪 Running self-test...".bright_cyan().bold());
+    println!("{}", "─".repeat(60).dimmed());
+
+    let mut results = vec![
+        report(CheckResult {
+            name: "Kyber key exchange".to_string(),
+            outcome: check_kyber(),
+        }),
+        report(CheckResult {
+            name: "Dilithium sign/verify".to_string(),
+            outcome: check_dilithium(),
+        }),
+    ];
+
+    match check_handshake() {
+        Ok((alice_session, bob_session)) => {
+            results.push(report(CheckResult {
+                name: "Handshake".to_string(),
+                outcome: Ok("both sides derived the same session key".to_string()),
+            }));
+            results.push(report(CheckResult {
+                name: "Message encrypt/decrypt".to_string(),
+                outcome: check_message_crypto(&alice_session, &bob_session),
+            }));
+        }
+        Err(e) => {
+            results.push(report(CheckResult {
+                name: "Handshake".to_string(),
+                outcome: Err(e),
+            }));
+            results.push(report(CheckResult {
+                name: "Message encrypt/decrypt".to_string(),
+                outcome: Err("skipped, no session key".to_string()),
+            }));
+        }
+    }
+
+    results.push(report(CheckResult {
+        name: "TLS listener".to_string(),
+        outcome: check_tls_listener().await,
+    }));
+
+    let failed = results.iter().filter(|result| result.outcome.is_err()).count();
+
+    println!("{}", "─".repeat(60).dimmed());
+    if failed == 0 {
+        println!("{}", format!("✅ All {} checks passed", results.len()).bright_green().bold());
+        Ok(())
+    } else {
+        println!("{}", format!("❌ {} of {} checks failed", failed, results.len()).bright_red().bold());
+        Err(format!("{} self-test checks failed", failed).into())
+    }
+}
+
+/// Run a Kyber exchange between two managers and compare the secrets
+fn check_kyber() -> Result<String, String> {
+    let mut initiator = KyberKeyExchangeManager::new();
+    let mut responder = KyberKeyExchangeManager::new();
+
+    let offer = initiator.initiate_key_exchange().map_err(|e| e.to_string())?;
+    let (response, responder_secret) = responder.respond_to_key_exchange(&offer).map_err(|e| e.to_string())?;
+    let initiator_secret = initiator.complete_key_exchange(&response).map_err(|e| e.to_string())?;
+
+    if initiator_secret != responder_secret {
+        return Err("shared secrets differ".to_string());
+    }
+    Ok(format!("{}-byte shared secret agreed", initiator_secret.len()))
+}
+
+/// Sign with a fresh Dilithium key, verify it and reject a tampered message
+fn check_dilithium() -> Result<String, String> {
+    let keypair = new_dilithium_keypair()?;
+    let message = b"dpq-chat selftest";
+    let signature = keypair.sign(message);
+
+    if !DilithiumVerifier::verify(message, &signature, keypair.public_key_bytes()).map_err(|e| e.to_string())? {
+        return Err("valid signature was rejected".to_string());
+    }
+    if DilithiumVerifier::verify(b"dpq-chat tampered", &signature, keypair.public_key_bytes()).map_err(|e| e.to_string())? {
+        return Err("tampered message was accepted".to_string());
+    }
+    Ok(format!("{}-byte signature verified", signature.len()))
+}
+
+/// Complete a signed handshake between two managers, returning both session keys
+fn check_handshake() -> Result<(SessionKey, SessionKey), String> {
+    let alice_keys = new_dilithium_keypair()?;
+    let bob_keys = new_dilithium_keypair()?;
+    let mut alice = HandshakeManager::new_with_dilithium(
+        "alice".to_string(),
+        "alice_fp".to_string(),
+        alice_keys.public_key_bytes().to_vec(),
+        alice_keys,
+    );
+    let mut bob = HandshakeManager::new_with_dilithium(
+        "bob".to_string(),
+        "bob_fp".to_string(),
+        bob_keys.public_key_bytes().to_vec(),
+        bob_keys,
+    );
+
+    let alice_handshake = alice.initiate_handshake("bob_fp").map_err(|e| e.to_string())?;
+    let (bob_session, bob_response) = bob.process_handshake(alice_handshake).map_err(|e| e.to_string())?;
+    let bob_response = bob_response.ok_or("bob sent no handshake response")?;
+    let (alice_session, _) = alice.process_handshake(bob_response).map_err(|e| e.to_string())?;
+
+    if alice_session.key() != bob_session.key() {
+        return Err("session keys differ".to_string());
+    }
+    Ok((alice_session, bob_session))
+}
+
+/// Encrypt a message with one side's session key and decrypt it with the other's
+fn check_message_crypto(sender: &SessionKey, receiver: &SessionKey) -> Result<String, String> {
+    let message = MessageCrypto::create_text_message("alice".to_string(), "hello from selftest".to_string());
+    let encrypted = MessageCrypto::encrypt_message(sender, &message, 1).map_err(|e| e.to_string())?;
+    let decrypted = MessageCrypto::decrypt_message(receiver, &encrypted).map_err(|e| e.to_string())?;
+
+    if decrypted.content != message.content {
+        return Err("decrypted text does not match".to_string());
+    }
+    Ok(format!("{} bytes of ciphertext round-tripped", encrypted.encrypted_content.len()))
+}
+
+/// Bind a TLS listener on an ephemeral loopback port and release it
+async fn check_tls_listener() -> Result<String, String> {
+    let mut cert_manager = CertificateManager::new("selftest".to_string());
+    cert_manager
+        .generate_self_signed_cert()
+        .await
+        .map_err(|e| e.to_string())?;
+    let tls_context = TlsContext::new(&cert_manager).await.map_err(|e| e.to_string())?;
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    let listener = TlsListener::bind_tls(addr, tls_context.server_config.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    let bound = listener.local_addr().map_err(|e| e.to_string())?;
+    drop(listener);
+
+    Ok(format!("bound and released {}", bound))
+}
+
+/// Generate a throwaway Dilithium keypair
+fn new_dilithium_keypair() -> Result<DilithiumKeypair, String> {
+    let keys = KeyPair::generate().map_err(|e| e.to_string())?;
+    DilithiumKeypair::from_bytes(keys.public_key_bytes(), keys.secret_key_bytes()).map_err(|e| e.to_string())
+}
+
+/// Print a single check result as it completes
+fn report(result: CheckResult) -> CheckResult {
+    match &result.outcome {
+        Ok(detail) => println!("{} {:<28} {}", "PASS".bright_green().bold(), result.name, detail.dimmed()),
+        Err(reason) => println!("{} {:<28} {}", "FAIL".bright_red().bold(), result.name, reason.bright_red()),
+    }
+    result
+}
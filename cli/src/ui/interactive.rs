@@ -1,18 +1,18 @@
 //! Interactive menu using dialoguer for professional UX
 
-use std::process::Command;
-use std::net::SocketAddr;
 use colored::*;
 use dialoguer::{theme::ColorfulTheme, Select, Input, Confirm};
-use indicatif::{ProgressBar, ProgressStyle};
-use std::time::Duration;
-use tokio::time::sleep;
-use shared::config::{HostOption, find_available_port, TLS_ENABLED};
-use crate::auth::AuthenticatedUser;
+use shared::config::{HostOption, TLS_ENABLED, find_available_port};
+use shared::utils::{parse_peer_target, resolve_peer_addrs};
+use identity_gen::{FileManager, Identity, IdentityStatus};
+use p2p_core::{ClientOptions, QuitReason};
+use crate::auth::{AuthSystem, AuthenticatedUser};
+use crate::commands::p2p::run_chat_session;
 
 /// Interactive menu system using dialoguer
 pub struct InteractiveMenu {
     authenticated_user: Option<AuthenticatedUser>,
+    quiet: bool,
 }
 
 impl InteractiveMenu {
@@ -20,6 +20,7 @@ impl InteractiveMenu {
     pub fn new() -> Self {
         Self {
             authenticated_user: None,
+            quiet: false,
         }
     }
     
@@ -27,12 +28,21 @@ impl InteractiveMenu {
     pub fn new_with_user(user: AuthenticatedUser) -> Self {
         Self {
             authenticated_user: Some(user),
+            quiet: false,
         }
     }
 
+    /// Hide the welcome banner, separators and startup spinner
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
     /// Show the main interactive menu
     pub async fn show(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.show_welcome();
+        if !self.quiet {
+            self.show_welcome();
+        }
         
         loop {
             let selection = self.show_main_menu()?;
@@ -47,10 +57,14 @@ impl InteractiveMenu {
                     self.show_coming_soon("Join Chat Room");
                 }
                 2 => {
+                    // Switch identity or log out
+                    self.handle_switch_identity().await?;
+                }
+                3 => {
                     // Settings
                     self.handle_settings().await?;
                 }
-                3 => {
+                4 => {
                     // Exit
                     if self.confirm_exit()? {
                         println!("{}", "👋 Goodbye! Thanks for using DPQ Chat!".bright_green().bold());
@@ -94,6 +108,7 @@ impl InteractiveMenu {
         let options = vec![
             "🔗 Create P2P Chat",
             "🏠 Join Chat Room (Coming Soon)",
+            "👤 Switch Identity / Log Out",
             "⚙️  Settings",
             "🚪 Exit",
         ];
@@ -111,25 +126,13 @@ impl InteractiveMenu {
     async fn handle_p2p_chat(&self) -> Result<(), Box<dyn std::error::Error>> {
         println!("{}", "\n🔗 Setting up P2P Chat Session".bright_cyan().bold());
         
-        // Use authenticated username
-        let username = if let Some(ref user) = self.authenticated_user {
-            user.username.clone()
-        } else {
-            // Fallback if no authenticated user (shouldn't happen)
-            let username: String = Input::with_theme(&ColorfulTheme::default())
-                .with_prompt("Enter your username")
-                .default("User".to_string())
-                .validate_with(|input: &String| -> Result<(), &str> {
-                    if input.trim().is_empty() {
-                        Err("Username cannot be empty")
-                    } else if input.len() > 32 {
-                        Err("Username must be 32 characters or less")
-                    } else {
-                        Ok(())
-                    }
-                })
-                .interact_text()?;
-            username
+        // Use the authenticated identity, or let the user log in or pick a name for this chat
+        let (username, identity) = match self.authenticated_user {
+            Some(ref user) => (user.username.clone(), Some(user.identity.clone())),
+            None => match self.pick_chat_identity().await? {
+                Some(choice) => choice,
+                None => return Ok(()),
+            },
         };
 
         // Step 2: Choose between create peer or connect to existing peer
@@ -146,7 +149,7 @@ impl InteractiveMenu {
 
         let (final_host, final_port, bootstrap, selected_host_display) = if peer_selection == 0 {
             // Create new peer - Step 3: Host selection
-            let host_options = vec![
+            let host_options = [
                 HostOption::Localhost,
                 HostOption::LocalNetwork,
                 HostOption::Wildcard,
@@ -171,11 +174,11 @@ impl InteractiveMenu {
         } else {
             // Connect to existing peer - use wildcard host (0.0.0.0) automatically
             let bootstrap_addr: String = Input::with_theme(&ColorfulTheme::default())
-                .with_prompt("Enter peer address to connect to (IP:PORT)")
+                .with_prompt("Enter peer address to connect to (HOST:PORT)")
                 .validate_with(|input: &String| -> Result<(), &str> {
-                    match input.parse::<std::net::SocketAddr>() {
+                    match parse_peer_target(input.trim()) {
                         Ok(_) => Ok(()),
-                        Err(_) => Err("Please enter a valid address (e.g., 192.168.1.100:40000)"),
+                        Err(_) => Err("Please enter a valid address (e.g., 192.168.1.100:40000 or chat.example.com:40000)"),
                     }
                 })
                 .interact_text()?;
@@ -188,7 +191,7 @@ impl InteractiveMenu {
 
         // Show selected configuration
         println!("\n{}", "📋 Configuration Summary".bright_yellow().bold());
-        println!("{}", "─".repeat(50).dimmed());
+        self.separator(50);
         println!("👤 Username: {}", username.bright_white());
         println!("🌐 Host: {} ({})", final_host.bright_white(), selected_host_display.dimmed());
         println!("🔌 Port: {}", final_port.unwrap_or(0).to_string().bright_white());
@@ -198,33 +201,142 @@ impl InteractiveMenu {
             println!("🆕 Creating new chat room");
         }
         println!("🔒 TLS: {}", "Enabled".bright_green());
-        println!("{}", "─".repeat(50).dimmed());
+        self.separator(50);
         println!();
 
-        // Show progress
-        self.show_connection_progress().await;
+        // Start P2P chat using the same client as the `p2p` subcommand
+        let bootstrap_targets: Vec<String> = bootstrap.into_iter().map(|addr| addr.trim().to_string()).collect();
 
-        // Build arguments for library function
-        let mut args = vec![
-            "p2p-core".to_string(),
-            "-u".to_string(),
-            username,
-            "--host".to_string(),
-            final_host,
-        ];
+        // Announce our identity so peers can recognise (or block) us
+        let options = ClientOptions {
+            fingerprint: identity.as_ref().map(|identity| identity.fingerprint.clone()),
+            identity_public_key: identity.as_ref().and_then(|identity| identity.get_public_key_bytes().ok()),
+            quiet: self.quiet,
+            ..ClientOptions::default()
+        };
 
-        if let Some(port) = final_port {
-            args.push("-p".to_string());
-            args.push(port.to_string());
+        // Same settings every time, so a dropped session can be rejoined without the prompts
+        loop {
+            // Looked up on every (re)join, the name may point somewhere new
+            let bootstrap_peers = match resolve_peer_addrs(&bootstrap_targets).await {
+                Ok(bootstrap_peers) => bootstrap_peers,
+                Err(e) => {
+                    self.show_error(&e.to_string());
+                    return Ok(());
+                }
+            };
+
+            let quit_reason = run_chat_session(
+                username.clone(),
+                final_host.clone(),
+                final_port,
+                bootstrap_peers,
+                TLS_ENABLED,
+                options.clone(),
+            ).await?;
+
+            if !self.offer_rejoin(&quit_reason)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Log in to a saved identity for this chat, or chat under a typed name without one
+    ///
+    /// A saved identity is only used once its password is entered. Returns
+    /// `None` if the login failed and the chat should not start.
+    async fn pick_chat_identity(&self) -> Result<Option<(String, Option<Identity>)>, Box<dyn std::error::Error>> {
+        let has_identities = FileManager::list_identity_statuses()?
+            .iter()
+            .any(|listing| listing.status == IdentityStatus::Active);
+
+        let items = ["🔐 Log in with a saved identity", "🕶️  Use anonymous / type a name"];
+        let selection = if has_identities {
+            Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Chat as")
+                .default(0)
+                .items(&items)
+                .interact()?
+        } else {
+            1
+        };
+
+        if selection == 0 {
+            return match AuthSystem::authenticate(self.quiet).await {
+                Ok(user) => Ok(Some((user.username, Some(user.identity)))),
+                Err(e) => {
+                    self.show_error(&e.to_string());
+                    Ok(None)
+                }
+            };
         }
 
-        if let Some(bootstrap_addr) = bootstrap {
-            args.push("-b".to_string());
-            args.push(bootstrap_addr);
+        let username: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Enter your username")
+            .default("User".to_string())
+            .validate_with(|input: &String| identity_gen::validate_username(input))
+            .interact_text()?;
+        Ok(Some((username, None)))
+    }
+
+    /// After a session ended without the user asking, offer to start it again
+    fn offer_rejoin(&self, quit_reason: &QuitReason) -> Result<bool, Box<dyn std::error::Error>> {
+        if !matches!(quit_reason, QuitReason::NetworkError | QuitReason::OwnerDisconnect) {
+            return Ok(false);
         }
 
-        // Start P2P chat using library function
-        self.run_chat_client_library(&args).await
+        let answer: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Press r to rejoin with the same settings, or Enter for the main menu")
+            .allow_empty(true)
+            .interact_text()?;
+
+        Ok(answer.trim().eq_ignore_ascii_case("r"))
+    }
+
+    /// Log in as another identity, or log out so chats ask who to be
+    async fn handle_switch_identity(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.authenticated_user {
+            Some(ref user) => println!("{} {}", "🔐 Logged in as".bright_green(), user.username.bright_white().bold()),
+            None => println!("{}", "🔓 Not logged in".dimmed()),
+        }
+
+        let options = vec![
+            "🔄 Switch to another identity",
+            "🚪 Log out",
+            "🔙 Back to Main Menu",
+        ];
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Identity")
+            .default(0)
+            .items(&options)
+            .interact()?;
+
+        match selection {
+            0 => {
+                // A failed login keeps whoever was logged in before
+                match AuthSystem::authenticate(self.quiet).await {
+                    Ok(user) => {
+                        self.authenticated_user = Some(user);
+                        if !self.quiet {
+                            self.show_welcome();
+                        }
+                    }
+                    Err(e) => self.show_error(&e.to_string()),
+                }
+            }
+            1 => {
+                if let Some(user) = self.authenticated_user.take() {
+                    self.show_info(&format!("Logged out of {}", user.username));
+                }
+            }
+            2 => {
+                // Back to main menu
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(())
     }
 
     /// Handle settings menu
@@ -263,18 +375,18 @@ impl InteractiveMenu {
         
         println!();
         println!("{}", "📋 Current Configuration".bright_yellow().bold());
-        println!("{}", "─".repeat(60).dimmed());
+        self.separator(60);
         
         println!("🔌 Fixed Port: {}", FIXED_PORT.to_string().bright_white());
         println!("🔄 Fallback Ports: {}-{}", FALLBACK_PORT_START.to_string().bright_white(), FALLBACK_PORT_END.to_string().bright_white());
         println!("🔒 TLS: {} (Always Enabled)", "true".bright_green());
         println!("📝 Log Level: {}", DEFAULT_LOG_LEVEL.bright_white());
-        println!("🌐 Multicast: {}", MULTICAST_ADDR.bright_white());
+        println!("🌐 Multicast: {}:{}", DEFAULT_MULTICAST_GROUP.to_string().bright_white(), DEFAULT_MULTICAST_PORT.to_string().bright_white());
         println!("⏱️  Connection Timeout: {}s", CONNECTION_TIMEOUT.to_string().bright_white());
         println!("💓 Heartbeat Interval: {}s", HEARTBEAT_INTERVAL.to_string().bright_white());
         println!("👥 Max Connections: {}", MAX_CONNECTIONS.to_string().bright_white());
         
-        println!("{}", "─".repeat(60).dimmed());
+        self.separator(60);
         println!("{}", "💡 Configuration is now hardcoded for security and simplicity".dimmed());
         println!();
 
@@ -311,34 +423,11 @@ impl InteractiveMenu {
         Ok(confirm)
     }
 
-    /// Show connection progress
-    async fn show_connection_progress(&self) {
-        let pb = ProgressBar::new(100);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} {msg}")
-                .unwrap()
-                .progress_chars("#>-")
-        );
-
-        pb.set_message("Initializing P2P connection...");
-
-        for i in 0..=100 {
-            pb.set_position(i);
-            
-            match i {
-                0..=30 => pb.set_message("Setting up networking..."),
-                31..=60 => pb.set_message("Generating TLS certificates..."),
-                61..=90 => pb.set_message("Starting peer discovery..."),
-                91..=100 => pb.set_message("Ready to connect!"),
-                _ => {}
-            }
-            
-            sleep(Duration::from_millis(20)).await;
+    /// Print a dimmed separator line, unless quiet
+    fn separator(&self, width: usize) {
+        if !self.quiet {
+            println!("{}", "─".repeat(width).dimmed());
         }
-
-        pb.finish_with_message("✅ P2P client initialized successfully!");
-        println!();
     }
 
     /// Show error message
@@ -355,118 +444,6 @@ impl InteractiveMenu {
     pub fn show_info(&self, message: &str) {
         println!("{} {}", "ℹ️  Info:".bright_blue().bold(), message.blue());
     }
-    
-
-    /// Run P2P chat client as library function
-    async fn run_chat_client_library(&self, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-        println!("{}", "🚀 Launching P2P Chat Client...".bright_cyan().bold());
-        
-        // Parse arguments
-        let mut username = "Anonymous".to_string();
-        let mut listen_port: Option<u16> = None;
-        let mut bootstrap_peers: Vec<SocketAddr> = vec![];
-        let mut custom_host: Option<String> = None;
-        let enable_tls = TLS_ENABLED; // Always true from hardcoded config
-        
-        let mut i = 1; // Skip program name
-        while i < args.len() {
-            match args[i].as_str() {
-                "-u" => {
-                    if i + 1 < args.len() {
-                        username = args[i + 1].clone();
-                        i += 2;
-                    } else {
-                        return Err("Username requires a value".into());
-                    }
-                }
-                "-p" => {
-                    if i + 1 < args.len() {
-                        listen_port = Some(args[i + 1].parse()?);
-                        i += 2;
-                    } else {
-                        return Err("Port requires a value".into());
-                    }
-                }
-                "--host" => {
-                    if i + 1 < args.len() {
-                        custom_host = Some(args[i + 1].clone());
-                        i += 2;
-                    } else {
-                        return Err("Host requires a value".into());
-                    }
-                }
-                "-b" => {
-                    if i + 1 < args.len() {
-                        let addr: SocketAddr = args[i + 1].parse()?;
-                        bootstrap_peers.push(addr);
-                        i += 2;
-                    } else {
-                        return Err("Bootstrap requires a value".into());
-                    }
-                }
-                _ => {
-                    i += 1;
-                }
-            }
-        }
-        
-        // Determine final host
-        let final_host = custom_host.unwrap_or_else(|| {
-            shared::config::DEFAULT_HOST_LOCALHOST.to_string()
-        });
-        
-        // Run P2P chat and get quit reason
-        let result = p2p_core::run_p2p_chat(username, Some(final_host), listen_port, bootstrap_peers, enable_tls).await;
-        
-        match result {
-            Ok(quit_reason) => {
-                match quit_reason {
-                    p2p_core::QuitReason::UserQuit => {
-                        println!("{}", "✅ Returned to main menu".bright_green());
-                    }
-                    p2p_core::QuitReason::OwnerDisconnect => {
-                        println!("{}", "⚠️  Owner disconnected, returning to menu".bright_yellow());
-                    }
-                    p2p_core::QuitReason::NetworkError => {
-                        println!("{}", "❌ Network error, returning to menu".bright_red());
-                    }
-                }
-                Ok(())
-            }
-            Err(e) => {
-                self.show_error(&format!("Chat client error: {}", e));
-                Err(e)
-            }
-        }
-    }
-
-    /// Run external p2p-core binary (fallback method)
-    #[allow(dead_code)]
-    async fn run_chat_client(&self, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-        println!("{}", "🚀 Launching P2P Chat Client...".bright_cyan().bold());
-        
-        let mut cmd = Command::new("cargo");
-        cmd.arg("run")
-           .arg("-p")
-           .arg("p2p-core")
-           .arg("--bin")
-           .arg("p2p-core")
-           .arg("--");
-        
-        // Add all arguments except the first one (program name)
-        for arg in args.iter().skip(1) {
-            cmd.arg(arg);
-        }
-        
-        let status = cmd.status()?;
-        
-        if !status.success() {
-            self.show_error("Chat client failed to start");
-            return Err("Chat client failed to start".into());
-        }
-        
-        Ok(())
-    }
 }
 
 impl Default for InteractiveMenu {
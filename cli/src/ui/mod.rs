@@ -10,6 +10,17 @@ pub use interactive::InteractiveMenu;
 
 use colored::*;
 
+/// Turn off colors everywhere: `colored` text, menus drawn with crossterm and dialoguer prompts
+///
+/// Without this each library picks colors itself, disabling them when output
+/// is not a terminal or `NO_COLOR` is set.
+pub fn disable_colors() {
+    colored::control::set_override(false);
+    crossterm::style::force_color_output(false);
+    dialoguer::console::set_colors_enabled(false);
+    dialoguer::console::set_colors_enabled_stderr(false);
+}
+
 /// Display application header for P2P chat mode
 pub fn display_header() {
     println!("{}", "=== DPQ Chat Client - P2P Mode ===".bright_cyan().bold());
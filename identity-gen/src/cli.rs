@@ -2,11 +2,11 @@ use clap::{Parser, Subcommand};
 use dialoguer::{Input, Password, Confirm, Select};
 use colored::*;
 use chrono::{Utc, Duration};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::identity::Identity;
-use crate::crypto::{KeyPair, Encryption};
-use crate::file_manager::FileManager;
+use crate::identity::{validate_username, Identity};
+use crate::crypto::{constant_time_eq, KeyPair, Encryption};
+use crate::file_manager::{FileManager, IdentityStatus};
 use crate::error::{IdentityError, Result};
 
 #[derive(Parser)]
@@ -14,6 +14,14 @@ use crate::error::{IdentityError, Result};
 #[command(about = "CRYSTALS-Dilithium Identity Generator for DPQ Chat")]
 #[command(version = "0.1.0")]
 pub struct Cli {
+    /// Directory holding identity files (default: $IDENTITY_DIR or ~/.dpq-chat/identities)
+    #[arg(long, global = true, value_name = "DIR")]
+    pub identity_dir: Option<PathBuf>,
+
+    /// Disable colored output (also set by the NO_COLOR environment variable)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -26,7 +34,7 @@ pub enum Commands {
         #[arg(short, long)]
         username: Option<String>,
         
-        /// Custom output path for the identity file
+        /// Custom output path for the identity file, the .pub/.key exports are written next to it
         #[arg(short, long)]
         output: Option<PathBuf>,
         
@@ -40,7 +48,19 @@ pub enum Commands {
     },
     
     /// List existing identities
-    List,
+    List {
+        /// Output format: text or json (an array of identity objects)
+        #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
+
+        /// Only list identities that have expired
+        #[arg(long, conflicts_with = "active_only")]
+        expired_only: bool,
+
+        /// Only list identities that have not expired
+        #[arg(long)]
+        active_only: bool,
+    },
     
     /// Show identity information
     Info {
@@ -59,20 +79,45 @@ pub enum Commands {
         /// Username to delete
         username: String,
     },
+
+    /// Delete all expired identities along with their key exports
+    Prune {
+        /// Also delete identities whose files can't be loaded
+        #[arg(long)]
+        corrupted: bool,
+
+        /// Delete without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 }
 
 pub struct CliHandler;
 
 impl CliHandler {
     pub fn run(cli: Cli) -> Result<()> {
+        if cli.identity_dir.is_some() {
+            FileManager::set_identity_dir(cli.identity_dir);
+        }
+
         match cli.command {
             Some(Commands::Generate { username, output, expires_days, non_interactive }) => {
                 Self::generate_identity(username, output, expires_days, non_interactive)
             },
-            Some(Commands::List) => Self::list_identities(),
+            Some(Commands::List { format, expired_only, active_only }) => {
+                let only = if expired_only {
+                    Some(IdentityStatus::Expired)
+                } else if active_only {
+                    Some(IdentityStatus::Active)
+                } else {
+                    None
+                };
+                Self::list_identities(format == "json", only)
+            },
             Some(Commands::Info { username }) => Self::show_identity_info(&username),
             Some(Commands::Verify { file }) => Self::verify_identity(&file),
             Some(Commands::Delete { username }) => Self::delete_identity(&username),
+            Some(Commands::Prune { corrupted, yes }) => Self::prune_identities(corrupted, yes),
             None => Self::interactive_mode(),
         }
     }
@@ -101,7 +146,7 @@ impl CliHandler {
             
             match selection {
                 0 => Self::generate_identity(None, None, None, false)?,
-                1 => Self::list_identities()?,
+                1 => Self::list_identities(false, None)?,
                 2 => {
                     let username: String = Input::new()
                         .with_prompt("Username")
@@ -147,21 +192,14 @@ impl CliHandler {
         
         // Get username
         let username = if let Some(name) = username {
+            validate_username(&name).map_err(|e| IdentityError::InvalidInput(e.to_string()))?;
             name
         } else if non_interactive {
             return Err(IdentityError::InvalidInput("Username required in non-interactive mode".to_string()));
         } else {
             Input::new()
-                .with_prompt("Username (display name)")
-                .validate_with(|input: &String| -> std::result::Result<(), &str> {
-                    if input.trim().is_empty() {
-                        Err("Username cannot be empty")
-                    } else if input.len() > 50 {
-                        Err("Username too long (max 50 characters)")
-                    } else {
-                        Ok(())
-                    }
-                })
+                .with_prompt("Username (also your chat name)")
+                .validate_with(|input: &String| validate_username(input))
                 .interact_text()
                 .map_err(|e| IdentityError::InvalidInput(e.to_string()))?
         };
@@ -253,40 +291,10 @@ impl CliHandler {
         // Save identity
         let file_path = FileManager::save_identity(&identity, output_path.as_deref())?;
         
-        // Export public and private key files
-        let identities_dir = FileManager::get_identities_dir()?;
-        let pub_key_path = identities_dir.join(format!("{}.pub", username));
-        let priv_key_path = identities_dir.join(format!("{}.key", username));
-        
-        // Save public key in PEM format
-        use base64::{Engine as _, engine::general_purpose};
-        let pub_key_b64 = general_purpose::STANDARD.encode(&keypair.public_key_bytes());
-        let pub_key_pem = format!(
-            "-----BEGIN DILITHIUM2 PUBLIC KEY-----\n{}\n-----END DILITHIUM2 PUBLIC KEY-----\n",
-            pub_key_b64
-        );
-        std::fs::write(&pub_key_path, pub_key_pem)?;
-        
-        // Save encrypted private key (base64 encoded for readability)
-        let priv_key_b64 = general_purpose::STANDARD.encode(&encrypted_secret_key);
-        std::fs::write(&priv_key_path, priv_key_b64)?;
-        
-        // Set file permissions (read/write for owner only)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            
-            // Set permissions for public key file
-            let mut pub_perms = std::fs::metadata(&pub_key_path)?.permissions();
-            pub_perms.set_mode(0o644); // rw-r--r--
-            std::fs::set_permissions(&pub_key_path, pub_perms)?;
-            
-            // Set permissions for private key file (more restrictive)
-            let mut priv_perms = std::fs::metadata(&priv_key_path)?.permissions();
-            priv_perms.set_mode(0o600); // rw-------
-            std::fs::set_permissions(&priv_key_path, priv_perms)?;
-        }
-        
+        // Export public and private key files next to the identity, wherever it went
+        let export_dir = file_path.parent().unwrap_or(Path::new(""));
+        let (pub_key_path, priv_key_path) = FileManager::export_keys(&identity, export_dir)?;
+
         println!("{}", "✓ Public key exported to:".green());
         println!("  {}", pub_key_path.display().to_string().cyan());
         println!("{}", "✓ Private key exported to:".green());
@@ -314,36 +322,50 @@ impl CliHandler {
         std::process::exit(0);
     }
     
-    fn list_identities() -> Result<()> {
+    /// List identities, optionally only those with one status, as text or JSON
+    fn list_identities(json: bool, only: Option<IdentityStatus>) -> Result<()> {
+        let identities: Vec<_> = FileManager::list_identity_statuses()?
+            .into_iter()
+            .filter(|listing| only.is_none_or(|status| listing.status == status))
+            .collect();
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&identities)?);
+            return Ok(());
+        }
+
         println!("{}", "📋 Existing Identities".cyan().bold());
         println!();
         
-        let identities = FileManager::list_identities()?;
-        
         if identities.is_empty() {
             println!("{}", "No identities found.".dimmed());
-            println!("Use 'generate' command to create a new identity.");
+            if only.is_none() {
+                println!("Use 'generate' command to create a new identity.");
+            }
             return Ok(());
         }
         
-        for (username, path) in identities {
-            match FileManager::load_identity(&path) {
-                Ok(identity) => {
-                    let status = if identity.is_expired() {
+        for listing in identities {
+            let username = &listing.username;
+            match listing.status {
+                IdentityStatus::Active | IdentityStatus::Expired => {
+                    let status = if listing.status == IdentityStatus::Expired {
                         "EXPIRED".red()
                     } else {
                         "ACTIVE".green()
                     };
                     
                     println!("👤 {} [{}]", username.cyan().bold(), status);
-                    println!("   Fingerprint: {}", identity.fingerprint.dimmed());
-                    println!("   Created: {}", identity.created_at.format("%Y-%m-%d").to_string().dimmed());
-                    if let Some(expires) = identity.expires_at {
+                    println!("   Fingerprint: {}", listing.fingerprint.unwrap_or_default().dimmed());
+                    if let Some(created) = listing.created_at {
+                        println!("   Created: {}", created.format("%Y-%m-%d").to_string().dimmed());
+                    }
+                    if let Some(expires) = listing.expires_at {
                         println!("   Expires: {}", expires.format("%Y-%m-%d").to_string().dimmed());
                     }
                     println!();
                 },
-                Err(_) => {
+                IdentityStatus::Corrupted => {
                     println!("❌ {} [CORRUPTED]", username.red());
                     println!();
                 }
@@ -384,7 +406,7 @@ impl CliHandler {
         Ok(())
     }
     
-    fn verify_identity(file_path: &PathBuf) -> Result<()> {
+    fn verify_identity(file_path: &Path) -> Result<()> {
         println!("{}", "🔍 Verifying identity file...".cyan().bold());
         
         let identity = FileManager::load_identity(file_path)?;
@@ -393,7 +415,7 @@ impl CliHandler {
         let public_key_bytes = identity.get_public_key_bytes()?;
         let calculated_fingerprint = Identity::generate_fingerprint(&public_key_bytes)?;
         
-        if calculated_fingerprint == identity.fingerprint {
+        if constant_time_eq(calculated_fingerprint.as_bytes(), identity.fingerprint.as_bytes()) {
             println!("{} Identity file is valid", "✅".green());
             println!("   Username: {}", identity.username.cyan());
             println!("   Fingerprint: {}", identity.fingerprint.cyan());
@@ -429,4 +451,63 @@ impl CliHandler {
         
         Ok(())
     }
+
+    /// Delete every expired identity, and corrupted ones if asked, after confirming
+    fn prune_identities(include_corrupted: bool, yes: bool) -> Result<()> {
+        let all = FileManager::list_identity_statuses()?;
+        let corrupted_count = all.iter().filter(|listing| listing.status == IdentityStatus::Corrupted).count();
+        let doomed: Vec<_> = all
+            .into_iter()
+            .filter(|listing| match listing.status {
+                IdentityStatus::Expired => true,
+                IdentityStatus::Corrupted => include_corrupted,
+                IdentityStatus::Active => false,
+            })
+            .collect();
+
+        if doomed.is_empty() {
+            println!("{}", "No expired identities to prune.".dimmed());
+        } else {
+            println!("{}", "🧹 Identities to delete".cyan().bold());
+            println!();
+            for listing in &doomed {
+                match listing.expires_at {
+                    Some(expires) => println!("👤 {} [{}] expired {}", listing.username.cyan().bold(), "EXPIRED".red(), expires.format("%Y-%m-%d")),
+                    None => println!("❌ {} [CORRUPTED] {}", listing.username.red(), listing.error.as_deref().unwrap_or_default().dimmed()),
+                }
+            }
+            println!();
+
+            let confirm = yes || Confirm::new()
+                .with_prompt(format!("Delete these {} identities and their key files?", doomed.len()))
+                .default(false)
+                .interact()
+                .map_err(|e| IdentityError::InvalidInput(e.to_string()))?;
+
+            if !confirm {
+                println!("{}", "Operation cancelled.".yellow());
+                return Ok(());
+            }
+
+            // Keep going past a failure so one bad file doesn't block the rest
+            let mut removed = 0;
+            for listing in &doomed {
+                match FileManager::delete_identity(&listing.username) {
+                    Ok(()) => removed += 1,
+                    Err(e) => println!("{} Failed to delete {}: {}", "✗".red().bold(), listing.username.red(), e),
+                }
+            }
+            println!();
+            println!("{} Removed {} of {} identities", "✓".green().bold(), removed, doomed.len());
+        }
+
+        if corrupted_count > 0 && !include_corrupted {
+            println!(
+                "{}",
+                format!("{} corrupted identities were left alone, run 'prune --corrupted' to remove them too", corrupted_count).yellow()
+            );
+        }
+
+        Ok(())
+    }
 }
@@ -7,9 +7,19 @@ use aes_gcm::{
 use argon2::{Argon2, PasswordHasher, password_hash::SaltString};
 use rand::rngs::OsRng as StdOsRng;
 use base64::{Engine as _, engine::general_purpose};
+use subtle::ConstantTimeEq;
 
 use crate::error::{IdentityError, Result};
 
+/// Compare byte strings in time that depends only on their lengths
+///
+/// Use this wherever one side is secret or attacker-controlled and must
+/// match exactly, e.g. a signed payload or a key check value, so response
+/// times don't reveal how many leading bytes matched.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
 pub struct KeyPair {
     pub public_key: dilithium2::PublicKey,
     pub secret_key: dilithium2::SecretKey,
@@ -37,10 +47,12 @@ impl KeyPair {
         dilithium2::sign(message, &self.secret_key).as_bytes().to_vec()
     }
     
-    pub fn verify(_message: &[u8], signature: &[u8], public_key: &[u8]) -> bool {
+    /// Check that `signature` is a signed copy of `message` by `public_key`
+    pub fn verify(message: &[u8], signature: &[u8], public_key: &[u8]) -> bool {
         if let Ok(pk) = dilithium2::PublicKey::from_bytes(public_key) {
             if let Ok(sig) = dilithium2::SignedMessage::from_bytes(signature) {
-                return dilithium2::open(&sig, &pk).is_ok();
+                // The signature embeds the message, it must be the one we were asked about
+                return dilithium2::open(&sig, &pk).is_ok_and(|signed| constant_time_eq(&signed, message));
             }
         }
         false
@@ -75,7 +87,7 @@ impl Encryption {
             .map_err(|e| IdentityError::Encryption(e.to_string()))?;
         
         // Combine salt + nonce + ciphertext with base64 encoding for binary data
-        let nonce_b64 = general_purpose::STANDARD.encode(&nonce);
+        let nonce_b64 = general_purpose::STANDARD.encode(nonce);
         let ciphertext_b64 = general_purpose::STANDARD.encode(&ciphertext);
         
         let combined = format!("{}|{}|{}", salt.as_str(), nonce_b64, ciphertext_b64);
@@ -118,10 +130,11 @@ impl Encryption {
         let cipher = Aes256Gcm::new(key);
         let nonce = Nonce::from_slice(&nonce_bytes);
         
+        // The data is well formed, so a failed tag check means the wrong password
         let plaintext = cipher
             .decrypt(nonce, ciphertext.as_slice())
-            .map_err(|e| IdentityError::Decryption(e.to_string()))?;
-        
+            .map_err(|_| IdentityError::IncorrectPassword)?;
+
         Ok(plaintext)
     }
 }
@@ -144,8 +157,17 @@ mod tests {
         
         let signature = keypair.sign(message);
         let is_valid = KeyPair::verify(message, &signature, keypair.public_key_bytes());
-        
+
         assert!(is_valid);
+        assert!(!KeyPair::verify(b"Hello, World?", &signature, keypair.public_key_bytes()));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"fingerprint", b"fingerprint"));
+        assert!(!constant_time_eq(b"fingerprint", b"fingerprinT"));
+        assert!(!constant_time_eq(b"fingerprint", b"finger"));
+        assert!(constant_time_eq(b"", b""));
     }
     
     #[test]
@@ -25,6 +25,12 @@ pub enum IdentityError {
     
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+
+    #[error("Incorrect password")]
+    IncorrectPassword,
+
+    #[error("Incorrect password entered {0} times, giving up")]
+    PasswordAttemptsExceeded(u32),
 }
 
 pub type Result<T> = std::result::Result<T, IdentityError>;
@@ -1,21 +1,92 @@
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::sync::RwLock;
+use chrono::{DateTime, Utc};
 use colored::*;
+use serde::Serialize;
 
 use crate::identity::Identity;
 use crate::error::{IdentityError, Result};
 
+/// Environment variable that overrides the identity directory
+pub const IDENTITY_DIR_ENV: &str = "IDENTITY_DIR";
+
+/// Directory set with `--identity-dir`, takes precedence over the environment
+static IDENTITY_DIR_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Whether an identity file is usable, as reported by `list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentityStatus {
+    Active,
+    Expired,
+    /// The file exists but could not be loaded
+    Corrupted,
+}
+
+/// One identity file and its status, serialized by `list --format json`
+#[derive(Debug, Clone, Serialize)]
+pub struct IdentityListing {
+    pub username: String,
+    pub status: IdentityStatus,
+    pub path: PathBuf,
+    pub fingerprint: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Why a corrupted identity could not be loaded
+    pub error: Option<String>,
+}
+
+impl IdentityListing {
+    fn new(username: String, path: PathBuf, loaded: Result<Identity>) -> Self {
+        match loaded {
+            Ok(identity) => Self {
+                username,
+                status: if identity.is_expired() { IdentityStatus::Expired } else { IdentityStatus::Active },
+                path,
+                fingerprint: Some(identity.fingerprint),
+                created_at: Some(identity.created_at),
+                expires_at: identity.expires_at,
+                error: None,
+            },
+            Err(e) => Self {
+                username,
+                status: IdentityStatus::Corrupted,
+                path,
+                fingerprint: None,
+                created_at: None,
+                expires_at: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
 pub struct FileManager;
 
 impl FileManager {
-    /// Get the default identity directory
+    /// Use `dir` for all identity files from now on, `None` restores the default
+    pub fn set_identity_dir(dir: Option<PathBuf>) {
+        *IDENTITY_DIR_OVERRIDE.write().unwrap() = dir;
+    }
+
+    /// Get the identity directory
+    ///
+    /// The directory set with [`FileManager::set_identity_dir`] wins, then the
+    /// `IDENTITY_DIR` environment variable, then `~/.dpq-chat/identities`.
     pub fn get_identity_dir() -> Result<PathBuf> {
-        let home_dir = dirs::home_dir()
-            .ok_or_else(|| IdentityError::FileIo(
-                std::io::Error::new(std::io::ErrorKind::NotFound, "Home directory not found")
-            ))?;
-        
-        let identity_dir = home_dir.join(".dpq-chat").join("identities");
+        let overridden = IDENTITY_DIR_OVERRIDE.read().unwrap().clone()
+            .or_else(|| std::env::var_os(IDENTITY_DIR_ENV).filter(|dir| !dir.is_empty()).map(PathBuf::from));
+
+        let identity_dir = match overridden {
+            Some(dir) => dir,
+            None => dirs::home_dir()
+                .ok_or_else(|| IdentityError::FileIo(
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "Home directory not found")
+                ))?
+                .join(".dpq-chat")
+                .join("identities"),
+        };
         
         // Create directory if it doesn't exist
         if !identity_dir.exists() {
@@ -38,6 +109,29 @@ impl FileManager {
     pub fn get_identity_filename(username: &str) -> String {
         format!("{}.identity.json", username.to_lowercase())
     }
+
+    /// Paths of the `.pub` and `.key` exports written next to an identity in `dir`
+    pub fn get_export_paths(dir: &Path, username: &str) -> (PathBuf, PathBuf) {
+        let username = username.to_lowercase();
+        (dir.join(format!("{}.pub", username)), dir.join(format!("{}.key", username)))
+    }
+
+    /// Write an identity's public key (PEM) and encrypted private key (base64)
+    /// exports to `dir`, returns their paths
+    pub fn export_keys(identity: &Identity, dir: &Path) -> Result<(PathBuf, PathBuf)> {
+        let (pub_key_path, priv_key_path) = Self::get_export_paths(dir, &identity.username);
+
+        let pub_key_pem = format!(
+            "-----BEGIN DILITHIUM2 PUBLIC KEY-----\n{}\n-----END DILITHIUM2 PUBLIC KEY-----\n",
+            identity.public_key
+        );
+        Self::write_atomic(&pub_key_path, pub_key_pem.as_bytes(), 0o644)?; // rw-r--r--
+
+        // The private key stays encrypted, and readable by the owner only
+        Self::write_atomic(&priv_key_path, identity.secret_key.as_bytes(), 0o600)?; // rw-------
+
+        Ok((pub_key_path, priv_key_path))
+    }
     
     /// Save identity to file
     pub fn save_identity(identity: &Identity, custom_path: Option<&Path>) -> Result<PathBuf> {
@@ -60,20 +154,11 @@ impl FileManager {
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent)?;
         }
-        
-        // Write identity to file
+
+        // Write identity to file, readable and writable by the owner only
         let json_content = identity.to_json()?;
-        fs::write(&file_path, json_content)?;
-        
-        // Set file permissions (read/write for owner only)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(&file_path)?.permissions();
-            perms.set_mode(0o600); // rw-------
-            fs::set_permissions(&file_path, perms)?;
-        }
-        
+        Self::write_atomic(&file_path, json_content.as_bytes(), 0o600)?;
+
         println!("{} Identity saved to: {}", 
             "✓".green().bold(), 
             file_path.display().to_string().cyan()
@@ -82,6 +167,54 @@ impl FileManager {
         Ok(file_path)
     }
     
+    /// Replace a file's contents so readers see either the old or the new file
+    ///
+    /// Writes a temporary file next to `path`, syncs it to disk and renames it
+    /// over `path`, so an interrupted write or a concurrent writer never leaves
+    /// a truncated file behind. `mode` is applied to the new file on Unix.
+    pub fn write_atomic(path: &Path, contents: &[u8], mode: u32) -> Result<()> {
+        use std::io::Write;
+
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file_name = path.file_name()
+            .ok_or_else(|| IdentityError::InvalidInput(format!("Not a file path: {}", path.display())))?;
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos())
+            .unwrap_or_default();
+        let temp_path = dir.join(format!(".{}.{}-{}.tmp", file_name.to_string_lossy(), std::process::id(), nanos));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(mode);
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        let written = options.open(&temp_path).and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| fs::rename(&temp_path, path)) {
+            fs::remove_file(&temp_path).ok();
+            return Err(e.into());
+        }
+
+        // Make the rename itself durable
+        #[cfg(unix)]
+        if let Ok(dir) = fs::File::open(dir) {
+            dir.sync_all().ok();
+        }
+
+        Ok(())
+    }
+
     /// Load identity from file
     pub fn load_identity(file_path: &Path) -> Result<Identity> {
         if !file_path.exists() {
@@ -94,7 +227,7 @@ impl FileManager {
         Identity::from_json(&json_content)
     }
     
-    /// List all identity files in the default directory
+    /// List all identity files in the identity directory
     pub fn list_identities() -> Result<Vec<(String, PathBuf)>> {
         let identity_dir = Self::get_identity_dir()?;
         let mut identities = Vec::new();
@@ -119,46 +252,59 @@ impl FileManager {
         Ok(identities)
     }
     
+    /// List identities with their status, keeping those that fail to load as corrupted
+    pub fn list_identity_statuses() -> Result<Vec<IdentityListing>> {
+        Ok(Self::list_identities()?
+            .into_iter()
+            .map(|(username, path)| {
+                let loaded = Self::load_identity(&path);
+                IdentityListing::new(username, path, loaded)
+            })
+            .collect())
+    }
+
     /// Delete identity file and associated key files
+    ///
+    /// The `.key` and `.pub` exports go first and the identity file last, so
+    /// if a removal fails the identity is still listed and the delete can be
+    /// retried. Files that are already gone are reported and skipped, which
+    /// also cleans up exports left behind by an identity deleted earlier.
     pub fn delete_identity(username: &str) -> Result<()> {
         let identity_dir = Self::get_identity_dir()?;
-        let filename = Self::get_identity_filename(username);
-        let file_path = identity_dir.join(filename);
-        
-        if file_path.exists() {
-            // Delete main identity file
-            fs::remove_file(&file_path)?;
-            
-            // Delete associated key files if they exist
-            let pub_key_path = identity_dir.join(format!("{}.pub", username));
-            let priv_key_path = identity_dir.join(format!("{}.key", username));
-            
-            if pub_key_path.exists() {
-                fs::remove_file(&pub_key_path)?;
-                println!("{} Public key file deleted: {}", 
-                    "✓".green().bold(), 
-                    pub_key_path.display().to_string().cyan()
-                );
-            }
-            
-            if priv_key_path.exists() {
-                fs::remove_file(&priv_key_path)?;
-                println!("{} Private key file deleted: {}", 
-                    "✓".green().bold(), 
-                    priv_key_path.display().to_string().cyan()
-                );
-            }
-            
-            println!("{} Identity deleted: {}", 
-                "✓".green().bold(), 
-                username.cyan()
-            );
-        } else {
+        let file_path = identity_dir.join(Self::get_identity_filename(username));
+        let (pub_key_path, priv_key_path) = Self::get_export_paths(&identity_dir, username);
+
+        let files = [
+            ("Private key file", priv_key_path),
+            ("Public key file", pub_key_path),
+            ("Identity file", file_path),
+        ];
+        if !files.iter().any(|(_, path)| path.exists()) {
             return Err(IdentityError::InvalidInput(
                 format!("Identity not found: {}", username)
             ));
         }
-        
+
+        for (label, path) in &files {
+            match fs::remove_file(path) {
+                Ok(()) => println!("{} {} deleted: {}", 
+                    "✓".green().bold(), 
+                    label,
+                    path.display().to_string().cyan()
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => println!("{} {} already missing: {}", 
+                    "-".dimmed(), 
+                    label,
+                    path.display().to_string().dimmed()
+                ),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        println!("{} Identity deleted: {}", 
+            "✓".green().bold(), 
+            username.cyan()
+        );
         Ok(())
     }
     
@@ -171,3 +317,86 @@ impl FileManager {
         Ok(file_path.exists())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Mutex;
+
+    /// The identity directory is process-wide, tests that point it somewhere take turns
+    static IDENTITY_DIR_LOCK: Mutex<()> = Mutex::const_new(());
+
+    #[tokio::test]
+    async fn test_identity_dir_override_round_trip() {
+        let _guard = IDENTITY_DIR_LOCK.lock().await;
+        let dir = std::env::temp_dir().join(format!("dpq-identities-{}", std::process::id()));
+        FileManager::set_identity_dir(Some(dir.clone()));
+
+        let generated = crate::generate_identity(Some("tester".to_string()), None, true).await.unwrap();
+        assert!(dir.join("tester.identity.json").exists());
+
+        let listed = FileManager::list_identities().unwrap();
+        assert_eq!(listed, vec![("tester".to_string(), dir.join("tester.identity.json"))]);
+
+        let loaded = crate::load_identity("tester").unwrap();
+        assert_eq!(loaded.fingerprint, generated.fingerprint);
+
+        // A file that doesn't load is listed as corrupted rather than left out
+        fs::write(dir.join("broken.identity.json"), "{ not json").unwrap();
+        let statuses = FileManager::list_identity_statuses().unwrap();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!((statuses[0].username.as_str(), statuses[0].status), ("broken", IdentityStatus::Corrupted));
+        assert!(statuses[0].error.is_some());
+        assert_eq!(statuses[1].status, IdentityStatus::Active);
+        assert_eq!(statuses[1].fingerprint.as_deref(), Some(generated.fingerprint.as_str()));
+        let json = serde_json::to_value(&statuses).unwrap();
+        assert_eq!(json[0]["status"], "corrupted");
+
+        FileManager::set_identity_dir(None);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_delete_identity_removes_exports() {
+        let _guard = IDENTITY_DIR_LOCK.lock().await;
+        let dir = std::env::temp_dir().join(format!("dpq-delete-{}", std::process::id()));
+        FileManager::set_identity_dir(Some(dir.clone()));
+
+        // Files are named in lowercase, whatever case the username was given in
+        let identity = crate::generate_identity(Some("Doomed".to_string()), None, true).await.unwrap();
+        let (pub_key_path, priv_key_path) = FileManager::export_keys(&identity, &dir).unwrap();
+        assert_eq!((pub_key_path.clone(), priv_key_path.clone()), (dir.join("doomed.pub"), dir.join("doomed.key")));
+
+        FileManager::delete_identity("Doomed").unwrap();
+        assert!(!dir.join("doomed.identity.json").exists());
+        assert!(!pub_key_path.exists());
+        assert!(!priv_key_path.exists());
+        assert!(FileManager::delete_identity("doomed").is_err());
+
+        FileManager::set_identity_dir(None);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_whole_file() {
+        let dir = std::env::temp_dir().join(format!("dpq-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("alice.key");
+
+        FileManager::write_atomic(&path, b"first version, rather long", 0o600).unwrap();
+        FileManager::write_atomic(&path, b"second", 0o600).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+
+        // Only the target is left, no temporary files
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(entries, vec![std::ffi::OsString::from("alice.key")]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
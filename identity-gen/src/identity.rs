@@ -5,6 +5,26 @@ use sha2::{Sha256, Digest};
 
 use crate::error::{IdentityError, Result};
 
+/// Longest username, in bytes, for identities and chat alike
+pub const MAX_USERNAME_LENGTH: usize = 32;
+
+/// Check a username against the one rule shared by identities and the chat
+///
+/// Usernames double as chat handles and file names, so they are limited to
+/// letters, digits, `_` and `-`, at most [`MAX_USERNAME_LENGTH`] bytes long.
+/// The error is a message fit to show the user.
+pub fn validate_username(username: &str) -> std::result::Result<(), &'static str> {
+    if username.is_empty() {
+        Err("Username cannot be empty")
+    } else if username.len() > MAX_USERNAME_LENGTH {
+        Err("Username must be 32 characters or less")
+    } else if !username.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        Err("Username may only contain letters, digits, '_' and '-'")
+    } else {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Identity {
     pub username: String,
@@ -24,6 +44,7 @@ impl Identity {
         encrypted_secret_key: &[u8],
         expires_at: Option<DateTime<Utc>>,
     ) -> Result<Self> {
+        validate_username(&username).map_err(|e| IdentityError::InvalidInput(e.to_string()))?;
         let public_key = general_purpose::STANDARD.encode(public_key_bytes); // Store as base64 string
         let secret_key = general_purpose::STANDARD.encode(encrypted_secret_key);
         let fingerprint = Self::generate_fingerprint(public_key_bytes)?;
@@ -84,11 +105,65 @@ impl Identity {
     }
     
     pub fn short_fingerprint(&self) -> String {
-        // Return first 2 segments for easy verification
-        self.fingerprint
-            .split(':')
-            .take(2)
-            .collect::<Vec<_>>()
-            .join(":")
+        short_fingerprint(&self.fingerprint)
+    }
+}
+
+/// Bytes in a fingerprint, see [`Identity::generate_fingerprint`]
+const FINGERPRINT_BYTES: usize = 6;
+
+/// Fingerprint as three groups of five digits, easier to read out over the phone
+///
+/// Both sides can compute it from the fingerprint alone, so each reads theirs
+/// out and the other compares. `None` if `fingerprint` is not six hex bytes.
+pub fn safety_number(fingerprint: &str) -> Option<String> {
+    let bytes = fingerprint
+        .split(':')
+        .map(|byte| u8::from_str_radix(byte, 16).ok().filter(|_| byte.len() == 2))
+        .collect::<Option<Vec<u8>>>()?;
+    if bytes.len() != FINGERPRINT_BYTES {
+        return None;
+    }
+
+    // 48 bits fit in 15 decimal digits
+    let value = bytes.iter().fold(0u64, |value, &byte| (value << 8) | u64::from(byte));
+    let digits = format!("{:015}", value);
+    Some(format!("{} {} {}", &digits[..5], &digits[5..10], &digits[10..]))
+}
+
+/// First two segments of a fingerprint, for quick visual checks
+pub fn short_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .split(':')
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_username_character_rules() {
+        for accepted in ["alice", "Bob_2", "night-owl", "ünïcode", "a", &"x".repeat(MAX_USERNAME_LENGTH)] {
+            assert!(validate_username(accepted).is_ok(), "{:?} should be accepted", accepted);
+        }
+        for rejected in ["", "My Cool Name!", "bob smith", "a/b", "../etc", "dot.name", "tab\there", "emoji👋", &"x".repeat(MAX_USERNAME_LENGTH + 1)] {
+            assert!(validate_username(rejected).is_err(), "{:?} should be rejected", rejected);
+        }
+        assert!(Identity::new("My Cool Name!".to_string(), "dilithium2".to_string(), b"key", b"secret", None).is_err());
+    }
+
+    #[test]
+    fn test_safety_number_groups_fingerprint_digits() {
+        assert_eq!(safety_number("00:00:00:00:00:01").as_deref(), Some("00000 00000 00001"));
+        assert_eq!(safety_number("ff:ff:ff:ff:ff:ff").as_deref(), Some("28147 49767 10655"));
+        let fingerprint = Identity::generate_fingerprint(b"some public key").unwrap();
+        assert_eq!(safety_number(&fingerprint).unwrap().len(), 17);
+
+        for malformed in ["", "d1:34", "d1:34:fe:77:ab:99:00", "d1:34:fe:77:ab:zz", "d1:34:fe:77:ab:9"] {
+            assert!(safety_number(malformed).is_none(), "{:?} should be rejected", malformed);
+        }
     }
 }
@@ -2,15 +2,17 @@ pub mod error;
 pub mod identity;
 pub mod crypto;
 pub mod file_manager;
+pub mod password;
 pub mod cli;
 
 use chrono::{Utc, Duration};
 
 // Re-export main types and functions for easy use
 pub use error::{IdentityError, Result};
-pub use identity::Identity;
-pub use crypto::{KeyPair, Encryption};
-pub use file_manager::FileManager;
+pub use identity::{safety_number, short_fingerprint, validate_username, Identity, MAX_USERNAME_LENGTH};
+pub use crypto::{constant_time_eq, KeyPair, Encryption};
+pub use file_manager::{FileManager, IdentityListing, IdentityStatus};
+pub use password::{unlock_secret_key, SecretPassword, MAX_PASSWORD_ATTEMPTS};
 pub use cli::{CliHandler, Commands};
 
 /// Main entry point for identity generation functionality
@@ -99,6 +101,6 @@ pub fn verify_identity_file(file_path: &std::path::Path) -> Result<bool> {
     // Verify public key fingerprint
     let public_key_bytes = identity.get_public_key_bytes()?;
     let calculated_fingerprint = Identity::generate_fingerprint(&public_key_bytes)?;
-    
-    Ok(calculated_fingerprint == identity.fingerprint)
+
+    Ok(constant_time_eq(calculated_fingerprint.as_bytes(), identity.fingerprint.as_bytes()))
 }
@@ -1,23 +1,21 @@
-mod error;
-mod identity;
-mod crypto;
-mod file_manager;
-mod cli;
-
 use clap::Parser;
 use colored::*;
 
-use cli::{Cli, CliHandler};
-use error::Result;
+use identity_gen::cli::{Cli, CliHandler};
+use identity_gen::Result;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize colored output
-    colored::control::set_override(true);
-    
     // Parse command line arguments
     let cli = Cli::parse();
-    
+
+    // Colors follow the terminal and NO_COLOR unless turned off explicitly
+    if cli.no_color {
+        colored::control::set_override(false);
+        dialoguer::console::set_colors_enabled(false);
+        dialoguer::console::set_colors_enabled_stderr(false);
+    }
+
     // Handle any errors gracefully
     if let Err(e) = CliHandler::run(cli) {
         eprintln!("{} {}", "Error:".red().bold(), e);
@@ -0,0 +1,96 @@
+use colored::*;
+use dialoguer::Password;
+use zeroize::Zeroizing;
+
+use crate::crypto::Encryption;
+use crate::error::{IdentityError, Result};
+use crate::identity::Identity;
+
+/// Wrong passwords allowed before unlocking a private key gives up
+pub const MAX_PASSWORD_ATTEMPTS: u32 = 3;
+
+/// A password that is wiped from memory when dropped
+pub struct SecretPassword(Zeroizing<String>);
+
+impl SecretPassword {
+    pub fn new(password: String) -> Self {
+        Self(Zeroizing::new(password))
+    }
+
+    /// Borrow the password, e.g. to derive a key from it
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for SecretPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretPassword(***)")
+    }
+}
+
+/// Prompt for an identity's password and decrypt its private key
+///
+/// A mistyped password can be retried, up to [`MAX_PASSWORD_ATTEMPTS`] tries
+/// in total. The decrypted key is wiped from memory when dropped.
+pub fn unlock_secret_key(identity: &Identity) -> Result<Zeroizing<Vec<u8>>> {
+    unlock_secret_key_with(identity, MAX_PASSWORD_ATTEMPTS, |attempt, max_attempts| {
+        Password::new()
+            .with_prompt(format!("Password for '{}' (attempt {}/{})", identity.username, attempt, max_attempts))
+            .interact()
+            .map(SecretPassword::new)
+            .map_err(|e| IdentityError::InvalidInput(e.to_string()))
+    })
+}
+
+/// Decrypt an identity's private key with passwords from `prompt`
+///
+/// `prompt` is called with the attempt number and `max_attempts` until a
+/// password is correct. Other errors, including from `prompt`, end it early.
+pub fn unlock_secret_key_with<F>(identity: &Identity, max_attempts: u32, mut prompt: F) -> Result<Zeroizing<Vec<u8>>>
+where
+    F: FnMut(u32, u32) -> Result<SecretPassword>,
+{
+    let encrypted_secret_key = identity.get_secret_key_bytes()?;
+
+    for attempt in 1..=max_attempts {
+        let password = prompt(attempt, max_attempts)?;
+        match Encryption::decrypt_secret_key(&encrypted_secret_key, password.expose()) {
+            Ok(secret_key) => return Ok(Zeroizing::new(secret_key)),
+            Err(IdentityError::IncorrectPassword) if attempt < max_attempts => {
+                println!("{} Incorrect password, {} attempts left", "❌".red(), max_attempts - attempt);
+            }
+            Err(IdentityError::IncorrectPassword) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(IdentityError::PasswordAttemptsExceeded(max_attempts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    #[test]
+    fn test_unlock_retries_until_attempt_limit() {
+        let keypair = KeyPair::generate().unwrap();
+        let encrypted = Encryption::encrypt_secret_key(keypair.secret_key_bytes(), "correct horse").unwrap();
+        let identity = Identity::new("alice".to_string(), "dilithium2".to_string(), keypair.public_key_bytes(), &encrypted, None).unwrap();
+
+        // A typo followed by the right password
+        let mut passwords = vec!["correct hrose", "correct horse"].into_iter();
+        let secret_key = unlock_secret_key_with(&identity, 3, |_, _| Ok(SecretPassword::new(passwords.next().unwrap().to_string()))).unwrap();
+        assert_eq!(secret_key.as_slice(), keypair.secret_key_bytes());
+
+        // Never the right password
+        let mut prompts = 0;
+        let result = unlock_secret_key_with(&identity, 2, |_, _| {
+            prompts += 1;
+            Ok(SecretPassword::new("wrong".to_string()))
+        });
+        assert!(matches!(result, Err(IdentityError::PasswordAttemptsExceeded(2))));
+        assert_eq!(prompts, 2);
+    }
+}
@@ -1,28 +1,81 @@
 //! Command line argument parsing for P2P core
 
-use std::net::SocketAddr;
-use shared::config::{DEFAULT_HOST_LOCALHOST, FIXED_PORT, find_available_port};
+use std::path::PathBuf;
+use shared::config::{
+    DEFAULT_CERT_VALIDITY_SECS, MAX_CERT_VALIDITY_HOURS, DEFAULT_HOST_LOCALHOST, DEFAULT_KNOWN_PEER_MAX_AGE_SECS, DEFAULT_MULTICAST_GROUP, DEFAULT_MULTICAST_PORT,
+    DEFAULT_REORDER_WINDOW_MS, FIXED_PORT, HEARTBEAT_INTERVAL, MAX_CONNECTIONS, ROOM_KEY_ENV, find_available_port,
+};
+use shared::p2p::RoomKey;
+use shared::utils::{parse_multicast_group, parse_peer_target, parse_room_key, validate_username};
+use std::net::Ipv4Addr;
+use p2p_core::{ClientOptions, NotifyMode, OutputMode, Theme, DEFAULT_HISTORY_SIZE};
 
 /// Parsed command line arguments
 pub struct P2PArgs {
     pub username: String,
     pub final_host: String,
     pub final_port: u16,
-    pub bootstrap_peers: Vec<SocketAddr>,
+    pub bootstrap_peers: Vec<String>,
     pub enable_tls: bool,
+    pub theme: Theme,
+    pub history_size: usize,
+    pub quiet: bool,
+    pub output: OutputMode,
+    pub auto_away_secs: u64,
+    pub typing_indicators: bool,
+    pub notify_mode: NotifyMode,
+    pub desktop_notifications: bool,
+    pub multicast_discovery: bool,
+    pub multicast_group: Ipv4Addr,
+    pub multicast_port: u16,
+    pub mdns_discovery: bool,
+    pub peers_file: Option<PathBuf>,
+    pub heartbeat_interval_secs: u64,
+    pub cleanup_interval_secs: u64,
+    pub dead_peer_timeout_secs: u64,
+    pub max_connections: usize,
+    pub allowlist_path: Option<PathBuf>,
+    pub reorder_window_ms: u64,
+    pub known_peers_path: Option<PathBuf>,
+    pub known_peers_max_age_secs: u64,
+    pub cert_validity_secs: u64,
+    pub room_key: Option<RoomKey>,
 }
 
 /// Parse command line arguments
 pub fn parse_args(args: &[String]) -> Result<Option<P2PArgs>, Box<dyn std::error::Error>> {
     // Use hardcoded configuration values
     let default_host = DEFAULT_HOST_LOCALHOST;
-    let default_port = FIXED_PORT;
     
     // Parse command line arguments
     let mut username = "Anonymous".to_string();
     let mut listen_port: Option<u16> = None;
-    let mut bootstrap_peers: Vec<SocketAddr> = vec![];
+    let mut bootstrap_peers: Vec<String> = vec![];
     let mut custom_host: Option<String> = None;
+    let mut theme = Theme::default();
+    let mut history_size = DEFAULT_HISTORY_SIZE;
+    let mut quiet = false;
+    let mut output = OutputMode::default();
+    let mut auto_away_secs = 0;
+    let mut typing_indicators = true;
+    let mut notify_mode = NotifyMode::default();
+    let mut desktop_notifications = false;
+    let mut multicast_discovery = true;
+    let mut multicast_group = DEFAULT_MULTICAST_GROUP;
+    let mut multicast_port = DEFAULT_MULTICAST_PORT;
+    let mut mdns_discovery = false;
+    let mut peers_file = None;
+    let mut heartbeat_interval_secs = HEARTBEAT_INTERVAL;
+    let mut cleanup_interval_secs = 0;
+    let mut dead_peer_timeout_secs = 0;
+    let mut max_connections = MAX_CONNECTIONS;
+    let mut allowlist_path = None;
+    let mut reorder_window_ms = DEFAULT_REORDER_WINDOW_MS;
+    let mut known_peers_path = None;
+    let mut known_peers_max_age_secs = DEFAULT_KNOWN_PEER_MAX_AGE_SECS;
+    let mut cert_validity_secs = DEFAULT_CERT_VALIDITY_SECS;
+    // Kept out of the process list when given through the environment
+    let mut room_passphrase = std::env::var(ROOM_KEY_ENV).ok();
     let enable_tls = true; // Always true
     
     let mut i = 1; // Skip program name only
@@ -57,14 +110,218 @@ pub fn parse_args(args: &[String]) -> Result<Option<P2PArgs>, Box<dyn std::error
             }
             "--bootstrap" | "-b" => {
                 if i + 1 < args.len() {
-                    let addr: SocketAddr = args[i + 1].parse()?;
-                    bootstrap_peers.push(addr);
+                    bootstrap_peers.push(parse_peer_target(&args[i + 1])?);
                     i += 2;
                 } else {
                     eprintln!("Error: --bootstrap requires a value");
                     return Ok(None);
                 }
             }
+            "--theme" => {
+                if i + 1 < args.len() {
+                    theme = args[i + 1].parse()?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --theme requires a value");
+                    return Ok(None);
+                }
+            }
+            "--history-size" => {
+                if i + 1 < args.len() {
+                    history_size = ClientOptions::validate_history_size(args[i + 1].parse()?)?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --history-size requires a value");
+                    return Ok(None);
+                }
+            }
+            "--output" => {
+                if i + 1 < args.len() {
+                    output = args[i + 1].parse()?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --output requires a value");
+                    return Ok(None);
+                }
+            }
+            "--auto-away" => {
+                if i + 1 < args.len() {
+                    auto_away_secs = args[i + 1].parse()?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --auto-away requires a value");
+                    return Ok(None);
+                }
+            }
+            "--heartbeat-interval" => {
+                if i + 1 < args.len() {
+                    heartbeat_interval_secs = args[i + 1].parse()?;
+                    if heartbeat_interval_secs == 0 {
+                        eprintln!("Error: --heartbeat-interval must be at least 1 second");
+                        return Ok(None);
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("Error: --heartbeat-interval requires a value");
+                    return Ok(None);
+                }
+            }
+            "--cleanup-interval" => {
+                if i + 1 < args.len() {
+                    cleanup_interval_secs = args[i + 1].parse()?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --cleanup-interval requires a value");
+                    return Ok(None);
+                }
+            }
+            "--dead-peer-timeout" => {
+                if i + 1 < args.len() {
+                    dead_peer_timeout_secs = args[i + 1].parse()?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --dead-peer-timeout requires a value");
+                    return Ok(None);
+                }
+            }
+            "--max-connections" => {
+                if i + 1 < args.len() {
+                    max_connections = args[i + 1].parse()?;
+                    if max_connections == 0 {
+                        eprintln!("Error: --max-connections must be at least 1");
+                        return Ok(None);
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("Error: --max-connections requires a value");
+                    return Ok(None);
+                }
+            }
+            "--allowlist" => {
+                if i + 1 < args.len() {
+                    allowlist_path = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                } else {
+                    eprintln!("Error: --allowlist requires a value");
+                    return Ok(None);
+                }
+            }
+            "--reorder-window" => {
+                if i + 1 < args.len() {
+                    reorder_window_ms = args[i + 1].parse()?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --reorder-window requires a value");
+                    return Ok(None);
+                }
+            }
+            "--room-key" => {
+                if i + 1 < args.len() {
+                    room_passphrase = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("Error: --room-key requires a value");
+                    return Ok(None);
+                }
+            }
+            "--known-peers" => {
+                if i + 1 < args.len() {
+                    known_peers_path = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                } else {
+                    eprintln!("Error: --known-peers requires a value");
+                    return Ok(None);
+                }
+            }
+            "--known-peers-max-age" => {
+                if i + 1 < args.len() {
+                    let hours: u64 = args[i + 1].parse()?;
+                    known_peers_max_age_secs = hours.saturating_mul(60 * 60);
+                    i += 2;
+                } else {
+                    eprintln!("Error: --known-peers-max-age requires a value");
+                    return Ok(None);
+                }
+            }
+            "--cert-validity" => {
+                if i + 1 < args.len() {
+                    let hours: u64 = args[i + 1].parse()?;
+                    if !(1..=MAX_CERT_VALIDITY_HOURS).contains(&hours) {
+                        eprintln!("Error: --cert-validity must be between 1 and {}", MAX_CERT_VALIDITY_HOURS);
+                        return Ok(None);
+                    }
+                    cert_validity_secs = hours.saturating_mul(60 * 60);
+                    i += 2;
+                } else {
+                    eprintln!("Error: --cert-validity requires a value");
+                    return Ok(None);
+                }
+            }
+            "--no-multicast" => {
+                multicast_discovery = false;
+                i += 1;
+            }
+            "--multicast-group" => {
+                if i + 1 < args.len() {
+                    multicast_group = parse_multicast_group(&args[i + 1])?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --multicast-group requires a value");
+                    return Ok(None);
+                }
+            }
+            "--multicast-port" => {
+                if i + 1 < args.len() {
+                    multicast_port = args[i + 1].parse()?;
+                    if multicast_port == 0 {
+                        eprintln!("Error: --multicast-port must be at least 1");
+                        return Ok(None);
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("Error: --multicast-port requires a value");
+                    return Ok(None);
+                }
+            }
+            "--mdns" => {
+                mdns_discovery = true;
+                i += 1;
+            }
+            "--peers-file" => {
+                if i + 1 < args.len() {
+                    peers_file = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                } else {
+                    eprintln!("Error: --peers-file requires a path");
+                    return Ok(None);
+                }
+            }
+            "--no-typing" => {
+                typing_indicators = false;
+                i += 1;
+            }
+            "--notify" => {
+                if i + 1 < args.len() {
+                    notify_mode = args[i + 1].parse()?;
+                    i += 2;
+                } else {
+                    eprintln!("Error: --notify requires a value");
+                    return Ok(None);
+                }
+            }
+            "--desktop-notify" => {
+                desktop_notifications = true;
+                i += 1;
+            }
+            "--no-color" => {
+                theme = Theme::None;
+                colored::control::set_override(false);
+                i += 1;
+            }
+            "--quiet" | "-q" => {
+                quiet = true;
+                i += 1;
+            }
             "--help" | "-h" => {
                 super::print_help();
                 return Ok(None);
@@ -78,8 +335,8 @@ pub fn parse_args(args: &[String]) -> Result<Option<P2PArgs>, Box<dyn std::error
     }
     
     // Validate username
-    if username.trim().is_empty() {
-        eprintln!("Error: Username cannot be empty");
+    if let Err(e) = validate_username(&username) {
+        eprintln!("Error: {}", e);
         return Ok(None);
     }
     
@@ -94,10 +351,15 @@ pub fn parse_args(args: &[String]) -> Result<Option<P2PArgs>, Box<dyn std::error
         // Use automatic port selection: try fixed port first, then fallback range
         match find_available_port(&final_host) {
             Ok(port) => {
-                if port == FIXED_PORT {
-                    println!("🔌 Using fixed port: {}", port);
+                let note = if port == FIXED_PORT {
+                    format!("🔌 Using fixed port: {}", port)
                 } else {
-                    println!("🔌 Fixed port {} unavailable, using fallback port: {}", FIXED_PORT, port);
+                    format!("🔌 Fixed port {} unavailable, using fallback port: {}", FIXED_PORT, port)
+                };
+                // Keep stdout for events in JSON mode
+                match output {
+                    OutputMode::Tui => println!("{}", note),
+                    OutputMode::Json => eprintln!("{}", note),
                 }
                 port
             }
@@ -108,11 +370,36 @@ pub fn parse_args(args: &[String]) -> Result<Option<P2PArgs>, Box<dyn std::error
         }
     };
     
+    let room_key = room_passphrase.as_deref().map(parse_room_key).transpose()?;
+
     Ok(Some(P2PArgs {
         username,
         final_host,
         final_port,
         bootstrap_peers,
         enable_tls,
+        theme,
+        history_size,
+        quiet,
+        output,
+        auto_away_secs,
+        typing_indicators,
+        notify_mode,
+        desktop_notifications,
+        multicast_discovery,
+        multicast_group,
+        multicast_port,
+        mdns_discovery,
+        peers_file,
+        heartbeat_interval_secs,
+        cleanup_interval_secs,
+        dead_peer_timeout_secs,
+        max_connections,
+        allowlist_path,
+        reorder_window_ms,
+        known_peers_path,
+        known_peers_max_age_secs,
+        cert_validity_secs,
+        room_key,
     }))
 }
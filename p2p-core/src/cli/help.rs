@@ -1,6 +1,7 @@
 //! Help text for P2P core
 
 use shared::config::*;
+use p2p_core::{DEFAULT_HISTORY_SIZE, MIN_HISTORY_SIZE, MAX_HISTORY_SIZE};
 
 /// Print help information
 pub fn print_help() {
@@ -11,7 +12,31 @@ pub fn print_help() {
     println!("  -u, --username <NAME>     Set username (required)");
     println!("  -p, --port <PORT>         Set listening port (default: auto-select from {}-{})", FIXED_PORT, FALLBACK_PORT_END);
     println!("      --host <HOST>         Set listening host (default: {})", DEFAULT_HOST_LOCALHOST);
-    println!("  -b, --bootstrap <IP:PORT> Add bootstrap peer (can be used multiple times)");
+    println!("  -b, --bootstrap <HOST:PORT> Add bootstrap peer, HOST may be an IP or DNS name (can be used multiple times)");
+    println!("      --peers-file <PATH>   Dial every ip:port listed in PATH, re-read while running");
+    println!("      --no-multicast        Don't announce or look for peers with LAN multicast");
+    println!("      --multicast-group <IP>  Multicast group for LAN discovery (default: {})", DEFAULT_MULTICAST_GROUP);
+    println!("      --multicast-port <PORT> UDP port for LAN discovery (default: {})", DEFAULT_MULTICAST_PORT);
+    println!("      --mdns                Advertise and find peers as an mDNS service (_dpqchat._tcp)");
+    println!("      --theme <THEME>       Color theme: dark, light or none (default: dark)");
+    println!("      --no-color            No colors at all, same as --theme none (also set by NO_COLOR)");
+    println!("      --history-size <N>    Messages kept in scrollback, {}-{} (default: {})", MIN_HISTORY_SIZE, MAX_HISTORY_SIZE, DEFAULT_HISTORY_SIZE);
+    println!("      --auto-away <SECS>    Tell peers you are away after SECS without input (default: off)");
+    println!("      --no-typing           Don't show when peers are typing");
+    println!("      --notify <MODE>       Ring the bell for incoming messages: all, mentions or off (default: off)");
+    println!("      --desktop-notify      Raise a desktop notification instead of ringing the bell");
+    println!("      --heartbeat-interval <SECS>  Seconds between heartbeats to each peer (default: {})", HEARTBEAT_INTERVAL);
+    println!("      --cleanup-interval <SECS>  Seconds between sweeps for dead peers (default: one heartbeat)");
+    println!("      --dead-peer-timeout <SECS>  Drop peers silent this long, at least {} heartbeats (default: {} heartbeats)", MIN_DEAD_PEER_HEARTBEATS, DEAD_PEER_HEARTBEATS);
+    println!("      --max-connections <N> Most peers connected at once, at least 1 (default: {})", MAX_CONNECTIONS);
+    println!("      --allowlist <PATH>    Only accept peers whose fingerprint (with mutual TLS), IP or ip:port is listed in PATH");
+    println!("      --room-key <PASSPHRASE>  Only admit peers that know PASSPHRASE (or set {})", ROOM_KEY_ENV);
+    println!("      --known-peers <PATH>  Save peers we reached to PATH on exit and reconnect to them on start");
+    println!("      --known-peers-max-age <HOURS>  Forget known peers not seen for this long (default: {})", DEFAULT_KNOWN_PEER_MAX_AGE_SECS / 3600);
+    println!("      --cert-validity <HOURS>  Lifetime of the TLS certificate, renewed before it runs out (default: {})", DEFAULT_CERT_VALIDITY_SECS / 3600);
+    println!("      --reorder-window <MS> Hold a message this long for an earlier one from its sender, 0 disables (default: {})", DEFAULT_REORDER_WINDOW_MS);
+    println!("      --output <MODE>       tui, or json for JSON lines on stdin/stdout (default: tui)");
+    println!("  -q, --quiet               Skip the welcome screen and startup spinner");
     println!("  -h, --help                Show this help");
     println!("\nConfiguration:");
     println!("  🔌 Fixed Port: {} (with fallback range {}-{})", FIXED_PORT, FALLBACK_PORT_START, FALLBACK_PORT_END);
@@ -1,4 +1,4 @@
-/// Terminal styling constants and ANSI codes for P2P chat client
+//! Terminal styling constants and ANSI codes for P2P chat client
 
 use crossterm::{
     execute,
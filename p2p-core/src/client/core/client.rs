@@ -1,16 +1,71 @@
 //! Main P2P Chat Client implementation
 
-use crate::ui::{ChatUI, MessageType};
+use crate::ui::{startup_spinner, ChatUI, KeyOutcome, MessageType, NetworkStatus, RawModeGuard, ResizeWatcher};
+use super::super::discovered::DiscoveredPeers;
 use super::super::history::MessageHistory;
-use super::{EventHandler, CommandHandler};
+use super::super::mute::MuteList;
+use super::super::notify::Notifier;
+use super::{EventHandler, CommandHandler, CommandContext, ClientOptions, PeerPresence};
 
-use shared::{P2PNode, P2PNodeConfig, P2PEvent};
-use shared::p2p::discovery::{DiscoveryMethod, DEFAULT_MULTICAST_ADDR};
+use crate::error::P2PError;
+use crate::session::P2PSession;
+use crossterm::{cursor::MoveTo, event::{self, Event, KeyEvent}, execute, terminal::{Clear, ClearType}};
+use shared::config::{find_available_port, FIXED_PORT};
+use shared::{P2PNode, P2PEvent, PresenceStatus};
 use std::net::SocketAddr;
 use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tracing::{info, error, warn};
 
+/// How often the event loop checks for auto-away
+const AWAY_CHECK_INTERVAL_SECS: u64 = 5;
+
+/// How often stale typing notices are cleared from the header
+const TYPING_CHECK_INTERVAL_SECS: u64 = 1;
+
+/// How often the status line picks up the node's message counts
+const STATUS_REFRESH_INTERVAL_SECS: u64 = 2;
+
+/// How long the input task waits for a key before checking whether to stop
+const KEY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the input task read from the terminal
+enum UserInput {
+    /// A key press, when the terminal is interactive
+    Key(KeyEvent),
+    /// A whole line, when input is piped in
+    Line(String),
+}
+
+/// Wait for the next key press, or for the next line if stdin isn't a terminal
+///
+/// Returns `None` at end of input or once `stop` says nobody is listening.
+fn read_input(interactive: bool, stop: impl Fn() -> bool) -> Option<UserInput> {
+    if !interactive {
+        use std::io::BufRead;
+        let mut line = String::new();
+        return match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(UserInput::Line(line.trim().to_string())),
+        };
+    }
+
+    while !stop() {
+        match event::poll(KEY_POLL_INTERVAL) {
+            Ok(false) => continue,
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) => return Some(UserInput::Key(key)),
+                Ok(_) => continue,
+                Err(_) => return None,
+            },
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
 /// P2P Chat Client with beautiful UI
 pub struct P2PChatClient {
     node: P2PNode,
@@ -19,10 +74,21 @@ pub struct P2PChatClient {
     running: bool,
     chat_ui: ChatUI,
     history: MessageHistory,
+    mute_list: MuteList,
+    notifier: Notifier, // bell or desktop notification on incoming messages
     connected_peers: HashMap<String, String>, // peer_id -> username
     peer_addresses: HashMap<String, SocketAddr>, // peer_id -> address
+    peer_presence: PeerPresence, // peer_id -> status, for peers not online
+    discovered: DiscoveredPeers, // peers found by discovery, numbered for /connect
+    presence: PresenceStatus, // our own status as last sent to peers
+    last_input: Instant, // when the user last submitted a line
+    auto_away_secs: u64, // go away after this long without input, 0 = never
     is_owner: bool, // true if this is the bootstrap/owner node
+    bootstrap_peers: Vec<SocketAddr>, // peers we joined through, losing them all means the owner left
     quit_reason: QuitReason, // reason for quitting
+    auto_selected_port: Option<u16>, // set when no port was given and one was picked for us
+    quiet: bool, // skip the welcome screen
+    discovery_active: bool, // multicast or mDNS discovery is running, shown on the status line
 }
 
 /// Reason for quitting the chat
@@ -31,6 +97,59 @@ pub enum QuitReason {
     UserQuit,       // User typed /quit
     OwnerDisconnect, // Owner disconnected
     NetworkError,   // Network error
+    StdinClosed,    // Input reached end of file, e.g. a supervisor closed the pipe
+}
+
+/// Whether a network event ends the chat, and why
+///
+/// `event` is None once the node's event stream has closed. A joining client
+/// leaves when the last peer goes and it was one we bootstrapped from, the
+/// owner can't come back on its own.
+fn quit_reason_for_event(
+    event: Option<&P2PEvent>,
+    is_owner: bool,
+    bootstrap_peers: &[SocketAddr],
+    peer_addresses: &HashMap<String, SocketAddr>,
+) -> Option<QuitReason> {
+    match event {
+        None => Some(QuitReason::NetworkError),
+        Some(P2PEvent::PeerDisconnected { peer_id, .. }) if !is_owner => {
+            let leaving = peer_addresses.get(peer_id)?;
+            let others_remain = peer_addresses.keys().any(|id| id != peer_id);
+            (bootstrap_peers.contains(leaving) && !others_remain).then_some(QuitReason::OwnerDisconnect)
+        }
+        Some(_) => None,
+    }
+}
+
+/// Why the chat ends once input stops it: a `/quit` line, or None at end of input
+fn quit_reason_for_input(input: Option<&str>) -> QuitReason {
+    match input {
+        Some(_) => QuitReason::UserQuit,
+        None => QuitReason::StdinClosed,
+    }
+}
+
+/// Work out the address to listen on, defaulting to localhost
+///
+/// Without an explicit port, the same fixed port + fallback range as the
+/// p2p-core binary is used.
+pub(crate) fn resolve_listen_addr(listen_host: Option<String>, listen_port: Option<u16>) -> Result<SocketAddr, P2PError> {
+    let host = listen_host.unwrap_or_else(|| "127.0.0.1".to_string());
+
+    let port = match listen_port {
+        Some(port) => port,
+        None => find_available_port(&host).map_err(|e| P2PError::Bind {
+            addr: host.clone(),
+            source: e.to_string().into(),
+        })?,
+    };
+
+    let listen_addr_str = format!("{}:{}", host, port);
+    listen_addr_str.parse().map_err(|e| P2PError::Bind {
+        addr: listen_addr_str.clone(),
+        source: Box::new(e),
+    })
 }
 
 impl P2PChatClient {
@@ -41,43 +160,43 @@ impl P2PChatClient {
         listen_port: Option<u16>,
         bootstrap_peers: Vec<SocketAddr>,
         enable_tls: bool,
-    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let host = listen_host.unwrap_or_else(|| "127.0.0.1".to_string());
-        let port = listen_port.unwrap_or(0);
-        
-        let listen_addr = if port == 0 {
-            format!("{}:0", host).parse()? // Random port
-        } else {
-            format!("{}:{}", host, port).parse()?
-        };
+    ) -> Result<Self, P2PError> {
+        Self::new_with_options(username, listen_host, listen_port, bootstrap_peers, enable_tls, ClientOptions::default()).await
+    }
+
+    /// Create a new P2P chat client with custom options
+    pub async fn new_with_options(
+        username: String,
+        listen_host: Option<String>,
+        listen_port: Option<u16>,
+        bootstrap_peers: Vec<SocketAddr>,
+        enable_tls: bool,
+        options: ClientOptions,
+    ) -> Result<Self, P2PError> {
+        let listen_addr = resolve_listen_addr(listen_host, listen_port)?;
+        let port = listen_addr.port();
+        let auto_selected_port = listen_port.is_none().then_some(port);
 
         // Determine if this is an owner node (no bootstrap peers = owner)
         let is_owner = bootstrap_peers.is_empty();
 
-        // Configure P2P node
-        let config = P2PNodeConfig {
-            username: username.clone(),
-            listen_addr,
-            enable_tls,
-            discovery_methods: vec![
-                DiscoveryMethod::Multicast {
-                    multicast_addr: DEFAULT_MULTICAST_ADDR.parse()?,
-                    interface: None,
-                },
-            ],
-            bootstrap_peers,
-            connection_timeout_secs: 30,
-            heartbeat_interval_secs: 60,
-            max_connections: 50,
-        };
-
-        let (mut node, event_rx) = P2PNode::new(config).await?;
-
-        // Start the node
-        node.start().await?;
+        // Configure and start the headless P2P session
+        let mut config = P2PSession::default_config(&username, listen_addr, bootstrap_peers.clone(), enable_tls)?;
+        options.configure_node(&mut config);
+        let spinner = (!options.quiet).then(|| startup_spinner("Starting P2P node...", options.theme));
+        let session = P2PSession::start(config).await;
+        if let Some(spinner) = spinner {
+            spinner.finish_and_clear();
+        }
+        let (node, event_rx) = session?.into_parts();
+        let event_rx = event_rx
+            .ok_or_else(|| std::io::Error::other("P2P session has no event stream"))?;
 
         // Create beautiful chat UI
-        let chat_ui = ChatUI::new(username.clone(), listen_port.clone(), 100)?;
+        let mut chat_ui = ChatUI::new(username.clone(), Some(port), options.history_size, options.theme)
+            .map_err(P2PError::from_io)?;
+        chat_ui.set_typing_indicators(options.typing_indicators);
+        chat_ui.set_cleartext_warning(!enable_tls);
 
         Ok(Self {
             node,
@@ -85,24 +204,46 @@ impl P2PChatClient {
             username,
             running: true,
             chat_ui,
-            history: MessageHistory::new(100),
+            history: MessageHistory::new(options.history_size),
+            mute_list: MuteList::new(),
+            notifier: Notifier::new(options.notify_mode, options.desktop_notifications),
             connected_peers: HashMap::new(),
             peer_addresses: HashMap::new(),
+            peer_presence: HashMap::new(),
+            discovered: DiscoveredPeers::new(),
+            presence: PresenceStatus::Online,
+            last_input: Instant::now(),
+            auto_away_secs: options.auto_away_secs,
             is_owner,
+            bootstrap_peers,
             quit_reason: QuitReason::UserQuit,
+            auto_selected_port,
+            quiet: options.quiet,
+            discovery_active: options.multicast_discovery || options.mdns_discovery,
         })
     }
 
     /// Start the chat client
-    pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Show welcome screen
-        self.chat_ui.show_welcome()?;
+    pub async fn start(&mut self) -> Result<(), P2PError> {
+        let result = self.run().await.map_err(P2PError::from_io);
         
-        // Show connection progress
-        self.chat_ui.show_connection_progress("Initializing P2P connection...").await?;
+        // The event loop shuts down on a normal exit, errors skip it
+        if self.running {
+            self.shutdown().await;
+        }
+        result
+    }
+
+    /// Show the chat interface and run until the user quits
+    async fn run(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Show welcome screen
+        if !self.quiet {
+            self.chat_ui.show_welcome()?;
+        }
         
         // Initialize the beautiful chat interface
         self.chat_ui.initialize()?;
+        self.refresh_network_status().await?;
         
         // Add welcome message
         let listen_addr = self.node.listen_addr().await;
@@ -112,6 +253,16 @@ impl P2PChatClient {
             MessageType::SystemMessage,
         )?;
         
+        // Tell the user which port was picked when none was given
+        if let Some(port) = self.auto_selected_port {
+            let selection = if port == FIXED_PORT {
+                format!("🔌 Using fixed port: {}", port)
+            } else {
+                format!("🔌 Fixed port {} unavailable, using fallback port: {}", FIXED_PORT, port)
+            };
+            self.chat_ui.add_message("System".to_string(), selection, MessageType::SystemMessage)?;
+        }
+        
         // Add help message
         self.chat_ui.add_message(
             "System".to_string(),
@@ -120,35 +271,29 @@ impl P2PChatClient {
         )?;
 
         // Run the main event loop
-        self.run_event_loop().await?;
-        
-        // Cleanup
-        self.shutdown().await;
-        Ok(())
+        self.run_event_loop().await
     }
 
     /// Main event loop with beautiful UI
     async fn run_event_loop(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Create a channel for input handling
-        let (input_tx, mut input_rx) = tokio::sync::mpsc::channel::<String>(100);
+        let (input_tx, mut input_rx) = tokio::sync::mpsc::channel::<UserInput>(100);
+        
+        // Read keys one at a time in a terminal, so the input line can be
+        // edited and recalled; piped input is still read line by line
+        let interactive = io::stdin().is_terminal();
+        let raw_mode = if interactive { Some(RawModeGuard::enable()?) } else { None };
         
-        // Spawn input handling task with proper cleanup
-        let input_tx_clone = input_tx.clone();
+        // Spawn input handling task with proper cleanup; it drops the sender
+        // at end of input, which the loop below sees as the channel closing
         let input_handle = tokio::spawn(async move {
             loop {
-                let input = tokio::task::spawn_blocking(|| {
-                    use std::io::{stdin, BufRead};
-                    let stdin = stdin();
-                    let mut line = String::new();
-                    match stdin.lock().read_line(&mut line) {
-                        Ok(_) => Some(line.trim().to_string()),
-                        Err(_) => None,
-                    }
-                }).await;
+                let stop_tx = input_tx.clone();
+                let input = tokio::task::spawn_blocking(move || read_input(interactive, || stop_tx.is_closed())).await;
                 
                 match input {
-                    Ok(Some(line)) => {
-                        if input_tx_clone.send(line).await.is_err() {
+                    Ok(Some(input)) => {
+                        if input_tx.send(input).await.is_err() {
                             break;
                         }
                     }
@@ -157,6 +302,14 @@ impl P2PChatClient {
             }
         });
         
+        // Watch for terminal resizes so the layout can be recomputed
+        let mut resize_watcher = ResizeWatcher::new();
+        
+        // Check now and then whether we have been idle long enough to go away
+        let mut away_check = tokio::time::interval(Duration::from_secs(AWAY_CHECK_INTERVAL_SECS));
+        let mut typing_check = tokio::time::interval(Duration::from_secs(TYPING_CHECK_INTERVAL_SECS));
+        let mut status_refresh = tokio::time::interval(Duration::from_secs(STATUS_REFRESH_INTERVAL_SECS));
+        
         // Position cursor initially
         self.chat_ui.position_cursor_for_input()?;
         
@@ -164,6 +317,12 @@ impl P2PChatClient {
             tokio::select! {
                 // Handle P2P events
                 event = self.event_rx.recv() => {
+                    let quit_reason = quit_reason_for_event(
+                        event.as_ref(),
+                        self.is_owner,
+                        &self.bootstrap_peers,
+                        &self.peer_addresses,
+                    );
                     match event {
                         Some(event) => {
                             EventHandler::handle_p2p_event(
@@ -171,34 +330,98 @@ impl P2PChatClient {
                                 &mut self.chat_ui,
                                 &mut self.connected_peers,
                                 &mut self.peer_addresses,
+                                &mut self.peer_presence,
+                                &self.history,
+                                &mut self.mute_list,
+                                &mut self.notifier,
+                                &mut self.discovered,
                             ).await?;
+                            self.refresh_network_status().await?;
                         }
                         None => {
                             error!("Event channel closed");
+                            let status = NetworkStatus { offline: true, ..self.chat_ui.network_status().clone() };
+                            self.chat_ui.set_network_status(status)?;
                             self.chat_ui.add_message(
                                 "System".to_string(),
                                 "❌ Network connection lost".to_string(),
                                 MessageType::ErrorMessage,
                             )?;
-                            break;
                         }
                     }
+                    if let Some(reason) = quit_reason {
+                        if reason == QuitReason::OwnerDisconnect {
+                            self.chat_ui.add_message(
+                                "System".to_string(),
+                                "👋 The owner left and no peers remain, leaving chat".to_string(),
+                                MessageType::SystemMessage,
+                            )?;
+                        }
+                        self.set_quit_reason(reason);
+                        break;
+                    }
                 }
                 
                 // Handle user input
                 input = input_rx.recv() => {
-                    match input {
-                        Some(input) => {
-                            if !self.handle_user_input(&input).await? {
-                                break;
+                    let input = match input {
+                        Some(UserInput::Key(key)) => match self.chat_ui.handle_key(key)? {
+                            KeyOutcome::Submitted(line) => Some(line),
+                            // Raw mode swallows Ctrl-C, so it quits like /quit would
+                            KeyOutcome::Quit => Some("/quit".to_string()),
+                            KeyOutcome::Edited => {
+                                if self.chat_ui.typing_started() {
+                                    self.node.send_typing().await;
+                                }
+                                continue;
                             }
-                        }
+                            KeyOutcome::Ignored => continue,
+                        },
+                        Some(UserInput::Line(line)) => Some(line),
+                        None => None,
+                    };
+                    let keep_going = match &input {
+                        Some(input) => self.handle_user_input(input).await?,
                         None => {
-                            error!("Input channel closed");
-                            break;
+                            // Nobody is left to type, leave quietly rather than as a failure
+                            info!("Input closed, leaving chat");
+                            false
                         }
+                    };
+                    if !keep_going {
+                        self.set_quit_reason(quit_reason_for_input(input.as_deref()));
+                        break;
                     }
                 }
+                
+                // Handle terminal resize
+                _ = resize_watcher.changed() => {
+                    self.chat_ui.handle_resize()?;
+                }
+                
+                // Mark ourselves away after a quiet spell
+                _ = away_check.tick(), if self.auto_away_secs > 0 => {
+                    let idle = self.last_input.elapsed() >= Duration::from_secs(self.auto_away_secs);
+                    if idle && self.presence == PresenceStatus::Online {
+                        CommandHandler::change_presence(
+                            &mut self.chat_ui,
+                            &self.node,
+                            &mut self.presence,
+                            PresenceStatus::Away,
+                            String::new(),
+                        ).await?;
+                    }
+                }
+                
+                // Clear typing notices from peers that went quiet
+                _ = typing_check.tick(), if self.chat_ui.has_typing_peers() => {
+                    self.chat_ui.expire_typing()?;
+                }
+                
+                // Pick up messages counted since the last event
+                _ = status_refresh.tick() => {
+                    self.refresh_network_status().await?;
+                }
             }
 
             if !self.running {
@@ -207,7 +430,7 @@ impl P2PChatClient {
         }
 
         // Close the input channel to signal shutdown
-        drop(input_tx);
+        drop(input_rx);
         
         // Give input task a brief moment to finish naturally
         tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
@@ -219,10 +442,30 @@ impl P2PChatClient {
         
         // Give a moment for cleanup
         tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        drop(raw_mode);
+        
+        // Disconnect from peers and save state before handing back the terminal
+        self.shutdown().await;
+        if self.quit_reason == QuitReason::UserQuit {
+            execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0)).ok();
+        }
         
         Ok(())
     }
 
+    /// Update the status line from the peer list and the node's counters
+    async fn refresh_network_status(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stats = self.node.get_stats().await;
+        let status = NetworkStatus {
+            peers: self.connected_peers.len(),
+            discovery_active: self.discovery_active,
+            messages_sent: stats.total_messages_sent,
+            messages_received: stats.total_messages_received,
+            offline: self.chat_ui.network_status().offline,
+        };
+        self.chat_ui.set_network_status(status)
+    }
+
     /// Handle user input with command processing
     async fn handle_user_input(&mut self, input: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         let input = input.trim();
@@ -233,19 +476,46 @@ impl P2PChatClient {
         if input.is_empty() {
             return Ok(true);
         }
+        self.chat_ui.record_input(input);
+        self.last_input = Instant::now();
+        
+        // Typing anything but another status change means we are back
+        let sets_presence = input.starts_with("/away") || input.starts_with("/busy");
+        if self.presence != PresenceStatus::Online && !sets_presence {
+            CommandHandler::change_presence(
+                &mut self.chat_ui,
+                &self.node,
+                &mut self.presence,
+                PresenceStatus::Online,
+                String::new(),
+            ).await?;
+        }
         
         // Handle commands
         if input.starts_with('/') {
-            return CommandHandler::handle_command(
-                input,
-                &mut self.chat_ui,
-                &self.connected_peers,
-                &self.peer_addresses,
-                self.is_owner,
-            ).await;
+            let mut ctx = CommandContext {
+                chat_ui: &mut self.chat_ui,
+                node: &self.node,
+                username: &self.username,
+                history: &self.history,
+                mute_list: &mut self.mute_list,
+                notifier: &mut self.notifier,
+                connected_peers: &self.connected_peers,
+                peer_addresses: &self.peer_addresses,
+                peer_presence: &self.peer_presence,
+                discovered: &mut self.discovered,
+                presence: &mut self.presence,
+                is_owner: self.is_owner,
+            };
+            return CommandHandler::handle_command(input, &mut ctx).await;
+        }
+        
+        // Regular message - reject it up front if peers would drop it anyway
+        if !CommandHandler::check_message_length(&mut self.chat_ui, input)? {
+            return Ok(true);
         }
         
-        // Regular message - send to all connected peers
+        // Send to all connected peers
         if self.connected_peers.is_empty() {
             self.chat_ui.add_message(
                 "System".to_string(),
@@ -290,7 +560,6 @@ impl P2PChatClient {
     }
 
     /// Set quit reason
-    #[allow(dead_code)]
     fn set_quit_reason(&mut self, reason: QuitReason) {
         self.quit_reason = reason;
     }
@@ -319,5 +588,48 @@ impl P2PChatClient {
         // Force yield to let tokio cleanup
         tokio::task::yield_now().await;
     }
-    
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disconnected(peer_id: &str) -> P2PEvent {
+        P2PEvent::PeerDisconnected { peer_id: peer_id.to_string(), reason: "Connection closed".to_string() }
+    }
+
+    #[test]
+    fn test_quit_command_is_user_quit() {
+        assert_eq!(quit_reason_for_input(Some("/quit")), QuitReason::UserQuit);
+    }
+
+    #[test]
+    fn test_end_of_input_is_stdin_closed() {
+        assert_eq!(quit_reason_for_input(None), QuitReason::StdinClosed);
+    }
+
+    #[test]
+    fn test_closed_event_stream_is_network_error() {
+        assert_eq!(quit_reason_for_event(None, true, &[], &HashMap::new()), Some(QuitReason::NetworkError));
+        assert_eq!(quit_reason_for_event(None, false, &[], &HashMap::new()), Some(QuitReason::NetworkError));
+    }
+
+    #[test]
+    fn test_losing_the_bootstrap_peer_is_owner_disconnect() {
+        let owner: SocketAddr = "192.168.1.10:40000".parse().unwrap();
+        let other: SocketAddr = "192.168.1.11:40000".parse().unwrap();
+        let mut peers = HashMap::from([("owner".to_string(), owner)]);
+
+        assert_eq!(
+            quit_reason_for_event(Some(&disconnected("owner")), false, &[owner], &peers),
+            Some(QuitReason::OwnerDisconnect)
+        );
+        // The owner itself never leaves because a peer did
+        assert_eq!(quit_reason_for_event(Some(&disconnected("owner")), true, &[], &peers), None);
+
+        // Others still around keep the chat going
+        peers.insert("bob".to_string(), other);
+        assert_eq!(quit_reason_for_event(Some(&disconnected("owner")), false, &[owner], &peers), None);
+        assert_eq!(quit_reason_for_event(Some(&disconnected("bob")), false, &[owner], &peers), None);
+    }
 }
@@ -1,8 +1,38 @@
 //! Command handling for P2P chat client
 
-use crate::ui::{ChatUI, MessageType};
+use crate::client::discovered::{format_age, DiscoveredPeers};
+use crate::client::history::{find_ignore_case, is_valid_url, MessageHistory};
+use crate::client::mute::MuteList;
+use crate::client::notify::{Notifier, NotifyMode};
+use crate::ui::{ChatUI, MessageType, ThemeRole};
+use shared::config::{BUILD_VERSION, MAX_MESSAGE_LENGTH, PROTOCOL_VERSION};
+use shared::utils::{safety_number, short_fingerprint};
+use shared::{P2PNode, PresenceStatus};
+use super::PeerPresence;
 use std::collections::HashMap;
+use std::io::Write;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// Client state that commands can read or act on
+pub struct CommandContext<'a> {
+    pub chat_ui: &'a mut ChatUI,
+    pub node: &'a P2PNode,
+    pub username: &'a str,
+    pub history: &'a MessageHistory,
+    pub mute_list: &'a mut MuteList,
+    pub notifier: &'a mut Notifier,
+    pub connected_peers: &'a HashMap<String, String>,
+    pub peer_addresses: &'a HashMap<String, SocketAddr>,
+    pub peer_presence: &'a PeerPresence,
+    pub discovered: &'a mut DiscoveredPeers,
+    pub presence: &'a mut PresenceStatus,
+    pub is_owner: bool,
+}
+
+/// How many recent links `/open` keeps numbered
+const RECENT_URL_LIMIT: usize = 20;
 
 /// Handles chat commands
 pub struct CommandHandler;
@@ -11,20 +41,24 @@ impl CommandHandler {
     /// Handle chat commands
     pub async fn handle_command(
         command: &str,
-        chat_ui: &mut ChatUI,
-        connected_peers: &HashMap<String, String>,
-        peer_addresses: &HashMap<String, SocketAddr>,
-        is_owner: bool,
+        ctx: &mut CommandContext<'_>,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         let parts: Vec<&str> = command.split_whitespace().collect();
+        let chat_ui = &mut *ctx.chat_ui;
+        let connected_peers = ctx.connected_peers;
+        let peer_addresses = ctx.peer_addresses;
         
-        match parts.get(0) {
+        match parts.first() {
             Some(&"/help") => {
                 Self::show_help(chat_ui).await?;
             }
+            Some(&"/me") => {
+                let action = command["/me".len()..].trim();
+                Self::send_action(ctx, action).await?;
+            }
             Some(&"/quit") | Some(&"/exit") => {
                 // Show appropriate goodbye message
-                if is_owner {
+                if ctx.is_owner {
                     chat_ui.add_message(
                         "System".to_string(),
                         "👋 Owner disconnecting. Goodbye!".to_string(),
@@ -33,30 +67,96 @@ impl CommandHandler {
                 } else {
                     chat_ui.add_message(
                         "System".to_string(),
-                        "👋 Goodbye! Exiting program...".to_string(),
+                        "👋 Goodbye! Leaving chat...".to_string(),
                         MessageType::SystemMessage,
                     )?;
                 }
                 
-                // Brief delay for message display
-                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-                
-                // Clear terminal before exit
-                use crossterm::{execute, terminal::{Clear, ClearType}, cursor::MoveTo};
-                use std::io;
-                execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0)).ok();
-                
-                // Exit program directly - both owner and peer
-                std::process::exit(0);
+                // The event loop records the reason and shuts the node down
+                return Ok(false);
             }
             Some(&"/peers") => {
-                Self::show_peers(chat_ui, connected_peers, peer_addresses).await?;
+                Self::show_peers(chat_ui, ctx.node, connected_peers, peer_addresses, ctx.peer_presence, ctx.mute_list).await?;
+            }
+            Some(&"/away") => {
+                let note = command["/away".len()..].trim().to_string();
+                Self::change_presence(chat_ui, ctx.node, ctx.presence, PresenceStatus::Away, note).await?;
+            }
+            Some(&"/busy") => {
+                let note = command["/busy".len()..].trim().to_string();
+                Self::change_presence(chat_ui, ctx.node, ctx.presence, PresenceStatus::Busy, note).await?;
+            }
+            Some(&"/connect") => {
+                let target = parts.get(1).copied().unwrap_or_default();
+                Self::connect_peer(chat_ui, ctx.node, ctx.discovered, target).await?;
+            }
+            Some(&"/reconnect") => {
+                Self::reconnect_bootstrap_peers(chat_ui, ctx.node).await?;
+            }
+            Some(&"/discovered") => {
+                Self::show_discovered(chat_ui, ctx.discovered, peer_addresses)?;
+            }
+            Some(&"/ping") => {
+                let username = command["/ping".len()..].trim();
+                Self::ping_peer(chat_ui, ctx.node, connected_peers, username).await?;
+            }
+            Some(&"/disconnect") => {
+                let username = command["/disconnect".len()..].trim();
+                Self::disconnect_peer(chat_ui, ctx.node, connected_peers, username).await?;
+            }
+            Some(&"/block") => {
+                let fingerprint = parts.get(1).copied().unwrap_or_default();
+                Self::block_peer(chat_ui, ctx.node, fingerprint).await?;
+            }
+            Some(&"/mute") => {
+                let username = command["/mute".len()..].trim();
+                Self::mute_peer(chat_ui, ctx.mute_list, connected_peers, username)?;
+            }
+            Some(&"/unmute") => {
+                let username = command["/unmute".len()..].trim();
+                Self::unmute_peer(chat_ui, ctx.mute_list, username)?;
+            }
+            Some(&"/notify") => {
+                Self::set_notify_mode(chat_ui, ctx.notifier, parts.get(1).copied())?;
             }
             Some(&"/clear") => {
                 chat_ui.clear_chat()?;
             }
+            Some(&"/statusline") => {
+                let shown = chat_ui.toggle_status_line()?;
+                let notice = if shown { "📶 Status line shown" } else { "📶 Status line hidden" };
+                chat_ui.add_message("System".to_string(), notice.to_string(), MessageType::SystemMessage)?;
+            }
+            Some(&"/history") => {
+                Self::show_history(chat_ui, ctx.history, parts.get(1).copied())?;
+            }
+            Some(&"/search") => {
+                let term = command["/search".len()..].trim();
+                Self::search_history(chat_ui, ctx.history, term)?;
+            }
+            Some(&"/save") => {
+                let args = command["/save".len()..].trim();
+                Self::save_transcript(chat_ui, ctx.history, args)?;
+            }
+            Some(&"/open") => {
+                Self::open_link(chat_ui, ctx.history, parts.get(1).copied())?;
+            }
+            Some(&"/whoami") => {
+                Self::show_whoami(chat_ui, ctx.node).await?;
+            }
+            Some(&"/fingerprint") => {
+                let username = command["/fingerprint".len()..].trim();
+                Self::show_peer_fingerprint(chat_ui, ctx.node, connected_peers, username).await?;
+            }
+            Some(&"/version") => {
+                Self::show_versions(chat_ui, ctx.node, connected_peers).await?;
+            }
+            Some(&"/peerstats") => {
+                let username = command["/peerstats".len()..].trim();
+                Self::show_peer_stats(chat_ui, ctx.node, connected_peers, username).await?;
+            }
             Some(&"/stats") => {
-                Self::show_stats(chat_ui, connected_peers, peer_addresses).await?;
+                Self::show_stats(chat_ui, ctx.node, connected_peers, peer_addresses).await?;
             }
             Some(cmd) => {
                 chat_ui.add_message(
@@ -76,9 +176,30 @@ impl CommandHandler {
         let help_messages = vec![
             "📖 Available Commands:",
             "/help     - Show this help message",
+            "/me <action> - Send an action, e.g. /me waves",
             "/peers    - List connected peers", 
+            "/away [message] - Tell peers you are away, typing anything brings you back",
+            "/busy [message] - Tell peers you are busy",
+            "/connect <ip:port|n> - Connect to another peer, or to the nth discovered one",
+            "/discovered - List peers found on the network and whether we're connected",
+            "/reconnect - Redial the bootstrap peers we aren't connected to right now",
+            "/disconnect <user> - Drop the connection to one peer",
+            "/ping <user> - Measure the round-trip time to a peer",
+            "/mute <user>   - Hide messages from a peer for this session",
+            "/unmute <user> - Show messages from a muted peer again",
+            "/block <fingerprint> - Refuse all connections from an identity",
             "/stats    - Show detailed peer statistics",
-            "/clear    - Clear chat display",
+            "/peerstats <user> - Show messages and bytes exchanged with a peer",
+            "/whoami   - Show your username, peer ID, address and fingerprint",
+            "/fingerprint <user> - Show a peer's fingerprint and safety number to verify out of band",
+            "/version  - Show your version and the versions peers are running",
+            "/notify [all|mentions|off] - Ring the bell for every message, only @mentions, or never",
+            "/clear    - Clear and redraw the chat display",
+            "/statusline - Show or hide the network status line above the input box",
+            "/history [n] - Show the last n stored messages (default all)",
+            "/search <term> - Find stored messages containing a term",
+            "/save <file> [--force] - Write stored messages to a text file",
+            "/open [n] - List recent links, or open the nth most recent one",
             "/quit     - Exit the chat",
             "",
             "💡 Tips:",
@@ -98,11 +219,573 @@ impl CommandHandler {
         Ok(())
     }
 
+    /// Check an outgoing message against `MAX_MESSAGE_LENGTH`, telling the user if it is rejected
+    pub fn check_message_length(
+        chat_ui: &mut ChatUI,
+        content: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        if content.len() <= MAX_MESSAGE_LENGTH {
+            return Ok(true);
+        }
+
+        chat_ui.add_message(
+            "System".to_string(),
+            format!(
+                "⚠️  Message too long ({} bytes, max {}). Your message was not sent.",
+                content.len(),
+                MAX_MESSAGE_LENGTH
+            ),
+            MessageType::ErrorMessage,
+        )?;
+        Ok(false)
+    }
+
+    /// Send a `/me` action to all peers and show it locally
+    async fn send_action(
+        ctx: &mut CommandContext<'_>,
+        action: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if action.is_empty() {
+            ctx.chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /me <action>".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        if !Self::check_message_length(ctx.chat_ui, action)? {
+            return Ok(());
+        }
+
+        if ctx.connected_peers.is_empty() {
+            ctx.chat_ui.add_message(
+                "System".to_string(),
+                "⚠️  No peers connected. Your message was not sent.".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        // Render our own action exactly as recipients will see it
+        ctx.chat_ui.add_message(
+            ctx.username.to_string(),
+            action.to_string(),
+            MessageType::ActionMessage,
+        )?;
+
+        if let Err(e) = ctx.node.send_action_message(action.to_string()).await {
+            warn!("Failed to send action: {}", e);
+            ctx.chat_ui.add_message(
+                "System".to_string(),
+                format!("⚠️  Failed to send message: {}", e),
+                MessageType::ErrorMessage,
+            )?;
+        }
+
+        ctx.history.add_message(format!("* {} {}", ctx.username, action));
+        Ok(())
+    }
+
+    /// Re-render stored messages from history into the chat area
+    fn show_history(
+        chat_ui: &mut ChatUI,
+        history: &MessageHistory,
+        count: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let count = match count.map(str::parse::<usize>) {
+            None => None,
+            Some(Ok(count)) if count > 0 => Some(count),
+            Some(_) => {
+                chat_ui.add_message(
+                    "System".to_string(),
+                    "❓ Usage: /history [n] where n is a positive number".to_string(),
+                    MessageType::SystemMessage,
+                )?;
+                return Ok(());
+            }
+        };
+
+        // Take a snapshot first so entries recorded while rendering are not repeated
+        let messages = history.recent(count);
+        if messages.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "📜 No messages in history yet".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        chat_ui.add_message(
+            "System".to_string(),
+            format!("📜 Last {} of {} stored messages:", messages.len(), history.message_count()),
+            MessageType::SystemMessage,
+        )?;
+
+        for message in messages {
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("  {}", message),
+                MessageType::SystemMessage,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// List the most recent links, or open one of them in the default browser
+    ///
+    /// Link 1 is the newest. Where no browser can be started the URL is
+    /// shown instead so it can be copied.
+    fn open_link(
+        chat_ui: &mut ChatUI,
+        history: &MessageHistory,
+        index: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let urls = history.recent_urls(RECENT_URL_LIMIT);
+
+        let Some(index) = index else {
+            if urls.is_empty() {
+                chat_ui.add_message(
+                    "System".to_string(),
+                    "🔗 No links in history yet".to_string(),
+                    MessageType::SystemMessage,
+                )?;
+                return Ok(());
+            }
+            chat_ui.add_message(
+                "System".to_string(),
+                "🔗 Recent links (open with /open <n>):".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            for (i, url) in urls.iter().enumerate() {
+                chat_ui.add_message("System".to_string(), format!("  {}. {}", i + 1, url), MessageType::SystemMessage)?;
+            }
+            return Ok(());
+        };
+
+        let url = match index.parse::<usize>() {
+            Ok(n) if n > 0 => urls.get(n - 1),
+            _ => {
+                chat_ui.add_message(
+                    "System".to_string(),
+                    "❓ Usage: /open [n] where n is a positive number".to_string(),
+                    MessageType::SystemMessage,
+                )?;
+                return Ok(());
+            }
+        };
+        let Some(url) = url else {
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("❌ No link #{} ({} recent links, see /open)", index, urls.len()),
+                MessageType::ErrorMessage,
+            )?;
+            return Ok(());
+        };
+
+        let text = match open_in_browser(url) {
+            Ok(()) => format!("🔗 Opening {}", url),
+            Err(reason) => format!("🔗 {} ({}, copy the link instead)", url, reason),
+        };
+        chat_ui.add_message("System".to_string(), text, MessageType::SystemMessage)?;
+        Ok(())
+    }
+
+    /// Write the stored messages as plain text to a file
+    ///
+    /// An existing file is only replaced when `--force` follows the path.
+    fn save_transcript(
+        chat_ui: &mut ChatUI,
+        history: &MessageHistory,
+        args: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (path, force) = parse_save_args(args);
+        if path.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /save <file> [--force]".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        let path = match (path.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(path),
+        };
+
+        let lines = history.plain_text();
+        let result = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .create_new(!force)
+            .open(&path)
+            .and_then(|mut file| {
+                for line in &lines {
+                    writeln!(file, "{}", line)?;
+                }
+                file.flush()
+            });
+
+        let (text, message_type) = match result {
+            Ok(()) => (
+                format!("💾 Saved {} messages to {}", lines.len(), path.display()),
+                MessageType::SystemMessage,
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (
+                format!("⚠️  {} already exists, use /save {} --force to overwrite it", path.display(), path.display()),
+                MessageType::ErrorMessage,
+            ),
+            Err(e) => (
+                format!("❌ Could not save to {}: {}", path.display(), e),
+                MessageType::ErrorMessage,
+            ),
+        };
+        chat_ui.add_message("System".to_string(), text, message_type)?;
+        Ok(())
+    }
+
+    /// List stored messages containing a term, with the term highlighted
+    fn search_history(
+        chat_ui: &mut ChatUI,
+        history: &MessageHistory,
+        term: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if term.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /search <term>".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        // Snapshot before rendering, like /history
+        let matches = history.search(term);
+        if matches.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("🔍 No stored messages match \"{}\"", term),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        chat_ui.add_message(
+            "System".to_string(),
+            format!("🔍 {} of {} stored messages match \"{}\":", matches.len(), history.message_count(), term),
+            MessageType::SystemMessage,
+        )?;
+
+        let theme = chat_ui.theme();
+        for (position, message) in matches {
+            let line = match find_ignore_case(&message, term) {
+                Some(range) => format!(
+                    "{}{}{}",
+                    &message[..range.start],
+                    theme.paint_bold(&message[range.clone()], theme.color(ThemeRole::OwnMessage)),
+                    &message[range.end..]
+                ),
+                None => message,
+            };
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("  #{} {}", position, line),
+                MessageType::SystemMessage,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Mute a peer by username for the rest of the session
+    fn mute_peer(
+        chat_ui: &mut ChatUI,
+        mute_list: &mut MuteList,
+        connected_peers: &HashMap<String, String>,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if username.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /mute <username>".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        let connection_ids = connected_peers
+            .iter()
+            .filter(|(_, name)| name.as_str() == username)
+            .map(|(peer_id, _)| peer_id);
+
+        let message = if mute_list.mute(username, connection_ids) {
+            format!("🔇 Muted {}. Use /unmute {} to see their messages again", username, username)
+        } else {
+            format!("❓ No peer named {} is connected or has sent a message", username)
+        };
+        chat_ui.add_message("System".to_string(), message, MessageType::SystemMessage)?;
+        Ok(())
+    }
+
+    /// Redial disconnected bootstrap peers, the outcome arrives as connection events
+    async fn reconnect_bootstrap_peers(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = match node.reconnect_bootstrap_peers().await {
+            0 => "🔄 No bootstrap peers to redial, all are connected or none were given".to_string(),
+            1 => "🔄 Redialing 1 bootstrap peer...".to_string(),
+            n => format!("🔄 Redialing {} bootstrap peers...", n),
+        };
+        chat_ui.add_message("System".to_string(), message, MessageType::SystemMessage)?;
+        Ok(())
+    }
+
+    /// Show the notification mode, or change it
+    fn set_notify_mode(
+        chat_ui: &mut ChatUI,
+        notifier: &mut Notifier,
+        mode: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = match mode.map(str::parse::<NotifyMode>) {
+            None => format!("🔔 Notifications: {} (use /notify all, mentions or off)", notifier.mode()),
+            Some(Ok(mode)) => {
+                notifier.set_mode(mode);
+                format!("🔔 Notifications set to {}", mode)
+            }
+            Some(Err(e)) => format!("❓ {}", e),
+        };
+        chat_ui.add_message("System".to_string(), message, MessageType::SystemMessage)?;
+        Ok(())
+    }
+
+    /// Unmute a previously muted peer
+    fn unmute_peer(
+        chat_ui: &mut ChatUI,
+        mute_list: &mut MuteList,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = if username.is_empty() {
+            "❓ Usage: /unmute <username>".to_string()
+        } else if mute_list.unmute(username) {
+            format!("🔊 Unmuted {}", username)
+        } else {
+            format!("❓ {} is not muted", username)
+        };
+        chat_ui.add_message("System".to_string(), message, MessageType::SystemMessage)?;
+        Ok(())
+    }
+
+    /// Dial a peer by address, or by its number in `/discovered`, while the chat is running
+    async fn connect_peer(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+        discovered: &mut DiscoveredPeers,
+        target: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let addr = match target.parse::<usize>() {
+            Ok(index) => match discovered.get(index) {
+                Some(addr) => addr,
+                None => {
+                    chat_ui.add_message(
+                        "System".to_string(),
+                        format!("❓ No discovered peer number {}, /discovered lists them", index),
+                        MessageType::SystemMessage,
+                    )?;
+                    return Ok(());
+                }
+            },
+            Err(_) => match target.parse::<SocketAddr>() {
+                Ok(addr) => addr,
+                Err(_) => {
+                    chat_ui.add_message(
+                        "System".to_string(),
+                        "❓ Usage: /connect <ip:port|n>, e.g. /connect 192.168.1.100:40000 or /connect 2".to_string(),
+                        MessageType::SystemMessage,
+                    )?;
+                    return Ok(());
+                }
+            },
+        };
+
+        chat_ui.add_message(
+            "System".to_string(),
+            format!("🔗 Connecting to {}...", addr),
+            MessageType::ConnectionInfo,
+        )?;
+
+        match node.connect_to(addr).await {
+            Ok(username) => {
+                info!("Connected to {} at {}", username, addr);
+                discovered.clear_failure(addr);
+            }
+            Err(e) => {
+                discovered.note_failure(addr, e.to_string());
+                chat_ui.add_message(
+                    "System".to_string(),
+                    format!("⚠️  Failed to connect to {}: {}", addr, e),
+                    MessageType::ErrorMessage,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// List discovered peers with their number for `/connect`
+    fn show_discovered(
+        chat_ui: &mut ChatUI,
+        discovered: &DiscoveredPeers,
+        peer_addresses: &HashMap<String, SocketAddr>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if discovered.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "🔍 No peers discovered yet".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        chat_ui.add_message("System".to_string(), "🔍 Discovered peers:".to_string(), MessageType::SystemMessage)?;
+        for (index, peer) in discovered.iter().enumerate() {
+            let status = if peer_addresses.values().any(|addr| *addr == peer.addr) {
+                "🟢 connected".to_string()
+            } else if let Some(error) = &peer.last_error {
+                format!("⚠️  failed: {}", error)
+            } else {
+                "⚪ not connected".to_string()
+            };
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("  {}. {} · {} · seen {} ago", index + 1, peer.addr, status, format_age(peer.last_seen.elapsed())),
+                MessageType::SystemMessage,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Ping a connected peer by username to measure the round-trip time
+    async fn ping_peer(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+        connected_peers: &HashMap<String, String>,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if username.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /ping <username>".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        let peer_id = connected_peers
+            .iter()
+            .find(|(_, name)| name.as_str() == username)
+            .map(|(peer_id, _)| peer_id);
+
+        // The reply or timeout is reported when its event arrives
+        let (text, message_type) = match peer_id {
+            Some(peer_id) => match node.ping(peer_id).await {
+                Ok(()) => (format!("📡 Pinging {}...", username), MessageType::SystemMessage),
+                Err(e) => (format!("❌ Could not ping {}: {}", username, e), MessageType::ErrorMessage),
+            },
+            None => (format!("❌ No connected peer named {}", username), MessageType::ErrorMessage),
+        };
+        chat_ui.add_message("System".to_string(), text, message_type)?;
+        Ok(())
+    }
+
+    /// Drop every connection of a peer without leaving the network
+    async fn disconnect_peer(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+        connected_peers: &HashMap<String, String>,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if username.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /disconnect <username>".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        let peer_ids: Vec<&String> = connected_peers
+            .iter()
+            .filter(|(_, name)| name.as_str() == username)
+            .map(|(peer_id, _)| peer_id)
+            .collect();
+
+        let mut dropped = 0;
+        for peer_id in peer_ids {
+            if node.disconnect_peer(peer_id).await {
+                dropped += 1;
+            }
+        }
+
+        // The peer list updates when the disconnect event arrives
+        if dropped == 0 {
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("❓ No peer named {} is connected", username),
+                MessageType::SystemMessage,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Block an identity fingerprint, disconnecting it if connected
+    async fn block_peer(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+        fingerprint: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if fingerprint.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /block <fingerprint> (see /peers for fingerprints)".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        match node.block_peer(fingerprint).await {
+            Ok(removed) => {
+                chat_ui.add_message(
+                    "System".to_string(),
+                    format!("⛔ Blocked {} ({} connected peer(s) dropped)", fingerprint, removed.len()),
+                    MessageType::SystemMessage,
+                )?;
+            }
+            Err(e) => {
+                chat_ui.add_message(
+                    "System".to_string(),
+                    format!("⚠️  Failed to block {}: {}", fingerprint, e),
+                    MessageType::ErrorMessage,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Show connected peers
     async fn show_peers(
         chat_ui: &mut ChatUI,
+        node: &P2PNode,
         connected_peers: &HashMap<String, String>,
         peer_addresses: &HashMap<String, SocketAddr>,
+        peer_presence: &PeerPresence,
+        mute_list: &MuteList,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if connected_peers.is_empty() {
             chat_ui.add_message(
@@ -121,10 +804,20 @@ impl CommandHandler {
                 let addr = peer_addresses.get(peer_id)
                     .map(|a| format!(" ({})", a))
                     .unwrap_or_default();
+                let muted = if mute_list.is_username_muted(username) { " 🔇 muted" } else { "" };
+                let presence = match peer_presence.get(peer_id) {
+                    Some((status, note)) if note.is_empty() => format!(" 💤 {}", status),
+                    Some((status, note)) => format!(" 💤 {}: {}", status, note),
+                    None => String::new(),
+                };
+                let fingerprint = node.peer_fingerprint(peer_id).await
+                    .filter(|fingerprint| !fingerprint.is_empty())
+                    .map(|fingerprint| format!(" [{}]", fingerprint))
+                    .unwrap_or_default();
                 
                 chat_ui.add_message(
                     "System".to_string(),
-                    format!("  • {}{}", username, addr),
+                    format!("  • {}{}{}{}{}", username, addr, fingerprint, presence, muted),
                     MessageType::SystemMessage,
                 )?;
             }
@@ -133,18 +826,204 @@ impl CommandHandler {
         Ok(())
     }
 
+    /// Set our own status and tell all direct peers
+    pub async fn change_presence(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+        presence: &mut PresenceStatus,
+        status: PresenceStatus,
+        note: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        *presence = status;
+        let text = match status {
+            PresenceStatus::Online => "👋 Welcome back, peers see you as online again".to_string(),
+            PresenceStatus::Away | PresenceStatus::Busy if note.is_empty() => format!("💤 You are now {}", status),
+            PresenceStatus::Away | PresenceStatus::Busy => format!("💤 You are now {}: {}", status, note),
+        };
+
+        node.send_presence(status, note).await;
+        chat_ui.add_message("System".to_string(), text, MessageType::SystemMessage)?;
+        Ok(())
+    }
+
+    /// Show who we are to peers, one value per line so each is easy to copy
+    async fn show_whoami(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut lines = vec![
+            format!("👤 You are {}", node.username()),
+            format!("  Peer ID: {}", node.peer_id()),
+            format!("  Listening on: {}", node.listen_addr().await),
+        ];
+        match node.fingerprint() {
+            Some(fingerprint) => {
+                lines.push(format!("  Fingerprint: {}", fingerprint));
+                lines.push(format!("  Short fingerprint: {}", short_fingerprint(fingerprint)));
+                if let Some(number) = safety_number(fingerprint) {
+                    lines.push(format!("  Safety number: {}", number));
+                }
+            }
+            None => lines.push("  No identity: peers can't verify or pin you this session".to_string()),
+        }
+
+        for line in lines {
+            chat_ui.add_message("System".to_string(), line, MessageType::SystemMessage)?;
+        }
+        Ok(())
+    }
+
+    /// Show a peer's fingerprint and safety number for out-of-band comparison
+    async fn show_peer_fingerprint(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+        connected_peers: &HashMap<String, String>,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if username.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /fingerprint <username>".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        let Some((peer_id, name)) = connected_peers.iter().find(|(_, name)| name.as_str() == username) else {
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("❓ No peer named {} is connected", username),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        };
+
+        let verified = node.is_peer_verified(peer_id).await;
+        let lines = match node.peer_fingerprint(peer_id).await.filter(|fingerprint| !fingerprint.is_empty()) {
+            Some(fingerprint) => {
+                let mut lines = vec![
+                    format!("🔐 {} presented this identity:", name),
+                    if verified {
+                        format!("  Fingerprint: {} (verified by mutual TLS)", fingerprint)
+                    } else {
+                        format!("  Fingerprint: {} (unverified, claimed in handshake)", fingerprint)
+                    },
+                    format!("  Short fingerprint: {}", short_fingerprint(&fingerprint)),
+                ];
+                if let Some(number) = safety_number(&fingerprint) {
+                    lines.push(format!("  Safety number: {}", number));
+                }
+                if !verified {
+                    lines.push("  Without --mutual-tls anyone can claim this fingerprint".to_string());
+                }
+                lines.push(format!("  Ask {} to read out what /whoami shows them, over a channel you trust; it must match", name));
+                lines
+            }
+            None => vec![format!("⚠️  {} is anonymous: no identity was presented, so there is nothing to verify", name)],
+        };
+
+        for line in lines {
+            chat_ui.add_message("System".to_string(), line, MessageType::SystemMessage)?;
+        }
+        Ok(())
+    }
+
+    /// Show our build and protocol version alongside those of connected peers
+    async fn show_versions(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+        connected_peers: &HashMap<String, String>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        chat_ui.add_message(
+            "System".to_string(),
+            format!("🚀 You are running v{} (protocol {})", BUILD_VERSION, PROTOCOL_VERSION),
+            MessageType::SystemMessage,
+        )?;
+
+        for (peer_id, username) in connected_peers {
+            let version = match node.peer_version(peer_id).await {
+                Some((protocol, build)) if build.is_empty() => format!("unknown build (protocol {})", protocol),
+                Some((protocol, build)) => {
+                    let stale = if build != BUILD_VERSION { " ⚠️  differs from yours" } else { "" };
+                    format!("v{} (protocol {}){}", build, protocol, stale)
+                }
+                None => "unknown".to_string(),
+            };
+
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("  • {}: {}", username, version),
+                MessageType::SystemMessage,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Show traffic counters for every connection of a peer
+    async fn show_peer_stats(
+        chat_ui: &mut ChatUI,
+        node: &P2PNode,
+        connected_peers: &HashMap<String, String>,
+        username: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if username.is_empty() {
+            chat_ui.add_message(
+                "System".to_string(),
+                "❓ Usage: /peerstats <username>".to_string(),
+                MessageType::SystemMessage,
+            )?;
+            return Ok(());
+        }
+
+        let mut found = false;
+        for (peer_id, name) in connected_peers.iter().filter(|(_, name)| name.as_str() == username) {
+            let Some(stats) = node.get_peer_stats(peer_id).await else {
+                continue;
+            };
+            found = true;
+
+            chat_ui.add_message(
+                "System".to_string(),
+                format!(
+                    "📊 {}: sent {} msgs / {} bytes, received {} msgs / {} bytes",
+                    name, stats.messages_sent, stats.bytes_sent, stats.messages_received, stats.bytes_received
+                ),
+                MessageType::ConnectionInfo,
+            )?;
+        }
+
+        if !found {
+            chat_ui.add_message(
+                "System".to_string(),
+                format!("❓ No peer named {} is connected", username),
+                MessageType::SystemMessage,
+            )?;
+        }
+
+        Ok(())
+    }
+
     /// Show detailed peer statistics
     async fn show_stats(
         chat_ui: &mut ChatUI,
+        node: &P2PNode,
         connected_peers: &HashMap<String, String>,
         peer_addresses: &HashMap<String, SocketAddr>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stats = node.get_stats().await;
+        let health = format!(
+            "🔁 Dials: {} succeeded, {} failed · {} peers discovered",
+            stats.successful_connections, stats.failed_connections, stats.discovery_attempts
+        );
+
         if connected_peers.is_empty() {
             chat_ui.add_message(
                 "System".to_string(),
                 "📊 No peers currently connected".to_string(),
                 MessageType::SystemMessage,
             )?;
+            chat_ui.add_message("System".to_string(), health, MessageType::SystemMessage)?;
             return Ok(());
         }
 
@@ -156,7 +1035,7 @@ impl CommandHandler {
         
         chat_ui.add_message(
             "System".to_string(),
-            format!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━"),
+            "━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━".to_string(),
             MessageType::SystemMessage,
         )?;
 
@@ -165,7 +1044,7 @@ impl CommandHandler {
             
             chat_ui.add_message(
                 "System".to_string(),
-                format!("🔗 Peer ID: {}", &peer_id[..8]), // Show first 8 chars of peer ID
+                format!("🔗 Peer ID: {}", peer_id.chars().take(8).collect::<String>()), // Show first 8 chars of peer ID
                 MessageType::ConnectionInfo,
             )?;
             
@@ -213,7 +1092,107 @@ impl CommandHandler {
             format!("📈 Total Connected Peers: {}", connected_peers.len()),
             MessageType::SystemMessage,
         )?;
-        
+        chat_ui.add_message("System".to_string(), health, MessageType::SystemMessage)?;
+
         Ok(())
     }
 }
+
+/// Split `/save` arguments into the path and whether `--force` was given
+///
+/// `--force` only counts as its own final word, so `notes--force` is a path.
+fn parse_save_args(args: &str) -> (&str, bool) {
+    let args = args.trim();
+    match args.rsplit_once(char::is_whitespace) {
+        Some((path, "--force")) => (path.trim_end(), true),
+        _ if args == "--force" => ("", true),
+        _ => (args, false),
+    }
+}
+
+/// Open a URL with the platform's default handler
+///
+/// On Linux and BSD a browser can only be started inside a graphical
+/// session, so without `DISPLAY` or `WAYLAND_DISPLAY` this fails early.
+/// The URL came from a peer, so it is never passed through a shell and
+/// only characters RFC 3986 allows are accepted.
+fn open_in_browser(url: &str) -> Result<(), String> {
+    if !is_valid_url(url) {
+        return Err("the link has characters URLs can't contain".to_string());
+    }
+
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else {
+        if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            return Err("no graphical session".to_string());
+        }
+        std::process::Command::new("xdg-open")
+    };
+
+    // The terminal belongs to the chat UI, keep the opener off it
+    command
+        .arg(url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map(|mut child| {
+            // Reap the opener once it exits so it doesn't linger as a zombie
+            std::thread::spawn(move || child.wait());
+        })
+        .map_err(|e| format!("could not start a browser: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::Theme;
+    use shared::P2PNodeConfig;
+
+    #[tokio::test]
+    async fn test_quit_command_ends_the_event_loop() {
+        let config = P2PNodeConfig { enable_tls: false, discovery_methods: vec![], ..Default::default() };
+        let (node, _events) = P2PNode::new(config).await.unwrap();
+        let mut chat_ui = ChatUI::with_size("alice".to_string(), None, 10, Theme::None, 80, 24);
+        let history = MessageHistory::new(10);
+        let mut mute_list = MuteList::new();
+        let mut notifier = Notifier::default();
+        let mut discovered = DiscoveredPeers::new();
+        let mut presence = PresenceStatus::Online;
+        let (connected_peers, peer_addresses, peer_presence) = (HashMap::new(), HashMap::new(), HashMap::new());
+        let mut ctx = CommandContext {
+            chat_ui: &mut chat_ui,
+            node: &node,
+            username: "alice",
+            history: &history,
+            mute_list: &mut mute_list,
+            notifier: &mut notifier,
+            connected_peers: &connected_peers,
+            peer_addresses: &peer_addresses,
+            peer_presence: &peer_presence,
+            discovered: &mut discovered,
+            presence: &mut presence,
+            is_owner: true,
+        };
+
+        // The client only shuts down and reports UserQuit when this returns false
+        assert!(!CommandHandler::handle_command("/quit", &mut ctx).await.unwrap());
+        assert!(!CommandHandler::handle_command("/exit", &mut ctx).await.unwrap());
+        assert!(CommandHandler::handle_command("/help", &mut ctx).await.unwrap());
+    }
+
+    #[test]
+    fn test_save_force_is_a_separate_final_word() {
+        assert_eq!(parse_save_args("chat.txt"), ("chat.txt", false));
+        assert_eq!(parse_save_args("chat.txt --force"), ("chat.txt", true));
+        assert_eq!(parse_save_args("my chat.txt  --force"), ("my chat.txt", true));
+        assert_eq!(parse_save_args("notes--force"), ("notes--force", false));
+        assert_eq!(parse_save_args("--force chat.txt"), ("--force chat.txt", false));
+        assert_eq!(parse_save_args("--force"), ("", true));
+    }
+}
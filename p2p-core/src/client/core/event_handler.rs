@@ -1,84 +1,227 @@
 //! Event handling for P2P chat client
 
-use crate::ui::{ChatUI, MessageType};
-use shared::P2PEvent;
+use crate::client::discovered::DiscoveredPeers;
+use crate::client::history::{find_mentions, MessageHistory};
+use crate::client::mute::MuteList;
+use crate::client::notify::Notifier;
+use crate::ui::{ChatUI, MessageType, ThemeRole};
+use shared::{ChatKind, P2PEvent, P2PMessage, PresenceStatus};
+use shared::config::PING_TIMEOUT_SECS;
+use shared::utils::sanitize_text;
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use tracing::{info, error};
-use colored::*;
+use std::time::Instant;
+use tracing::{debug, info, error};
+
+/// Status each direct peer last announced: peer_id -> (status, note)
+///
+/// Peers that never sent one, or came back online, have no entry.
+pub type PeerPresence = HashMap<String, (PresenceStatus, String)>;
 
 /// Handles P2P events for the chat client
 pub struct EventHandler;
 
 impl EventHandler {
+    /// Peer names for the header, marking those who are away or busy
+    pub fn peer_names(connected_peers: &HashMap<String, String>, peer_presence: &PeerPresence) -> Vec<String> {
+        connected_peers
+            .iter()
+            .map(|(peer_id, username)| match peer_presence.get(peer_id) {
+                Some((status, _)) => format!("{} ({})", username, status),
+                None => username.clone(),
+            })
+            .collect()
+    }
+
     /// Handle P2P events with beautiful display
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle_p2p_event(
         event: P2PEvent,
         chat_ui: &mut ChatUI,
         connected_peers: &mut HashMap<String, String>,
         peer_addresses: &mut HashMap<String, SocketAddr>,
+        peer_presence: &mut PeerPresence,
+        history: &MessageHistory,
+        mute_list: &mut MuteList,
+        notifier: &mut Notifier,
+        discovered: &mut DiscoveredPeers,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         match event {
             P2PEvent::PeerConnected { peer_id, addr, username: peer_username } => {
+                // Usernames come from the remote peer, never trust them as terminal output
+                let peer_username = sanitize_text(&peer_username);
+                
                 // Store peer info
                 connected_peers.insert(peer_id.clone(), peer_username.clone());
                 peer_addresses.insert(peer_id.clone(), addr);
                 
                 // Update UI
-                let peer_list: Vec<String> = connected_peers.values().cloned().collect();
-                chat_ui.update_connected_peers(peer_list)?;
+                chat_ui.update_connected_peers(Self::peer_names(connected_peers, peer_presence))?;
                 
                 // Add connection message
                 chat_ui.add_message(
                     "System".to_string(),
-                    format!("🔗 {} connected from {}", peer_username.bright_green(), addr),
+                    format!("🔗 {} connected from {}", chat_ui.theme().paint(&peer_username, ThemeRole::Connection), addr),
                     MessageType::ConnectionInfo,
                 )?;
                 
                 info!("Peer connected: {} ({})", peer_username, addr);
             }
             
+            P2PEvent::UsernameCollision { requested, assigned, .. } => {
+                chat_ui.add_message(
+                    "System".to_string(),
+                    format!(
+                        "⚠️  {} is already in use by another peer, showing the new one as {}",
+                        sanitize_text(&requested),
+                        sanitize_text(&assigned)
+                    ),
+                    MessageType::SystemMessage,
+                )?;
+            }
+            
             P2PEvent::PeerDisconnected { peer_id, reason } => {
+                let reason = sanitize_text(&reason);
+                
                 // Get username before removing
                 let peer_username = connected_peers.get(&peer_id).cloned().unwrap_or("Unknown".to_string());
                 
                 // Remove peer info
                 connected_peers.remove(&peer_id);
+                peer_presence.remove(&peer_id);
                 let addr = peer_addresses.remove(&peer_id);
                 
                 // Update UI
-                let peer_list: Vec<String> = connected_peers.values().cloned().collect();
-                chat_ui.update_connected_peers(peer_list)?;
+                chat_ui.update_connected_peers(Self::peer_names(connected_peers, peer_presence))?;
                 
                 // Add disconnection message
                 let addr_str = addr.map(|a| format!(" ({})", a)).unwrap_or_default();
                 chat_ui.add_message(
                     "System".to_string(),
-                    format!("🔌 {} disconnected: {}{}", peer_username.bright_red(), reason, addr_str),
+                    format!("🔌 {} disconnected: {}{}", chat_ui.theme().paint(&peer_username, ThemeRole::Error), reason, addr_str),
                     MessageType::ConnectionInfo,
                 )?;
                 
                 info!("Peer disconnected: {} ({})", peer_username, reason);
             }
             
-            P2PEvent::MessageReceived { message, from_peer: _ } => {
+            P2PEvent::MessageReceived { message: P2PMessage::Presence { status, message: note, .. }, from_peer, .. } => {
+                // Presence is never forwarded, so it always describes the sending connection
+                let Some(username) = connected_peers.get(&from_peer).cloned() else {
+                    return Ok(());
+                };
+                let note = sanitize_text(&note);
+
+                if status == PresenceStatus::Online {
+                    peer_presence.remove(&from_peer);
+                } else {
+                    peer_presence.insert(from_peer, (status, note.clone()));
+                }
+                chat_ui.update_connected_peers(Self::peer_names(connected_peers, peer_presence))?;
+
+                let note = if note.is_empty() { String::new() } else { format!(": {}", note) };
+                let text = match status {
+                    PresenceStatus::Online => format!("👋 {} is back{}", username, note),
+                    PresenceStatus::Away | PresenceStatus::Busy => format!("💤 {} is {}{}", username, status, note),
+                };
+                chat_ui.add_message("System".to_string(), text, MessageType::SystemMessage)?;
+            }
+            
+            P2PEvent::MessageReceived { message: P2PMessage::Typing { .. }, from_peer, .. } => {
+                // Only direct peers send typing notices; show them under their admitted name
+                if let Some(username) = connected_peers.get(&from_peer) {
+                    if !mute_list.is_username_muted(username) {
+                        chat_ui.peer_typing(username)?;
+                    }
+                }
+            }
+            
+            P2PEvent::MessageReceived { message, from_peer, security } => {
                 // Extract message content
-                if let shared::message::P2PMessage::ChatMessage { username, content, .. } = &message {
-                    // Add message to chat
-                    chat_ui.add_message(
+                if let P2PMessage::ChatMessage { sender_id, username, content, kind, .. } = &message {
+                    let message_type = match kind {
+                        ChatKind::Text => MessageType::UserMessage,
+                        ChatKind::Action => MessageType::ActionMessage,
+                    };
+                    
+                    // Strip escape sequences a peer could use to hijack the terminal,
+                    // and show direct peers under the name they were admitted with
+                    let username = connected_peers
+                        .get(sender_id)
+                        .cloned()
+                        .unwrap_or_else(|| sanitize_text(username));
+                    let content = sanitize_text(content);
+                    
+                    // Drop messages from muted peers before they reach the screen or history
+                    mute_list.note_sender(&username, sender_id);
+                    if mute_list.is_message_muted(sender_id, &from_peer, &username) {
+                        debug!("Dropped message from muted peer {}", username);
+                        return Ok(());
+                    }
+                    
+                    // Add message to chat, replacing any typing notice
+                    chat_ui.peer_stopped_typing(&username)?;
+                    chat_ui.add_received_message(
                         username.clone(),
                         content.clone(),
-                        MessageType::UserMessage,
+                        message_type,
+                        security,
                     )?;
                     
+                    // Muted peers never get this far, so they can't ring the bell
+                    let mentioned = !find_mentions(&content, &[chat_ui.username()]).is_empty();
+                    if notifier.should_alert(mentioned, Instant::now()) {
+                        if let Err(e) = notifier.alert(&username, &content) {
+                            debug!("Failed to alert for message from {}: {}", username, e);
+                        }
+                    }
+                    
+                    // Keep it in scrollback for /history
+                    history.add_message(match kind {
+                        ChatKind::Text => format!("{}: {}", username, content),
+                        ChatKind::Action => format!("* {} {}", username, content),
+                    });
+                    
                     info!("Message from {}: {}", username, content);
                 }
             }
             
+            P2PEvent::PingReply { peer_id, rtt_micros } => {
+                let name = connected_peers.get(&peer_id).cloned().unwrap_or(peer_id);
+                chat_ui.add_message(
+                    "System".to_string(),
+                    format!("📡 Reply from {}: {:.1} ms", name, rtt_micros as f64 / 1000.0),
+                    MessageType::SystemMessage,
+                )?;
+            }
+
+            P2PEvent::PingTimeout { peer_id } => {
+                let name = connected_peers.get(&peer_id).cloned().unwrap_or(peer_id);
+                chat_ui.add_message(
+                    "System".to_string(),
+                    format!("⏱️  No reply from {} within {}s", name, PING_TIMEOUT_SECS),
+                    MessageType::ErrorMessage,
+                )?;
+            }
+
             P2PEvent::PeersDiscovered { peers } => {
+                // Peers announce themselves repeatedly, only mention the ones we hadn't seen
+                let now = Instant::now();
+                let new_peers = peers.into_iter().filter(|addr| discovered.note(*addr, now)).count();
+                if new_peers > 0 {
+                    chat_ui.add_message(
+                        "System".to_string(),
+                        format!("🔍 Discovered {} new peers, /discovered to list them", new_peers),
+                        MessageType::SystemMessage,
+                    )?;
+                }
+            }
+
+            P2PEvent::CertificateRotated { fingerprint, expires_at } => {
+                info!("TLS certificate rotated: {} (expires at {})", fingerprint, expires_at);
                 chat_ui.add_message(
                     "System".to_string(),
-                    format!("🔍 Discovered {} new peers", peers.len()),
+                    format!("🔄 TLS certificate renewed, new fingerprint {}", fingerprint),
                     MessageType::SystemMessage,
                 )?;
             }
@@ -86,7 +229,7 @@ impl EventHandler {
             P2PEvent::TopologyChanged { connected_peers: topology_peers } => {
                 // Update peer list from topology change
                 let peer_names: Vec<String> = topology_peers.iter()
-                    .map(|p| p.username.clone())
+                    .map(|p| sanitize_text(&p.username))
                     .collect();
                 
                 chat_ui.update_connected_peers(peer_names)?;
@@ -99,8 +242,9 @@ impl EventHandler {
             }
             
             P2PEvent::Error { error, peer_id } => {
+                let error = sanitize_text(&error);
                 let error_msg = if let Some(pid) = peer_id {
-                    format!("Error from {}: {}", pid, error)
+                    format!("Error from {}: {}", sanitize_text(&pid), error)
                 } else {
                     format!("Error: {}", error)
                 };
@@ -5,7 +5,9 @@
 pub mod client;
 pub mod event_handler;
 pub mod command_handler;
+pub mod options;
 
 pub use client::{P2PChatClient, QuitReason};
-pub use event_handler::EventHandler;
-pub use command_handler::CommandHandler;
+pub use options::{ClientOptions, OutputMode, DEFAULT_HISTORY_SIZE, MIN_HISTORY_SIZE, MAX_HISTORY_SIZE};
+pub use event_handler::{EventHandler, PeerPresence};
+pub use command_handler::{CommandHandler, CommandContext};
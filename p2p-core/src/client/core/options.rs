@@ -0,0 +1,270 @@
+//! Optional settings for the P2P chat client
+
+use crate::client::notify::NotifyMode;
+use crate::ui::Theme;
+use shared::p2p::discovery::DEFAULT_MDNS_SERVICE;
+use shared::p2p::{DiscoveryMethod, RoomKey};
+use shared::config::{
+    DEFAULT_CERT_VALIDITY_SECS, DEFAULT_KNOWN_PEER_MAX_AGE_SECS, DEFAULT_MULTICAST_GROUP, DEFAULT_MULTICAST_PORT, DEFAULT_REORDER_WINDOW_MS,
+    HEARTBEAT_INTERVAL, MAX_CONNECTIONS,
+};
+use shared::crypto::DilithiumKeypair;
+use shared::P2PNodeConfig;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Default number of messages kept in scrollback
+pub const DEFAULT_HISTORY_SIZE: usize = 100;
+
+/// Smallest accepted `--history-size`
+pub const MIN_HISTORY_SIZE: usize = 10;
+
+/// Largest accepted `--history-size`
+///
+/// Both the chat area and `/history` keep this many messages, so at the
+/// maximum a long session holds a few tens of MB of chat text.
+pub const MAX_HISTORY_SIZE: usize = 100_000;
+
+/// How the client talks to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Full-screen terminal chat UI
+    #[default]
+    Tui,
+    /// JSON lines on stdin and stdout, see [`crate::headless`]
+    Json,
+}
+
+impl OutputMode {
+    /// Mode name as accepted by `--output`
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputMode::Tui => "tui",
+            OutputMode::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for OutputMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [OutputMode::Tui, OutputMode::Json]
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown output mode '{}' (expected tui or json)", s))
+    }
+}
+
+/// Client settings that are not required to start a chat
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Color theme for the chat UI
+    pub theme: Theme,
+    /// Number of messages kept for the chat area and `/history`
+    pub history_size: usize,
+    /// Identity fingerprint announced to peers in the handshake
+    pub fingerprint: Option<String>,
+    /// Identity public key, gives the node the same peer ID on every run
+    pub identity_public_key: Option<Vec<u8>>,
+    /// File of fingerprints refused by `/block`, defaults to `~/.dpq-chat/blocklist`
+    pub blocklist_path: Option<PathBuf>,
+    /// Only accept peers listed in this file (fingerprints, IPs or `ip:port`)
+    pub allowlist_path: Option<PathBuf>,
+    /// Remember peers in this file across restarts and dial them on start
+    pub known_peers_path: Option<PathBuf>,
+    /// Forget known peers not seen for this many seconds
+    pub known_peers_max_age_secs: u64,
+    /// Lifetime of our TLS certificate in seconds, renewed before it runs out
+    pub cert_validity_secs: u64,
+    /// Identity keypair to bind our TLS certificate to, turning on mutual TLS
+    pub tls_identity: Option<DilithiumKeypair>,
+    /// Skip the welcome screen and startup spinner
+    pub quiet: bool,
+    /// Terminal UI or JSON lines for scripts
+    pub output: OutputMode,
+    /// Mark ourselves away after this many seconds without input, 0 disables
+    pub auto_away_secs: u64,
+    /// Show when peers are typing
+    pub typing_indicators: bool,
+    /// Which incoming messages ring the bell or raise a notification
+    pub notify_mode: NotifyMode,
+    /// Ask the terminal for a desktop notification instead of ringing the bell
+    pub desktop_notifications: bool,
+    /// Announce ourselves and find peers with LAN multicast
+    pub multicast_discovery: bool,
+    /// Multicast group announcements are sent to, must be an IPv4 multicast address
+    pub multicast_group: Ipv4Addr,
+    /// UDP port announcements are sent to
+    pub multicast_port: u16,
+    /// Advertise and browse for peers as the `_dpqchat._tcp` mDNS service
+    pub mdns_discovery: bool,
+    /// File of `ip:port` lines to dial, re-read while running
+    pub peers_file: Option<PathBuf>,
+    /// Seconds between heartbeats sent to each peer
+    pub heartbeat_interval_secs: u64,
+    /// Seconds between sweeps for dead peers, 0 sweeps once per heartbeat
+    pub cleanup_interval_secs: u64,
+    /// Drop peers silent for this many seconds, 0 derives it from the heartbeat
+    pub dead_peer_timeout_secs: u64,
+    /// Most peers connected at once, inbound and outbound together
+    pub max_connections: usize,
+    /// Milliseconds to hold a message back for an earlier one from the same sender, 0 disables
+    pub reorder_window_ms: u64,
+    /// Only admit peers that know this room key
+    pub room_key: Option<RoomKey>,
+}
+
+impl ClientOptions {
+    /// Check a requested history size against the supported bounds
+    pub fn validate_history_size(size: usize) -> Result<usize, String> {
+        if (MIN_HISTORY_SIZE..=MAX_HISTORY_SIZE).contains(&size) {
+            Ok(size)
+        } else {
+            Err(format!(
+                "History size must be between {} and {} (got {})",
+                MIN_HISTORY_SIZE, MAX_HISTORY_SIZE, size
+            ))
+        }
+    }
+
+    /// Apply the identity, blocking and discovery settings to a node configuration
+    pub(crate) fn configure_node(&self, config: &mut P2PNodeConfig) {
+        config.fingerprint = self.fingerprint.clone();
+        config.identity_public_key = self.identity_public_key.clone();
+        config.blocklist_path = self.blocklist_path.clone();
+        config.allowlist_path = self.allowlist_path.clone();
+        config.known_peers_path = self.known_peers_path.clone();
+        config.known_peers_max_age_secs = self.known_peers_max_age_secs;
+        config.cert_validity_secs = self.cert_validity_secs;
+        config.tls_identity = self.tls_identity.clone();
+        config.heartbeat_interval_secs = self.heartbeat_interval_secs;
+        config.cleanup_interval_secs = self.cleanup_interval_secs;
+        config.dead_peer_timeout_secs = self.dead_peer_timeout_secs;
+        config.max_connections = self.max_connections;
+        config.reorder_window_ms = self.reorder_window_ms;
+        config.room_key = self.room_key.clone();
+
+        if !self.multicast_discovery {
+            config
+                .discovery_methods
+                .retain(|method| !matches!(method, DiscoveryMethod::Multicast { .. }));
+        }
+        for method in &mut config.discovery_methods {
+            if let DiscoveryMethod::Multicast { multicast_addr, .. } = method {
+                *multicast_addr = SocketAddr::from((self.multicast_group, self.multicast_port));
+            }
+        }
+        if self.mdns_discovery {
+            config.discovery_methods.push(DiscoveryMethod::Mdns {
+                service_name: DEFAULT_MDNS_SERVICE.to_string(),
+            });
+        }
+
+        if let Some(path) = &self.peers_file {
+            // Peers that leave the file may still dial us, so keep them connected
+            config.discovery_methods.push(DiscoveryMethod::StaticFile {
+                path: path.clone(),
+                disconnect_removed: false,
+            });
+        }
+    }
+}
+
+/// Block list next to the identity files
+fn default_blocklist_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".dpq-chat").join("blocklist"))
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            history_size: DEFAULT_HISTORY_SIZE,
+            fingerprint: None,
+            identity_public_key: None,
+            blocklist_path: default_blocklist_path(),
+            allowlist_path: None,
+            known_peers_path: None,
+            known_peers_max_age_secs: DEFAULT_KNOWN_PEER_MAX_AGE_SECS,
+            cert_validity_secs: DEFAULT_CERT_VALIDITY_SECS,
+            tls_identity: None,
+            quiet: false,
+            output: OutputMode::default(),
+            auto_away_secs: 0,
+            typing_indicators: true,
+            notify_mode: NotifyMode::default(),
+            desktop_notifications: false,
+            multicast_discovery: true,
+            multicast_group: DEFAULT_MULTICAST_GROUP,
+            multicast_port: DEFAULT_MULTICAST_PORT,
+            mdns_discovery: false,
+            peers_file: None,
+            heartbeat_interval_secs: HEARTBEAT_INTERVAL,
+            cleanup_interval_secs: 0,
+            dead_peer_timeout_secs: 0,
+            max_connections: MAX_CONNECTIONS,
+            reorder_window_ms: DEFAULT_REORDER_WINDOW_MS,
+            room_key: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_size_bounds() {
+        assert_eq!(ClientOptions::validate_history_size(MIN_HISTORY_SIZE), Ok(MIN_HISTORY_SIZE));
+        assert_eq!(ClientOptions::validate_history_size(MAX_HISTORY_SIZE), Ok(MAX_HISTORY_SIZE));
+        assert!(ClientOptions::validate_history_size(MIN_HISTORY_SIZE - 1).is_err());
+        assert!(ClientOptions::validate_history_size(MAX_HISTORY_SIZE + 1).is_err());
+    }
+
+    #[test]
+    fn test_multicast_can_be_disabled() {
+        let options = ClientOptions {
+            multicast_discovery: false,
+            blocklist_path: None,
+            ..ClientOptions::default()
+        };
+        let mut config = P2PNodeConfig::default();
+        options.configure_node(&mut config);
+        assert!(!config
+            .discovery_methods
+            .iter()
+            .any(|method| matches!(method, DiscoveryMethod::Multicast { .. })));
+    }
+
+    #[test]
+    fn test_multicast_group_and_port_are_applied() {
+        let options = ClientOptions {
+            multicast_group: Ipv4Addr::new(239, 1, 2, 3),
+            multicast_port: 9100,
+            blocklist_path: None,
+            ..ClientOptions::default()
+        };
+        let mut config = P2PNodeConfig::default();
+        options.configure_node(&mut config);
+        assert!(config.discovery_methods.iter().any(|method| matches!(
+            method,
+            DiscoveryMethod::Multicast { multicast_addr, .. } if *multicast_addr == "239.1.2.3:9100".parse().unwrap()
+        )));
+    }
+
+    #[test]
+    fn test_output_mode_parsing() {
+        assert_eq!("json".parse::<OutputMode>(), Ok(OutputMode::Json));
+        assert_eq!("TUI".parse::<OutputMode>(), Ok(OutputMode::Tui));
+        assert!("xml".parse::<OutputMode>().is_err());
+    }
+}
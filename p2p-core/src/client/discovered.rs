@@ -0,0 +1,131 @@
+/// Peers found by discovery for P2P chat client
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Most discovered peers kept, announcements are unauthenticated
+const MAX_DISCOVERED_PEERS: usize = 256;
+
+/// Discovered peer with when it was last announced
+#[derive(Debug, Clone)]
+pub struct DiscoveredPeer {
+    pub addr: SocketAddr,
+    pub last_seen: Instant,
+    /// Why the last `/connect` to it failed, cleared once one succeeds
+    pub last_error: Option<String>,
+}
+
+/// Peers discovery turned up this session, whether or not we connected
+///
+/// Kept in the order they were first found, so the numbers shown by
+/// `/discovered` stay the same while new peers are added. Once the list is
+/// full the peer announced least recently makes room for a new one.
+#[derive(Default)]
+pub struct DiscoveredPeers {
+    peers: Vec<DiscoveredPeer>,
+}
+
+impl DiscoveredPeers {
+    /// Create an empty list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that a peer was announced, returns true if it is new
+    pub fn note(&mut self, addr: SocketAddr, now: Instant) -> bool {
+        match self.peers.iter_mut().find(|peer| peer.addr == addr) {
+            Some(peer) => {
+                peer.last_seen = now;
+                false
+            }
+            None => {
+                if self.peers.len() >= MAX_DISCOVERED_PEERS {
+                    if let Some(stalest) = self.peers.iter().enumerate().min_by_key(|(_, peer)| peer.last_seen).map(|(i, _)| i) {
+                        self.peers.remove(stalest);
+                    }
+                }
+                self.peers.push(DiscoveredPeer { addr, last_seen: now, last_error: None });
+                true
+            }
+        }
+    }
+
+    /// Remember why connecting to a discovered peer failed
+    pub fn note_failure(&mut self, addr: SocketAddr, error: String) {
+        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.addr == addr) {
+            peer.last_error = Some(error);
+        }
+    }
+
+    /// Forget a failure once a connection worked
+    pub fn clear_failure(&mut self, addr: SocketAddr) {
+        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.addr == addr) {
+            peer.last_error = None;
+        }
+    }
+
+    /// Address of the peer shown as number `index` by `/discovered`, counting from 1
+    pub fn get(&self, index: usize) -> Option<SocketAddr> {
+        index.checked_sub(1).and_then(|i| self.peers.get(i)).map(|peer| peer.addr)
+    }
+
+    /// All discovered peers in the order they were found
+    pub fn iter(&self) -> impl Iterator<Item = &DiscoveredPeer> {
+        self.peers.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+/// Short "how long ago" text, e.g. "42s", "5m" or "3h"
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h", secs / 3600),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovered_peers_keep_their_numbers() {
+        let mut discovered = DiscoveredPeers::new();
+        let start = Instant::now();
+        let a: SocketAddr = "192.168.1.5:40000".parse().unwrap();
+        let b: SocketAddr = "192.168.1.6:40000".parse().unwrap();
+
+        assert!(discovered.note(a, start));
+        assert!(discovered.note(b, start));
+        // Announced again later: same number, newer last_seen
+        assert!(!discovered.note(a, start + Duration::from_secs(30)));
+
+        assert_eq!(discovered.get(1), Some(a));
+        assert_eq!(discovered.get(2), Some(b));
+        assert_eq!(discovered.get(0), None);
+        assert_eq!(discovered.get(3), None);
+        assert_eq!(discovered.iter().next().unwrap().last_seen, start + Duration::from_secs(30));
+
+        discovered.note_failure(b, "Connection refused".to_string());
+        assert_eq!(discovered.iter().nth(1).unwrap().last_error.as_deref(), Some("Connection refused"));
+        discovered.clear_failure(b);
+        assert!(discovered.iter().all(|peer| peer.last_error.is_none()));
+    }
+
+    #[test]
+    fn test_discovered_peers_are_capped() {
+        let mut discovered = DiscoveredPeers::new();
+        let start = Instant::now();
+        for port in 0..MAX_DISCOVERED_PEERS as u16 + 10 {
+            discovered.note(SocketAddr::from(([10, 0, 0, 1], port)), start + Duration::from_secs(port.into()));
+        }
+
+        assert_eq!(discovered.iter().count(), MAX_DISCOVERED_PEERS);
+        // The oldest announcements made room
+        assert_eq!(discovered.get(1), Some(SocketAddr::from(([10, 0, 0, 1], 10))));
+    }
+}
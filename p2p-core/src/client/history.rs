@@ -1,37 +1,320 @@
 /// Message history management for P2P chat client
+use shared::utils::sanitize_text;
 use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ops::Range;
 
 /// Message history manager
 pub struct MessageHistory {
-    messages: RefCell<Vec<String>>,
+    messages: RefCell<VecDeque<String>>,
     max_history: usize,
 }
 
 impl MessageHistory {
-    /// Create new message history manager
+    /// Create new message history manager, keeping at least one message
     pub fn new(max_history: usize) -> Self {
         Self {
-            messages: RefCell::new(Vec::new()),
-            max_history,
+            // Grow on demand so a large limit doesn't allocate up front
+            messages: RefCell::new(VecDeque::new()),
+            max_history: max_history.max(1),
         }
     }
 
     /// Add message to history
     pub fn add_message(&self, message: String) {
         let mut history = self.messages.borrow_mut();
-        history.push(message);
-        
+        history.push_back(message);
+
         // Keep only the last max_history messages
-        let len = history.len();
-        if len > self.max_history {
-            history.drain(0..len - self.max_history);
+        while history.len() > self.max_history {
+            history.pop_front();
         }
     }
 
+    /// Snapshot of the last `count` messages (all stored messages if `None`), oldest first
+    pub fn recent(&self, count: Option<usize>) -> Vec<String> {
+        let history = self.messages.borrow();
+        let count = count.unwrap_or(history.len()).min(history.len());
+        history.iter().skip(history.len() - count).cloned().collect()
+    }
+
+    /// All stored messages as plain text, oldest first, with escape sequences stripped
+    pub fn plain_text(&self) -> Vec<String> {
+        self.messages.borrow().iter().map(|message| sanitize_text(message)).collect()
+    }
+
+    /// Stored messages containing `term`, ignoring case, with their 1-based position
+    ///
+    /// Matching runs on the plain text, so escape sequences in a stored
+    /// message are neither matched nor returned.
+    pub fn search(&self, term: &str) -> Vec<(usize, String)> {
+        if term.is_empty() {
+            return Vec::new();
+        }
+
+        self.messages
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(index, message)| (index + 1, sanitize_text(message)))
+            .filter(|(_, message)| find_ignore_case(message, term).is_some())
+            .collect()
+    }
+
+    /// Distinct URLs in the stored messages, newest first, at most `limit` of them
+    pub fn recent_urls(&self, limit: usize) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        for message in self.messages.borrow().iter().rev() {
+            let message = sanitize_text(message);
+            // Within a message the later URL is the more recent one
+            for range in find_urls(&message).into_iter().rev() {
+                let url = &message[range];
+                if urls.len() < limit && !urls.iter().any(|seen| seen == url) {
+                    urls.push(url.to_string());
+                }
+            }
+        }
+        urls
+    }
 
     /// Get current message count
-    #[allow(dead_code)]
     pub fn message_count(&self) -> usize {
         self.messages.borrow().len()
     }
 }
+
+/// Byte range of the first case-insensitive occurrence of `term` in `text`
+pub fn find_ignore_case(text: &str, term: &str) -> Option<Range<usize>> {
+    if term.is_empty() {
+        return None;
+    }
+
+    text.char_indices()
+        .find_map(|(start, _)| match_len_at(&text[start..], term).map(|len| start..start + len))
+}
+
+/// Byte ranges of `@name` mentions of any of `names` in `text`, ignoring case
+///
+/// The `@` has to start a word and the name has to end one, so neither
+/// "bob@alice.org" nor "@alicia" mentions alice. When one name is a prefix of
+/// another the longer match wins.
+pub fn find_mentions(text: &str, names: &[&str]) -> Vec<Range<usize>> {
+    let mut mentions = Vec::new();
+    let mut previous = None;
+
+    for (start, ch) in text.char_indices() {
+        let starts_word = !previous.is_some_and(is_name_char);
+        previous = Some(ch);
+        if ch != '@' || !starts_word {
+            continue;
+        }
+
+        let rest = &text[start + 1..];
+        let longest = names
+            .iter()
+            .filter_map(|name| match_len_at(rest, name))
+            .filter(|&len| len > 0 && !rest[len..].chars().next().is_some_and(is_name_char))
+            .max();
+        if let Some(len) = longest {
+            mentions.push(start..start + 1 + len);
+        }
+    }
+    mentions
+}
+
+/// Characters allowed in usernames, which a mention can't be followed by
+fn is_name_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '-'
+}
+
+/// Byte ranges of the `http://` and `https://` URLs in `text`
+///
+/// A URL runs until whitespace. Trailing punctuation such as a full stop or
+/// an unmatched closing parenthesis is left out, so "(see https://x.org)."
+/// yields just the URL.
+pub fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let Some(offset) = ["http://", "https://"]
+            .iter()
+            .filter_map(|scheme| find_ignore_case(&text[pos..], scheme).map(|range| range.start))
+            .min()
+        else {
+            break;
+        };
+
+        let start = pos + offset;
+        let end = text[start..]
+            .find(char::is_whitespace)
+            .map_or(text.len(), |len| start + len);
+        let url = trim_url_end(&text[start..end]);
+
+        // Require something after the scheme
+        if !url.ends_with("//") {
+            urls.push(start..start + url.len());
+        }
+        pos = end;
+    }
+    urls
+}
+
+/// Whether `url` only uses characters RFC 3986 allows in a URL
+///
+/// Links come from peers, so anything else, such as `|`, `^`, quotes or
+/// control characters, is refused before the URL goes near an opener.
+pub fn is_valid_url(url: &str) -> bool {
+    !url.is_empty()
+        && url.chars().all(|ch| {
+            ch.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=%".contains(ch)
+        })
+}
+
+/// Strip punctuation that ends the sentence around a URL rather than the URL
+fn trim_url_end(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"', ']', '>']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(rest) if rest.matches('(').count() < trimmed.matches(')').count() => rest,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// Length in bytes of `text`'s prefix matching `term` ignoring case, if it matches
+fn match_len_at(text: &str, term: &str) -> Option<usize> {
+    let mut needle = term.chars().flat_map(char::to_lowercase).peekable();
+
+    for (index, ch) in text.char_indices() {
+        for lower in ch.to_lowercase() {
+            if needle.next() != Some(lower) {
+                return None;
+            }
+        }
+        if needle.peek().is_none() {
+            return Some(index + ch.len_utf8());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_returns_newest_messages_in_order() {
+        let history = MessageHistory::new(3);
+        for i in 0..5 {
+            history.add_message(format!("msg {}", i));
+        }
+
+        assert_eq!(history.recent(None), vec!["msg 2", "msg 3", "msg 4"]);
+        assert_eq!(history.recent(Some(2)), vec!["msg 3", "msg 4"]);
+        assert_eq!(history.recent(Some(10)).len(), 3);
+        assert!(history.recent(Some(0)).is_empty());
+    }
+
+    #[test]
+    fn test_eviction_with_extreme_sizes() {
+        let tiny = MessageHistory::new(0);
+        tiny.add_message("first".to_string());
+        tiny.add_message("second".to_string());
+        assert_eq!(tiny.recent(None), vec!["second"]);
+
+        let large = MessageHistory::new(100_000);
+        for i in 0..100_005 {
+            large.add_message(i.to_string());
+        }
+        assert_eq!(large.message_count(), 100_000);
+        assert_eq!(large.recent(Some(1)), vec!["100004"]);
+        assert_eq!(large.recent(None)[0], "5");
+    }
+
+    #[test]
+    fn test_search_ignores_case_and_escape_codes() {
+        let history = MessageHistory::new(10);
+        history.add_message("alice: Hello there".to_string());
+        history.add_message("bob: \x1b[31mHELLO\x1b[0m again".to_string());
+        history.add_message("carol: [31m is not a color".to_string());
+        history.add_message("dave: goodbye".to_string());
+
+        let matches = history.search("hello");
+        assert_eq!(matches, vec![
+            (1, "alice: Hello there".to_string()),
+            (2, "bob: HELLO again".to_string()),
+        ]);
+
+        // Only the plain text "[31m" in carol's message matches
+        assert_eq!(history.search("[31M").len(), 1);
+        assert!(history.search("missing").is_empty());
+        assert!(history.search("").is_empty());
+    }
+
+    #[test]
+    fn test_plain_text_strips_escape_codes() {
+        let history = MessageHistory::new(10);
+        history.add_message("alice: \x1b[1mbold\x1b[0m move".to_string());
+        history.add_message("bob: plain".to_string());
+
+        assert_eq!(history.plain_text(), vec!["alice: bold move", "bob: plain"]);
+    }
+
+    #[test]
+    fn test_find_urls_skips_surrounding_punctuation() {
+        let text = "docs at https://example.org/a?b=1. (mirror: HTTP://m.example.org/x_(y)) and http:// alone";
+        let urls: Vec<&str> = find_urls(text).into_iter().map(|range| &text[range]).collect();
+
+        assert_eq!(urls, vec!["https://example.org/a?b=1", "HTTP://m.example.org/x_(y)"]);
+        assert!(find_urls("no links here").is_empty());
+    }
+
+    #[test]
+    fn test_valid_url_allows_only_rfc3986_characters() {
+        assert!(is_valid_url("https://example.org/a?b=1&c=%20#top"));
+        assert!(!is_valid_url("https://example.org/a|calc"));
+        assert!(!is_valid_url("https://example.org/^x"));
+        assert!(!is_valid_url("https://example.org/\"x"));
+        assert!(!is_valid_url("https://exämple.org"));
+        assert!(!is_valid_url(""));
+    }
+
+    #[test]
+    fn test_find_mentions_needs_word_boundaries() {
+        let text = "@Alice look, mail bob@alice.org or ask @alice-b and @alicia, thanks @ALICE.";
+        let found: Vec<&str> = find_mentions(text, &["alice"]).into_iter().map(|range| &text[range]).collect();
+        assert_eq!(found, vec!["@Alice", "@ALICE"]);
+
+        let found: Vec<&str> = find_mentions(text, &["alice", "alice-b"]).into_iter().map(|range| &text[range]).collect();
+        assert_eq!(found, vec!["@Alice", "@alice-b", "@ALICE"]);
+        assert!(find_mentions("just @", &["alice"]).is_empty());
+    }
+
+    #[test]
+    fn test_recent_urls_newest_first_without_duplicates() {
+        let history = MessageHistory::new(10);
+        history.add_message("alice: see https://a.example and https://b.example".to_string());
+        history.add_message("bob: \x1b[1mhttps://c.example\x1b[0m".to_string());
+        history.add_message("carol: again https://a.example".to_string());
+
+        assert_eq!(
+            history.recent_urls(10),
+            vec!["https://a.example", "https://c.example", "https://b.example"]
+        );
+        assert_eq!(history.recent_urls(1), vec!["https://a.example"]);
+    }
+
+    #[test]
+    fn test_find_ignore_case_ranges() {
+        assert_eq!(find_ignore_case("Say HeLLo", "hello"), Some(4..9));
+        assert_eq!(find_ignore_case("ÄPFEL und äpfel", "äpfel"), Some(0..6));
+        assert_eq!(find_ignore_case("abc", "abcd"), None);
+        assert_eq!(find_ignore_case("abc", ""), None);
+    }
+}
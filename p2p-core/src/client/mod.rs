@@ -3,5 +3,8 @@
 //! Contains the P2P chat client implementation and related utilities.
 
 pub mod constants;
+pub mod discovered;
 pub mod history;
+pub mod mute;
+pub mod notify;
 pub mod core;
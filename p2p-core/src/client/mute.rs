@@ -0,0 +1,100 @@
+/// Session mute list for P2P chat client
+use std::collections::HashMap;
+
+/// Peers whose chat messages are hidden for this session
+///
+/// Connections are identified by a per-connection peer ID, while chat
+/// messages carry the sender's node ID, so both kinds of ID are recorded
+/// against the muted username.
+#[derive(Default)]
+pub struct MuteList {
+    muted: HashMap<String, String>,   // peer_id -> username
+    senders: HashMap<String, String>, // username -> last seen sender_id
+}
+
+impl MuteList {
+    /// Create an empty mute list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember which sender ID a username last used
+    pub fn note_sender(&mut self, username: &str, sender_id: &str) {
+        self.senders.insert(username.to_string(), sender_id.to_string());
+
+        // Keep following a muted user if their node ID changes
+        if self.is_username_muted(username) {
+            self.muted.insert(sender_id.to_string(), username.to_string());
+        }
+    }
+
+    /// Mute a username, given the connection IDs currently using it
+    ///
+    /// Returns false if the username was not known at all
+    pub fn mute<'a>(&mut self, username: &str, connection_ids: impl IntoIterator<Item = &'a String>) -> bool {
+        let mut ids: Vec<String> = connection_ids.into_iter().cloned().collect();
+        if let Some(sender_id) = self.senders.get(username) {
+            ids.push(sender_id.clone());
+        }
+
+        for id in &ids {
+            self.muted.insert(id.clone(), username.to_string());
+        }
+        !ids.is_empty()
+    }
+
+    /// Unmute a username, returns false if it was not muted
+    pub fn unmute(&mut self, username: &str) -> bool {
+        let before = self.muted.len();
+        self.muted.retain(|_, name| name != username);
+        self.muted.len() != before
+    }
+
+    /// Whether a message from this sender, delivered over this connection, should be hidden
+    pub fn is_message_muted(&self, sender_id: &str, from_peer: &str, username: &str) -> bool {
+        if self.muted.contains_key(sender_id) {
+            return true;
+        }
+
+        // A muted connection may also forward messages from other peers
+        self.muted.get(from_peer).is_some_and(|name| name == username)
+    }
+
+    /// Whether any ID for this username is muted
+    pub fn is_username_muted(&self, username: &str) -> bool {
+        self.muted.values().any(|name| name == username)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mute_by_connection_and_sender_id() {
+        let mut mutes = MuteList::new();
+        mutes.note_sender("mallory", "node-m");
+
+        let connections = vec!["conn-m".to_string()];
+        assert!(mutes.mute("mallory", &connections));
+        assert!(mutes.is_username_muted("mallory"));
+
+        // Sent by mallory directly or relayed by another peer
+        assert!(mutes.is_message_muted("node-m", "conn-m", "mallory"));
+        assert!(mutes.is_message_muted("node-m", "conn-b", "mallory"));
+
+        // Bob's message relayed through mallory's connection is still shown
+        assert!(!mutes.is_message_muted("node-b", "conn-m", "bob"));
+
+        assert!(mutes.unmute("mallory"));
+        assert!(!mutes.is_message_muted("node-m", "conn-m", "mallory"));
+        assert!(!mutes.unmute("mallory"));
+    }
+
+    #[test]
+    fn test_mute_unknown_username() {
+        let mut mutes = MuteList::new();
+        assert!(!mutes.mute("nobody", &Vec::new()));
+        assert!(!mutes.is_username_muted("nobody"));
+    }
+}
@@ -0,0 +1,154 @@
+/// Alerts on incoming messages for P2P chat client
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Shortest gap between two alerts, so a burst of messages alerts once
+pub const NOTIFY_DEBOUNCE: Duration = Duration::from_secs(3);
+
+/// Longest message preview put in a desktop notification, in characters
+const NOTIFICATION_PREVIEW_CHARS: usize = 200;
+
+/// Which incoming messages alert the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotifyMode {
+    /// Every chat message from a peer
+    All,
+    /// Only messages that mention us with `@username`
+    Mentions,
+    /// Never
+    #[default]
+    Off,
+}
+
+impl NotifyMode {
+    /// Every mode, in the order they are listed in help texts
+    pub const ALL: [NotifyMode; 3] = [NotifyMode::All, NotifyMode::Mentions, NotifyMode::Off];
+
+    /// Mode name as accepted by `--notify` and `/notify`
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotifyMode::All => "all",
+            NotifyMode::Mentions => "mentions",
+            NotifyMode::Off => "off",
+        }
+    }
+}
+
+impl fmt::Display for NotifyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for NotifyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NotifyMode::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown notify mode '{}' (expected all, mentions or off)", s))
+    }
+}
+
+/// Rings the terminal bell, or asks for a desktop notification, on incoming messages
+#[derive(Debug, Default)]
+pub struct Notifier {
+    mode: NotifyMode,
+    desktop: bool,
+    last_alert: Option<Instant>,
+}
+
+impl Notifier {
+    /// Create a notifier, `desktop` picks a desktop notification over the bell
+    pub fn new(mode: NotifyMode, desktop: bool) -> Self {
+        Self { mode, desktop, last_alert: None }
+    }
+
+    /// Current mode
+    pub fn mode(&self) -> NotifyMode {
+        self.mode
+    }
+
+    /// Change the mode, e.g. from `/notify`
+    pub fn set_mode(&mut self, mode: NotifyMode) {
+        self.mode = mode;
+    }
+
+    /// Whether a peer's message should alert, remembering the alert if so
+    ///
+    /// Messages within [`NOTIFY_DEBOUNCE`] of the last alert don't alert again.
+    pub fn should_alert(&mut self, mentioned: bool, now: Instant) -> bool {
+        let wanted = match self.mode {
+            NotifyMode::All => true,
+            NotifyMode::Mentions => mentioned,
+            NotifyMode::Off => false,
+        };
+        if !wanted || self.last_alert.is_some_and(|last| now.duration_since(last) < NOTIFY_DEBOUNCE) {
+            return false;
+        }
+        self.last_alert = Some(now);
+        true
+    }
+
+    /// Alert the user about a message from `sender`
+    pub fn alert(&self, sender: &str, content: &str) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        if self.desktop {
+            stdout.write_all(desktop_notification(sender, content).as_bytes())?;
+        } else {
+            stdout.write_all(b"\x07")?;
+        }
+        stdout.flush()
+    }
+}
+
+/// OSC 9 sequence asking the terminal for a desktop notification
+///
+/// Understood by iTerm2, WezTerm and ConEmu, terminals without support
+/// ignore it. Control characters are dropped so the text can't end the
+/// sequence early.
+fn desktop_notification(sender: &str, content: &str) -> String {
+    let text: String = format!("{}: {}", sender, content)
+        .chars()
+        .filter(|ch| !ch.is_control())
+        .take(NOTIFICATION_PREVIEW_CHARS)
+        .collect();
+    format!("\x1b]9;{}\x07", text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alerts_follow_mode_and_debounce() {
+        let start = Instant::now();
+        let mut notifier = Notifier::new(NotifyMode::All, false);
+        assert!(notifier.should_alert(false, start));
+        // A burst right after the first message stays quiet
+        assert!(!notifier.should_alert(false, start + Duration::from_secs(1)));
+        assert!(notifier.should_alert(false, start + NOTIFY_DEBOUNCE));
+
+        let mut notifier = Notifier::new(NotifyMode::Mentions, false);
+        assert!(!notifier.should_alert(false, start));
+        assert!(notifier.should_alert(true, start));
+
+        notifier.set_mode(NotifyMode::Off);
+        assert!(!notifier.should_alert(true, start + NOTIFY_DEBOUNCE));
+    }
+
+    #[test]
+    fn test_notify_mode_parses_names() {
+        assert_eq!("Mentions".parse::<NotifyMode>(), Ok(NotifyMode::Mentions));
+        assert!("loud".parse::<NotifyMode>().is_err());
+        assert_eq!(NotifyMode::default(), NotifyMode::Off);
+    }
+
+    #[test]
+    fn test_desktop_notification_drops_control_characters() {
+        assert_eq!(desktop_notification("bob", "hi\x07\x1b]9;x"), "\x1b]9;bob: hi]9;x\x07");
+    }
+}
@@ -0,0 +1,118 @@
+//! Error types for P2P chat client
+
+use shared::p2p::{NodeConfigError, NodeStartError};
+use thiserror::Error;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Error, Debug)]
+pub enum P2PError {
+    #[error("TLS certificate generation failed: {0}")]
+    CertGeneration(#[source] BoxError),
+
+    #[error("Failed to bind {addr}: {source}")]
+    Bind {
+        addr: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Failed to load {path}: {source}")]
+    Config {
+        path: String,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error("Peer discovery failed: {0}")]
+    Discovery(#[source] BoxError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl P2PError {
+    /// Classify an error returned by `P2PNode::new`
+    pub(crate) fn from_node_new(err: BoxError) -> Self {
+        let err = match err.downcast::<NodeConfigError>() {
+            Ok(config_error) => {
+                let NodeConfigError { path, source } = *config_error;
+                return P2PError::Config {
+                    path: path.display().to_string(),
+                    source,
+                };
+            }
+            Err(err) => err,
+        };
+        match err.downcast::<std::io::Error>() {
+            Ok(io_error) => P2PError::Io(*io_error),
+            Err(err) => P2PError::CertGeneration(err),
+        }
+    }
+
+    /// Classify an error returned by `P2PNode::start`
+    pub(crate) fn from_node_start(err: BoxError) -> Self {
+        match err.downcast::<NodeStartError>() {
+            Ok(start_error) => match *start_error {
+                NodeStartError::Bind { addr, source } => P2PError::Bind {
+                    addr: addr.to_string(),
+                    source,
+                },
+                NodeStartError::Discovery(source) => P2PError::Discovery(source),
+            },
+            Err(err) => Self::from_io(err),
+        }
+    }
+
+    /// Wrap an error from terminal or socket I/O, keeping the original `io::Error` when there is one
+    pub(crate) fn from_io(err: BoxError) -> Self {
+        match err.downcast::<std::io::Error>() {
+            Ok(io_error) => P2PError::Io(*io_error),
+            Err(err) => P2PError::Io(std::io::Error::other(err)),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, P2PError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_start_errors_keep_their_stage() {
+        let bind: BoxError = Box::new(NodeStartError::Bind {
+            addr: "127.0.0.1:9000".parse().unwrap(),
+            source: Box::new(std::io::Error::from(std::io::ErrorKind::AddrInUse)),
+        });
+        assert!(matches!(
+            P2PError::from_node_start(bind),
+            P2PError::Bind { ref addr, .. } if addr == "127.0.0.1:9000"
+        ));
+
+        let discovery: BoxError = Box::new(NodeStartError::Discovery("no multicast".into()));
+        assert!(matches!(P2PError::from_node_start(discovery), P2PError::Discovery(_)));
+    }
+
+    #[test]
+    fn test_list_load_failures_are_not_cert_errors() {
+        let load: BoxError = Box::new(NodeConfigError {
+            path: "/etc/dpq/allowlist".into(),
+            source: Box::new(std::io::Error::from(std::io::ErrorKind::PermissionDenied)),
+        });
+        assert!(matches!(
+            P2PError::from_node_new(load),
+            P2PError::Config { ref path, .. } if path == "/etc/dpq/allowlist"
+        ));
+        assert!(matches!(P2PError::from_node_new("bad key".into()), P2PError::CertGeneration(_)));
+    }
+
+    #[test]
+    fn test_io_errors_are_not_flattened() {
+        let err: BoxError = Box::new(std::io::Error::from(std::io::ErrorKind::Interrupted));
+        match P2PError::from_io(err) {
+            P2PError::Io(io_error) => assert_eq!(io_error.kind(), std::io::ErrorKind::Interrupted),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
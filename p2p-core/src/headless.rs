@@ -0,0 +1,175 @@
+//! Headless JSON-lines front-end for scripts and bots
+//!
+//! Selected with `--output json`. Instead of the terminal UI, commands are
+//! read from stdin as one JSON object per line:
+//!
+//! ```text
+//! {"cmd":"send","content":"hello"}
+//! {"cmd":"action","content":"waves"}
+//! {"cmd":"connect","addr":"192.168.1.20:40000"}
+//! {"cmd":"disconnect","peer_id":"<peer id from a PeerConnected event>"}
+//! {"cmd":"typing"}
+//! {"cmd":"peers"}
+//! {"cmd":"quit"}
+//! ```
+//!
+//! Every [`P2PEvent`] is written to stdout as one JSON object per line.
+//! Failed or malformed commands are reported as `P2PEvent::Error` and `peers`
+//! is answered with `P2PEvent::TopologyChanged`, so stdout only ever carries
+//! events. `typing` tells peers we are composing a message and may be sent
+//! on every keystroke; it goes out at most once per
+//! [`TYPING_NOTIFY_INTERVAL`](crate::ui::TYPING_NOTIFY_INTERVAL). The session
+//! ends on `quit` or when stdin is closed.
+
+use crate::error::Result;
+use crate::session::P2PSession;
+use crate::ui::TypingThrottle;
+use serde::Deserialize;
+use shared::P2PEvent;
+use std::io::Write;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// A command read from stdin
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum HeadlessCommand {
+    /// Send a chat message to all peers
+    Send { content: String },
+    /// Send a `/me` action to all peers
+    Action { content: String },
+    /// Dial a peer
+    Connect { addr: SocketAddr },
+    /// Drop the connection to a peer
+    Disconnect { peer_id: String },
+    /// Tell peers we are typing, throttled
+    Typing,
+    /// List connected peers
+    Peers,
+    /// Leave the chat
+    Quit,
+}
+
+/// Run the session until `quit` or end of input, then stop the node
+pub async fn run(mut session: P2PSession) -> Result<()> {
+    let mut events = session
+        .take_events()
+        .ok_or_else(|| std::io::Error::other("P2P session has no event stream"))?;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut typing = TypingThrottle::default();
+
+    let result = loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(event) => {
+                    if let Err(e) = write_event(&event) {
+                        break Err(e);
+                    }
+                }
+                None => break Ok(()),
+            },
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => match handle_line(&session, &mut typing, &line).await {
+                    Ok(true) => {}
+                    Ok(false) => break Ok(()),
+                    Err(e) => break Err(e),
+                },
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e.into()),
+            },
+        }
+    };
+
+    session.stop().await;
+    result
+}
+
+/// Handle one input line, returns false once asked to quit
+async fn handle_line(session: &P2PSession, typing: &mut TypingThrottle, line: &str) -> Result<bool> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(true);
+    }
+
+    let command = match serde_json::from_str::<HeadlessCommand>(line) {
+        Ok(command) => command,
+        Err(e) => {
+            write_error(format!("Invalid command: {}", e))?;
+            return Ok(true);
+        }
+    };
+
+    let node = session.node();
+    let outcome = match command {
+        HeadlessCommand::Send { content } => {
+            typing.reset();
+            node.send_chat_message(content).await
+        }
+        HeadlessCommand::Action { content } => {
+            typing.reset();
+            node.send_action_message(content).await
+        }
+        HeadlessCommand::Typing => {
+            if typing.should_notify(std::time::Instant::now()) {
+                node.send_typing().await;
+            }
+            Ok(())
+        }
+        HeadlessCommand::Connect { addr } => node.connect_to(addr).await.map(|_| ()),
+        HeadlessCommand::Disconnect { peer_id } => {
+            if node.disconnect_peer(&peer_id).await {
+                Ok(())
+            } else {
+                Err(format!("Not connected to {}", peer_id).into())
+            }
+        }
+        HeadlessCommand::Peers => {
+            write_event(&P2PEvent::TopologyChanged {
+                connected_peers: node.get_connected_peers().await,
+            })?;
+            Ok(())
+        }
+        HeadlessCommand::Quit => return Ok(false),
+    };
+
+    if let Err(e) = outcome {
+        write_error(e.to_string())?;
+    }
+    Ok(true)
+}
+
+/// Report a failed command as an error event
+fn write_error(error: String) -> Result<()> {
+    write_event(&P2PEvent::Error { error, peer_id: None })
+}
+
+/// Write one event as a JSON line and flush it right away
+fn write_event(event: &P2PEvent) -> Result<()> {
+    let line = serde_json::to_string(event).map_err(std::io::Error::other)?;
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_parse_from_json_lines() {
+        assert_eq!(
+            serde_json::from_str::<HeadlessCommand>(r#"{"cmd":"send","content":"hi"}"#).unwrap(),
+            HeadlessCommand::Send { content: "hi".to_string() }
+        );
+        assert_eq!(
+            serde_json::from_str::<HeadlessCommand>(r#"{"cmd":"connect","addr":"127.0.0.1:40000"}"#).unwrap(),
+            HeadlessCommand::Connect { addr: "127.0.0.1:40000".parse().unwrap() }
+        );
+        assert_eq!(serde_json::from_str::<HeadlessCommand>(r#"{"cmd":"typing"}"#).unwrap(), HeadlessCommand::Typing);
+        assert_eq!(serde_json::from_str::<HeadlessCommand>(r#"{"cmd":"quit"}"#).unwrap(), HeadlessCommand::Quit);
+
+        assert!(serde_json::from_str::<HeadlessCommand>(r#"{"cmd":"whisper","content":"hi"}"#).is_err());
+        assert!(serde_json::from_str::<HeadlessCommand>(r#"{"cmd":"connect","addr":"nowhere"}"#).is_err());
+    }
+}
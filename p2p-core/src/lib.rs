@@ -1,11 +1,19 @@
 //! P2P Core Library
 //! 
 //! Provides P2P chat functionality as a library that can be used by other components.
+//! Use [`P2PSession`] to embed the networking core without the terminal UI.
 
 pub mod client;
+pub mod error;
+pub mod headless;
+pub mod session;
 pub mod ui;
 
-pub use client::core::{P2PChatClient, QuitReason};
+pub use client::core::{P2PChatClient, QuitReason, ClientOptions, OutputMode, DEFAULT_HISTORY_SIZE, MIN_HISTORY_SIZE, MAX_HISTORY_SIZE};
+pub use client::notify::NotifyMode;
+pub use error::P2PError;
+pub use session::P2PSession;
+pub use ui::Theme;
 
 use std::net::SocketAddr;
 
@@ -16,25 +24,50 @@ pub async fn run_p2p_chat(
     listen_port: Option<u16>,
     bootstrap_peers: Vec<SocketAddr>,
     enable_tls: bool,
-) -> Result<QuitReason, Box<dyn std::error::Error + Send + Sync>> {
-    let mut client = P2PChatClient::new(username, listen_host, listen_port, bootstrap_peers, enable_tls).await?;
+) -> Result<QuitReason, P2PError> {
+    run_p2p_chat_with_options(username, listen_host, listen_port, bootstrap_peers, enable_tls, ClientOptions::default()).await
+}
+
+/// Create and run a P2P chat client with custom options
+pub async fn run_p2p_chat_with_options(
+    username: String,
+    listen_host: Option<String>,
+    listen_port: Option<u16>,
+    bootstrap_peers: Vec<SocketAddr>,
+    enable_tls: bool,
+    options: ClientOptions,
+) -> Result<QuitReason, P2PError> {
+    if options.output == OutputMode::Json {
+        return run_headless_chat(username, listen_host, listen_port, bootstrap_peers, enable_tls, options).await;
+    }
+
+    let mut client = P2PChatClient::new_with_options(username, listen_host, listen_port, bootstrap_peers, enable_tls, options).await?;
     
-    // Run the client and get the result
+    // Run the client, the caller decides how to report a failure
     let result = client.start().await;
-    let quit_reason = client.get_quit_reason();
-    
-    // Handle any errors but don't propagate them to avoid terminal issues
-    if let Err(e) = result {
-        // Only log IO errors, don't print to stderr to avoid terminal corruption
-        if e.to_string().contains("read interrupted") || e.to_string().contains("IO error") {
-            // Silently ignore IO interruption errors during shutdown
-        } else {
-            eprintln!("Chat client error: {}", e);
-        }
-    }
     
     // Give a moment for final cleanup
     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     
-    Ok(quit_reason)
+    result.map(|_| client.get_quit_reason())
+}
+
+/// Run a chat session without the terminal UI, speaking JSON lines on stdin/stdout
+///
+/// See [`headless`] for the command and event format.
+pub async fn run_headless_chat(
+    username: String,
+    listen_host: Option<String>,
+    listen_port: Option<u16>,
+    bootstrap_peers: Vec<SocketAddr>,
+    enable_tls: bool,
+    options: ClientOptions,
+) -> Result<QuitReason, P2PError> {
+    let listen_addr = client::core::client::resolve_listen_addr(listen_host, listen_port)?;
+
+    let mut config = P2PSession::default_config(&username, listen_addr, bootstrap_peers, enable_tls)?;
+    options.configure_node(&mut config);
+
+    headless::run(P2PSession::start(config).await?).await?;
+    Ok(QuitReason::UserQuit)
 }
@@ -3,13 +3,12 @@
 //! Pure P2P chat functionality without CLI interface.
 //! This binary is called by the launcher/CLI when P2P chat is needed.
 
-mod client;
-mod ui;
 mod cli;
 
-use client::core::P2PChatClient;
-use client::constants::force_cleanup_terminal;
+use p2p_core::{ClientOptions, OutputMode, P2PChatClient};
+use p2p_core::client::constants::force_cleanup_terminal;
 use shared::config::DEFAULT_LOG_LEVEL;
+use shared::utils::resolve_peer_addrs;
 use std::env;
 
 #[tokio::main]
@@ -42,12 +41,55 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     match cli::parse_args(&args)? {
         Some(parsed_args) => {
             // Create and start P2P client
-            let mut client = P2PChatClient::new(
+            let options = ClientOptions {
+                theme: parsed_args.theme,
+                history_size: parsed_args.history_size,
+                quiet: parsed_args.quiet,
+                output: parsed_args.output,
+                auto_away_secs: parsed_args.auto_away_secs,
+                typing_indicators: parsed_args.typing_indicators,
+                notify_mode: parsed_args.notify_mode,
+                desktop_notifications: parsed_args.desktop_notifications,
+                multicast_discovery: parsed_args.multicast_discovery,
+                multicast_group: parsed_args.multicast_group,
+                multicast_port: parsed_args.multicast_port,
+                mdns_discovery: parsed_args.mdns_discovery,
+                peers_file: parsed_args.peers_file,
+                heartbeat_interval_secs: parsed_args.heartbeat_interval_secs,
+                cleanup_interval_secs: parsed_args.cleanup_interval_secs,
+                dead_peer_timeout_secs: parsed_args.dead_peer_timeout_secs,
+                max_connections: parsed_args.max_connections,
+                allowlist_path: parsed_args.allowlist_path,
+                reorder_window_ms: parsed_args.reorder_window_ms,
+                known_peers_path: parsed_args.known_peers_path,
+                known_peers_max_age_secs: parsed_args.known_peers_max_age_secs,
+                cert_validity_secs: parsed_args.cert_validity_secs,
+                room_key: parsed_args.room_key,
+                ..ClientOptions::default()
+            };
+
+            // Host names are looked up now, right before connecting
+            let bootstrap_peers = resolve_peer_addrs(&parsed_args.bootstrap_peers).await.map_err(|e| e.to_string())?;
+
+            if options.output == OutputMode::Json {
+                p2p_core::run_headless_chat(
+                    parsed_args.username,
+                    Some(parsed_args.final_host),
+                    Some(parsed_args.final_port),
+                    bootstrap_peers,
+                    parsed_args.enable_tls,
+                    options,
+                ).await.map_err(|e| format!("P2P session failed: {}", e))?;
+                return Ok(());
+            }
+            
+            let mut client = P2PChatClient::new_with_options(
                 parsed_args.username,
                 Some(parsed_args.final_host),
                 Some(parsed_args.final_port),
-                parsed_args.bootstrap_peers,
+                bootstrap_peers,
                 parsed_args.enable_tls,
+                options,
             ).await.map_err(|e| format!("Failed to create P2P client: {}", e))?;
             
             client.start().await
@@ -0,0 +1,129 @@
+//! Headless P2P session
+//!
+//! `P2PSession` owns a running `P2PNode` and its event stream without touching
+//! stdin or the terminal, so the networking core can be embedded in other
+//! front-ends. The terminal client in [`crate::client`] is built on top of it.
+//!
+//! A GUI front-end typically moves the event receiver into its own task and
+//! forwards events to the UI thread, while sending messages through the session:
+//!
+//! ```no_run
+//! use p2p_core::P2PSession;
+//! use shared::P2PEvent;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let mut session = P2PSession::start(
+//!     P2PSession::default_config("alice", "127.0.0.1:0".parse()?, vec![], true)?,
+//! ).await?;
+//!
+//! let mut events = session.take_events().expect("events are only taken once");
+//! tokio::spawn(async move {
+//!     while let Some(event) = events.recv().await {
+//!         match event {
+//!             P2PEvent::MessageReceived { message, .. } => println!("{}", message),
+//!             P2PEvent::PeerConnected { username, .. } => println!("{} joined", username),
+//!             other => println!("{:?}", other),
+//!         }
+//!     }
+//! });
+//!
+//! session.send_chat_message("hello from my GUI".to_string()).await?;
+//! session.stop().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::P2PError;
+use shared::config::MAX_CONNECTIONS;
+use shared::p2p::discovery::{DiscoveryMethod, DEFAULT_MULTICAST_ADDR};
+use shared::{P2PEvent, P2PNode, P2PNodeConfig, P2PStats, PeerInfo};
+use std::net::SocketAddr;
+use tokio::sync::mpsc;
+
+/// A running P2P node and its event stream, without any terminal I/O
+pub struct P2PSession {
+    node: P2PNode,
+    events: Option<mpsc::Receiver<P2PEvent>>,
+}
+
+impl P2PSession {
+    /// Node configuration used by the chat client
+    pub fn default_config(
+        username: &str,
+        listen_addr: SocketAddr,
+        bootstrap_peers: Vec<SocketAddr>,
+        enable_tls: bool,
+    ) -> Result<P2PNodeConfig, P2PError> {
+        Ok(P2PNodeConfig {
+            username: username.to_string(),
+            listen_addr,
+            enable_tls,
+            discovery_methods: vec![
+                DiscoveryMethod::Multicast {
+                    multicast_addr: DEFAULT_MULTICAST_ADDR,
+                    interface: None,
+                },
+            ],
+            bootstrap_peers,
+            connection_timeout_secs: 30,
+            max_connections: MAX_CONNECTIONS,
+            // Identity, blocking and routing settings keep their defaults
+            ..P2PNodeConfig::default()
+        })
+    }
+
+    /// Create and start a node with the given configuration
+    ///
+    /// A block list or allowlist that can't be used is reported as
+    /// [`P2PError::Config`], other node creation failures come from setting
+    /// up TLS and are reported as [`P2PError::CertGeneration`].
+    pub async fn start(config: P2PNodeConfig) -> Result<Self, P2PError> {
+        let (mut node, event_rx) = P2PNode::new(config).await.map_err(P2PError::from_node_new)?;
+        node.start().await.map_err(P2PError::from_node_start)?;
+
+        Ok(Self {
+            node,
+            events: Some(event_rx),
+        })
+    }
+
+    /// Take the event receiver, returns `None` if it was already taken
+    pub fn take_events(&mut self) -> Option<mpsc::Receiver<P2PEvent>> {
+        self.events.take()
+    }
+
+    /// Split the session into the node and its event receiver
+    pub fn into_parts(self) -> (P2PNode, Option<mpsc::Receiver<P2PEvent>>) {
+        (self.node, self.events)
+    }
+
+    /// Underlying node
+    pub fn node(&self) -> &P2PNode {
+        &self.node
+    }
+
+    /// Send a chat message to all peers
+    pub async fn send_chat_message(&self, content: String) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.node.send_chat_message(content).await
+    }
+
+    /// Currently connected peers
+    pub async fn connected_peers(&self) -> Vec<PeerInfo> {
+        self.node.get_connected_peers().await
+    }
+
+    /// Current network statistics
+    pub async fn stats(&self) -> P2PStats {
+        self.node.get_stats().await
+    }
+
+    /// Address the node is listening on
+    pub async fn listen_addr(&self) -> SocketAddr {
+        self.node.listen_addr().await
+    }
+
+    /// Disconnect from all peers and stop the node
+    pub async fn stop(&mut self) {
+        self.node.stop().await;
+    }
+}
@@ -2,74 +2,141 @@
 
 use std::io::{self, Write};
 use std::collections::VecDeque;
-use colored::*;
+use std::ops::Range;
 use crossterm::{
     terminal::{Clear, ClearType},
-    cursor::{MoveTo, MoveToColumn},
+    cursor::{MoveTo, MoveToColumn, RestorePosition, SavePosition},
     execute, queue,
     style::Print,
 };
 use indicatif::{ProgressBar, ProgressStyle};
-use tokio::time::{sleep, Duration};
+use std::time::Duration;
+use unicode_width::UnicodeWidthChar;
 
+use shared::MessageSecurity;
+use crate::client::history::{find_mentions, find_urls};
 use super::messages::{ChatMessage, MessageType};
+use super::status::NetworkStatus;
+use super::theme::{Theme, ThemeRole};
+
+/// Rows reserved for the header and input box around the chat area
+const RESERVED_ROWS: u16 = 8;
+
+/// Narrowest terminal the framed layout is drawn in
+pub const MIN_TERMINAL_WIDTH: u16 = 40;
+
+/// Fewest chat rows worth drawing; the status line is dropped before going below
+const MIN_CHAT_ROWS: u16 = 3;
+
+/// Shortest terminal the framed layout is drawn in
+pub const MIN_TERMINAL_HEIGHT: u16 = RESERVED_ROWS + MIN_CHAT_ROWS;
 
 /// Display manager handles all terminal drawing operations
 pub struct DisplayManager {
     terminal_width: u16,
     terminal_height: u16,
+    theme: Theme,
+    /// Whether a row above the input box is given to the status line
+    status_line: bool,
+    /// Warn in the header that TLS is off
+    cleartext: bool,
 }
 
 impl DisplayManager {
     /// Create new display manager
-    pub fn new(width: u16, height: u16) -> Self {
+    pub fn new(width: u16, height: u16, theme: Theme) -> Self {
         Self {
             terminal_width: width,
             terminal_height: height,
+            theme,
+            status_line: true,
+            cleartext: false,
         }
     }
 
-    /// Update terminal size
-    pub fn update_size(&mut self, width: u16, height: u16) {
+    /// Active color theme
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    /// Update terminal size, returns true if the layout changed
+    pub fn update_size(&mut self, width: u16, height: u16) -> bool {
+        if (width, height) == (self.terminal_width, self.terminal_height) {
+            return false;
+        }
         self.terminal_width = width;
         self.terminal_height = height;
+        true
+    }
+
+    /// Current terminal width
+    pub fn terminal_width(&self) -> u16 {
+        self.terminal_width
+    }
+
+    /// Height of the chat message area for the current terminal size
+    pub fn chat_area_height(&self) -> u16 {
+        self.terminal_height.saturating_sub(RESERVED_ROWS + self.status_rows())
+    }
+
+    /// Row of the border at the top of the input box
+    pub fn input_top(&self) -> u16 {
+        4 + self.chat_area_height() + self.status_rows()
+    }
+
+    /// Warn in the header for the whole session that traffic is unencrypted
+    pub fn set_cleartext_warning(&mut self, cleartext: bool) {
+        self.cleartext = cleartext;
+    }
+
+    /// Show or hide the status line, the caller redraws the screen
+    pub fn set_status_line(&mut self, enabled: bool) {
+        self.status_line = enabled;
+    }
+
+    /// Whether the status line is shown
+    pub fn status_line(&self) -> bool {
+        self.status_line
+    }
+
+    /// Rows taken by the status line, 0 when hidden or when the chat
+    /// area would shrink below [`MIN_CHAT_ROWS`] to make room for it
+    fn status_rows(&self) -> u16 {
+        let room = self.terminal_height > MIN_TERMINAL_HEIGHT;
+        u16::from(self.status_line && room)
+    }
+
+    /// Whether the terminal is big enough for the framed layout
+    pub fn fits(&self) -> bool {
+        self.terminal_width >= MIN_TERMINAL_WIDTH && self.terminal_height >= MIN_TERMINAL_HEIGHT
+    }
+
+    /// Replace the layout with a notice asking for a bigger terminal
+    ///
+    /// Drawn instead of the frame while [`fits`](Self::fits) is false, the
+    /// chat is redrawn in full once a resize makes room again.
+    pub fn draw_too_small(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut stdout = io::stdout();
+        let width = self.terminal_width as usize;
+        let lines = [
+            "Terminal too small".to_string(),
+            format!("need at least {}x{}", MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT),
+            format!("now {}x{}", self.terminal_width, self.terminal_height),
+        ];
+
+        queue!(stdout, Clear(ClearType::All))?;
+        for (row, line) in (0..self.terminal_height).zip(lines.iter()) {
+            let text: String = line.chars().take(width).collect();
+            queue!(stdout, MoveTo(0, row), Print(self.theme.paint(&text, ThemeRole::Error)))?;
+        }
+
+        stdout.flush()?;
+        Ok(())
     }
 
     /// Get visible length of string (excluding ANSI escape codes, accounting for emoji width)
     fn get_visible_length(&self, text: &str) -> usize {
-        let mut visible_len = 0;
-        let mut in_escape = false;
-        let mut chars = text.chars().peekable();
-        
-        while let Some(ch) = chars.next() {
-            if ch == '\x1b' {
-                // Start of ANSI escape sequence
-                in_escape = true;
-            } else if in_escape {
-                // Skip all characters until we find the end of escape sequence
-                if ch.is_ascii_alphabetic() || ch == 'm' || ch == 'K' || ch == 'J' {
-                    in_escape = false;
-                }
-                // Continue skipping characters in escape sequence
-            } else {
-                // Count visible character width
-                match ch {
-                    // Common emoji characters that take 2 display columns
-                    '💬' | '🔔' | '🔗' | '❌' | '👤' | '🔍' | '🚀' | '💡' | '👥' | '📜' | '👋' | '🔌' => {
-                        visible_len += 2;
-                    }
-                    // Regular ASCII characters
-                    _ if ch.is_ascii() => {
-                        visible_len += 1;
-                    }
-                    // Other Unicode characters (assume 1 column for most)
-                    _ => {
-                        visible_len += 1;
-                    }
-                }
-            }
-        }
-        visible_len
+        visible_width(text)
     }
 
     /// Safely truncate string while preserving ANSI escape codes
@@ -83,9 +150,8 @@ impl DisplayManager {
         let mut result = String::new();
         let mut visible_count = 0;
         let mut in_escape = false;
-        let mut chars = text.chars().peekable();
         
-        while let Some(ch) = chars.next() {
+        for ch in text.chars() {
             if ch == '\x1b' {
                 // Start of ANSI escape sequence - always include
                 result.push(ch);
@@ -98,12 +164,13 @@ impl DisplayManager {
                 }
             } else {
                 // Regular character - count towards visible limit
-                if visible_count >= max_width.saturating_sub(3) {
+                let width = char_width(ch);
+                if visible_count + width > max_width.saturating_sub(3) {
                     result.push_str("...");
                     break;
                 }
                 result.push(ch);
-                visible_count += 1;
+                visible_count += width;
             }
         }
         
@@ -111,18 +178,23 @@ impl DisplayManager {
     }
 
     /// Draw beautiful header with connection info
-    pub fn draw_header(&self, username: &str, listen_port: Option<u16>, connected_peers: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    pub fn draw_header(&self, username: &str, listen_port: Option<u16>, connected_peers: &[String], typing: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut stdout = io::stdout();
         
         // Top border - fix width calculation
         let border_width = (self.terminal_width as usize).saturating_sub(2);
         let border = "═".repeat(border_width);
-        queue!(stdout, MoveTo(0, 0), Print(format!("╔{}╗", border).bright_cyan()))?;
+        queue!(stdout, MoveTo(0, 0), Print(self.theme.paint(&format!("╔{}╗", border), ThemeRole::Border)))?;
         
-        // Title line
-        let title = "💬 P2P DPQ Chat";
-        let visible_title_len = self.get_visible_length(title);
+        // Title line, with a warning that can't be missed when TLS is off
         let content_width = (self.terminal_width as usize).saturating_sub(4); // Account for borders
+        let title = if self.cleartext {
+            let warning = self.theme.paint_bold("⚠ TLS DISABLED — traffic is in cleartext", self.theme.color(ThemeRole::Error));
+            self.safe_truncate(&format!("💬 P2P DPQ Chat  {}", warning), content_width)
+        } else {
+            "💬 P2P DPQ Chat".to_string()
+        };
+        let visible_title_len = self.get_visible_length(&title);
         let padding = content_width.saturating_sub(visible_title_len) / 2;
         let title_line = format!("║ {}{title}{} ║", 
             " ".repeat(padding),
@@ -143,23 +215,28 @@ impl DisplayManager {
             format!("🔗 Connected: {}", connected_peers.join(", "))
         };
         
-        let user_info = format!("👤 {} | {} | {}", username, listen_info, peer_status);
+        let mut user_info = format!("👤 {} | {} | {}", username, listen_info, peer_status);
+        match typing {
+            [] => {}
+            [name] => user_info.push_str(&format!(" | ✏️ {} is typing…", name)),
+            names => user_info.push_str(&format!(" | ✏️ {} are typing…", names.join(", "))),
+        }
         let visible_info_len = self.get_visible_length(&user_info);
         let info_padding = content_width.saturating_sub(visible_info_len) / 2;
         let info_line = format!("║ {}{user_info}{} ║", 
             " ".repeat(info_padding),
-            " ".repeat(content_width - info_padding - visible_info_len)
+            " ".repeat(content_width.saturating_sub(info_padding + visible_info_len))
         );
         queue!(stdout, MoveTo(0, 2), Print(info_line))?;
         
-        queue!(stdout, MoveTo(0, 3), Print(format!("╠{}╣", border).bright_cyan()))?;
+        queue!(stdout, MoveTo(0, 3), Print(self.theme.paint(&format!("╠{}╣", border), ThemeRole::Border)))?;
         
         stdout.flush()?;
         Ok(())
     }
     
     /// Get user color based on username hash
-    fn get_user_color(&self, username: &str) -> colored::Color {
+    fn get_user_color(&self, username: &str) -> Option<colored::Color> {
         use std::collections::hash_map::DefaultHasher;
         use std::hash::{Hash, Hasher};
         
@@ -167,82 +244,161 @@ impl DisplayManager {
         username.hash(&mut hasher);
         let hash = hasher.finish();
         
-        // Use hash to select from a set of nice colors
-        let colors = [
-            colored::Color::BrightBlue,
-            colored::Color::BrightGreen, 
-            colored::Color::BrightMagenta,
-            colored::Color::BrightCyan,
-            colored::Color::Yellow,
-            colored::Color::BrightRed,
-        ];
+        // Use hash to select from the theme's set of nice colors
+        let colors = self.theme.peer_palette();
+        if colors.is_empty() {
+            return None;
+        }
         
-        colors[(hash as usize) % colors.len()]
+        Some(colors[(hash as usize) % colors.len()])
     }
     
     /// Draw chat message area
-    pub fn draw_chat_area(&self, chat_area_height: u16, messages: &VecDeque<ChatMessage>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    pub fn draw_chat_area(&self, username: &str, messages: &VecDeque<ChatMessage>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut stdout = io::stdout();
+        let chat_area_height = self.chat_area_height();
         
         // Clear chat area first
         for i in 4..(4 + chat_area_height) {
-            queue!(stdout, MoveTo(0, i), Print("║".bright_cyan()))?;
+            queue!(stdout, MoveTo(0, i), Print(self.theme.paint("║", ThemeRole::Border)))?;
             // Clear the entire line content
             queue!(stdout, MoveTo(2, i))?;
             let clear_width = (self.terminal_width as usize).saturating_sub(4);
             queue!(stdout, Print(" ".repeat(clear_width)))?;
-            queue!(stdout, MoveToColumn(self.terminal_width - 1), Print("║".bright_cyan()))?;
+            queue!(stdout, MoveToColumn(self.terminal_width - 1), Print(self.theme.paint("║", ThemeRole::Border)))?;
         }
         
-        // Display messages
+        // Lay out the newest messages until the area is full; the oldest
+        // one shown may only partly fit, so keep its last lines
         let start_line = 4;
         let available_lines = chat_area_height as usize;
-        let messages_to_show = messages.iter()
-            .rev()
-            .take(available_lines)
-            .collect::<Vec<_>>();
-        
-        for (i, message) in messages_to_show.iter().rev().enumerate() {
-            if i >= available_lines {
+        let content_width = (self.terminal_width as usize).saturating_sub(4); // Account for borders
+        let mut lines: Vec<String> = Vec::new();
+        for message in messages.iter().rev() {
+            if lines.len() >= available_lines {
                 break;
             }
-            
-            let line = start_line + i as u16;
-            self.draw_message(line, message)?;
+            let mut message_lines = self.message_lines(username, message, content_width);
+            message_lines.append(&mut lines);
+            lines = message_lines;
         }
-        
+
+        let skip = lines.len().saturating_sub(available_lines);
+        for (i, text) in lines.iter().skip(skip).enumerate() {
+            self.draw_line(start_line + i as u16, text)?;
+        }
+
         stdout.flush()?;
         Ok(())
     }
-    
-    /// Draw a single message
-    fn draw_message(&self, line: u16, message: &ChatMessage) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+
+    /// Lay out a message as styled lines at most `content_width` columns wide
+    ///
+    /// Text wraps at word boundaries. Continuation lines are indented to start
+    /// under the sender name, or under the text for system messages.
+    fn message_lines(&self, username: &str, message: &ChatMessage, content_width: usize) -> Vec<String> {
+        let theme = &self.theme;
+
+        let user_color = if message.sender == username {
+            theme.color(ThemeRole::OwnMessage)
+        } else {
+            self.get_user_color(&message.sender)
+        };
+
+        // Received messages show how they were protected in transit
+        let security = message.security.map(security_glyph).unwrap_or_default();
+        let stamp = format!("{}[{}] ", security, theme.paint(&message.timestamp, ThemeRole::Timestamp));
+
+        // Styled first-line prefix, the text to wrap, and the continuation indent
+        let (head, body, indent) = match message.message_type {
+            MessageType::UserMessage => (
+                format!("{}{}: ", stamp, theme.paint_bold(&message.sender, user_color)),
+                message.content.clone(),
+                visible_width(&stamp),
+            ),
+            MessageType::ActionMessage => (
+                stamp.clone(),
+                format!("* {} {}", message.sender, message.content),
+                visible_width(&stamp),
+            ),
+            MessageType::SystemMessage => ("🔔 ".to_string(), message.content.clone(), visible_width("🔔 ")),
+            MessageType::ConnectionInfo => ("🔗 ".to_string(), message.content.clone(), visible_width("🔗 ")),
+            MessageType::ErrorMessage => ("❌ ".to_string(), message.content.clone(), visible_width("❌ ")),
+        };
+
+        let paint = |text: &str| match message.message_type {
+            MessageType::UserMessage => theme.paint(text, ThemeRole::PeerMessage),
+            MessageType::ActionMessage => theme.paint_italic(text, user_color),
+            MessageType::SystemMessage => theme.paint(text, ThemeRole::System),
+            MessageType::ConnectionInfo => theme.paint(text, ThemeRole::Connection),
+            MessageType::ErrorMessage => theme.paint(text, ThemeRole::Error),
+        };
+
+        // On a very narrow terminal the indent would leave no room for text
+        let indent = indent.min(content_width / 2);
+        let first_width = content_width.saturating_sub(visible_width(&head));
+        let rest_width = content_width - indent;
+
+        // Wrapping joins words with single spaces, so URLs and mentions of us
+        // are found in the same normalized text to keep their offsets valid
+        let body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut spans: Vec<(Range<usize>, ThemeRole)> = find_urls(&body)
+            .into_iter()
+            .map(|range| (range, ThemeRole::Link))
+            .collect();
+        if message.sender != username {
+            for range in find_mentions(&body, &[username]) {
+                if !spans.iter().any(|(link, _)| link.start < range.end && range.start < link.end) {
+                    spans.push((range, ThemeRole::Mention));
+                }
+            }
+        }
+        spans.sort_by_key(|(range, _)| range.start);
+        let mut offset = 0;
+
+        wrap_text(&body, first_width, rest_width)
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let start = offset;
+                offset += line.len();
+                if body[offset..].starts_with(' ') {
+                    offset += 1;
+                }
+
+                // Paint URL and mention parts with their own style and the rest as usual
+                let mut text = String::new();
+                let mut pos = start;
+                for (span, role) in spans.iter().filter(|(span, _)| span.start < offset && span.end > start) {
+                    let span_start = span.start.max(start);
+                    let span_end = span.end.min(start + line.len());
+                    if span_start > pos {
+                        text.push_str(&paint(&body[pos..span_start]).to_string());
+                    }
+                    let part = &body[span_start..span_end];
+                    let styled = match role {
+                        ThemeRole::Mention => theme.paint_bold(part, theme.color(ThemeRole::Mention)),
+                        _ => theme.paint_link(part),
+                    };
+                    text.push_str(&styled.to_string());
+                    pos = span_end;
+                }
+                if pos < start + line.len() {
+                    text.push_str(&paint(&body[pos..start + line.len()]).to_string());
+                }
+
+                let prefix = if i == 0 { head.clone() } else { " ".repeat(indent) };
+                self.safe_truncate(&format!("{}{}", prefix, text), content_width)
+            })
+            .collect()
+    }
+
+    /// Draw one laid-out chat line, padded to the full width
+    fn draw_line(&self, line: u16, truncated_message: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut stdout = io::stdout();
         let content_width = (self.terminal_width as usize).saturating_sub(4); // Account for borders
         
-        let formatted_message = match message.message_type {
-            MessageType::UserMessage => {
-                let user_color = self.get_user_color(&message.sender);
-                format!("[{}] {}: {}", 
-                    message.timestamp.dimmed(),
-                    message.sender.color(user_color).bold(),
-                    message.content.white()
-                )
-            }
-            MessageType::SystemMessage => {
-                format!("🔔 {}", message.content.bright_yellow())
-            }
-            MessageType::ConnectionInfo => {
-                format!("🔗 {}", message.content.bright_green())
-            }
-            MessageType::ErrorMessage => {
-                format!("❌ {}", message.content.bright_red())
-            }
-        };
-        
-        // Safely truncate message if needed and pad to full width
-        let truncated_message = self.safe_truncate(&formatted_message, content_width);
-        let visible_len = self.get_visible_length(&truncated_message);
+        let visible_len = self.get_visible_length(truncated_message);
         let display_message = format!("{}{}", 
             truncated_message, 
             " ".repeat(content_width.saturating_sub(visible_len))
@@ -252,15 +408,40 @@ impl DisplayManager {
         Ok(())
     }
 
+    /// Draw the status line between the chat area and the input box
+    ///
+    /// The cursor is saved and restored around the drawing, so it can be
+    /// updated while the user is typing without moving their cursor.
+    pub fn draw_status_line(&self, status: &NetworkStatus) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.status_line {
+            return Ok(());
+        }
+        let mut stdout = io::stdout();
+        let line = 4 + self.chat_area_height();
+        let content_width = (self.terminal_width as usize).saturating_sub(4); // Account for borders
+
+        let text = self.safe_truncate(&status.summary(), content_width);
+        let padding = content_width.saturating_sub(self.get_visible_length(&text));
+
+        queue!(stdout, SavePosition)?;
+        queue!(stdout, MoveTo(0, line), Print(self.theme.paint("║", ThemeRole::Border)))?;
+        queue!(stdout, MoveTo(2, line), Print(format!("{}{}", self.theme.paint(&text, ThemeRole::System), " ".repeat(padding))))?;
+        queue!(stdout, MoveToColumn(self.terminal_width - 1), Print(self.theme.paint("║", ThemeRole::Border)))?;
+        queue!(stdout, RestorePosition)?;
+
+        stdout.flush()?;
+        Ok(())
+    }
+
     /// Draw input area
-    pub fn draw_input_area(&self, username: &str, chat_area_height: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    pub fn draw_input_area(&self, username: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut stdout = io::stdout();
-        let input_line = 4 + chat_area_height;
+        let input_line = self.input_top();
         
         // Input area border - fix width calculation
         let border_width = (self.terminal_width as usize).saturating_sub(2);
         let border = "═".repeat(border_width);
-        queue!(stdout, MoveTo(0, input_line), Print(format!("╠{}╣", border).bright_cyan()))?;
+        queue!(stdout, MoveTo(0, input_line), Print(self.theme.paint(&format!("╠{}╣", border), ThemeRole::Border)))?;
         
         // Input prompt line
         let prompt = format!("💬 {}@chat > ", username);
@@ -268,50 +449,174 @@ impl DisplayManager {
         let content_width = (self.terminal_width as usize).saturating_sub(4); // Account for borders
         let padding = content_width.saturating_sub(prompt_visible_len);
         
-        queue!(stdout, MoveTo(0, input_line + 1), Print("║".bright_cyan()))?;
-        queue!(stdout, MoveTo(2, input_line + 1), Print(format!("{}{}", prompt.bright_green().bold(), " ".repeat(padding))))?;
-        queue!(stdout, MoveToColumn(self.terminal_width - 1), Print("║".bright_cyan()))?;
+        queue!(stdout, MoveTo(0, input_line + 1), Print(self.theme.paint("║", ThemeRole::Border)))?;
+        queue!(stdout, MoveTo(2, input_line + 1), Print(format!("{}{}", self.theme.paint_bold(&prompt, self.theme.color(ThemeRole::Prompt)), " ".repeat(padding))))?;
+        queue!(stdout, MoveToColumn(self.terminal_width - 1), Print(self.theme.paint("║", ThemeRole::Border)))?;
         
         // Bottom border
-        queue!(stdout, MoveTo(0, input_line + 2), Print(format!("╚{}╝", border).bright_cyan()))?;
+        queue!(stdout, MoveTo(0, input_line + 2), Print(self.theme.paint(&format!("╚{}╝", border), ThemeRole::Border)))?;
         
         stdout.flush()?;
         Ok(())
     }
 
-    /// Show connection progress
-    pub async fn show_connection_progress(&self, message: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let pb = ProgressBar::new(100);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos:>7}/{len:7} {msg}")
-                .unwrap()
-                .progress_chars("█▉▊▋▌▍▎▏ ")
-        );
-
-        pb.set_message(message.to_string());
-
-        for i in 0..=100 {
-            pb.set_position(i);
-            sleep(Duration::from_millis(30)).await;
-        }
-
-        pb.finish_with_message("✅ Connected!");
-        Ok(())
-    }
-
     /// Show welcome screen
     pub fn show_welcome(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
         
         println!();
-        println!("{}", "╔══════════════════════════════════════════════════════════════╗".bright_cyan());
-        println!("{}", "║                    💬 P2P DPQ Chat                          ║".bright_cyan());
-        println!("{}", "║                   Welcome to secure chat!                    ║".bright_cyan());
-        println!("{}", "║                  🔒 Encrypted • 🌐 Peer-to-Peer              ║".bright_cyan());
-        println!("{}", "╚══════════════════════════════════════════════════════════════╝".bright_cyan());
+        println!("{}", self.theme.paint("╔══════════════════════════════════════════════════════════════╗", ThemeRole::Border));
+        println!("{}", self.theme.paint("║                    💬 P2P DPQ Chat                          ║", ThemeRole::Border));
+        println!("{}", self.theme.paint("║                   Welcome to secure chat!                    ║", ThemeRole::Border));
+        println!("{}", self.theme.paint("║                  🔒 Encrypted • 🌐 Peer-to-Peer              ║", ThemeRole::Border));
+        println!("{}", self.theme.paint("╚══════════════════════════════════════════════════════════════╝", ThemeRole::Border));
         println!();
         
         Ok(())
     }
 }
+
+/// Start a spinner shown while the P2P node starts up
+///
+/// The spinner has no fixed length, the caller finishes it once the node
+/// has bound its listener and started discovery.
+pub fn startup_spinner(message: &str, theme: Theme) -> ProgressBar {
+    let template = if theme.is_colored() {
+        "{spinner:.green} [{elapsed}] {msg}"
+    } else {
+        "{spinner} [{elapsed}] {msg}"
+    };
+
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(ProgressStyle::default_spinner().template(template).unwrap());
+    spinner.set_message(message.to_string());
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+/// Display columns a character takes, 0 for combining marks and controls
+fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
+
+/// Display width of text, skipping ANSI escape sequences
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+
+    for ch in text.chars() {
+        if ch == '\x1b' {
+            in_escape = true;
+        } else if in_escape {
+            // Escape sequences end with a letter, e.g. `m` for colors
+            if ch.is_ascii_alphabetic() {
+                in_escape = false;
+            }
+        } else {
+            width += char_width(ch);
+        }
+    }
+    width
+}
+
+/// Split plain text into lines at word boundaries
+///
+/// The first line holds at most `first_width` columns and the others
+/// `rest_width`. Words longer than a line are broken across lines.
+fn wrap_text(text: &str, first_width: usize, rest_width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+
+    for word in text.split_whitespace() {
+        let limit = if lines.is_empty() { first_width } else { rest_width }.max(1);
+        let word_width = visible_width(word);
+        if line_width > 0 && line_width + 1 + word_width > limit {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        } else if line_width > 0 {
+            line.push(' ');
+            line_width += 1;
+        }
+
+        for ch in word.chars() {
+            let limit = if lines.is_empty() { first_width } else { rest_width }.max(1);
+            let width = char_width(ch);
+            if line_width > 0 && line_width + width > limit {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            line.push(ch);
+            line_width += width;
+        }
+    }
+
+    lines.push(line);
+    lines
+}
+
+/// Prefix marking how a received message was protected
+///
+/// A closed lock means end-to-end encryption, an open lock means only the
+/// TLS connection protected it, and a warning sign means it arrived in the clear.
+fn security_glyph(security: MessageSecurity) -> &'static str {
+    match security {
+        MessageSecurity::EndToEnd => "🔒 ",
+        MessageSecurity::Transport => "🔓 ",
+        MessageSecurity::Plain => "⚠ ",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_width_counts_columns() {
+        assert_eq!(visible_width("abc"), 3);
+        assert_eq!(visible_width("\x1b[1;31mred\x1b[0m"), 3);
+        assert_eq!(visible_width("🔔 日本"), 7);
+        assert_eq!(visible_width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn test_wrap_text_breaks_at_words() {
+        assert_eq!(wrap_text("the quick brown fox", 10, 10), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap_text("the quick brown fox", 4, 20), vec!["the", "quick brown fox"]);
+        assert_eq!(wrap_text("", 10, 10), vec![""]);
+    }
+
+    #[test]
+    fn test_status_line_takes_a_chat_row() {
+        let mut display = DisplayManager::new(80, 24, Theme::None);
+        assert_eq!(display.chat_area_height(), 24 - RESERVED_ROWS - 1);
+        assert_eq!(display.input_top(), 4 + display.chat_area_height() + 1);
+
+        display.set_status_line(false);
+        assert_eq!(display.chat_area_height(), 24 - RESERVED_ROWS);
+        assert_eq!(display.input_top(), 4 + display.chat_area_height());
+    }
+
+    #[test]
+    fn test_small_terminals_fall_back() {
+        let mut display = DisplayManager::new(80, MIN_TERMINAL_HEIGHT, Theme::None);
+        assert!(display.fits());
+        // No room for the status line, the chat area keeps its minimum
+        assert_eq!(display.chat_area_height(), MIN_CHAT_ROWS);
+
+        display.update_size(80, MIN_TERMINAL_HEIGHT + 1);
+        assert_eq!(display.chat_area_height(), MIN_CHAT_ROWS);
+
+        display.update_size(80, MIN_TERMINAL_HEIGHT - 1);
+        assert!(!display.fits());
+        display.update_size(MIN_TERMINAL_WIDTH - 1, 24);
+        assert!(!display.fits());
+    }
+
+    #[test]
+    fn test_wrap_text_splits_long_and_wide_words() {
+        assert_eq!(wrap_text("abcdefgh", 3, 3), vec!["abc", "def", "gh"]);
+        // Each of these takes two columns, so only two fit per line
+        assert_eq!(wrap_text("日本語の", 5, 5), vec!["日本", "語の"]);
+    }
+}
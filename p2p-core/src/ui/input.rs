@@ -1,21 +1,274 @@
 //! Input handling for chat UI
 
+use std::collections::VecDeque;
 use std::io::{self, Write};
+use std::time::{Duration, Instant};
 use crossterm::{
     cursor::MoveTo,
+    event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
     style::Print,
+    terminal,
 };
+use unicode_width::UnicodeWidthChar;
 
-/// Input handler manages cursor positioning and input area clearing
+/// Number of submitted lines kept for recall
+pub const INPUT_HISTORY_SIZE: usize = 100;
+
+/// Lines the user submitted, for up/down arrow recall
+///
+/// Separate from the chat `MessageHistory`: this holds what was typed,
+/// commands included, not what was shown. Repeating the previous line
+/// is only recorded once.
+#[derive(Debug, Clone)]
+pub struct InputHistory {
+    lines: VecDeque<String>,
+    capacity: usize,
+    cursor: Option<usize>, // index into `lines` while navigating
+}
+
+impl InputHistory {
+    /// Create an empty history keeping at most `capacity` lines
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            cursor: None,
+        }
+    }
+
+    /// Record a submitted line and reset navigation to the newest entry
+    pub fn record(&mut self, line: &str) {
+        self.cursor = None;
+
+        if line.trim().is_empty() || self.lines.back().is_some_and(|last| last == line) {
+            return;
+        }
+
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.to_string());
+    }
+
+    /// Step back to an older line (up arrow), staying on the oldest one
+    pub fn previous(&mut self) -> Option<&str> {
+        let index = match self.cursor {
+            Some(index) => index.saturating_sub(1),
+            None => self.lines.len().checked_sub(1)?,
+        };
+        self.cursor = Some(index);
+        self.lines.get(index).map(String::as_str)
+    }
+
+    /// Step forward to a newer line (down arrow)
+    ///
+    /// Returns `None` once past the newest line, meaning an empty prompt.
+    /// Not an `Iterator`: it pairs with `previous` and moves a cursor.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&str> {
+        let index = self.cursor? + 1;
+        if index < self.lines.len() {
+            self.cursor = Some(index);
+            self.lines.get(index).map(String::as_str)
+        } else {
+            self.cursor = None;
+            None
+        }
+    }
+
+    /// Number of recorded lines
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Whether nothing was recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+impl Default for InputHistory {
+    fn default() -> Self {
+        Self::new(INPUT_HISTORY_SIZE)
+    }
+}
+
+/// Shortest gap between two typing notifications to peers
+pub const TYPING_NOTIFY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Limits how often peers are told we are typing
+#[derive(Debug, Clone)]
+pub struct TypingThrottle {
+    interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl TypingThrottle {
+    /// Allow one notification per `interval`
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, last_sent: None }
+    }
+
+    /// Whether a notification should go out now, recording it if so
+    pub fn should_notify(&mut self, now: Instant) -> bool {
+        match self.last_sent {
+            Some(last) if now.duration_since(last) < self.interval => false,
+            _ => {
+                self.last_sent = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Forget the last notification, e.g. after the message was sent
+    pub fn reset(&mut self) {
+        self.last_sent = None;
+    }
+}
+
+impl Default for TypingThrottle {
+    fn default() -> Self {
+        Self::new(TYPING_NOTIFY_INTERVAL)
+    }
+}
+
+/// Keeps the terminal in raw mode, so keys arrive one at a time, until dropped
+pub struct RawModeGuard;
+
+impl RawModeGuard {
+    /// Switch the terminal to raw mode
+    pub fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+/// What a key press did to the input line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyOutcome {
+    /// Nothing changed
+    Ignored,
+    /// The line changed and needs redrawing
+    Edited,
+    /// Enter was pressed, with the line as typed
+    Submitted(String),
+    /// Ctrl-C, or Ctrl-D on an empty line
+    Quit,
+}
+
+/// Input handler manages the line being typed, cursor positioning, input
+/// area clearing and input recall
 pub struct InputHandler {
     username: String,
+    line: String,
+    history: InputHistory,
+    typing: TypingThrottle,
 }
 
 impl InputHandler {
     /// Create new input handler
     pub fn new(username: String) -> Self {
-        Self { username }
+        Self {
+            username,
+            line: String::new(),
+            history: InputHistory::default(),
+            typing: TypingThrottle::default(),
+        }
+    }
+
+    /// Apply a key press to the line being typed
+    ///
+    /// Up and down recall submitted lines; the cursor always stays at the end.
+    pub fn handle_key(&mut self, key: KeyEvent) -> KeyOutcome {
+        if key.kind == KeyEventKind::Release {
+            return KeyOutcome::Ignored;
+        }
+
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => KeyOutcome::Quit,
+            KeyCode::Char('d') if control && self.line.is_empty() => KeyOutcome::Quit,
+            KeyCode::Char('u') if control => self.replace_line(String::new()),
+            KeyCode::Char(_) if control => KeyOutcome::Ignored,
+            KeyCode::Char(ch) => {
+                self.line.push(ch);
+                KeyOutcome::Edited
+            }
+            KeyCode::Backspace => match self.line.pop() {
+                Some(_) => KeyOutcome::Edited,
+                None => KeyOutcome::Ignored,
+            },
+            KeyCode::Enter => {
+                // The next message starts a new typing notice right away
+                self.typing.reset();
+                KeyOutcome::Submitted(std::mem::take(&mut self.line))
+            }
+            KeyCode::Up => match self.history.previous() {
+                Some(line) => {
+                    let line = line.to_string();
+                    self.replace_line(line)
+                }
+                None => KeyOutcome::Ignored,
+            },
+            KeyCode::Down => {
+                let line = self.history.next().unwrap_or_default().to_string();
+                self.replace_line(line)
+            }
+            _ => KeyOutcome::Ignored,
+        }
+    }
+
+    /// The line typed so far
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    fn replace_line(&mut self, line: String) -> KeyOutcome {
+        if line == self.line {
+            return KeyOutcome::Ignored;
+        }
+        self.line = line;
+        KeyOutcome::Edited
+    }
+
+    /// End of the typed line that fits in `width` columns, with its width
+    fn visible_tail(&self, width: usize) -> (&str, usize) {
+        let mut used = 0;
+        let mut start = self.line.len();
+        for (index, ch) in self.line.char_indices().rev() {
+            let ch_width = ch.width().unwrap_or(0);
+            if used + ch_width > width {
+                break;
+            }
+            used += ch_width;
+            start = index;
+        }
+        (&self.line[start..], used)
+    }
+
+    /// Columns left for the typed line after the prompt and the borders
+    fn line_width(&self, terminal_width: u16) -> usize {
+        let prompt = format!("💬 {}@chat > ", self.username);
+        (terminal_width as usize).saturating_sub(4 + self.get_visible_prompt_length(&prompt) + 1)
+    }
+
+    /// Note a keystroke in the input line, true if peers should be told we are typing
+    ///
+    /// An empty line or a command doesn't count as composing a message.
+    pub fn typing_started(&mut self) -> bool {
+        !self.line.is_empty() && !self.line.starts_with('/') && self.typing.should_notify(Instant::now())
+    }
+
+    /// Submitted lines available for recall
+    pub fn history(&mut self) -> &mut InputHistory {
+        &mut self.history
     }
 
     /// Get visible length of prompt (accounting for emoji width)
@@ -34,20 +287,38 @@ impl InputHandler {
         visible_len
     }
 
-    /// Position cursor for input
-    pub fn position_cursor_for_input(&self, chat_area_height: u16, _terminal_width: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let input_line = 4 + chat_area_height + 1;
+    /// Position cursor for input, after the line typed so far
+    pub fn position_cursor_for_input(&self, input_top: u16, terminal_width: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let input_line = input_top + 1;
         let prompt = format!("💬 {}@chat > ", self.username);
         // Calculate visible length properly (excluding emoji and ANSI codes)
         let prompt_visible_len = self.get_visible_prompt_length(&prompt);
-        execute!(io::stdout(), MoveTo((2 + prompt_visible_len) as u16, input_line))?;
+        let (_, typed_width) = self.visible_tail(self.line_width(terminal_width));
+        execute!(io::stdout(), MoveTo((2 + prompt_visible_len + typed_width) as u16, input_line))?;
         io::stdout().flush()?;
         Ok(())
     }
+
+    /// Draw the line typed so far after the prompt, scrolled to its end if too long
+    pub fn draw_input_line(&self, input_top: u16, terminal_width: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let input_line = input_top + 1;
+        let prompt = format!("💬 {}@chat > ", self.username);
+        let cursor_pos = 2 + self.get_visible_prompt_length(&prompt);
+        let width = self.line_width(terminal_width);
+        let (tail, typed_width) = self.visible_tail(width);
+
+        execute!(
+            io::stdout(),
+            MoveTo(cursor_pos as u16, input_line),
+            Print(tail),
+            Print(" ".repeat(width - typed_width)),
+        )?;
+        self.position_cursor_for_input(input_top, terminal_width)
+    }
     
     /// Clear input area after sending message
-    pub fn clear_input_area(&self, chat_area_height: u16, terminal_width: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let input_line = 4 + chat_area_height + 1;
+    pub fn clear_input_area(&self, input_top: u16, terminal_width: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let input_line = input_top + 1;
         let prompt = format!("💬 {}@chat > ", self.username);
         let prompt_visible_len = self.get_visible_prompt_length(&prompt);
         
@@ -64,5 +335,117 @@ impl InputHandler {
         io::stdout().flush()?;
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_history_navigation() {
+        let mut history = InputHistory::new(10);
+        assert_eq!(history.previous(), None);
 
+        history.record("hello");
+        history.record("/who");
+        history.record("/who");
+        history.record("");
+        assert_eq!(history.len(), 2);
+
+        assert_eq!(history.previous(), Some("/who"));
+        assert_eq!(history.previous(), Some("hello"));
+        assert_eq!(history.previous(), Some("hello"));
+        assert_eq!(history.next(), Some("/who"));
+        assert_eq!(history.next(), None);
+        assert_eq!(history.next(), None);
+
+        // Recording resets navigation to the newest line
+        history.previous();
+        history.previous();
+        history.record("bye");
+        assert_eq!(history.previous(), Some("bye"));
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_arrow_keys_recall_submitted_lines() {
+        let mut input = InputHandler::new("alice".to_string());
+        for ch in "hi".chars() {
+            assert_eq!(input.handle_key(key(KeyCode::Char(ch))), KeyOutcome::Edited);
+        }
+        assert_eq!(input.handle_key(key(KeyCode::Enter)), KeyOutcome::Submitted("hi".to_string()));
+        input.history().record("hi");
+        input.history().record("/who");
+
+        assert_eq!(input.handle_key(key(KeyCode::Up)), KeyOutcome::Edited);
+        assert_eq!(input.line(), "/who");
+        input.handle_key(key(KeyCode::Up));
+        assert_eq!(input.line(), "hi");
+        input.handle_key(key(KeyCode::Down));
+        input.handle_key(key(KeyCode::Backspace));
+        assert_eq!(input.line(), "/wh");
+
+        // Past the newest line the prompt is empty again
+        input.handle_key(key(KeyCode::Down));
+        assert_eq!(input.line(), "");
+        assert_eq!(input.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)), KeyOutcome::Quit);
+    }
+
+    #[test]
+    fn test_typing_is_noticed_for_messages_only() {
+        let mut input = InputHandler::new("alice".to_string());
+        assert!(!input.typing_started());
+
+        input.handle_key(key(KeyCode::Char('/')));
+        assert!(!input.typing_started());
+        input.handle_key(key(KeyCode::Backspace));
+
+        input.handle_key(key(KeyCode::Char('h')));
+        assert!(input.typing_started());
+        input.handle_key(key(KeyCode::Char('i')));
+        assert!(!input.typing_started());
+
+        input.handle_key(key(KeyCode::Enter));
+        input.handle_key(key(KeyCode::Char('o')));
+        assert!(input.typing_started());
+    }
+
+    #[test]
+    fn test_long_line_shows_its_end() {
+        let mut input = InputHandler::new("alice".to_string());
+        for ch in "abcdefgh".chars() {
+            input.handle_key(key(KeyCode::Char(ch)));
+        }
+        assert_eq!(input.visible_tail(3), ("fgh", 3));
+        assert_eq!(input.visible_tail(20), ("abcdefgh", 8));
+    }
+
+    #[test]
+    fn test_typing_notifications_are_throttled() {
+        let mut throttle = TypingThrottle::new(Duration::from_secs(2));
+        let start = Instant::now();
+
+        assert!(throttle.should_notify(start));
+        assert!(!throttle.should_notify(start + Duration::from_secs(1)));
+        assert!(throttle.should_notify(start + Duration::from_secs(2)));
+
+        throttle.reset();
+        assert!(throttle.should_notify(start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_input_history_is_bounded() {
+        let mut history = InputHistory::new(2);
+        for line in ["one", "two", "three"] {
+            history.record(line);
+        }
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.previous(), Some("three"));
+        assert_eq!(history.previous(), Some("two"));
+        assert_eq!(history.previous(), Some("two"));
+    }
 }
@@ -1,5 +1,6 @@
 //! Message management for chat UI
 
+use shared::MessageSecurity;
 use std::collections::VecDeque;
 
 /// Chat message structure for display
@@ -9,11 +10,14 @@ pub struct ChatMessage {
     pub sender: String,
     pub content: String,
     pub message_type: MessageType,
+    /// Protection of a message received from a peer, `None` for local messages
+    pub security: Option<MessageSecurity>,
 }
 
 #[derive(Clone)]
 pub enum MessageType {
     UserMessage,
+    ActionMessage,
     SystemMessage,
     ConnectionInfo,
     ErrorMessage,
@@ -29,13 +33,25 @@ impl MessageManager {
     /// Create new message manager
     pub fn new(max_messages: usize) -> Self {
         Self {
-            messages: VecDeque::with_capacity(max_messages),
+            // Grow on demand so a large limit doesn't allocate up front
+            messages: VecDeque::new(),
             max_messages,
         }
     }
 
     /// Add a new message
     pub fn add_message(&mut self, sender: String, content: String, message_type: MessageType) {
+        self.add_message_with_security(sender, content, message_type, None);
+    }
+
+    /// Add a new message along with how it was protected in transit
+    pub fn add_message_with_security(
+        &mut self,
+        sender: String,
+        content: String,
+        message_type: MessageType,
+        security: Option<MessageSecurity>,
+    ) {
         let timestamp = chrono::Local::now().format("%H:%M:%S").to_string();
         
         let message = ChatMessage {
@@ -43,6 +59,7 @@ impl MessageManager {
             sender,
             content,
             message_type,
+            security,
         };
         
         self.messages.push_back(message);
@@ -6,58 +6,78 @@
 pub mod display;
 pub mod input;
 pub mod messages;
+pub mod resize;
+pub mod status;
+pub mod theme;
 
-pub use display::DisplayManager;
-pub use input::InputHandler;
+pub use display::{DisplayManager, startup_spinner};
+pub use input::{InputHandler, InputHistory, KeyOutcome, RawModeGuard, TypingThrottle, INPUT_HISTORY_SIZE, TYPING_NOTIFY_INTERVAL};
 pub use messages::{MessageType, MessageManager};
+pub use resize::ResizeWatcher;
+pub use status::{ConnectionState, NetworkStatus};
+pub use theme::{Theme, ThemeRole};
 
 use crossterm::{
     terminal::{self, Clear, ClearType},
     cursor::MoveTo,
+    event::KeyEvent,
     execute,
 };
+use std::collections::HashMap;
 use std::io;
+use std::time::{Duration, Instant};
+
+/// How long a typing notice stays in the header without a new one
+pub const TYPING_INDICATOR_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Main chat UI coordinator
 pub struct ChatUI {
     username: String,
     listen_port: Option<u16>,
-    terminal_width: u16,
-    terminal_height: u16,
-    chat_area_height: u16,
     connected_peers: Vec<String>,
     display_manager: DisplayManager,
     input_handler: InputHandler,
     message_manager: MessageManager,
+    typing: HashMap<String, Instant>, // username -> last typing notice
+    show_typing: bool,
+    status: NetworkStatus,
 }
 
 impl ChatUI {
     /// Create new chat UI
-    pub fn new(username: String, listen_port: Option<u16>, max_messages: usize) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+    pub fn new(username: String, listen_port: Option<u16>, max_messages: usize, theme: Theme) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let (width, height) = terminal::size()?;
-        let chat_area_height = height.saturating_sub(8); // Reserve space for header and input
-        
-        Ok(Self {
+        Ok(Self::with_size(username, listen_port, max_messages, theme, width, height))
+    }
+
+    /// Create a chat UI for a terminal of the given size
+    pub(crate) fn with_size(username: String, listen_port: Option<u16>, max_messages: usize, theme: Theme, width: u16, height: u16) -> Self {
+        Self {
             username: username.clone(),
             listen_port,
-            terminal_width: width,
-            terminal_height: height,
-            chat_area_height,
             connected_peers: Vec::new(),
-            display_manager: DisplayManager::new(width, height),
+            display_manager: DisplayManager::new(width, height, theme),
             input_handler: InputHandler::new(username.clone()),
             message_manager: MessageManager::new(max_messages),
-        })
+            typing: HashMap::new(),
+            show_typing: true,
+            status: NetworkStatus::default(),
+        }
     }
 
     /// Initialize the chat interface
     pub fn initialize(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Clear screen
         execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+        if !self.display_manager.fits() {
+            return self.display_manager.draw_too_small();
+        }
         
-        self.display_manager.draw_header(&self.username, self.listen_port, &self.connected_peers)?;
-        self.display_manager.draw_chat_area(self.chat_area_height, &self.message_manager.get_messages())?;
-        self.display_manager.draw_input_area(&self.username, self.chat_area_height)?;
+        self.display_manager.draw_header(&self.username, self.listen_port, &self.connected_peers, &self.typing_names())?;
+        self.display_manager.draw_chat_area(&self.username, self.message_manager.get_messages())?;
+        self.display_manager.draw_status_line(&self.status)?;
+        self.display_manager.draw_input_area(&self.username)?;
+        self.input_handler.draw_input_line(self.display_manager.input_top(), self.display_manager.terminal_width())?;
         
         Ok(())
     }
@@ -70,47 +90,196 @@ impl ChatUI {
         self.refresh_display()?;
         
         // Reposition cursor to input area
-        self.input_handler.position_cursor_for_input(self.chat_area_height, self.terminal_width)?;
+        self.position_cursor_for_input()?;
         
         Ok(())
     }
 
+    /// Add a message received from a peer, marked with how it was protected
+    pub fn add_received_message(
+        &mut self,
+        sender: String,
+        content: String,
+        message_type: MessageType,
+        security: shared::MessageSecurity,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.message_manager.add_message_with_security(sender, content, message_type, Some(security));
+        self.refresh_display()?;
+        self.position_cursor_for_input()?;
+        Ok(())
+    }
+
+    /// Active color theme
+    pub fn theme(&self) -> Theme {
+        self.display_manager.theme()
+    }
+
+    /// Our own username, as shown in the prompt
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
     /// Update connected peers list
     pub fn update_connected_peers(&mut self, peers: Vec<String>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         self.connected_peers = peers;
-        self.display_manager.draw_header(&self.username, self.listen_port, &self.connected_peers)?;
+        if self.display_manager.fits() {
+            self.display_manager.draw_header(&self.username, self.listen_port, &self.connected_peers, &self.typing_names())?;
+        }
+        Ok(())
+    }
+
+    /// Keep a cleartext warning in the header, for sessions without TLS
+    pub fn set_cleartext_warning(&mut self, cleartext: bool) {
+        self.display_manager.set_cleartext_warning(cleartext);
+    }
+
+    /// Show or hide peers' typing notices
+    pub fn set_typing_indicators(&mut self, enabled: bool) {
+        self.show_typing = enabled;
+        if !enabled {
+            self.typing.clear();
+        }
+    }
+
+    /// Show that a peer is typing until the notice times out
+    pub fn peer_typing(&mut self, username: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.show_typing {
+            return Ok(());
+        }
+        if self.typing.insert(username.to_string(), Instant::now()).is_none() {
+            self.redraw_header()?;
+        }
+        Ok(())
+    }
+
+    /// Drop a peer's typing notice, e.g. once their message arrives
+    pub fn peer_stopped_typing(&mut self, username: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.typing.remove(username).is_some() {
+            self.redraw_header()?;
+        }
+        Ok(())
+    }
+
+    /// Drop typing notices older than [`TYPING_INDICATOR_TIMEOUT`]
+    pub fn expire_typing(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let before = self.typing.len();
+        self.typing.retain(|_, seen| seen.elapsed() < TYPING_INDICATOR_TIMEOUT);
+        if self.typing.len() != before {
+            self.redraw_header()?;
+        }
         Ok(())
     }
 
+    /// Whether any typing notice is showing
+    pub fn has_typing_peers(&self) -> bool {
+        !self.typing.is_empty()
+    }
+
+    /// Network figures last shown on the status line
+    pub fn network_status(&self) -> &NetworkStatus {
+        &self.status
+    }
+
+    /// Update the status line, redrawing it only if something changed
+    pub fn set_network_status(&mut self, status: NetworkStatus) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if status != self.status {
+            self.status = status;
+            if self.display_manager.fits() {
+                self.display_manager.draw_status_line(&self.status)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Show or hide the status line, returns whether it is now shown
+    pub fn toggle_status_line(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let enabled = !self.display_manager.status_line();
+        self.display_manager.set_status_line(enabled);
+        self.redraw()?;
+        Ok(enabled)
+    }
+
+    /// Names of peers currently typing, in a stable order
+    fn typing_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.typing.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Redraw only the header and put the cursor back
+    fn redraw_header(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.display_manager.fits() {
+            return Ok(());
+        }
+        self.display_manager.draw_header(&self.username, self.listen_port, &self.connected_peers, &self.typing_names())?;
+        self.position_cursor_for_input()
+    }
+
+    /// Re-read terminal size and recompute layout, returns true if it changed
+    fn update_layout(&mut self) -> bool {
+        match terminal::size() {
+            Ok((width, height)) => self.display_manager.update_size(width, height),
+            Err(_) => false,
+        }
+    }
+
     /// Refresh the entire display
     pub fn refresh_display(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Update terminal size in case it changed
-        if let Ok((width, height)) = terminal::size() {
-            self.terminal_width = width;
-            self.terminal_height = height;
-            self.chat_area_height = height.saturating_sub(8);
-            self.display_manager.update_size(width, height);
+        // Update terminal size in case it changed; a new size leaves stale
+        // borders behind, so wipe the screen before drawing
+        if self.update_layout() {
+            execute!(io::stdout(), Clear(ClearType::All))?;
+        }
+        if !self.display_manager.fits() {
+            return self.display_manager.draw_too_small();
         }
         
-        self.display_manager.draw_header(&self.username, self.listen_port, &self.connected_peers)?;
-        self.display_manager.draw_chat_area(self.chat_area_height, &self.message_manager.get_messages())?;
-        self.display_manager.draw_input_area(&self.username, self.chat_area_height)?;
+        self.display_manager.draw_header(&self.username, self.listen_port, &self.connected_peers, &self.typing_names())?;
+        self.display_manager.draw_chat_area(&self.username, self.message_manager.get_messages())?;
+        self.display_manager.draw_status_line(&self.status)?;
+        self.display_manager.draw_input_area(&self.username)?;
+        self.input_handler.draw_input_line(self.display_manager.input_top(), self.display_manager.terminal_width())?;
         Ok(())
     }
 
     /// Position cursor for input
     pub fn position_cursor_for_input(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.input_handler.position_cursor_for_input(self.chat_area_height, self.terminal_width)
+        if !self.display_manager.fits() {
+            return Ok(());
+        }
+        self.input_handler.position_cursor_for_input(self.display_manager.input_top(), self.display_manager.terminal_width())
     }
     
-    /// Clear input area after sending message
-    pub fn clear_input_area(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.input_handler.clear_input_area(self.chat_area_height, self.terminal_width)
+    /// Apply a key press to the line being typed, redrawing it if it changed
+    pub fn handle_key(&mut self, key: KeyEvent) -> Result<KeyOutcome, Box<dyn std::error::Error + Send + Sync>> {
+        let outcome = self.input_handler.handle_key(key);
+        if outcome == KeyOutcome::Edited && self.display_manager.fits() {
+            self.input_handler.draw_input_line(self.display_manager.input_top(), self.display_manager.terminal_width())?;
+        }
+        Ok(outcome)
+    }
+
+    /// Whether peers should now be told we are typing, throttled
+    pub fn typing_started(&mut self) -> bool {
+        self.input_handler.typing_started()
     }
 
-    /// Show connection progress
-    pub async fn show_connection_progress(&self, message: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.display_manager.show_connection_progress(message).await
+    /// Remember a submitted line for up/down arrow recall
+    pub fn record_input(&mut self, line: &str) {
+        self.input_handler.history().record(line);
+    }
+
+    /// Submitted lines available for recall
+    pub fn input_history(&mut self) -> &mut InputHistory {
+        self.input_handler.history()
+    }
+
+    /// Clear input area after sending message
+    pub fn clear_input_area(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.display_manager.fits() {
+            return Ok(());
+        }
+        self.input_handler.clear_input_area(self.display_manager.input_top(), self.display_manager.terminal_width())
     }
 
     /// Show welcome screen
@@ -118,18 +287,31 @@ impl ChatUI {
         self.display_manager.show_welcome()
     }
 
-    /// Clear all chat messages and refresh display
+    /// Wipe the whole screen and redraw header, chat area and input prompt
+    pub fn redraw(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.update_layout();
+        self.initialize()?;
+        self.position_cursor_for_input()
+    }
+
+    /// Handle a terminal resize by recomputing the layout and redrawing
+    pub fn handle_resize(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.update_layout() {
+            self.initialize()?;
+            self.position_cursor_for_input()?;
+        }
+        Ok(())
+    }
+
+    /// Clear all chat messages and redraw the screen
+    ///
+    /// Only the on-screen buffer is dropped; the client's `MessageHistory` is untouched.
     pub fn clear_chat(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Clear all messages
         self.message_manager.clear_messages();
         
-        // Refresh display to show empty chat area
-        self.refresh_display()?;
-        
-        // Reposition cursor to input area
-        self.position_cursor_for_input()?;
-        
-        Ok(())
+        // Redraw from a blank screen to get rid of any leftover artifacts
+        self.redraw()
     }
 
 }
@@ -0,0 +1,75 @@
+//! Terminal resize notifications for chat UI
+
+use crossterm::terminal;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::time::{interval, Duration, Interval, MissedTickBehavior};
+
+/// How often the terminal size is polled when no resize signal is available
+const SIZE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Resize watcher yields whenever the terminal window changes size
+///
+/// Uses `SIGWINCH` where available and falls back to polling `terminal::size()`,
+/// so it also works on platforms without resize signals.
+pub struct ResizeWatcher {
+    #[cfg(unix)]
+    sigwinch: Option<Signal>,
+    poll: Interval,
+    last_size: Option<(u16, u16)>,
+}
+
+impl ResizeWatcher {
+    /// Create new resize watcher
+    pub fn new() -> Self {
+        let mut poll = interval(SIZE_POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        Self {
+            // SIGWINCH registration only fails if the signal driver is unavailable;
+            // the size poll still covers that case
+            #[cfg(unix)]
+            sigwinch: signal(SignalKind::window_change()).ok(),
+            poll,
+            last_size: terminal::size().ok(),
+        }
+    }
+
+    /// Wait until the terminal size differs from the last observed size
+    pub async fn changed(&mut self) -> (u16, u16) {
+        loop {
+            self.wait_for_tick().await;
+
+            if let Ok(size) = terminal::size() {
+                if self.last_size != Some(size) {
+                    self.last_size = Some(size);
+                    return size;
+                }
+            }
+        }
+    }
+
+    /// Wait for either a resize signal or the next poll tick
+    async fn wait_for_tick(&mut self) {
+        #[cfg(unix)]
+        if let Some(sigwinch) = self.sigwinch.as_mut() {
+            tokio::select! {
+                received = sigwinch.recv() => {
+                    if received.is_none() {
+                        self.sigwinch = None;
+                    }
+                }
+                _ = self.poll.tick() => {}
+            }
+            return;
+        }
+
+        self.poll.tick().await;
+    }
+}
+
+impl Default for ResizeWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
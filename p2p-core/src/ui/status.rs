@@ -0,0 +1,85 @@
+//! Network health shown on the status line
+
+/// How we are linked to the chat network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Listening, but no peer is connected yet
+    Waiting,
+    /// At least one peer is connected
+    Connected,
+    /// The node stopped delivering events
+    Offline,
+}
+
+impl ConnectionState {
+    /// Short label with an indicator, e.g. "🟢 Connected"
+    pub fn label(self) -> &'static str {
+        match self {
+            ConnectionState::Waiting => "🟡 Waiting",
+            ConnectionState::Connected => "🟢 Connected",
+            ConnectionState::Offline => "🔴 Offline",
+        }
+    }
+}
+
+/// Figures shown on the status line, updated from events and node stats
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkStatus {
+    pub peers: usize,
+    pub discovery_active: bool,
+    /// Chat messages sent and received this session
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    /// Set once the node's event stream has closed
+    pub offline: bool,
+}
+
+impl NetworkStatus {
+    /// Current connection state
+    pub fn state(&self) -> ConnectionState {
+        if self.offline {
+            ConnectionState::Offline
+        } else if self.peers > 0 {
+            ConnectionState::Connected
+        } else {
+            ConnectionState::Waiting
+        }
+    }
+
+    /// One-line summary, e.g. "🟢 Connected | 👥 2 peers | 📡 Discovery on | ↑ 5 ↓ 7"
+    pub fn summary(&self) -> String {
+        let peers = match self.peers {
+            1 => "1 peer".to_string(),
+            n => format!("{} peers", n),
+        };
+        let discovery = if self.discovery_active { "on" } else { "off" };
+        format!(
+            "{} | 👥 {} | 📡 Discovery {} | ↑ {} ↓ {}",
+            self.state().label(),
+            peers,
+            discovery,
+            self.messages_sent,
+            self.messages_received
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_summary_follows_state() {
+        let mut status = NetworkStatus { discovery_active: true, ..Default::default() };
+        assert_eq!(status.state(), ConnectionState::Waiting);
+        assert_eq!(status.summary(), "🟡 Waiting | 👥 0 peers | 📡 Discovery on | ↑ 0 ↓ 0");
+
+        status.peers = 1;
+        status.messages_sent = 3;
+        status.messages_received = 4;
+        assert_eq!(status.summary(), "🟢 Connected | 👥 1 peer | 📡 Discovery on | ↑ 3 ↓ 4");
+
+        status.offline = true;
+        assert_eq!(status.state(), ConnectionState::Offline);
+    }
+}
@@ -0,0 +1,154 @@
+//! Color themes for chat UI
+
+use colored::{Color, ColoredString, Colorize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Part of the chat UI that a theme assigns a color to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeRole {
+    Border,
+    Timestamp,
+    OwnMessage,
+    PeerMessage,
+    System,
+    Connection,
+    Error,
+    Prompt,
+    Link,
+    Mention,
+}
+
+/// Color theme for the chat UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    /// Bright colors for dark terminal backgrounds
+    #[default]
+    Dark,
+    /// Darker colors that stay readable on light backgrounds
+    Light,
+    /// No colors at all, output contains no color escape sequences
+    None,
+}
+
+impl Theme {
+    /// All available themes
+    pub const ALL: [Theme; 3] = [Theme::Dark, Theme::Light, Theme::None];
+
+    /// Theme name as accepted by `--theme`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::None => "none",
+        }
+    }
+
+    /// Color for a UI role, `None` means leave the text uncolored
+    pub fn color(&self, role: ThemeRole) -> Option<Color> {
+        match self {
+            Theme::Dark => Some(match role {
+                ThemeRole::Border => Color::BrightCyan,
+                ThemeRole::Timestamp => Color::BrightBlack,
+                ThemeRole::OwnMessage => Color::BrightGreen,
+                ThemeRole::PeerMessage => Color::White,
+                ThemeRole::System => Color::BrightYellow,
+                ThemeRole::Connection => Color::BrightGreen,
+                ThemeRole::Error => Color::BrightRed,
+                ThemeRole::Prompt => Color::BrightGreen,
+                ThemeRole::Link => Color::BrightBlue,
+                ThemeRole::Mention => Color::BrightMagenta,
+            }),
+            Theme::Light => Some(match role {
+                ThemeRole::Border => Color::Blue,
+                ThemeRole::Timestamp => Color::BrightBlack,
+                ThemeRole::OwnMessage => Color::Green,
+                ThemeRole::PeerMessage => Color::Black,
+                ThemeRole::System => Color::Magenta,
+                ThemeRole::Connection => Color::Green,
+                ThemeRole::Error => Color::Red,
+                ThemeRole::Prompt => Color::Blue,
+                ThemeRole::Link => Color::Blue,
+                ThemeRole::Mention => Color::Magenta,
+            }),
+            Theme::None => None,
+        }
+    }
+
+    /// Colors used to tell peer usernames apart
+    pub fn peer_palette(&self) -> &'static [Color] {
+        match self {
+            Theme::Dark => &[
+                Color::BrightBlue,
+                Color::BrightGreen,
+                Color::BrightMagenta,
+                Color::BrightCyan,
+                Color::Yellow,
+                Color::BrightRed,
+            ],
+            Theme::Light => &[
+                Color::Blue,
+                Color::Green,
+                Color::Magenta,
+                Color::Cyan,
+                Color::Red,
+                Color::Black,
+            ],
+            Theme::None => &[],
+        }
+    }
+
+    /// Whether this theme emits any styling at all
+    pub fn is_colored(&self) -> bool {
+        *self != Theme::None
+    }
+
+    /// Apply the color for a role to some text
+    pub fn paint(&self, text: &str, role: ThemeRole) -> ColoredString {
+        match self.color(role) {
+            Some(color) => text.color(color),
+            None => text.normal(),
+        }
+    }
+
+    /// Apply a color and bold, unless the theme is colorless
+    pub fn paint_bold(&self, text: &str, color: Option<Color>) -> ColoredString {
+        match color {
+            Some(color) => text.color(color).bold(),
+            None => text.normal(),
+        }
+    }
+
+    /// Style a URL with the link color and an underline, unless the theme is colorless
+    pub fn paint_link(&self, text: &str) -> ColoredString {
+        match self.color(ThemeRole::Link) {
+            Some(color) => text.color(color).underline(),
+            None => text.normal(),
+        }
+    }
+
+    /// Apply a color and italics, unless the theme is colorless
+    pub fn paint_italic(&self, text: &str, color: Option<Color>) -> ColoredString {
+        match color {
+            Some(color) => text.color(color).italic(),
+            None => text.normal(),
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Theme::ALL
+            .into_iter()
+            .find(|theme| theme.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown theme '{}' (expected dark, light or none)", s))
+    }
+}
@@ -0,0 +1,104 @@
+//! Benchmarks for the post-quantum handshake and message encryption
+//!
+//! Run with `cargo bench -p shared`. Everything runs in-process, no network
+//! is needed.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use shared::crypto::{
+    DilithiumKeypair, DilithiumVerifier, HandshakeManager, KyberKeyExchangeManager, MessageCrypto, SessionKey,
+};
+
+/// A chat line of typical length
+const TYPICAL_MESSAGE: &str = "Hey, are we still on for the review at three? I pushed the fixes from this morning.";
+
+/// Handshake manager with a fixed name and fingerprint and its own signing key
+fn handshake_manager(name: &str, keypair: &DilithiumKeypair) -> HandshakeManager {
+    HandshakeManager::new_with_dilithium(
+        name.to_string(),
+        format!("{}_fp", name),
+        keypair.public_key_bytes().to_vec(),
+        keypair.clone(),
+    )
+}
+
+fn bench_kyber(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kyber");
+
+    group.bench_function("initiate", |b| {
+        b.iter(|| KyberKeyExchangeManager::new().initiate_key_exchange().unwrap())
+    });
+
+    group.bench_function("respond", |b| {
+        let offer = KyberKeyExchangeManager::new().initiate_key_exchange().unwrap();
+        b.iter(|| KyberKeyExchangeManager::new().respond_to_key_exchange(black_box(&offer)).unwrap())
+    });
+
+    group.bench_function("complete", |b| {
+        b.iter_batched(
+            || {
+                let mut initiator = KyberKeyExchangeManager::new();
+                let offer = initiator.initiate_key_exchange().unwrap();
+                let (response, _) = KyberKeyExchangeManager::new().respond_to_key_exchange(&offer).unwrap();
+                (initiator, response)
+            },
+            |(mut initiator, response)| initiator.complete_key_exchange(&response).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_dilithium(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dilithium");
+    let keypair = DilithiumKeypair::generate();
+    let data = [0x5a; 32];
+    let signature = keypair.sign(&data);
+
+    group.bench_function("sign", |b| b.iter(|| keypair.sign(black_box(&data))));
+    group.bench_function("verify", |b| {
+        b.iter(|| DilithiumVerifier::verify(black_box(&data), &signature, keypair.public_key_bytes()).unwrap())
+    });
+
+    group.finish();
+}
+
+fn bench_handshake(c: &mut Criterion) {
+    let alice_keys = DilithiumKeypair::generate();
+    let bob_keys = DilithiumKeypair::generate();
+
+    c.bench_function("handshake/round_trip", |b| {
+        b.iter_batched(
+            || (handshake_manager("alice", &alice_keys), handshake_manager("bob", &bob_keys)),
+            |(mut alice, mut bob)| {
+                let offer = alice.initiate_handshake("bob_fp").unwrap();
+                let (_, response) = bob.process_handshake(offer).unwrap();
+                alice.process_handshake(response.unwrap()).unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_message_crypto(c: &mut Criterion) {
+    let mut group = c.benchmark_group("message_crypto");
+    let session_key = SessionKey::generate("bob_fp".to_string());
+    let message = MessageCrypto::create_text_message("alice".to_string(), TYPICAL_MESSAGE.to_string());
+    let encrypted = MessageCrypto::encrypt_message(&session_key, &message, 1).unwrap();
+    let batch = vec![message.clone(); 100];
+
+    group.bench_function("encrypt", |b| {
+        b.iter(|| MessageCrypto::encrypt_message(&session_key, black_box(&message), 1).unwrap())
+    });
+    group.bench_function("decrypt", |b| {
+        b.iter(|| MessageCrypto::decrypt_message(&session_key, black_box(&encrypted)).unwrap())
+    });
+    group.bench_function("encrypt_batch_100", |b| {
+        b.iter(|| MessageCrypto::encrypt_batch(&session_key, black_box(&batch), 1).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_kyber, bench_dilithium, bench_handshake, bench_message_crypto);
+criterion_main!(benches);
@@ -2,7 +2,7 @@
 pub mod constants {
     // Message and username limits
     pub const MAX_MESSAGE_LENGTH: usize = 1024;
-    pub const MAX_USERNAME_LENGTH: usize = 32;
+    pub const MAX_USERNAME_LENGTH: usize = identity_gen::MAX_USERNAME_LENGTH;
     
     // Network configuration
     pub const DEFAULT_HOST_LOCALHOST: &str = "127.0.0.1";
@@ -15,10 +15,38 @@ pub mod constants {
     pub const TLS_ENABLED: bool = true;
     
     // Other network settings
-    pub const MULTICAST_ADDR: &str = "224.0.0.1:9999";
+    pub const DEFAULT_MULTICAST_GROUP: std::net::Ipv4Addr = std::net::Ipv4Addr::new(239, 255, 42, 99); // LAN discovery group
+    pub const DEFAULT_MULTICAST_PORT: u16 = 8899;
     pub const CONNECTION_TIMEOUT: u64 = 30; // seconds
-    pub const HEARTBEAT_INTERVAL: u64 = 60; // seconds
+    pub const HEARTBEAT_INTERVAL: u64 = 30; // seconds
+    pub const DEAD_PEER_HEARTBEATS: u64 = 4; // missed heartbeats before a silent peer is dropped
+    pub const MIN_DEAD_PEER_HEARTBEATS: u64 = 2; // shortest dead-peer timeout, in heartbeats
+    pub const MAX_HEARTBEAT_INTERVAL_SECS: u64 = 3600; // longest heartbeat interval a peer may advertise
+    pub const PING_TIMEOUT_SECS: u64 = 5; // wait for a /ping reply
     pub const MAX_CONNECTIONS: usize = 50;
+    pub const DEFAULT_MESSAGE_TTL: u8 = 8; // hops a chat message may travel
+    pub const DEFAULT_RATE_LIMIT: u32 = 20; // chat messages per source per rate window
+    pub const LINK_RATE_LIMIT_FACTOR: u32 = 5; // a direct peer may relay this many sources' worth per rate window
+    // Longest line a peer may send: content escaped as JSON can grow up to 6x,
+    // plus room for IDs, the seen_by list and peer lists
+    pub const MAX_FRAME_LENGTH: usize = MAX_MESSAGE_LENGTH * 6 + 16 * 1024;
+    pub const RATE_LIMIT_WINDOW_SECS: u64 = 10;
+    pub const DEFAULT_REORDER_WINDOW_MS: u64 = 300; // hold a sender's overtaking messages this long
+    pub const DEFAULT_KNOWN_PEER_MAX_AGE_SECS: u64 = 7 * 24 * 60 * 60; // forget known peers unseen for a week
+    pub const DEFAULT_CERT_VALIDITY_SECS: u64 = 365 * 24 * 60 * 60; // self-signed TLS certificate lifetime
+    pub const MAX_CERT_VALIDITY_HOURS: u64 = 10 * 365 * 24; // longest --cert-validity accepted, ten years
+    pub const CERT_RENEW_RETRY_SECS: u64 = 60; // wait before retrying a failed certificate renewal
+    pub const KNOWN_PEERS_SAVE_INTERVAL_SECS: u64 = 60; // rewrite the known peers file this often while running
+    pub const KNOWN_PEER_DIAL_ATTEMPTS: u32 = 3; // tries per known peer on start, with doubling delays
+    pub const MAX_KNOWN_PEERS: usize = 256; // known peers remembered, the least recently seen are dropped
+    pub const KNOWN_PEER_DIAL_CONCURRENCY: usize = 8; // known peers dialed at the same time on start
+    pub const ROOM_KEY_ENV: &str = "DPQ_ROOM_KEY"; // room key passphrase, instead of --room-key
+    
+    // Wire protocol version sent in handshakes, peers must share the major version
+    pub const PROTOCOL_VERSION: &str = "1.0";
+    
+    // Release of this build, shared with peers to spot stale builds
+    pub const BUILD_VERSION: &str = env!("CARGO_PKG_VERSION");
     
     // Logging
     pub const DEFAULT_LOG_LEVEL: &str = "error";
@@ -2,6 +2,7 @@
 
 use pqcrypto_dilithium::dilithium2;
 use pqcrypto_traits::sign::{PublicKey, SecretKey, SignedMessage};
+use subtle::ConstantTimeEq;
 
 /// Dilithium keypair for signing operations
 #[derive(Clone)]
@@ -20,6 +21,15 @@ impl std::fmt::Debug for DilithiumKeypair {
 }
 
 impl DilithiumKeypair {
+    /// Generate a fresh keypair, e.g. for tests and benchmarks
+    pub fn generate() -> Self {
+        let (public_key, secret_key) = dilithium2::keypair();
+        Self {
+            public_key,
+            secret_key,
+        }
+    }
+    
     /// Create keypair from raw bytes (loaded from identity)
     pub fn from_bytes(
         public_key_bytes: &[u8],
@@ -70,8 +80,8 @@ impl DilithiumVerifier {
         
         match dilithium2::open(&signed_message, &public_key) {
             Ok(verified_message) => {
-                // Verify that the message content matches
-                Ok(verified_message == message)
+                // Verify that the message content matches, without leaking where it differs
+                Ok(verified_message.ct_eq(message).into())
             }
             Err(_) => Ok(false),
         }
@@ -103,7 +113,7 @@ mod tests {
         let (public_key, secret_key) = dilithium2::keypair();
         
         let keypair = DilithiumKeypair {
-            public_key: public_key.clone(),
+            public_key,
             secret_key,
         };
         
@@ -257,15 +257,15 @@ impl HandshakeManager {
         hasher.update(&peer_info.username);
         hasher.update(&peer_info.fingerprint);
         hasher.update(&peer_info.public_key);
-        hasher.update(&peer_info.timestamp.to_le_bytes());
+        hasher.update(peer_info.timestamp.to_le_bytes());
         
         // Hash Kyber exchange data
         hasher.update(&kyber_exchange.public_key);
         if let Some(ref ciphertext) = kyber_exchange.ciphertext {
             hasher.update(ciphertext);
         }
-        hasher.update(&kyber_exchange.timestamp.to_le_bytes());
-        hasher.update(&format!("{:?}", kyber_exchange.role));
+        hasher.update(kyber_exchange.timestamp.to_le_bytes());
+        hasher.update(format!("{:?}", kyber_exchange.role));
         
         Ok(hasher.finalize().to_vec())
     }
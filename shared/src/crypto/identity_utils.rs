@@ -60,6 +60,7 @@ pub fn create_handshake_manager_from_identity(
 mod tests {
     use super::*;
     use pqcrypto_dilithium::dilithium2;
+    use pqcrypto_traits::sign::{PublicKey as _, SecretKey as _};
     
     #[test]
     fn test_load_dilithium_keypair() {
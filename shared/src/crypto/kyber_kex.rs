@@ -61,7 +61,7 @@ impl KyberKeyExchangeManager {
         let (public_key, secret_key) = kyber768::keypair();
         
         // Store our keypair
-        self.our_keypair = Some((public_key.clone(), secret_key));
+        self.our_keypair = Some((public_key, secret_key));
         
         // Create key exchange data
         let key_exchange = KyberKeyExchange {
@@ -2,6 +2,7 @@
 
 use serde::{Serialize, Deserialize};
 use crate::crypto::session::SessionKey;
+use crate::utils::sanitize_text;
 
 /// Encrypted message structure for network transmission
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,19 +13,24 @@ pub struct EncryptedMessage {
     pub encrypted_content: Vec<u8>,
     /// Message timestamp
     pub timestamp: u64,
-    /// Message type (text, file, system, etc.)
+    /// Message type (text, file, system, etc.), sent unencrypted
     pub message_type: MessageType,
     /// Message sequence number (for ordering)
     pub sequence: u64,
 }
 
 /// Types of messages that can be encrypted
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// The type travels in the clear next to the ciphertext, so anything
+/// identifying, like a file name, belongs in [`PlainMessage::attachment`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageType {
     /// Regular text message
     Text,
-    /// File transfer
-    File { filename: String, size: u64 },
+    /// File offer, described by the message's attachment
+    File,
+    /// Image offer, described by the message's attachment
+    Image,
     /// System message (join, leave, etc.)
     System,
     /// Typing indicator
@@ -33,6 +39,17 @@ pub enum MessageType {
     Ack { message_id: u64 },
 }
 
+/// What a file or image offer contains
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// File name as chosen by the sender, without any directory
+    pub filename: String,
+    /// MIME type, e.g. `image/png`
+    pub mime: String,
+    /// Size in bytes
+    pub size: u64,
+}
+
 /// Plain text message structure (before encryption)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlainMessage {
@@ -44,6 +61,33 @@ pub struct PlainMessage {
     pub timestamp: u64,
     /// Message type
     pub message_type: MessageType,
+    /// File or image being offered, only for `File` and `Image` messages
+    #[serde(default)]
+    pub attachment: Option<FileMetadata>,
+}
+
+impl PlainMessage {
+    /// One-line description of the message for the chat window
+    ///
+    /// Attachment names come from the peer and are sanitized like chat text.
+    pub fn summary(&self) -> String {
+        match &self.message_type {
+            MessageType::Text | MessageType::System => self.content.clone(),
+            MessageType::Typing => format!("{} is typing...", self.sender),
+            MessageType::Ack { message_id } => format!("{} received message {}", self.sender, message_id),
+            MessageType::File | MessageType::Image => match &self.attachment {
+                Some(file) => format!(
+                    "{} offers {} {} ({}, {} bytes)",
+                    self.sender,
+                    if self.message_type == MessageType::Image { "image" } else { "file" },
+                    sanitize_text(&file.filename),
+                    sanitize_text(&file.mime),
+                    file.size
+                ),
+                None => format!("{} offered a file without describing it", self.sender),
+            },
+        }
+    }
 }
 
 /// Message encryption and decryption utilities
@@ -71,6 +115,56 @@ impl MessageCrypto {
         })
     }
     
+    /// Encrypt a run of messages, numbering them from `starting_sequence`
+    ///
+    /// The cipher is set up once for the whole batch. The result matches
+    /// calling [`Self::encrypt_message`] with `starting_sequence`,
+    /// `starting_sequence + 1` and so on, so the two can be mixed freely.
+    /// Fails if the batch would number past `u64::MAX`.
+    pub fn encrypt_batch(
+        session_key: &SessionKey,
+        messages: &[PlainMessage],
+        starting_sequence: u64,
+    ) -> Result<Vec<EncryptedMessage>, Box<dyn std::error::Error>> {
+        let cipher = session_key.cipher();
+        
+        messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                let sequence = u64::try_from(index)
+                    .ok()
+                    .and_then(|index| starting_sequence.checked_add(index))
+                    .ok_or("Message sequence number overflowed")?;
+                let message_bytes = serde_json::to_vec(message)?;
+                
+                Ok(EncryptedMessage {
+                    sender_fingerprint: session_key.peer_fingerprint().to_string(),
+                    encrypted_content: SessionKey::encrypt_with(&cipher, &message_bytes)?,
+                    timestamp: message.timestamp,
+                    message_type: message.message_type.clone(),
+                    sequence,
+                })
+            })
+            .collect()
+    }
+    
+    /// Decrypt a run of messages, failing on the first one that doesn't decrypt
+    pub fn decrypt_batch(
+        session_key: &SessionKey,
+        encrypted_messages: &[EncryptedMessage],
+    ) -> Result<Vec<PlainMessage>, Box<dyn std::error::Error>> {
+        let cipher = session_key.cipher();
+        
+        encrypted_messages
+            .iter()
+            .map(|encrypted_message| {
+                let decrypted_bytes = SessionKey::decrypt_with(&cipher, &encrypted_message.encrypted_content)?;
+                Ok(serde_json::from_slice(&decrypted_bytes)?)
+            })
+            .collect()
+    }
+    
     /// Decrypt an encrypted message using session key
     pub fn decrypt_message(
         session_key: &SessionKey,
@@ -98,6 +192,7 @@ impl MessageCrypto {
                 .unwrap()
                 .as_secs(),
             message_type: MessageType::Text,
+            attachment: None,
         }
     }
     
@@ -114,6 +209,7 @@ impl MessageCrypto {
                 .unwrap()
                 .as_secs(),
             message_type: MessageType::System,
+            attachment: None,
         }
     }
     
@@ -127,6 +223,27 @@ impl MessageCrypto {
                 .unwrap()
                 .as_secs(),
             message_type: MessageType::Typing,
+            attachment: None,
+        }
+    }
+
+    /// Create a file offer, sent as an image offer for `image/*` types
+    pub fn create_file_offer(sender: String, file: FileMetadata) -> PlainMessage {
+        let message_type = if file.mime.starts_with("image/") {
+            MessageType::Image
+        } else {
+            MessageType::File
+        };
+
+        PlainMessage {
+            content: String::new(),
+            sender,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            message_type,
+            attachment: Some(file),
         }
     }
     
@@ -186,13 +303,11 @@ impl MessageSequenceManager {
         sequence: u64,
     ) -> Result<(), Box<dyn std::error::Error>> {
         match self.peer_sequences.get(peer_fingerprint) {
-            Some(&last_sequence) => {
-                if sequence <= last_sequence {
-                    return Err("Duplicate or old message sequence".into());
-                }
+            Some(&last_sequence) if sequence <= last_sequence => {
+                return Err("Duplicate or old message sequence".into());
             }
-            None => {
-                // First message from this peer
+            _ => {
+                // First message from this peer, or a newer sequence
             }
         }
         
@@ -233,6 +348,76 @@ mod tests {
         assert_eq!(plain_message.sender, decrypted.sender);
     }
     
+    #[test]
+    fn test_batch_matches_per_message_path() {
+        let session_key = SessionKey::generate("test_peer".to_string());
+        let messages: Vec<PlainMessage> = (0..3)
+            .map(|i| MessageCrypto::create_text_message("alice".to_string(), format!("message {}", i)))
+            .collect();
+        
+        let batch = MessageCrypto::encrypt_batch(&session_key, &messages, 7).unwrap();
+        assert_eq!(batch.iter().map(|m| m.sequence).collect::<Vec<_>>(), vec![7, 8, 9]);
+        
+        // Batch output decrypts one by one, and single messages decrypt as a batch
+        for (encrypted, plain) in batch.iter().zip(&messages) {
+            assert_eq!(MessageCrypto::decrypt_message(&session_key, encrypted).unwrap().content, plain.content);
+        }
+        let single: Vec<EncryptedMessage> = messages
+            .iter()
+            .zip(7..)
+            .map(|(plain, sequence)| MessageCrypto::encrypt_message(&session_key, plain, sequence).unwrap())
+            .collect();
+        let decrypted = MessageCrypto::decrypt_batch(&session_key, &single).unwrap();
+        assert_eq!(decrypted.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), vec!["message 0", "message 1", "message 2"]);
+        assert!(MessageCrypto::encrypt_batch(&session_key, &[], 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_batch_sequence_overflow_is_an_error() {
+        let session_key = SessionKey::generate("test_peer".to_string());
+        let messages: Vec<PlainMessage> = (0..2)
+            .map(|i| MessageCrypto::create_text_message("alice".to_string(), format!("message {}", i)))
+            .collect();
+
+        let last = MessageCrypto::encrypt_batch(&session_key, &messages[..1], u64::MAX).unwrap();
+        assert_eq!(last[0].sequence, u64::MAX);
+        assert!(MessageCrypto::encrypt_batch(&session_key, &messages, u64::MAX).is_err());
+    }
+    
+    #[test]
+    fn test_file_offer_metadata_stays_encrypted() {
+        let session_key = SessionKey::generate("test_peer".to_string());
+        let offer = MessageCrypto::create_file_offer(
+            "alice".to_string(),
+            FileMetadata {
+                filename: "holiday.png".to_string(),
+                mime: "image/png".to_string(),
+                size: 2048,
+            },
+        );
+        assert_eq!(offer.message_type, MessageType::Image);
+
+        let encrypted = MessageCrypto::encrypt_message(&session_key, &offer, 1).unwrap();
+        assert!(!serde_json::to_string(&encrypted).unwrap().contains("holiday"));
+
+        let decrypted = MessageCrypto::decrypt_message(&session_key, &encrypted).unwrap();
+        assert_eq!(decrypted.attachment, offer.attachment);
+        assert_eq!(decrypted.summary(), "alice offers image holiday.png (image/png, 2048 bytes)");
+    }
+
+    #[test]
+    fn test_file_offer_summary_strips_control_sequences() {
+        let offer = MessageCrypto::create_file_offer(
+            "alice".to_string(),
+            FileMetadata {
+                filename: "\x1b[2Jevil\x07.txt".to_string(),
+                mime: "text/plain\x1b]0;pwned\x07".to_string(),
+                size: 1,
+            },
+        );
+        assert_eq!(offer.summary(), "alice offers file evil.txt (text/plain, 1 bytes)");
+    }
+
     #[test]
     fn test_sequence_manager() {
         let mut manager = MessageSequenceManager::new();
@@ -9,9 +9,9 @@ pub mod kyber_kex;
 pub mod dilithium_ops;
 pub mod identity_utils;
 
-pub use session::{SessionKey, SessionManager};
+pub use session::{Renewals, SessionKey, SessionManager};
 pub use handshake::{HandshakeManager, HandshakeData, PeerInfo};
-pub use message_crypto::{MessageCrypto, EncryptedMessage, MessageType, PlainMessage};
+pub use message_crypto::{MessageCrypto, EncryptedMessage, FileMetadata, MessageType, PlainMessage};
 pub use kyber_kex::{KyberKeyExchangeManager, KyberKeyExchange};
 pub use dilithium_ops::{DilithiumKeypair, DilithiumVerifier};
 pub use identity_utils::{
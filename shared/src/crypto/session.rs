@@ -8,6 +8,28 @@ use rand::RngCore;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
+use crate::crypto::handshake::{HandshakeData, HandshakeManager};
+use crate::crypto::message_crypto::{EncryptedMessage, MessageCrypto, PlainMessage};
+
+/// How long a session key is used before it is renewed
+pub const SESSION_KEY_LIFETIME_SECS: u64 = 3600;
+
+/// How long a disconnected peer's session is kept for a quick reconnect
+pub const SESSION_GRACE_SECS: u64 = 30;
+
+/// How long a renewal may wait for the peer's handshake before it is given up
+pub const RENEWAL_TIMEOUT_SECS: u64 = 60;
+
+/// Most messages queued for one peer while its session is renewed
+pub const MAX_RENEWAL_QUEUE: usize = 100;
+
+/// Current Unix time in seconds
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
 
 /// Ephemeral session key for peer-to-peer communication
 #[derive(Debug, Clone)]
@@ -78,20 +100,33 @@ impl SessionKey {
         &self.peer_fingerprint
     }
     
-    /// Check if session key is expired (older than 1 hour)
+    /// Check if session key is expired (older than [`SESSION_KEY_LIFETIME_SECS`])
     pub fn is_expired(&self) -> bool {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
         
-        now - self.created_at > 3600 // 1 hour
+        now.saturating_sub(self.created_at) > SESSION_KEY_LIFETIME_SECS
     }
     
     /// Encrypt a message using this session key
     pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let key = Key::<Aes256Gcm>::from_slice(&self.key);
-        let cipher = Aes256Gcm::new(key);
+        Self::encrypt_with(&self.cipher(), plaintext)
+    }
+    
+    /// Decrypt a message using this session key
+    pub fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Self::decrypt_with(&self.cipher(), encrypted_data)
+    }
+    
+    /// AES-256-GCM cipher for this key, reusable across many messages
+    pub(crate) fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key))
+    }
+    
+    /// Encrypt with a prepared cipher, prepending a fresh random nonce
+    pub(crate) fn encrypt_with(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
         
         let ciphertext = cipher
@@ -106,15 +141,12 @@ impl SessionKey {
         Ok(result)
     }
     
-    /// Decrypt a message using this session key
-    pub fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    /// Decrypt with a prepared cipher, reading the nonce from the front
+    pub(crate) fn decrypt_with(cipher: &Aes256Gcm, encrypted_data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         if encrypted_data.len() < 12 {
             return Err("Invalid encrypted data: too short".into());
         }
         
-        let key = Key::<Aes256Gcm>::from_slice(&self.key);
-        let cipher = Aes256Gcm::new(key);
-        
         // Extract nonce and ciphertext
         let nonce = Nonce::from_slice(&encrypted_data[..12]);
         let ciphertext = &encrypted_data[12..];
@@ -127,11 +159,31 @@ impl SessionKey {
     }
 }
 
+/// A session being renewed, with messages waiting for the new key
+#[derive(Debug)]
+struct Renewal {
+    started_at: u64,
+    queued: Vec<PlainMessage>,
+}
+
+/// Outcome of [`SessionManager::renew_expired`]
+#[derive(Debug, Default)]
+pub struct Renewals {
+    /// Handshakes to send, one per peer whose renewal started
+    pub started: Vec<(String, HandshakeData)>,
+    /// Peers whose renewal could not start or timed out, with the reason
+    pub failed: Vec<(String, String)>,
+}
+
 /// Manages session keys for multiple peers
 #[derive(Debug)]
 pub struct SessionManager {
     /// Active session keys indexed by peer fingerprint
     sessions: HashMap<String, SessionKey>,
+    /// Peers whose session is being renewed
+    renewing: HashMap<String, Renewal>,
+    /// Sessions of disconnected peers and when they left, kept for [`SESSION_GRACE_SECS`]
+    parked: HashMap<String, (SessionKey, u64)>,
 }
 
 impl SessionManager {
@@ -139,13 +191,21 @@ impl SessionManager {
     pub fn new() -> Self {
         Self {
             sessions: HashMap::new(),
+            renewing: HashMap::new(),
+            parked: HashMap::new(),
         }
     }
     
     /// Add a new session key for a peer
-    pub fn add_session(&mut self, peer_fingerprint: String, session_key: SessionKey) {
+    ///
+    /// Returns the messages queued while the previous session was being
+    /// renewed, for the caller to encrypt with the new key and send.
+    pub fn add_session(&mut self, peer_fingerprint: String, session_key: SessionKey) -> Vec<PlainMessage> {
         tracing::info!("Adding session key for peer: {}", peer_fingerprint);
+        let queued = self.renewing.remove(&peer_fingerprint).map(|renewal| renewal.queued).unwrap_or_default();
+        self.parked.remove(&peer_fingerprint);
         self.sessions.insert(peer_fingerprint, session_key);
+        queued
     }
     
     /// Get session key for a peer
@@ -154,23 +214,163 @@ impl SessionManager {
     }
     
     /// Remove session key for a peer (when they disconnect)
+    ///
+    /// Messages still queued for a renewal are dropped.
     pub fn remove_session(&mut self, peer_fingerprint: &str) -> Option<SessionKey> {
         tracing::info!("Removing session key for peer: {}", peer_fingerprint);
+        self.renewing.remove(peer_fingerprint);
+        self.parked.remove(peer_fingerprint);
         self.sessions.remove(peer_fingerprint)
     }
+
+    /// Keep a disconnected peer's session for a grace period instead of removing it
+    ///
+    /// If the same identity reconnects within [`SESSION_GRACE_SECS`],
+    /// [`resume_session`](Self::resume_session) restores the session without a
+    /// new key exchange. Messages queued for a renewal are dropped. Returns
+    /// false if there was no session to keep.
+    pub fn park_session(&mut self, peer_fingerprint: &str) -> bool {
+        self.renewing.remove(peer_fingerprint);
+        match self.sessions.remove(peer_fingerprint) {
+            Some(session_key) => {
+                tracing::info!("Keeping session with {} for {}s after disconnect", peer_fingerprint, SESSION_GRACE_SECS);
+                self.parked.insert(peer_fingerprint.to_string(), (session_key, now_secs()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Restore the session of a peer that reconnected within the grace period
+    ///
+    /// Returns false if a full handshake is needed: the peer was never
+    /// parked, left too long ago, or its session key has expired meanwhile.
+    pub fn resume_session(&mut self, peer_fingerprint: &str) -> bool {
+        let Some((session_key, parked_at)) = self.parked.remove(peer_fingerprint) else {
+            return false;
+        };
+
+        if now_secs().saturating_sub(parked_at) > SESSION_GRACE_SECS || session_key.is_expired() {
+            tracing::info!("Session with {} is too old to resume", peer_fingerprint);
+            return false;
+        }
+
+        tracing::info!("Resuming session with {}", peer_fingerprint);
+        self.sessions.insert(peer_fingerprint.to_string(), session_key);
+        true
+    }
+
+    /// Purge parked sessions whose grace period is over, returning their peers
+    pub fn purge_parked(&mut self) -> Vec<String> {
+        let now = now_secs();
+        let stale: Vec<String> = self.parked
+            .iter()
+            .filter(|(_, (_, parked_at))| now.saturating_sub(*parked_at) > SESSION_GRACE_SECS)
+            .map(|(peer, _)| peer.clone())
+            .collect();
+
+        for peer in &stale {
+            tracing::info!("Purging parked session for peer: {}", peer);
+            self.parked.remove(peer);
+        }
+        stale
+    }
+
+    /// Check if a disconnected peer's session is being kept for a reconnect
+    pub fn is_parked(&self, peer_fingerprint: &str) -> bool {
+        self.parked.contains_key(peer_fingerprint)
+    }
     
-    /// Clean up expired session keys
-    pub fn cleanup_expired(&mut self) {
+    /// Clean up expired session keys, returning the peers they belonged to
+    pub fn cleanup_expired(&mut self) -> Vec<String> {
         let expired_peers: Vec<String> = self.sessions
             .iter()
             .filter(|(_, session)| session.is_expired())
             .map(|(peer, _)| peer.clone())
             .collect();
         
-        for peer in expired_peers {
+        for peer in &expired_peers {
             tracing::info!("Removing expired session key for peer: {}", peer);
-            self.sessions.remove(&peer);
+            self.sessions.remove(peer);
         }
+
+        expired_peers
+    }
+
+    /// Clean up expired session keys and start new handshakes for peers still connected
+    ///
+    /// Returns the handshakes to send. Until each completes and its key is
+    /// passed to [`add_session`](Self::add_session), messages for that peer
+    /// are queued by [`encrypt_or_queue`](Self::encrypt_or_queue). A peer
+    /// whose handshake can't be started doesn't hold up the others, and a
+    /// renewal still pending after [`RENEWAL_TIMEOUT_SECS`] is dropped along
+    /// with its queued messages; both are reported as failed.
+    pub fn renew_expired(
+        &mut self,
+        handshakes: &mut HandshakeManager,
+        is_connected: impl Fn(&str) -> bool,
+    ) -> Renewals {
+        let mut renewals = Renewals::default();
+
+        let now = now_secs();
+        let stalled: Vec<String> = self.renewing
+            .iter()
+            .filter(|(_, renewal)| now.saturating_sub(renewal.started_at) > RENEWAL_TIMEOUT_SECS)
+            .map(|(peer, _)| peer.clone())
+            .collect();
+        for peer in stalled {
+            tracing::warn!("Renewing the session with {} timed out", peer);
+            self.renewing.remove(&peer);
+            renewals.failed.push((peer, "Session renewal timed out".to_string()));
+        }
+
+        for peer in self.cleanup_expired() {
+            if !is_connected(&peer) {
+                continue;
+            }
+
+            tracing::info!("Session with {} expired, starting a new handshake", peer);
+            match handshakes.initiate_handshake(&peer) {
+                Ok(handshake) => {
+                    self.renewing.insert(peer.clone(), Renewal { started_at: now, queued: Vec::new() });
+                    renewals.started.push((peer, handshake));
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to start renewing the session with {}: {}", peer, e);
+                    renewals.failed.push((peer, e.to_string()));
+                }
+            }
+        }
+
+        renewals
+    }
+
+    /// Encrypt a message for a peer, or queue it while the peer's session is renewed
+    ///
+    /// Returns `None` when the message was queued, and an error once
+    /// [`MAX_RENEWAL_QUEUE`] messages are waiting.
+    pub fn encrypt_or_queue(
+        &mut self,
+        peer_fingerprint: &str,
+        message: PlainMessage,
+        sequence: u64,
+    ) -> Result<Option<EncryptedMessage>, Box<dyn std::error::Error>> {
+        if let Some(renewal) = self.renewing.get_mut(peer_fingerprint) {
+            if renewal.queued.len() >= MAX_RENEWAL_QUEUE {
+                return Err(format!("Too many messages waiting for the session with {} to be renewed", peer_fingerprint).into());
+            }
+            renewal.queued.push(message);
+            return Ok(None);
+        }
+
+        let session_key = self.sessions.get(peer_fingerprint)
+            .ok_or_else(|| format!("No session with peer {}", peer_fingerprint))?;
+        MessageCrypto::encrypt_message(session_key, &message, sequence).map(Some)
+    }
+
+    /// Check if a new session with a peer is being negotiated
+    pub fn is_renewing(&self, peer_fingerprint: &str) -> bool {
+        self.renewing.contains_key(peer_fingerprint)
     }
     
     /// Get all active peer fingerprints
@@ -242,4 +442,113 @@ mod tests {
         assert!(!manager.has_session("peer1"));
         assert_eq!(manager.session_count(), 0);
     }
+
+    #[test]
+    fn test_expired_session_is_renewed_and_queued_messages_delivered() {
+        let mut alice = HandshakeManager::new("alice".to_string(), "alice_fp".to_string(), vec![1, 2, 3, 4]);
+        let mut bob = HandshakeManager::new("bob".to_string(), "bob_fp".to_string(), vec![5, 6, 7, 8]);
+        let mut alice_sessions = SessionManager::new();
+
+        // Establish the first session, then let it run out
+        let (bob_session, response) = bob.process_handshake(alice.initiate_handshake("bob_fp").unwrap()).unwrap();
+        let (mut alice_session, _) = alice.process_handshake(response.unwrap()).unwrap();
+        alice_session.created_at -= SESSION_KEY_LIFETIME_SECS + 1;
+        alice_sessions.add_session("bob_fp".to_string(), alice_session);
+        drop(bob_session);
+
+        // Only connected peers get a new handshake
+        let renewals = alice_sessions.renew_expired(&mut alice, |peer| peer == "bob_fp");
+        assert_eq!(renewals.started.len(), 1);
+        assert!(renewals.failed.is_empty());
+        assert!(alice_sessions.is_renewing("bob_fp"));
+        assert!(!alice_sessions.has_session("bob_fp"));
+
+        // Messages sent meanwhile wait for the new key
+        let message = MessageCrypto::create_text_message("alice".to_string(), "still there?".to_string());
+        assert!(alice_sessions.encrypt_or_queue("bob_fp", message, 1).unwrap().is_none());
+
+        let (_, handshake) = renewals.started.into_iter().next().unwrap();
+        let (bob_session, response) = bob.process_handshake(handshake).unwrap();
+        let (alice_session, _) = alice.process_handshake(response.unwrap()).unwrap();
+        let queued = alice_sessions.add_session("bob_fp".to_string(), alice_session);
+        assert!(!alice_sessions.is_renewing("bob_fp"));
+        assert_eq!(queued.len(), 1);
+
+        // The queued message goes out under the new key and Bob can read it
+        let encrypted = alice_sessions.encrypt_or_queue("bob_fp", queued[0].clone(), 2).unwrap().unwrap();
+        let delivered = MessageCrypto::decrypt_message(&bob_session, &encrypted).unwrap();
+        assert_eq!(delivered.content, "still there?");
+    }
+
+    #[test]
+    fn test_expired_session_of_disconnected_peer_is_dropped() {
+        let mut handshakes = HandshakeManager::new("alice".to_string(), "alice_fp".to_string(), vec![1]);
+        let mut manager = SessionManager::new();
+        let mut session_key = SessionKey::generate("gone_fp".to_string());
+        session_key.created_at -= SESSION_KEY_LIFETIME_SECS + 1;
+        manager.add_session("gone_fp".to_string(), session_key);
+
+        let renewals = manager.renew_expired(&mut handshakes, |_| false);
+        assert!(renewals.started.is_empty() && renewals.failed.is_empty());
+        assert!(!manager.is_renewing("gone_fp"));
+        assert!(manager.encrypt_or_queue("gone_fp", MessageCrypto::create_system_message("alice".to_string(), "hi".to_string()), 1).is_err());
+    }
+
+    #[test]
+    fn test_stalled_renewal_is_bounded_and_times_out() {
+        let mut handshakes = HandshakeManager::new("alice".to_string(), "alice_fp".to_string(), vec![1]);
+        let mut manager = SessionManager::new();
+        let mut session_key = SessionKey::generate("bob_fp".to_string());
+        session_key.created_at -= SESSION_KEY_LIFETIME_SECS + 1;
+        manager.add_session("bob_fp".to_string(), session_key);
+        assert_eq!(manager.renew_expired(&mut handshakes, |_| true).started.len(), 1);
+
+        // The queue stops growing while Bob doesn't answer
+        let message = || MessageCrypto::create_text_message("alice".to_string(), "hello?".to_string());
+        for sequence in 0..MAX_RENEWAL_QUEUE as u64 {
+            assert!(manager.encrypt_or_queue("bob_fp", message(), sequence).unwrap().is_none());
+        }
+        assert!(manager.encrypt_or_queue("bob_fp", message(), 0).is_err());
+
+        // And is given up once the renewal has waited too long
+        manager.renewing.get_mut("bob_fp").unwrap().started_at -= RENEWAL_TIMEOUT_SECS + 1;
+        let renewals = manager.renew_expired(&mut handshakes, |_| true);
+        assert!(renewals.started.is_empty());
+        assert_eq!(renewals.failed.len(), 1);
+        assert!(!manager.is_renewing("bob_fp"));
+    }
+
+    #[test]
+    fn test_quick_reconnect_resumes_parked_session() {
+        let mut manager = SessionManager::new();
+        let session_key = SessionKey::generate("bob_fp".to_string());
+        let key = *session_key.key();
+        manager.add_session("bob_fp".to_string(), session_key);
+
+        assert!(manager.park_session("bob_fp"));
+        assert!(!manager.has_session("bob_fp"));
+        assert!(manager.is_parked("bob_fp"));
+
+        // Same key as before, no new handshake
+        assert!(manager.resume_session("bob_fp"));
+        assert_eq!(manager.get_session("bob_fp").unwrap().key(), &key);
+        assert!(!manager.is_parked("bob_fp"));
+        assert!(!manager.park_session("nobody"));
+    }
+
+    #[test]
+    fn test_parked_session_is_purged_after_grace_period() {
+        let mut manager = SessionManager::new();
+        manager.add_session("bob_fp".to_string(), SessionKey::generate("bob_fp".to_string()));
+        manager.add_session("carol_fp".to_string(), SessionKey::generate("carol_fp".to_string()));
+        manager.park_session("bob_fp");
+        manager.park_session("carol_fp");
+
+        for peer in ["bob_fp", "carol_fp"] {
+            manager.parked.get_mut(peer).unwrap().1 -= SESSION_GRACE_SECS + 1;
+        }
+        assert!(!manager.resume_session("bob_fp"));
+        assert_eq!(manager.purge_parked(), vec!["carol_fp".to_string()]);
+        assert!(!manager.is_parked("carol_fp"));
+    }
 }
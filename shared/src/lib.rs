@@ -5,9 +5,10 @@ pub mod p2p;
 pub mod tls;
 pub mod constants;
 pub mod crypto;
+pub mod utils;
 
 // re-export main types for convenience
-pub use message::{P2PMessage, PeerInfo};
+pub use message::{ChatKind, MessageSecurity, P2PMessage, PeerInfo, PresenceStatus};
 pub use config::*;
 pub use tls::{TlsContext, TlsConfig, CertificateManager};
 pub use p2p::{P2PNode, P2PEvent, P2PStats, P2PNodeConfig};
@@ -27,12 +27,24 @@ pub enum P2PMessage {
         content: String,
         ttl: u8, // Time to live for message flooding
         seen_by: Vec<String>, // Peers that have already seen this message
+        #[serde(default)]
+        kind: ChatKind, // Plain text or /me action
+        #[serde(default)]
+        sequence: u64, // Per-sender order, 0 from builds that don't number messages
     },
     /// Peer connection handshake
     Handshake {
         peer_id: String,
         username: String,
         protocol_version: String,
+        #[serde(default)]
+        fingerprint: String, // Identity fingerprint, empty if the peer has none
+        #[serde(default)]
+        build_version: String, // Release of the peer's build, empty for older builds
+        #[serde(default)]
+        heartbeat_interval_secs: u64, // Seconds between the peer's heartbeats, 0 from older builds
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        room_proof: String, // Room key proof for this connection, empty without a room key
     },
     /// Heartbeat to maintain connection
     Heartbeat {
@@ -44,6 +56,71 @@ pub enum P2PMessage {
         peer_id: String,
         reason: String,
     },
+    /// Availability change, sent to direct peers only
+    Presence {
+        peer_id: String,
+        status: PresenceStatus,
+        #[serde(default)]
+        message: String, // Optional note such as "back in 10", empty if none
+    },
+    /// The user is composing a message, sent to direct peers only
+    Typing {
+        peer_id: String,
+        username: String,
+    },
+    /// Round-trip probe, answered by a `Pong` with the same nonce
+    Ping {
+        peer_id: String,
+        nonce: u64,
+    },
+    /// Answer to a `Ping`
+    Pong {
+        peer_id: String,
+        nonce: u64,
+    },
+}
+
+/// Whether a user is watching the chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PresenceStatus {
+    /// Active, the default for a new connection
+    #[default]
+    Online,
+    /// Not watching the chat
+    Away,
+    /// Watching, but not wanting to be disturbed
+    Busy,
+}
+
+impl fmt::Display for PresenceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PresenceStatus::Online => "online",
+            PresenceStatus::Away => "away",
+            PresenceStatus::Busy => "busy",
+        })
+    }
+}
+
+/// How the content of a chat message should be presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChatKind {
+    /// Regular `username: content` message
+    #[default]
+    Text,
+    /// `/me` action, rendered as `* username content`
+    Action,
+}
+
+/// How a received message was protected on its way to us
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageSecurity {
+    /// Encrypted end-to-end with a session key
+    EndToEnd,
+    /// Protected only by TLS on the connection it arrived over
+    Transport,
+    /// Arrived over an unencrypted connection
+    Plain,
 }
 
 /// Information about a peer in the network
@@ -68,10 +145,13 @@ impl fmt::Display for P2PMessage {
             P2PMessage::PeerListResponse { peers } => {
                 write!(f, "*** Peer list response with {} peers", peers.len())
             }
+            P2PMessage::ChatMessage { username, content, kind: ChatKind::Action, .. } => {
+                write!(f, "* {} {}", username, content)
+            }
             P2PMessage::ChatMessage { username, content, .. } => {
                 write!(f, "{}: {}", username, content)
             }
-            P2PMessage::Handshake { peer_id, username, protocol_version } => {
+            P2PMessage::Handshake { peer_id, username, protocol_version, .. } => {
                 write!(f, "*** Handshake from {} ({}) using protocol {}", username, peer_id, protocol_version)
             }
             P2PMessage::Heartbeat { peer_id, .. } => {
@@ -80,6 +160,18 @@ impl fmt::Display for P2PMessage {
             P2PMessage::Disconnect { peer_id, reason } => {
                 write!(f, "*** Peer {} disconnected: {}", peer_id, reason)
             }
+            P2PMessage::Presence { peer_id, status, .. } => {
+                write!(f, "*** Peer {} is {}", peer_id, status)
+            }
+            P2PMessage::Typing { username, .. } => {
+                write!(f, "*** {} is typing", username)
+            }
+            P2PMessage::Ping { peer_id, .. } => {
+                write!(f, "*** Ping from {}", peer_id)
+            }
+            P2PMessage::Pong { peer_id, .. } => {
+                write!(f, "*** Pong from {}", peer_id)
+            }
         }
     }
 }
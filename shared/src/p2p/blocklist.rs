@@ -0,0 +1,207 @@
+/// Identity block list for refusing peer connections
+use std::collections::HashSet;
+use std::fs;
+use std::fmt::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use identity_gen::FileManager;
+use tracing::{info, warn};
+
+/// Set of identity fingerprints whose connections are refused
+///
+/// The file format is one fingerprint per line; blank lines and lines
+/// starting with `#` are ignored. Cloning shares the same underlying set.
+///
+/// Fingerprints are matched against the one a peer states in its handshake.
+/// Only with mutual TLS is that checked against the peer's certificate,
+/// otherwise a blocked peer can get around the list by claiming another.
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    path: Option<PathBuf>,
+    fingerprints: Arc<RwLock<HashSet<String>>>,
+}
+
+impl Blocklist {
+    /// Create an empty in-memory block list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a block list from a file, a missing file means nothing is blocked
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let fingerprints = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        info!("Loaded {} blocked fingerprints from {}", fingerprints.len(), path.display());
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            fingerprints: Arc::new(RwLock::new(fingerprints)),
+        })
+    }
+
+    /// Check whether a fingerprint is blocked, an empty fingerprint never is
+    pub fn is_blocked(&self, fingerprint: &str) -> bool {
+        !fingerprint.is_empty() && self.fingerprints.read().unwrap().contains(fingerprint)
+    }
+
+    /// Block a fingerprint and add it to the backing file
+    ///
+    /// Returns false if it was already blocked
+    pub fn block(&self, fingerprint: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let fingerprint = fingerprint.trim();
+        if fingerprint.is_empty() {
+            return Err("Fingerprint cannot be empty".into());
+        }
+
+        if !self.fingerprints.write().unwrap().insert(fingerprint.to_string()) {
+            return Ok(false);
+        }
+
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // Rewrite the whole file atomically, keeping comments, so a crash can't truncate it
+            let mut contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e.into()),
+            };
+            if !contents.is_empty() && !contents.ends_with('\n') {
+                contents.push('\n');
+            }
+            writeln!(contents, "{}", fingerprint)?;
+            FileManager::write_atomic(path, contents.as_bytes(), 0o644)?;
+        } else {
+            warn!("Blocked {} for this session only, no block list file configured", fingerprint);
+        }
+
+        Ok(true)
+    }
+
+    /// Number of blocked fingerprints
+    pub fn len(&self) -> usize {
+        self.fingerprints.read().unwrap().len()
+    }
+
+    /// Whether nothing is blocked
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Peers allowed to connect when a node runs in allowlist mode
+///
+/// Uses the block list file format, but entries may also be IP addresses
+/// or `ip:port`, and a peer has to match one of them to be let in. Like the
+/// block list, fingerprint entries are matched against the fingerprint a
+/// peer states, which is only proven with mutual TLS.
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist {
+    fingerprints: HashSet<String>,
+    addresses: HashSet<String>,
+}
+
+impl Allowlist {
+    /// Load an allowlist from a file, a missing file allows no one
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut allowlist = Self::default();
+        for entry in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            if entry.parse::<IpAddr>().is_ok() || entry.parse::<SocketAddr>().is_ok() {
+                allowlist.addresses.insert(entry.to_string());
+            } else {
+                allowlist.fingerprints.insert(entry.to_string());
+            }
+        }
+
+        info!(
+            "Loaded {} fingerprints and {} addresses from allowlist {}",
+            allowlist.fingerprints.len(),
+            allowlist.addresses.len(),
+            path.display()
+        );
+        Ok(allowlist)
+    }
+
+    /// Check whether a peer is listed by fingerprint, IP address or `ip:port`
+    pub fn matches(&self, fingerprint: &str, addr: SocketAddr) -> bool {
+        (!fingerprint.is_empty() && self.fingerprints.contains(fingerprint))
+            || self.addresses.contains(&addr.ip().to_string())
+            || self.addresses.contains(&addr.to_string())
+    }
+
+    /// Whether any entries are identity fingerprints rather than addresses
+    pub fn has_fingerprints(&self) -> bool {
+        !self.fingerprints.is_empty()
+    }
+
+    /// Whether the list allows no one
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty() && self.addresses.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocklist_persists_to_file() {
+        let path = std::env::temp_dir()
+            .join(format!("dpq-blocklist-{}", uuid::Uuid::new_v4()))
+            .join("blocklist");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "# blocked peers\nab:cd\n\n").unwrap();
+
+        let blocklist = Blocklist::load(&path).unwrap();
+        assert!(blocklist.is_blocked("ab:cd"));
+        assert!(!blocklist.is_blocked(""));
+
+        assert!(blocklist.block("ef:01").unwrap());
+        assert!(!blocklist.block("ef:01").unwrap());
+
+        let reloaded = Blocklist::load(&path).unwrap();
+        assert!(reloaded.is_blocked("ef:01"));
+        assert_eq!(reloaded.len(), 2);
+
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_missing_blocklist_file_is_empty() {
+        let path = std::env::temp_dir().join(format!("dpq-missing-{}", uuid::Uuid::new_v4()));
+        assert!(Blocklist::load(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_allowlist_matches_fingerprint_or_address() {
+        let path = std::env::temp_dir().join(format!("dpq-allowlist-{}", uuid::Uuid::new_v4()));
+        fs::write(&path, "aa:aa\n10.0.0.5\n10.0.0.6:40000\n").unwrap();
+        let allowlist = Allowlist::load(&path).unwrap();
+
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        assert!(allowlist.matches("aa:aa", addr("192.168.1.2:50000")));
+        assert!(allowlist.matches("", addr("10.0.0.5:51234")));
+        assert!(allowlist.matches("", addr("10.0.0.6:40000")));
+        assert!(!allowlist.matches("", addr("10.0.0.6:40001")));
+        assert!(!allowlist.matches("bb:bb", addr("192.168.1.2:50000")));
+        assert!(allowlist.has_fingerprints());
+
+        fs::remove_file(&path).ok();
+    }
+}
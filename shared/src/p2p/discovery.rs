@@ -1,27 +1,76 @@
 /// Peer discovery mechanisms for P2P networking
-use std::net::SocketAddr;
+use crate::config::{DEFAULT_MULTICAST_GROUP, DEFAULT_MULTICAST_PORT, PROTOCOL_VERSION};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
 use tokio::time::{interval, timeout};
+use tokio_util::sync::CancellationToken;
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn, debug};
 
 /// Discovery methods for finding peers
+///
+/// A node runs every method in its configuration side by side. An empty list
+/// is valid: the node then only knows the peers it dials itself, such as
+/// `P2PNodeConfig::bootstrap_peers`, which suits networks that block multicast.
 #[derive(Debug, Clone)]
 pub enum DiscoveryMethod {
     /// Multicast discovery on local network
+    ///
+    /// Announces this node to the group every 30 seconds and reports the
+    /// announcements of other nodes. Only reaches hosts on the same LAN segment.
+    /// Nodes using a different group or port don't see each other, which keeps
+    /// unrelated deployments on one LAN apart.
     Multicast {
         multicast_addr: SocketAddr,
         interface: Option<std::net::Ipv4Addr>,
     },
+    /// mDNS / DNS-SD service discovery
+    ///
+    /// Registers this node as an instance of `service_name` (e.g.
+    /// `_dpqchat._tcp.local.`) and reports every other instance that resolves.
+    /// Unlike `Multicast` the node shows up in generic tools such as
+    /// `avahi-browse` or `dns-sd -B`.
+    Mdns {
+        service_name: String,
+    },
     /// Bootstrap from known peers
+    ///
+    /// Asks each listed peer once, over UDP, for the peers it knows about.
     Bootstrap {
         peers: Vec<SocketAddr>,
     },
+    /// Trusted peers listed in a file, one `ip:port` per line
+    ///
+    /// The file is re-read every few seconds. Added addresses are dialed, and
+    /// removed ones are disconnected if `disconnect_removed` is set. A missing
+    /// or unreadable file keeps the last list it had.
+    StaticFile {
+        path: PathBuf,
+        disconnect_removed: bool,
+    },
     /// Manual peer addition
+    ///
+    /// Starts nothing; peers are only added with `add_manual_peer` or dialed directly.
     Manual,
 }
 
+/// What a discovery method reports to the node
+#[derive(Debug, Clone)]
+pub enum DiscoveryEvent {
+    /// A peer announced itself or was reported by another peer
+    Discovered(DiscoveredPeer),
+    /// A trusted address that should be dialed
+    Listed(SocketAddr),
+    /// A trusted address was dropped from its list and should be disconnected
+    Unlisted(SocketAddr),
+}
+
 /// Discovery message types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DiscoveryMessage {
@@ -64,7 +113,8 @@ pub struct PeerDiscovery {
     discovery_methods: Vec<DiscoveryMethod>,
     discovered_peers: std::collections::HashMap<String, DiscoveredPeer>,
     protocol_version: String,
-    running: std::sync::Arc<tokio::sync::RwLock<bool>>,
+    shutdown: CancellationToken,
+    tasks: Vec<JoinHandle<()>>,
 }
 
 impl PeerDiscovery {
@@ -81,28 +131,40 @@ impl PeerDiscovery {
             listen_addr,
             discovery_methods,
             discovered_peers: std::collections::HashMap::new(),
-            protocol_version: "1.0".to_string(),
-            running: std::sync::Arc::new(tokio::sync::RwLock::new(false)),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            shutdown: CancellationToken::new(),
+            tasks: Vec::new(),
         }
     }
 
     /// Start the discovery service
-    pub async fn start(&mut self) -> Result<tokio::sync::mpsc::Receiver<DiscoveredPeer>, Box<dyn std::error::Error + Send + Sync>> {
-        // Set running flag
-        {
-            let mut running = self.running.write().await;
-            *running = true;
-        }
+    pub async fn start(&mut self) -> Result<tokio::sync::mpsc::Receiver<DiscoveryEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        // Fresh token so discovery can be restarted after stop
+        self.shutdown = CancellationToken::new();
         
         let (tx, rx) = tokio::sync::mpsc::channel(100);
 
-        for method in &self.discovery_methods {
+        if self.discovery_methods.is_empty() {
+            info!("No discovery methods enabled, only dialed peers will be found");
+        }
+
+        for method in self.discovery_methods.clone() {
             match method {
                 DiscoveryMethod::Multicast { multicast_addr, interface } => {
-                    self.start_multicast_discovery(*multicast_addr, *interface, tx.clone()).await?;
+                    let tasks = self.start_multicast_discovery(multicast_addr, interface, tx.clone()).await?;
+                    self.tasks.extend(tasks);
+                }
+                DiscoveryMethod::Mdns { service_name } => {
+                    let task = self.start_mdns_discovery(&service_name, tx.clone())?;
+                    self.tasks.push(task);
                 }
                 DiscoveryMethod::Bootstrap { peers } => {
-                    self.start_bootstrap_discovery(peers.clone(), tx.clone()).await?;
+                    let tasks = self.start_bootstrap_discovery(peers, tx.clone()).await?;
+                    self.tasks.extend(tasks);
+                }
+                DiscoveryMethod::StaticFile { path, disconnect_removed } => {
+                    let task = self.start_static_file_discovery(path, disconnect_removed, tx.clone())?;
+                    self.tasks.push(task);
                 }
                 DiscoveryMethod::Manual => {
                     info!("Manual discovery method enabled");
@@ -116,8 +178,14 @@ impl PeerDiscovery {
     /// Stop the discovery service
     pub async fn stop(&mut self) {
         info!("Stopping peer discovery");
-        let mut running = self.running.write().await;
-        *running = false;
+        self.shutdown.cancel();
+        
+        // Wait for the tasks so their sockets are closed
+        for task in self.tasks.drain(..) {
+            if timeout(Duration::from_secs(2), task).await.is_err() {
+                warn!("Timed out waiting for a discovery task to finish");
+            }
+        }
     }
 
     /// Start multicast discovery
@@ -125,34 +193,34 @@ impl PeerDiscovery {
         &self,
         multicast_addr: SocketAddr,
         _interface: Option<std::net::Ipv4Addr>,
-        tx: tokio::sync::mpsc::Sender<DiscoveredPeer>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        tx: tokio::sync::mpsc::Sender<DiscoveryEvent>,
+    ) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error + Send + Sync>> {
+        let group = match multicast_addr {
+            SocketAddr::V4(addr) if addr.ip().is_multicast() => *addr.ip(),
+            _ => return Err(format!("Discovery address {} is not an IPv4 multicast address", multicast_addr).into()),
+        };
         info!("Starting multicast discovery on {}", multicast_addr);
 
-        let socket = UdpSocket::bind("0.0.0.0:0").await?;
-        socket.join_multicast_v4(
-            multicast_addr.ip().to_string().parse()?,
-            std::net::Ipv4Addr::UNSPECIFIED,
-        )?;
+        let socket = bind_multicast_listener(group, multicast_addr.port())?;
+        socket.join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
 
         let peer_id = self.peer_id.clone();
         let username = self.username.clone();
         let listen_addr = self.listen_addr;
         let protocol_version = self.protocol_version.clone();
-        let running = self.running.clone();
 
         // Spawn announcement task
         let announce_socket = UdpSocket::bind("0.0.0.0:0").await?;
-        announce_socket.join_multicast_v4(
-            multicast_addr.ip().to_string().parse()?,
-            std::net::Ipv4Addr::UNSPECIFIED,
-        )?;
+        announce_socket.join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
         let peer_id_announce = peer_id.clone();
-        let running_announce = running.clone();
-        tokio::spawn(async move {
+        let shutdown_announce = self.shutdown.clone();
+        let announce_task = tokio::spawn(async move {
             let mut interval = interval(Duration::from_secs(30));
-            while *running_announce.read().await {
-                interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = shutdown_announce.cancelled() => break,
+                    _ = interval.tick() => {}
+                }
                 
                 let announce_msg = DiscoveryMessage::Announce {
                     peer_id: peer_id_announce.clone(),
@@ -178,11 +246,16 @@ impl PeerDiscovery {
         // Spawn listener task
         let listen_socket = socket;
         let tx_clone = tx.clone();
-        let running_listen = running.clone();
-        tokio::spawn(async move {
+        let shutdown_listen = self.shutdown.clone();
+        let listen_task = tokio::spawn(async move {
             let mut buf = [0u8; 1024];
-            while *running_listen.read().await {
-                match listen_socket.recv_from(&mut buf).await {
+            loop {
+                let received = tokio::select! {
+                    _ = shutdown_listen.cancelled() => break,
+                    received = listen_socket.recv_from(&mut buf) => received,
+                };
+                
+                match received {
                     Ok((len, from_addr)) => {
                         if let Ok(msg) = serde_json::from_slice::<DiscoveryMessage>(&buf[..len]) {
                             match msg {
@@ -203,7 +276,7 @@ impl PeerDiscovery {
                                         };
 
                                         debug!("Discovered peer via multicast: {:?}", discovered_peer);
-                                        if let Err(e) = tx_clone.send(discovered_peer).await {
+                                        if let Err(e) = tx_clone.send(DiscoveryEvent::Discovered(discovered_peer)).await {
                                             warn!("Failed to send discovered peer: {}", e);
                                         }
                                     }
@@ -221,33 +294,166 @@ impl PeerDiscovery {
             }
         });
 
-        Ok(())
+        Ok(vec![announce_task, listen_task])
+    }
+
+    /// Start mDNS discovery: advertise this node and browse for others
+    fn start_mdns_discovery(
+        &self,
+        service_name: &str,
+        tx: tokio::sync::mpsc::Sender<DiscoveryEvent>,
+    ) -> Result<JoinHandle<()>, Box<dyn std::error::Error + Send + Sync>> {
+        info!("Starting mDNS discovery for {}", service_name);
+
+        let daemon = ServiceDaemon::new()?;
+
+        let properties: HashMap<String, String> = [
+            ("peer_id".to_string(), self.peer_id.clone()),
+            ("username".to_string(), self.username.clone()),
+            ("protocol_version".to_string(), self.protocol_version.clone()),
+        ]
+        .into_iter()
+        .collect();
+        let host_name = format!("{}.local.", self.peer_id);
+        let port = self.listen_addr.port();
+
+        // A wildcard listen address is reachable on every interface, so let
+        // the daemon advertise whatever addresses the host has
+        let service = if self.listen_addr.ip().is_unspecified() {
+            ServiceInfo::new(service_name, &self.peer_id, &host_name, (), port, properties)?.enable_addr_auto()
+        } else {
+            ServiceInfo::new(service_name, &self.peer_id, &host_name, self.listen_addr.ip(), port, properties)?
+        };
+        daemon.register(service)?;
+        let events = daemon.browse(service_name)?;
+
+        let peer_id = self.peer_id.clone();
+        let shutdown = self.shutdown.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    event = events.recv_async() => event,
+                };
+
+                match event {
+                    Ok(ServiceEvent::ServiceResolved(info)) => {
+                        if let Some(discovered_peer) = peer_from_service(&info, &peer_id) {
+                            debug!("Discovered peer via mDNS: {:?}", discovered_peer);
+                            if let Err(e) = tx.send(DiscoveryEvent::Discovered(discovered_peer)).await {
+                                warn!("Failed to send discovered peer: {}", e);
+                            }
+                        }
+                    }
+                    Ok(other) => debug!("mDNS event: {:?}", other),
+                    Err(_) => {
+                        debug!("mDNS browse channel closed");
+                        break;
+                    }
+                }
+            }
+
+            // Withdraws our registration and stops the daemon thread
+            if let Err(e) = daemon.shutdown() {
+                warn!("Failed to stop mDNS daemon: {}", e);
+            }
+        });
+
+        Ok(task)
+    }
+
+    /// Start watching a peers file, dialing what it lists
+    ///
+    /// The file has to be readable at startup, so a mistyped path fails
+    /// loudly instead of silently finding nobody.
+    fn start_static_file_discovery(
+        &self,
+        path: PathBuf,
+        disconnect_removed: bool,
+        tx: tokio::sync::mpsc::Sender<DiscoveryEvent>,
+    ) -> Result<JoinHandle<()>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Err(e) = load_peers_file(&path) {
+            return Err(format!("Cannot read peers file {}: {}", path.display(), e).into());
+        }
+        info!("Watching peers file {}", path.display());
+
+        let shutdown = self.shutdown.clone();
+        Ok(tokio::spawn(async move {
+            let mut listed: Vec<SocketAddr> = Vec::new();
+            let mut readable = true;
+            let mut interval = interval(Duration::from_secs(STATIC_FILE_POLL_SECS));
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = interval.tick() => {}
+                }
+
+                let peers = match load_peers_file(&path) {
+                    Ok(peers) => {
+                        readable = true;
+                        peers
+                    }
+                    Err(e) => {
+                        // Editors often replace the file, so only warn once per outage
+                        if readable {
+                            warn!("Cannot read peers file {}: {}", path.display(), e);
+                            readable = false;
+                        }
+                        continue;
+                    }
+                };
+
+                let (added, removed) = diff_peers(&listed, &peers);
+                let mut events: Vec<DiscoveryEvent> = added.into_iter().map(DiscoveryEvent::Listed).collect();
+                for addr in removed {
+                    info!("{} was removed from {}", addr, path.display());
+                    if disconnect_removed {
+                        events.push(DiscoveryEvent::Unlisted(addr));
+                    }
+                }
+                listed = peers;
+
+                for event in events {
+                    if tx.send(event).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }))
     }
 
     /// Start bootstrap discovery
     async fn start_bootstrap_discovery(
         &self,
         bootstrap_peers: Vec<SocketAddr>,
-        tx: tokio::sync::mpsc::Sender<DiscoveredPeer>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        tx: tokio::sync::mpsc::Sender<DiscoveryEvent>,
+    ) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error + Send + Sync>> {
         info!("Starting bootstrap discovery with {} peers", bootstrap_peers.len());
 
         let peer_id = self.peer_id.clone();
         let username = self.username.clone();
         let protocol_version = self.protocol_version.clone();
+        let mut tasks = Vec::new();
 
         for bootstrap_addr in bootstrap_peers {
             let tx_clone = tx.clone();
             let peer_id_clone = peer_id.clone();
             let username_clone = username.clone();
             let protocol_version_clone = protocol_version.clone();
+            let shutdown = self.shutdown.clone();
 
-            tokio::spawn(async move {
+            tasks.push(tokio::spawn(async move {
                 // Try to connect to bootstrap peer and request peer list
-                match Self::query_bootstrap_peer(bootstrap_addr, peer_id_clone, username_clone, protocol_version_clone).await {
+                let query = Self::query_bootstrap_peer(bootstrap_addr, peer_id_clone, username_clone, protocol_version_clone);
+                let result = tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    result = query => result,
+                };
+                
+                match result {
                     Ok(peers) => {
                         for peer in peers {
-                            if let Err(e) = tx_clone.send(peer).await {
+                            if let Err(e) = tx_clone.send(DiscoveryEvent::Discovered(peer)).await {
                                 warn!("Failed to send bootstrap discovered peer: {}", e);
                             }
                         }
@@ -256,10 +462,10 @@ impl PeerDiscovery {
                         warn!("Failed to query bootstrap peer {}: {}", bootstrap_addr, e);
                     }
                 }
-            });
+            }));
         }
 
-        Ok(())
+        Ok(tasks)
     }
 
     /// Query a bootstrap peer for its peer list
@@ -346,15 +552,218 @@ impl PeerDiscovery {
 }
 
 /// Default multicast address for P2P discovery
-pub const DEFAULT_MULTICAST_ADDR: &str = "239.255.42.99:8899";
+pub const DEFAULT_MULTICAST_ADDR: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(DEFAULT_MULTICAST_GROUP, DEFAULT_MULTICAST_PORT));
+
+/// Socket receiving announcements sent to `group:port`
+///
+/// The port is shared so several nodes can run on one machine. Binding to the
+/// group rather than any address keeps out traffic for other groups that use
+/// the same port; Windows can't bind to a multicast address, so there only
+/// the port separates deployments.
+fn bind_multicast_listener(group: Ipv4Addr, port: u16) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+
+    let bind_ip = if cfg!(windows) { Ipv4Addr::UNSPECIFIED } else { group };
+    socket.bind(&SocketAddrV4::new(bind_ip, port).into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// DNS-SD service type chat nodes register under
+pub const DEFAULT_MDNS_SERVICE: &str = "_dpqchat._tcp.local.";
+
+/// Turn a resolved mDNS service into a discovered peer, skipping our own
+fn peer_from_service(info: &ServiceInfo, own_peer_id: &str) -> Option<DiscoveredPeer> {
+    let peer_id = info.get_property_val_str("peer_id")?.to_string();
+    if peer_id == own_peer_id {
+        return None;
+    }
+
+    // Prefer IPv4, the listener is normally bound to an IPv4 address
+    let addresses = info.get_addresses();
+    let ip = addresses
+        .iter()
+        .find(|ip| ip.is_ipv4())
+        .or_else(|| addresses.iter().next())
+        .copied()?;
+
+    Some(DiscoveredPeer {
+        peer_id,
+        addr: SocketAddr::new(ip, info.get_port()),
+        username: info.get_property_val_str("username").unwrap_or_default().to_string(),
+        last_seen: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        protocol_version: info.get_property_val_str("protocol_version").unwrap_or_default().to_string(),
+    })
+}
+
+/// How often a `StaticFile` peers list is re-read
+pub const STATIC_FILE_POLL_SECS: u64 = 5;
+
+/// Addresses added to and removed from a peers list, in file order
+fn diff_peers(old: &[SocketAddr], new: &[SocketAddr]) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+    let added = new.iter().filter(|addr| !old.contains(addr)).copied().collect();
+    let removed = old.iter().filter(|addr| !new.contains(addr)).copied().collect();
+    (added, removed)
+}
+
+/// Read a peers file: one `ip:port` per line
+///
+/// Blank lines and lines starting with `#` are ignored. Malformed lines are
+/// logged and skipped, only failing to read the file is an error.
+pub fn load_peers_file(path: &Path) -> Result<Vec<SocketAddr>, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(parse_peers(&contents, path))
+}
+
+/// Parse the contents of a peers file, see [`load_peers_file`]
+fn parse_peers(contents: &str, path: &Path) -> Vec<SocketAddr> {
+    let mut peers = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.parse::<SocketAddr>() {
+            Ok(addr) if !peers.contains(&addr) => peers.push(addr),
+            Ok(_) => {}
+            Err(e) => warn!("Skipping {}:{}: '{}' is not ip:port ({})", path.display(), number + 1, line, e),
+        }
+    }
+    peers
+}
 
 /// Create default discovery methods
 pub fn default_discovery_methods() -> Vec<DiscoveryMethod> {
     vec![
         DiscoveryMethod::Multicast {
-            multicast_addr: DEFAULT_MULTICAST_ADDR.parse().unwrap(),
+            multicast_addr: DEFAULT_MULTICAST_ADDR,
             interface: None,
         },
         DiscoveryMethod::Manual,
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_peers_file_skips_malformed_lines() {
+        let contents = "# trusted peers\n192.168.1.20:40000\n\nnot-an-address\n10.0.0.5:40001\n192.168.1.20:40000\n";
+        let peers = parse_peers(contents, Path::new("peers"));
+        assert_eq!(peers, vec!["192.168.1.20:40000".parse().unwrap(), "10.0.0.5:40001".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_peers_file_changes_are_diffed() {
+        let a: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        let b: SocketAddr = "10.0.0.2:40000".parse().unwrap();
+        let c: SocketAddr = "10.0.0.3:40000".parse().unwrap();
+
+        assert_eq!(diff_peers(&[], &[a, b]), (vec![a, b], vec![]));
+        assert_eq!(diff_peers(&[a, b], &[b, c]), (vec![c], vec![a]));
+        assert_eq!(diff_peers(&[a], &[a]), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_mdns_service_becomes_discovered_peer() {
+        let properties: HashMap<String, String> = [
+            ("peer_id".to_string(), "node-b".to_string()),
+            ("username".to_string(), "bob".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let info = ServiceInfo::new(
+            DEFAULT_MDNS_SERVICE,
+            "node-b",
+            "node-b.local.",
+            IpAddr::from([192, 168, 1, 20]),
+            40000,
+            properties,
+        )
+        .unwrap();
+
+        let peer = peer_from_service(&info, "node-a").unwrap();
+        assert_eq!(peer.addr, "192.168.1.20:40000".parse().unwrap());
+        assert_eq!(peer.username, "bob");
+
+        // Our own advertisement is ignored
+        assert!(peer_from_service(&info, "node-b").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_multicast_groups_are_isolated() {
+        let multicast = |addr: &str| {
+            vec![DiscoveryMethod::Multicast {
+                multicast_addr: addr.parse().unwrap(),
+                interface: None,
+            }]
+        };
+        let node = |id: &str, addr: &str| {
+            PeerDiscovery::new(id.to_string(), id.to_string(), "127.0.0.1:40000".parse().unwrap(), multicast(addr))
+        };
+
+        let mut listener = node("listener", "239.255.77.1:18899");
+        let mut rx = listener.start().await.unwrap();
+        let mut other_group = node("other-group", "239.255.77.2:18899");
+        let mut other_port = node("other-port", "239.255.77.1:18900");
+        let mut same = node("same", "239.255.77.1:18899");
+        let _other_group_rx = other_group.start().await.unwrap();
+        let _other_port_rx = other_port.start().await.unwrap();
+        let _same_rx = same.start().await.unwrap();
+
+        // Only the node on the same group and port is heard
+        let mut heard = Vec::new();
+        while let Ok(Some(DiscoveryEvent::Discovered(peer))) = timeout(Duration::from_millis(500), rx.recv()).await {
+            heard.push(peer.peer_id);
+        }
+        assert_eq!(heard, vec!["same".to_string()]);
+
+        for discovery in [&mut listener, &mut other_group, &mut other_port, &mut same] {
+            discovery.stop().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multicast_rejects_unicast_address() {
+        let mut discovery = PeerDiscovery::new(
+            "me".to_string(),
+            "me".to_string(),
+            "127.0.0.1:0".parse().unwrap(),
+            vec![DiscoveryMethod::Multicast {
+                multicast_addr: "192.168.1.10:8899".parse().unwrap(),
+                interface: None,
+            }],
+        );
+        let error = discovery.start().await.unwrap_err();
+        assert!(error.to_string().contains("not an IPv4 multicast address"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_peers_file_fails_at_startup() {
+        let path = std::env::temp_dir().join(format!("dpq-missing-peers-{}", uuid::Uuid::new_v4()));
+        let mut discovery = PeerDiscovery::new(
+            "me".to_string(),
+            "me".to_string(),
+            "127.0.0.1:0".parse().unwrap(),
+            vec![DiscoveryMethod::StaticFile { path: path.clone(), disconnect_removed: false }],
+        );
+        let error = discovery.start().await.unwrap_err();
+        assert!(error.to_string().starts_with(&format!("Cannot read peers file {}", path.display())));
+    }
+
+    #[tokio::test]
+    async fn test_discovery_without_methods_finds_nothing() {
+        let mut discovery = PeerDiscovery::new("me".to_string(), "me".to_string(), "127.0.0.1:0".parse().unwrap(), vec![]);
+        let mut rx = discovery.start().await.unwrap();
+        assert!(rx.recv().await.is_none());
+    }
+}
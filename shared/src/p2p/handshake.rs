@@ -0,0 +1,335 @@
+/// Connection handshake exchanged before a peer is admitted
+use crate::config::PROTOCOL_VERSION;
+use crate::message::P2PMessage;
+use crate::p2p::room::{RoomKey, ROOM_BINDING_LABEL};
+use crate::p2p::node::derive_peer_id;
+use crate::tls::{BoundIdentity, TlsConnection};
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio_util::codec::{Framed, LinesCodec, LinesCodecError};
+use uuid::Uuid;
+
+/// Line-framed peer connection as used by `PeerConnection`
+pub type PeerFramed = Framed<TlsConnection, LinesCodec>;
+
+/// Frame a peer connection, refusing lines longer than `max_frame_length` bytes
+///
+/// Without a limit a peer that never sends a newline makes us buffer forever.
+pub fn frame_connection(connection: TlsConnection, max_frame_length: usize) -> PeerFramed {
+    Framed::new(connection, LinesCodec::new_with_max_length(max_frame_length))
+}
+
+/// What a remote peer told us about itself in its handshake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerHello {
+    pub peer_id: String,
+    pub username: String,
+    pub protocol_version: String,
+    pub fingerprint: String,
+    pub build_version: String,
+    pub heartbeat_interval_secs: u64,
+    pub room_proof: String,
+}
+
+/// Send our handshake and wait for the remote one
+///
+/// Both sides send first and then read, so neither waits on the other.
+/// Anything the peer sends before its handshake is a protocol error.
+pub async fn exchange_handshake(
+    framed: &mut PeerFramed,
+    local_hello: &P2PMessage,
+    timeout: Duration,
+) -> Result<PeerHello, Box<dyn std::error::Error + Send + Sync>> {
+    framed.send(serde_json::to_string(local_hello)?).await?;
+
+    let line = match tokio::time::timeout(timeout, framed.next()).await {
+        Ok(Some(Err(LinesCodecError::MaxLineLengthExceeded))) => {
+            return Err(format!("Handshake exceeds {} bytes", framed.codec().max_length()).into())
+        }
+        Ok(Some(line)) => line?,
+        Ok(None) => return Err("Connection closed during handshake".into()),
+        Err(_) => return Err(format!("Handshake timed out after {}s", timeout.as_secs()).into()),
+    };
+
+    match serde_json::from_str::<P2PMessage>(&line)? {
+        P2PMessage::Handshake {
+            peer_id,
+            username,
+            protocol_version,
+            fingerprint,
+            build_version,
+            heartbeat_interval_secs,
+            room_proof,
+        } => {
+            Ok(PeerHello {
+                peer_id,
+                username,
+                protocol_version,
+                fingerprint,
+                build_version,
+                heartbeat_interval_secs,
+                room_proof,
+            })
+        }
+        other => Err(format!("Expected handshake, got {:?}", other).into()),
+    }
+}
+
+/// Check that a peer speaks a compatible protocol
+///
+/// Versions are `major.minor`; only the major version has to match ours.
+/// On mismatch the error is a reason suitable for a `Disconnect` message.
+pub fn check_protocol_version(remote_version: &str) -> Result<(), String> {
+    let major = |version: &str| version.split('.').next().unwrap_or_default().trim().to_string();
+    let remote_major = major(remote_version);
+
+    if !remote_major.is_empty() && remote_major == major(PROTOCOL_VERSION) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Incompatible protocol version {} (expected {}.x)",
+            if remote_version.is_empty() { "<none>" } else { remote_version },
+            major(PROTOCOL_VERSION)
+        ))
+    }
+}
+
+/// Check that a peer ID looks like one we would generate, a hyphenated UUID
+///
+/// Peer IDs are shown shortened and used as keys, so anything else is refused.
+pub fn check_peer_id(peer_id: &str) -> Result<(), String> {
+    if peer_id.len() == 36 && Uuid::parse_str(peer_id).is_ok() {
+        Ok(())
+    } else {
+        Err("Malformed peer ID".to_string())
+    }
+}
+
+/// Channel binding room key proofs are tied to, empty over plain TCP
+pub fn room_binding(connection: &TlsConnection) -> Vec<u8> {
+    connection
+        .channel_binding(ROOM_BINDING_LABEL)
+        .map(|binding| binding.to_vec())
+        .unwrap_or_default()
+}
+
+/// Our handshake with a room key proof for this connection filled in
+///
+/// Without a room key the handshake is sent as is.
+pub fn prove_room_key(local_hello: &P2PMessage, room_key: Option<&RoomKey>, binding: &[u8]) -> P2PMessage {
+    let mut hello = local_hello.clone();
+    if let (Some(key), P2PMessage::Handshake { peer_id, room_proof, .. }) = (room_key, &mut hello) {
+        *room_proof = key.proof(binding, peer_id);
+    }
+    hello
+}
+
+/// Check a peer's room key proof, failing closed when we have a key
+///
+/// On failure the error is a reason suitable for a `Disconnect` message.
+pub fn check_room_proof(hello: &PeerHello, room_key: Option<&RoomKey>, binding: &[u8]) -> Result<(), String> {
+    match room_key {
+        None => Ok(()),
+        Some(_) if hello.room_proof.is_empty() => Err("This room needs a room key".to_string()),
+        Some(key) if key.verify(&hello.room_proof, binding, &hello.peer_id) => Ok(()),
+        Some(_) => Err("Wrong room key".to_string()),
+    }
+}
+
+/// Check the handshake fingerprint and peer ID against the identity the
+/// peer's TLS certificate is bound to, if it is bound to one
+///
+/// Stops a peer with a valid certificate for its own identity from claiming
+/// another identity's fingerprint or peer ID in the handshake.
+pub fn check_tls_identity(hello: &PeerHello, tls_identity: Option<&BoundIdentity>) -> Result<(), String> {
+    match tls_identity {
+        Some(identity) if identity.fingerprint != hello.fingerprint => {
+            Err("Handshake fingerprint does not match the TLS certificate".to_string())
+        }
+        Some(identity) if derive_peer_id(&identity.public_key) != hello.peer_id => {
+            Err("Handshake peer ID does not match the TLS certificate".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Wait after the handshake to learn whether the peer admitted us
+///
+/// A refusing peer sends a `Disconnect` with its reason straight away, an
+/// admitting one starts with a heartbeat. Anything but a `Disconnect`
+/// counts as admitted.
+pub async fn wait_for_admission(framed: &mut PeerFramed, timeout: Duration) -> Result<(), String> {
+    match tokio::time::timeout(timeout, framed.next()).await {
+        Ok(Some(Ok(line))) => match serde_json::from_str::<P2PMessage>(&line) {
+            Ok(P2PMessage::Disconnect { reason, .. }) => Err(format!("refused: {}", reason)),
+            _ => Ok(()),
+        },
+        Ok(Some(Err(e))) => Err(e.to_string()),
+        Ok(None) => Err("Connection closed after the handshake".to_string()),
+        Err(_) => Err(format!("No word from the peer within {}s of the handshake", timeout.as_secs())),
+    }
+}
+
+/// Tell a peer why it is being refused, ignoring send failures
+pub async fn refuse(framed: &mut PeerFramed, local_peer_id: &str, reason: &str) {
+    let disconnect = P2PMessage::Disconnect {
+        peer_id: local_peer_id.to_string(),
+        reason: reason.to_string(),
+    };
+
+    if let Ok(line) = serde_json::to_string(&disconnect) {
+        framed.send(line).await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BUILD_VERSION;
+    use crate::tls::TlsListener;
+
+    fn hello(peer_id: &str, fingerprint: &str) -> P2PMessage {
+        P2PMessage::Handshake {
+            peer_id: peer_id.to_string(),
+            username: peer_id.to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            fingerprint: fingerprint.to_string(),
+            build_version: BUILD_VERSION.to_string(),
+            heartbeat_interval_secs: 0,
+            room_proof: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_exchanges_identities() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let connection = TlsConnection::connect_plain(addr).await.unwrap();
+            let mut framed = Framed::new(connection, LinesCodec::new());
+            exchange_handshake(&mut framed, &hello("alice", "aa:aa"), Duration::from_secs(5)).await.unwrap()
+        });
+
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut framed = Framed::new(connection, LinesCodec::new());
+        let from_client = exchange_handshake(&mut framed, &hello("bob", ""), Duration::from_secs(5)).await.unwrap();
+        let from_server = client.await.unwrap();
+
+        assert_eq!(from_client.peer_id, "alice");
+        assert_eq!(from_client.fingerprint, "aa:aa");
+        assert_eq!(from_client.build_version, BUILD_VERSION);
+        assert_eq!(from_server.peer_id, "bob");
+        assert_eq!(from_server.fingerprint, "");
+    }
+
+    #[tokio::test]
+    async fn test_refusal_reason_reaches_the_prober() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let connection = TlsConnection::connect_plain(addr).await.unwrap();
+            let mut framed = Framed::new(connection, LinesCodec::new());
+            wait_for_admission(&mut framed, Duration::from_secs(5)).await
+        });
+
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut framed = Framed::new(connection, LinesCodec::new());
+        refuse(&mut framed, "bob", "This room needs a room key").await;
+        assert_eq!(client.await.unwrap(), Err("refused: This room needs a room key".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_times_out_on_silent_peer() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _silent = TlsConnection::connect_plain(addr).await.unwrap();
+
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut framed = Framed::new(connection, LinesCodec::new());
+        let result = exchange_handshake(&mut framed, &hello("bob", ""), Duration::from_millis(100)).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_handshake_is_rejected() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let connection = TlsConnection::connect_plain(addr).await.unwrap();
+            let mut framed = Framed::new(connection, LinesCodec::new());
+            framed.send("x".repeat(4096)).await.unwrap();
+            framed
+        });
+
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut framed = frame_connection(connection, 1024);
+        let error = exchange_handshake(&mut framed, &hello("bob", ""), Duration::from_secs(5)).await.unwrap_err();
+        assert_eq!(error.to_string(), "Handshake exceeds 1024 bytes");
+        drop(client.await.unwrap());
+    }
+
+    #[test]
+    fn test_room_proof_fails_closed() {
+        let key = RoomKey::from_passphrase("hunter2hunter2");
+        let binding = [7u8; 32];
+        let received = |message: P2PMessage| match message {
+            P2PMessage::Handshake { peer_id, username, protocol_version, fingerprint, build_version, heartbeat_interval_secs, room_proof } => {
+                PeerHello { peer_id, username, protocol_version, fingerprint, build_version, heartbeat_interval_secs, room_proof }
+            }
+            other => panic!("unexpected message: {:?}", other),
+        };
+
+        let proven = received(prove_room_key(&hello("alice", "aa:aa"), Some(&key), &binding));
+        assert!(check_room_proof(&proven, Some(&key), &binding).is_ok());
+        assert_eq!(check_room_proof(&proven, Some(&RoomKey::from_passphrase("other")), &binding), Err("Wrong room key".to_string()));
+
+        let bare = received(prove_room_key(&hello("alice", "aa:aa"), None, &binding));
+        assert_eq!(check_room_proof(&bare, Some(&key), &binding), Err("This room needs a room key".to_string()));
+        assert!(check_room_proof(&bare, None, &binding).is_ok());
+    }
+
+    #[test]
+    fn test_tls_identity_must_match_handshake() {
+        let bound = |fingerprint: &str, public_key: &[u8]| BoundIdentity {
+            fingerprint: fingerprint.to_string(),
+            public_key: public_key.to_vec(),
+        };
+        let hello = PeerHello {
+            peer_id: derive_peer_id(b"alice key"),
+            username: "alice".to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            fingerprint: "aa:aa".to_string(),
+            build_version: BUILD_VERSION.to_string(),
+            heartbeat_interval_secs: 0,
+            room_proof: String::new(),
+        };
+        assert!(check_tls_identity(&hello, Some(&bound("aa:aa", b"alice key"))).is_ok());
+        assert!(check_tls_identity(&hello, None).is_ok());
+        assert!(check_tls_identity(&hello, Some(&bound("bb:bb", b"alice key"))).is_err());
+        // A proven identity can't claim another identity's peer ID
+        assert_eq!(
+            check_tls_identity(&hello, Some(&bound("aa:aa", b"mallory key"))),
+            Err("Handshake peer ID does not match the TLS certificate".to_string())
+        );
+    }
+
+    #[test]
+    fn test_peer_id_must_be_a_uuid() {
+        assert!(check_peer_id(&Uuid::new_v4().to_string()).is_ok());
+        assert!(check_peer_id("").is_err());
+        assert!(check_peer_id("alice").is_err());
+        assert!(check_peer_id(&Uuid::new_v4().simple().to_string()).is_err());
+    }
+
+    #[test]
+    fn test_protocol_major_version_must_match() {
+        assert!(check_protocol_version(PROTOCOL_VERSION).is_ok());
+        assert!(check_protocol_version("1.7").is_ok());
+        assert!(check_protocol_version("2.0").is_err());
+        assert!(check_protocol_version("").is_err());
+        assert!(check_protocol_version("garbage").unwrap_err().contains("garbage"));
+    }
+}
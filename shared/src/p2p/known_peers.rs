@@ -0,0 +1,129 @@
+/// Addresses of peers seen before, kept across restarts
+use crate::config::MAX_KNOWN_PEERS;
+use identity_gen::FileManager;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use tracing::{debug, warn};
+
+/// Dialable peer addresses with when each was last seen, in Unix seconds
+///
+/// Stored one `ip:port last_seen` pair per line. Only addresses we dialed
+/// and completed a handshake with are kept, the source port of an inbound
+/// connection can't be dialed back. At most [`MAX_KNOWN_PEERS`] are kept.
+pub type KnownPeers = HashMap<SocketAddr, u64>;
+
+/// Record a peer as seen, dropping the least recently seen one when full
+pub fn remember_known_peer(peers: &mut KnownPeers, addr: SocketAddr, last_seen: u64) {
+    if !peers.contains_key(&addr) && peers.len() >= MAX_KNOWN_PEERS {
+        if let Some(oldest) = peers.iter().min_by_key(|(_, seen)| **seen).map(|(addr, _)| *addr) {
+            peers.remove(&oldest);
+        }
+    }
+    let entry = peers.entry(addr).or_insert(last_seen);
+    *entry = (*entry).max(last_seen);
+}
+
+/// Load known peers, leaving out entries older than `max_age_secs`
+///
+/// A missing file is an empty list; lines that don't parse are skipped.
+pub fn load_known_peers(path: &Path, max_age_secs: u64, now: u64) -> Result<KnownPeers, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(KnownPeers::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut peers = KnownPeers::new();
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let mut fields = line.split_whitespace();
+        let (Some(Ok(addr)), Some(Ok(last_seen))) = (
+            fields.next().map(str::parse::<SocketAddr>),
+            fields.next().map(str::parse::<u64>),
+        ) else {
+            warn!("Ignoring malformed known peer entry '{}' in {}", line, path.display());
+            continue;
+        };
+        if now.saturating_sub(last_seen) > max_age_secs {
+            debug!("Dropping stale known peer {}", addr);
+            continue;
+        }
+        remember_known_peer(&mut peers, addr, last_seen);
+    }
+    Ok(peers)
+}
+
+/// Write known peers seen within `max_age_secs`, replacing the file atomically
+pub fn save_known_peers(
+    path: &Path,
+    peers: &KnownPeers,
+    max_age_secs: u64,
+    now: u64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut entries: Vec<_> = peers
+        .iter()
+        .filter(|(_, last_seen)| now.saturating_sub(**last_seen) <= max_age_secs)
+        .collect();
+    // Most recently seen first, so the file reads like a history
+    entries.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    entries.truncate(MAX_KNOWN_PEERS);
+
+    let mut contents = String::from("# Known peers: address and when it was last seen (Unix seconds)\n");
+    for (addr, last_seen) in entries {
+        writeln!(contents, "{} {}", addr, last_seen)?;
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    FileManager::write_atomic(path, contents.as_bytes(), 0o600)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_peers_round_trip_drops_stale_entries() {
+        let path = std::env::temp_dir().join(format!("dpq-known-peers-{}", uuid::Uuid::new_v4()));
+        let now = 1_000_000;
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+
+        let peers = KnownPeers::from([
+            (addr("10.0.0.1:40000"), now - 10),
+            (addr("10.0.0.2:40000"), now - 500),
+            (addr("10.0.0.3:40000"), now - 5_000),
+        ]);
+        save_known_peers(&path, &peers, 1_000, now).unwrap();
+        let loaded = load_known_peers(&path, 1_000, now).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(!loaded.contains_key(&addr("10.0.0.3:40000")));
+
+        // Entries age out on load as well
+        let later = load_known_peers(&path, 1_000, now + 700).unwrap();
+        assert_eq!(later, KnownPeers::from([(addr("10.0.0.1:40000"), now - 10)]));
+
+        fs::write(&path, "garbage\n10.0.0.4:40000 not-a-time\n10.0.0.5:40000 999999\n").unwrap();
+        assert_eq!(load_known_peers(&path, 1_000, now).unwrap().len(), 1);
+
+        fs::remove_file(&path).ok();
+        assert!(load_known_peers(&path, 1_000, now).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_known_peers_are_capped() {
+        let now = 1_000_000;
+        let mut peers = KnownPeers::new();
+        for i in 0..MAX_KNOWN_PEERS as u64 + 10 {
+            let addr = SocketAddr::from(([10, 0, (i / 256) as u8, (i % 256) as u8], 40000));
+            remember_known_peer(&mut peers, addr, now - 1_000 + i);
+        }
+        assert_eq!(peers.len(), MAX_KNOWN_PEERS);
+        // The least recently seen went first
+        assert!(!peers.contains_key(&SocketAddr::from(([10, 0, 0, 0], 40000))));
+        assert!(peers.contains_key(&SocketAddr::from(([10, 0, 1, 9], 40000))));
+    }
+}
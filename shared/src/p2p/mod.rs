@@ -1,20 +1,30 @@
 /// P2P networking module for peer-to-peer chat
+pub mod blocklist;
+pub mod handshake;
+pub mod known_peers;
 pub mod node;
+pub mod ordering;
 pub mod peer;
+pub mod room;
 pub mod discovery;
 pub mod routing;
 
 // Re-export main types for convenience
-pub use node::{P2PNode, P2PNodeConfig};
-pub use peer::{Peer, PeerConnection, PeerManager};
-pub use discovery::{PeerDiscovery, DiscoveryMethod};
+pub use node::{derive_peer_id, NodeConfigError, NodeStartError, P2PNode, P2PNodeConfig, UsernamePolicy};
+pub use peer::{AddedPeer, Peer, PeerConnection, PeerManager, PeerStats};
+pub use discovery::{PeerDiscovery, DiscoveryMethod, DiscoveryEvent, load_peers_file};
 pub use routing::{MessageRouter, RoutingTable};
+pub use blocklist::{Allowlist, Blocklist};
+pub use handshake::PeerHello;
+pub use known_peers::{load_known_peers, save_known_peers, KnownPeers};
+pub use room::RoomKey;
 
-use crate::message::{P2PMessage, PeerInfo};
+use crate::message::{MessageSecurity, P2PMessage, PeerInfo};
+use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 
 /// P2P network events
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum P2PEvent {
     /// A new peer connected
     PeerConnected {
@@ -22,6 +32,12 @@ pub enum P2PEvent {
         addr: SocketAddr,
         username: String,
     },
+    /// A peer connected with a username already in use and was renamed
+    UsernameCollision {
+        peer_id: String,
+        requested: String,
+        assigned: String,
+    },
     /// A peer disconnected
     PeerDisconnected {
         peer_id: String,
@@ -31,15 +47,31 @@ pub enum P2PEvent {
     MessageReceived {
         message: P2PMessage,
         from_peer: String,
+        security: MessageSecurity,
     },
     /// Network topology changed
     TopologyChanged {
         connected_peers: Vec<PeerInfo>,
     },
+    /// A peer answered our ping
+    PingReply {
+        peer_id: String,
+        rtt_micros: u64,
+    },
+    /// A peer did not answer our ping in time
+    PingTimeout {
+        peer_id: String,
+    },
     /// Discovery found new peers
     PeersDiscovered {
         peers: Vec<SocketAddr>,
     },
+    /// Our TLS certificate was renewed before it expired
+    CertificateRotated {
+        fingerprint: String,
+        /// Unix seconds when the new certificate expires
+        expires_at: u64,
+    },
     /// Error occurred
     Error {
         error: String,
@@ -48,7 +80,7 @@ pub enum P2PEvent {
 }
 
 /// P2P network statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct P2PStats {
     pub connected_peers: usize,
     pub total_messages_sent: u64,
@@ -61,18 +93,89 @@ pub struct P2PStats {
     pub failed_connections: u64,
 }
 
-impl Default for P2PStats {
-    fn default() -> Self {
-        Self {
-            connected_peers: 0,
-            total_messages_sent: 0,
-            total_messages_received: 0,
-            bytes_sent: 0,
-            bytes_received: 0,
-            uptime_secs: 0,
-            discovery_attempts: 0,
-            successful_connections: 0,
-            failed_connections: 0,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::ChatKind;
+
+    /// Serialize, parse back and check nothing was lost
+    fn assert_round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) {
+        let json = serde_json::to_value(value).unwrap();
+        let decoded: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+    }
+
+    #[test]
+    fn test_every_event_round_trips_through_json() {
+        let addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let events = vec![
+            P2PEvent::PeerConnected {
+                peer_id: "node-a".to_string(),
+                addr,
+                username: "alice".to_string(),
+            },
+            P2PEvent::UsernameCollision {
+                peer_id: "node-b".to_string(),
+                requested: "alice".to_string(),
+                assigned: "alice#2".to_string(),
+            },
+            P2PEvent::PeerDisconnected {
+                peer_id: "node-a".to_string(),
+                reason: "User quit".to_string(),
+            },
+            P2PEvent::MessageReceived {
+                message: P2PMessage::ChatMessage {
+                    message_id: "m1".to_string(),
+                    sender_id: "node-a".to_string(),
+                    username: "alice".to_string(),
+                    content: "hello".to_string(),
+                    ttl: 8,
+                    seen_by: vec!["node-a".to_string()],
+                    kind: ChatKind::Action,
+                    sequence: 3,
+                },
+                from_peer: "node-a".to_string(),
+                security: MessageSecurity::Transport,
+            },
+            P2PEvent::TopologyChanged {
+                connected_peers: vec![PeerInfo {
+                    peer_id: "node-a".to_string(),
+                    username: "alice".to_string(),
+                    addr,
+                    last_seen: 1_700_000_000,
+                }],
+            },
+            P2PEvent::PingReply {
+                peer_id: "node-a".to_string(),
+                rtt_micros: 1500,
+            },
+            P2PEvent::PingTimeout {
+                peer_id: "node-a".to_string(),
+            },
+            P2PEvent::PeersDiscovered { peers: vec![addr] },
+            P2PEvent::CertificateRotated {
+                fingerprint: "0123456789abcdef".to_string(),
+                expires_at: 1_700_000_000,
+            },
+            P2PEvent::Error {
+                error: "boom".to_string(),
+                peer_id: None,
+            },
+        ];
+
+        for event in &events {
+            assert_round_trip(event);
         }
     }
+
+    #[test]
+    fn test_stats_round_trip_through_json() {
+        let stats = P2PStats {
+            connected_peers: 2,
+            total_messages_sent: 5,
+            bytes_received: 1024,
+            ..P2PStats::default()
+        };
+        assert_round_trip(&stats);
+    }
 }
@@ -1,20 +1,102 @@
 /// Main P2P node implementation
-use crate::message::{P2PMessage, PeerInfo};
+use crate::crypto::DilithiumKeypair;
+use crate::message::{P2PMessage, PeerInfo, PresenceStatus};
 use crate::tls::{TlsContext, CertificateManager, TlsListener, TlsConnection};
+use crate::config::{
+    CERT_RENEW_RETRY_SECS, KNOWN_PEERS_SAVE_INTERVAL_SECS, DEAD_PEER_HEARTBEATS, DEFAULT_CERT_VALIDITY_SECS, DEFAULT_KNOWN_PEER_MAX_AGE_SECS, DEFAULT_MESSAGE_TTL, DEFAULT_RATE_LIMIT,
+    DEFAULT_REORDER_WINDOW_MS, HEARTBEAT_INTERVAL, KNOWN_PEER_DIAL_ATTEMPTS, KNOWN_PEER_DIAL_CONCURRENCY, MAX_CONNECTIONS, MAX_FRAME_LENGTH,
+    MAX_MESSAGE_LENGTH, MIN_DEAD_PEER_HEARTBEATS, PING_TIMEOUT_SECS,
+};
+use crate::utils::is_valid_message_content;
 use crate::p2p::{
-    peer::PeerManager,
-    discovery::{PeerDiscovery, DiscoveryMethod},
+    blocklist::{Allowlist, Blocklist},
+    handshake::{check_peer_id, check_protocol_version, check_room_proof, check_tls_identity, exchange_handshake, frame_connection, prove_room_key, refuse, room_binding},
+    known_peers::{load_known_peers, remember_known_peer, save_known_peers, KnownPeers},
+    room::RoomKey,
+    ordering::ReorderBuffer,
+    peer::{AddPeerOutcome, PeerManager, PeerStats, REPLACED_REASON},
+    discovery::{PeerDiscovery, DiscoveryEvent, DiscoveryMethod},
     routing::MessageRouter,
     P2PEvent, P2PStats,
 };
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
-use tokio::sync::{mpsc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, RwLock, Semaphore};
+use tokio::task::JoinHandle;
 use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, warn, error, debug};
 use uuid::Uuid;
 
+/// Stage of node startup that failed
+///
+/// Returned boxed from [`P2PNode::start`] so callers can tell a port that
+/// could not be bound apart from a discovery failure.
+#[derive(Debug)]
+pub enum NodeStartError {
+    /// Binding the listening socket failed
+    Bind {
+        addr: SocketAddr,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Starting peer discovery failed
+    Discovery(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl std::fmt::Display for NodeStartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeStartError::Bind { addr, source } => write!(f, "Failed to listen on {}: {}", addr, source),
+            NodeStartError::Discovery(source) => write!(f, "Failed to start peer discovery: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for NodeStartError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NodeStartError::Bind { source, .. } => Some(source.as_ref()),
+            NodeStartError::Discovery(source) => Some(source.as_ref()),
+        }
+    }
+}
+
+/// A file named in the node configuration could not be used
+///
+/// Returned boxed from [`P2PNode::new`] when the block list or allowlist
+/// fails to load, so callers can tell it apart from a TLS setup failure.
+#[derive(Debug)]
+pub struct NodeConfigError {
+    pub path: PathBuf,
+    pub source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl std::fmt::Display for NodeConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cannot use {}: {}", self.path.display(), self.source)
+    }
+}
+
+impl std::error::Error for NodeConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// What to do when a connecting peer's username is already in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UsernamePolicy {
+    /// Admit the peer under a name with a suffix, e.g. `alice#2`
+    #[default]
+    Disambiguate,
+    /// Refuse the connection
+    Reject,
+}
+
 /// Configuration for P2P node
 #[derive(Debug, Clone)]
 pub struct P2PNodeConfig {
@@ -24,16 +106,57 @@ pub struct P2PNodeConfig {
     pub username: String,
     /// Enable TLS
     pub enable_tls: bool,
+    /// Lifetime of our self-signed TLS certificate in seconds, it is
+    /// renewed once three quarters of that has passed
+    pub cert_validity_secs: u64,
+    /// Dilithium identity to bind our TLS certificate to, which turns on
+    /// mutual TLS: peers must present a certificate bound to an identity,
+    /// the one whose fingerprint they send in the handshake
+    pub tls_identity: Option<DilithiumKeypair>,
     /// Maximum number of connections
     pub max_connections: usize,
     /// Connection timeout in seconds
     pub connection_timeout_secs: u64,
-    /// Heartbeat interval in seconds
+    /// Seconds between heartbeats on each connection
     pub heartbeat_interval_secs: u64,
+    /// Seconds between sweeps for dead and idle peers, 0 sweeps once per heartbeat
+    pub cleanup_interval_secs: u64,
+    /// Drop peers silent for this many seconds, 0 means [`DEAD_PEER_HEARTBEATS`]
+    /// heartbeats. Never less than [`MIN_DEAD_PEER_HEARTBEATS`] heartbeats, so a
+    /// single late heartbeat doesn't get a healthy peer reaped
+    pub dead_peer_timeout_secs: u64,
     /// Discovery methods
     pub discovery_methods: Vec<DiscoveryMethod>,
     /// Bootstrap peers
     pub bootstrap_peers: Vec<SocketAddr>,
+    /// Identity fingerprint sent in the handshake
+    pub fingerprint: Option<String>,
+    /// Public key of the user's identity; the peer ID is derived from it if set, random otherwise.
+    /// With `tls_identity` set the peer ID is derived from that identity instead
+    pub identity_public_key: Option<Vec<u8>>,
+    /// File of blocked identity fingerprints, in-memory only if `None`
+    pub blocklist_path: Option<PathBuf>,
+    /// Allowlist file; when set only the peers it lists may connect
+    pub allowlist_path: Option<PathBuf>,
+    /// File of peers seen in earlier sessions, dialed on start and rewritten on stop
+    pub known_peers_path: Option<PathBuf>,
+    /// Known peers not seen for this many seconds are forgotten
+    pub known_peers_max_age_secs: u64,
+    /// Disconnect peers that send no chat messages for this many seconds, 0 disables
+    pub idle_timeout_secs: u64,
+    /// Hops our chat messages may travel through the mesh
+    pub message_ttl: u8,
+    /// Chat messages relayed per source in each rate window, 0 disables the limit
+    pub message_rate_limit: u32,
+    /// Longest line in bytes a peer may send before it is disconnected
+    pub max_frame_length: usize,
+    /// Milliseconds a sender's message may wait for an earlier one that
+    /// took a slower path, 0 delivers messages as they arrive
+    pub reorder_window_ms: u64,
+    /// Handling of peers whose username is already taken
+    pub username_policy: UsernamePolicy,
+    /// Only admit peers that prove they know this key
+    pub room_key: Option<RoomKey>,
 }
 
 impl Default for P2PNodeConfig {
@@ -42,13 +165,86 @@ impl Default for P2PNodeConfig {
             listen_addr: "127.0.0.1:0".parse().unwrap(),
             username: "Anonymous".to_string(),
             enable_tls: true,
-            max_connections: 50,
+            cert_validity_secs: DEFAULT_CERT_VALIDITY_SECS,
+            tls_identity: None,
+            max_connections: MAX_CONNECTIONS,
             connection_timeout_secs: 30,
-            heartbeat_interval_secs: 30,
+            heartbeat_interval_secs: HEARTBEAT_INTERVAL,
+            cleanup_interval_secs: 0,
+            dead_peer_timeout_secs: 0,
             discovery_methods: crate::p2p::discovery::default_discovery_methods(),
             bootstrap_peers: vec![],
+            fingerprint: None,
+            identity_public_key: None,
+            blocklist_path: None,
+            allowlist_path: None,
+            known_peers_path: None,
+            known_peers_max_age_secs: DEFAULT_KNOWN_PEER_MAX_AGE_SECS,
+            idle_timeout_secs: 0,
+            message_ttl: DEFAULT_MESSAGE_TTL,
+            message_rate_limit: DEFAULT_RATE_LIMIT,
+            max_frame_length: MAX_FRAME_LENGTH,
+            reorder_window_ms: DEFAULT_REORDER_WINDOW_MS,
+            username_policy: UsernamePolicy::default(),
+            room_key: None,
+        }
+    }
+}
+
+impl P2PNodeConfig {
+    /// Seconds between cleanup sweeps, defaulting to the heartbeat interval
+    pub fn effective_cleanup_interval_secs(&self) -> u64 {
+        match self.cleanup_interval_secs {
+            0 => self.heartbeat_interval_secs.max(1),
+            secs => secs,
+        }
+    }
+
+    /// Seconds of silence before a peer is dropped, after defaults and the
+    /// lower bound of [`MIN_DEAD_PEER_HEARTBEATS`] heartbeats are applied
+    pub fn effective_dead_peer_timeout_secs(&self) -> u64 {
+        self.dead_peer_timeout_for(0)
+    }
+
+    /// Dead peer timeout for a peer that heartbeats every `peer_heartbeat_secs`,
+    /// counted in whichever of its heartbeats and ours is slower
+    pub fn dead_peer_timeout_for(&self, peer_heartbeat_secs: u64) -> u64 {
+        let heartbeat = self.heartbeat_interval_secs.max(peer_heartbeat_secs).max(1);
+        match self.dead_peer_timeout_secs {
+            0 => heartbeat.saturating_mul(DEAD_PEER_HEARTBEATS),
+            secs => secs.max(heartbeat.saturating_mul(MIN_DEAD_PEER_HEARTBEATS)),
         }
     }
+
+    /// Lifetime of our TLS certificate, at least one second
+    pub fn cert_validity(&self) -> Duration {
+        Duration::from_secs(self.cert_validity_secs.max(1))
+    }
+
+    /// Certificate manager for our TLS certificates, bound to the identity if set
+    fn certificate_manager(&self, peer_id: &str) -> CertificateManager {
+        let cert_manager = CertificateManager::new(peer_id.to_string()).with_validity(self.cert_validity());
+        match &self.tls_identity {
+            Some(identity) => cert_manager.with_identity(identity.clone()),
+            None => cert_manager,
+        }
+    }
+}
+
+/// What a connection task needs to handshake with and admit a peer
+#[derive(Clone)]
+struct Admission {
+    local_peer_id: String,
+    local_hello: P2PMessage,
+    /// Limit on dialing a peer and, separately, on its handshake
+    connection_timeout: Duration,
+    max_frame_length: usize,
+    room_key: Option<RoomKey>,
+    peer_manager: PeerManager,
+    event_tx: mpsc::Sender<P2PEvent>,
+    stats: Arc<RwLock<P2PStats>>,
+    /// Addresses we reached, remembered for the known peers file
+    known_peers: Arc<RwLock<KnownPeers>>,
 }
 
 /// Main P2P node
@@ -69,8 +265,14 @@ pub struct P2PNode {
     event_tx: mpsc::Sender<P2PEvent>,
     /// Statistics
     stats: Arc<RwLock<P2PStats>>,
-    /// Running flag
-    running: Arc<RwLock<bool>>,
+    /// Pings awaiting a reply: nonce -> (peer ID, send time)
+    pending_pings: Arc<RwLock<HashMap<u64, (String, Instant)>>>,
+    /// Dialable peer addresses seen in this or earlier sessions
+    known_peers: Arc<RwLock<KnownPeers>>,
+    /// Cancelled on stop so every spawned loop exits promptly
+    shutdown: CancellationToken,
+    /// Handles of the long-running tasks, awaited on stop
+    tasks: Vec<JoinHandle<()>>,
     /// Actual listening address
     actual_listen_addr: Arc<RwLock<Option<SocketAddr>>>,
     /// Message receiver
@@ -79,17 +281,73 @@ pub struct P2PNode {
     disconnect_rx: Option<mpsc::Receiver<String>>,
 }
 
+/// Stable peer ID for an identity public key
+///
+/// SHA-256 over a domain tag and the full Dilithium public key, with the first
+/// 128 bits formatted like a UUID. The user-facing fingerprint keeps only 48
+/// bits of a hash of the same key, which is too few to rule out collisions,
+/// so it is not used here.
+pub fn derive_peer_id(public_key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"dpq-chat peer-id v1\0");
+    hasher.update(public_key);
+    let hash = hasher.finalize();
+
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    Uuid::from_bytes(bytes).to_string()
+}
+
+/// Seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default()
+}
+
+/// Pass a received message through the reorder buffer if it is a chat message
+fn order_chat_event(reorder: &mut ReorderBuffer<P2PEvent>, event: P2PEvent) -> Vec<P2PEvent> {
+    let (sender_id, sequence) = match &event {
+        P2PEvent::MessageReceived {
+            message: P2PMessage::ChatMessage { sender_id, sequence, .. },
+            ..
+        } => (sender_id.clone(), *sequence),
+        _ => return vec![event],
+    };
+    reorder.push(&sender_id, sequence, event, Instant::now())
+}
+
 impl P2PNode {
     /// Create a new P2P node
     pub async fn new(
         config: P2PNodeConfig,
     ) -> Result<(Self, mpsc::Receiver<P2PEvent>), Box<dyn std::error::Error + Send + Sync>> {
-        let peer_id = Uuid::new_v4().to_string();
+        // With mutual TLS peers check our peer ID against our certificate's identity
+        let identity_public_key = match &config.tls_identity {
+            Some(identity) => Some(identity.public_key_bytes()),
+            None => config.identity_public_key.as_deref(),
+        };
+        let peer_id = match identity_public_key {
+            Some(public_key) => derive_peer_id(public_key),
+            None => Uuid::new_v4().to_string(),
+        };
         let (event_tx, event_rx) = mpsc::channel(1000);
 
+        let config_error = |path: &Path, source| NodeConfigError { path: path.to_path_buf(), source };
+        let blocklist = match &config.blocklist_path {
+            Some(path) => {
+                let blocklist = Blocklist::load(path).map_err(|source| config_error(path, source))?;
+                // Without mutual TLS a blocked peer just claims another fingerprint
+                if !blocklist.is_empty() && config.tls_identity.is_none() {
+                    let source = "the block list has identity fingerprints, which peers can only prove with mutual TLS".into();
+                    return Err(config_error(path, source).into());
+                }
+                blocklist
+            }
+            None => Blocklist::new(),
+        };
+
         // Initialize TLS if enabled
         let tls_context = if config.enable_tls {
-            let mut cert_manager = CertificateManager::new(peer_id.clone());
+            let mut cert_manager = config.certificate_manager(&peer_id);
             cert_manager.generate_self_signed_cert().await?;
             Some(TlsContext::new(&cert_manager).await?)
         } else {
@@ -101,10 +359,28 @@ impl P2PNode {
             peer_id.clone(),
             config.username.clone(),
             config.max_connections,
+            blocklist,
         );
+        let mut peer_manager = peer_manager
+            .with_heartbeat_interval(config.heartbeat_interval_secs)
+            .with_username_policy(config.username_policy);
+        if let Some(path) = &config.allowlist_path {
+            let allowlist = Allowlist::load(path).map_err(|source| config_error(path, source))?;
+            // Without mutual TLS anyone can claim a listed fingerprint
+            if allowlist.has_fingerprints() && config.tls_identity.is_none() {
+                let source = "the allowlist has identity fingerprints, which peers can only prove with mutual TLS".into();
+                return Err(config_error(path, source).into());
+            }
+            if allowlist.is_empty() {
+                warn!("Allowlist {} is empty, every peer will be refused", path.display());
+            }
+            peer_manager = peer_manager.with_allowlist(allowlist);
+        }
 
         // Create message router
-        let message_router = MessageRouter::new(peer_id.clone(), config.username.clone());
+        let message_router = MessageRouter::new(peer_id.clone(), config.username.clone())
+            .with_message_ttl(config.message_ttl)
+            .with_rate_limit(config.message_rate_limit);
 
         // Create peer discovery
         let peer_discovery = PeerDiscovery::new(
@@ -123,7 +399,10 @@ impl P2PNode {
             peer_discovery,
             event_tx,
             stats: Arc::new(RwLock::new(P2PStats::default())),
-            running: Arc::new(RwLock::new(false)),
+            pending_pings: Arc::new(RwLock::new(HashMap::new())),
+            known_peers: Arc::new(RwLock::new(KnownPeers::new())),
+            shutdown: CancellationToken::new(),
+            tasks: Vec::new(),
             actual_listen_addr: Arc::new(RwLock::new(None)),
             message_rx: Some(message_rx),
             disconnect_rx: Some(disconnect_rx),
@@ -133,31 +412,38 @@ impl P2PNode {
     }
 
     /// Start the P2P node
+    ///
+    /// A node runs once, after `stop` create a new one to go online again.
     pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!("Starting P2P node {} with username: {}", self.peer_id, self.config.username);
 
-        // Set running flag
-        {
-            let mut running = self.running.write().await;
-            *running = true;
-        }
+        // Message processing owns the receivers, so a node only runs once
+        let (Some(message_rx), Some(disconnect_rx)) = (self.message_rx.take(), self.disconnect_rx.take()) else {
+            return Err("This node was already started, a stopped node can't be restarted".into());
+        };
 
         // Start listening for incoming connections
-        self.start_listener().await?;
+        let listener_task = self.start_listener().await.map_err(|source| NodeStartError::Bind {
+            addr: self.config.listen_addr,
+            source,
+        })?;
+        self.tasks.push(listener_task);
 
         // Start peer discovery
-        self.start_discovery().await?;
+        let discovery_task = self.start_discovery().await.map_err(NodeStartError::Discovery)?;
+        self.tasks.push(discovery_task);
 
         // Start message processing
-        if let (Some(message_rx), Some(disconnect_rx)) = (self.message_rx.take(), self.disconnect_rx.take()) {
-            self.start_message_processing(message_rx, disconnect_rx).await;
-        }
+        let processing_task = self.start_message_processing(message_rx, disconnect_rx).await;
+        self.tasks.push(processing_task);
 
         // Start background tasks
-        self.start_background_tasks().await;
+        let background_tasks = self.start_background_tasks().await;
+        self.tasks.extend(background_tasks);
 
-        // Connect to bootstrap peers
+        // Connect to bootstrap peers, then to peers from earlier sessions
         self.connect_to_bootstrap_peers().await;
+        self.connect_to_known_peers().await;
         info!("P2P node started successfully");
         Ok(())
     }
@@ -166,12 +452,6 @@ impl P2PNode {
     pub async fn stop(&mut self) {
         info!("Stopping P2P node {}", self.peer_id);
 
-        // Set running flag to false first
-        {
-            let mut running = self.running.write().await;
-            *running = false;
-        }
-
         // Send disconnect messages to all peers
         let disconnect_msg = P2PMessage::Disconnect {
             peer_id: self.peer_id.clone(),
@@ -183,14 +463,24 @@ impl P2PNode {
         // Give a moment for disconnect messages to be sent
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
         
+        // Signal every spawned loop to exit
+        self.shutdown.cancel();
+        
         // Stop peer discovery
         self.peer_discovery.stop().await;
         
+        // Remember who we were connected to before the connections go away
+        self.save_known_peers().await;
+
         // Disconnect all peers
         self.peer_manager.disconnect_all_peers().await;
         
-        // Give a moment for cleanup
-        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        // Wait for the tasks so the listener socket is closed once stop returns
+        for task in self.tasks.drain(..) {
+            if tokio::time::timeout(Duration::from_secs(2), task).await.is_err() {
+                warn!("Timed out waiting for a node task to finish");
+            }
+        }
 
         info!("P2P node stopped completely");
     }
@@ -198,6 +488,79 @@ impl P2PNode {
     /// Send a chat message to the network
     pub async fn send_chat_message(&self, content: String) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let message = self.message_router.create_chat_message(content);
+        self.broadcast_chat(message).await
+    }
+
+    /// Send a `/me` action message to all peers
+    pub async fn send_action_message(&self, content: String) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let message = self.message_router.create_action_message(content);
+        self.broadcast_chat(message).await
+    }
+
+    /// Tell all direct peers whether we are online, away or busy
+    pub async fn send_presence(&self, status: PresenceStatus, message: String) {
+        let presence = P2PMessage::Presence {
+            peer_id: self.peer_id.clone(),
+            status,
+            message,
+        };
+        self.peer_manager.broadcast_message(presence).await;
+    }
+
+    /// Tell all direct peers that we are composing a message
+    ///
+    /// Callers should throttle this, it is sent on every call.
+    pub async fn send_typing(&self) {
+        let typing = P2PMessage::Typing {
+            peer_id: self.peer_id.clone(),
+            username: self.config.username.clone(),
+        };
+        self.peer_manager.broadcast_message(typing).await;
+    }
+
+    /// Ping a direct peer to measure the round-trip time
+    ///
+    /// The result arrives later as [`P2PEvent::PingReply`], or as
+    /// [`P2PEvent::PingTimeout`] if there is no answer within
+    /// [`PING_TIMEOUT_SECS`].
+    pub async fn ping(&self, peer_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let nonce = rand::random::<u64>();
+        self.pending_pings.write().await.insert(nonce, (peer_id.to_string(), Instant::now()));
+
+        let ping = P2PMessage::Ping {
+            peer_id: self.peer_id.clone(),
+            nonce,
+        };
+        if let Err(e) = self.peer_manager.send_to_peer(peer_id, ping).await {
+            self.pending_pings.write().await.remove(&nonce);
+            return Err(e);
+        }
+
+        let pending_pings = self.pending_pings.clone();
+        let event_tx = self.event_tx.clone();
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = shutdown.cancelled() => {}
+                _ = tokio::time::sleep(Duration::from_secs(PING_TIMEOUT_SECS)) => {
+                    // Still pending means no reply came
+                    if let Some((peer_id, _)) = pending_pings.write().await.remove(&nonce) {
+                        event_tx.send(P2PEvent::PingTimeout { peer_id }).await.ok();
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Broadcast an outgoing chat message and count it
+    async fn broadcast_chat(&self, message: P2PMessage) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let P2PMessage::ChatMessage { content, .. } = &message {
+            if !is_valid_message_content(content) {
+                return Err(format!("Message must be 1-{} bytes", MAX_MESSAGE_LENGTH).into());
+            }
+        }
+
         self.peer_manager.broadcast_message(message).await;
 
         // Update statistics
@@ -209,6 +572,21 @@ impl P2PNode {
         Ok(())
     }
 
+    /// Send a message to one connected peer instead of broadcasting it
+    pub async fn send_to_peer(&self, peer_id: &str, message: P2PMessage) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.peer_manager.send_to_peer(peer_id, message).await
+    }
+
+    /// Send a message to the connected peer presenting an identity fingerprint
+    ///
+    /// Returns the peer ID the message went to
+    pub async fn send_to_fingerprint(&self, fingerprint: &str, message: P2PMessage) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let peer_id = self.peer_manager.peer_id_for_fingerprint(fingerprint).await
+            .ok_or_else(|| format!("No connected peer with fingerprint {}", fingerprint))?;
+        self.peer_manager.send_to_peer(&peer_id, message).await?;
+        Ok(peer_id)
+    }
+
     /// Get current network statistics
     pub async fn get_stats(&self) -> P2PStats {
         let stats = self.stats.read().await;
@@ -222,8 +600,89 @@ impl P2PNode {
         self.peer_manager.get_connected_peers().await
     }
 
+    /// Identity fingerprint a connected peer sent in its handshake
+    pub async fn peer_fingerprint(&self, peer_id: &str) -> Option<String> {
+        self.peer_manager.peer_fingerprint(peer_id).await
+    }
+
+    /// Whether mutual TLS proved a connected peer's fingerprint, rather than the peer only claiming it
+    pub async fn is_peer_verified(&self, peer_id: &str) -> bool {
+        self.peer_manager.is_peer_verified(peer_id).await
+    }
+
+    /// Traffic exchanged with a connected peer
+    pub async fn get_peer_stats(&self, peer_id: &str) -> Option<PeerStats> {
+        self.peer_manager.get_peer_stats(peer_id).await
+    }
+
+    /// Protocol and build version a connected peer sent in its handshake
+    pub async fn peer_version(&self, peer_id: &str) -> Option<(String, String)> {
+        self.peer_manager.peer_version(peer_id).await
+    }
+
+    /// Drop a single connected peer, telling it why
+    ///
+    /// Returns false if the peer was not connected
+    pub async fn disconnect_peer(&self, peer_id: &str) -> bool {
+        let reason = "Disconnected by user".to_string();
+        if !self.peer_manager.remove_peer(peer_id, reason.clone()).await {
+            return false;
+        }
+
+        let event = P2PEvent::PeerDisconnected {
+            peer_id: peer_id.to_string(),
+            reason,
+        };
+        if let Err(e) = self.event_tx.send(event).await {
+            warn!("Failed to send peer disconnected event: {}", e);
+        }
+        true
+    }
+
+    /// Block an identity fingerprint and drop any connected peer using it
+    ///
+    /// Needs mutual TLS, since otherwise peers state whatever fingerprint they
+    /// like. Returns the peer IDs that were disconnected
+    pub async fn block_peer(&self, fingerprint: &str) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        if self.config.tls_identity.is_none() {
+            return Err("blocking needs mutual TLS, without it peers can claim any fingerprint".into());
+        }
+        self.peer_manager.blocklist().block(fingerprint)?;
+        let removed = self.peer_manager.remove_peers_with_fingerprint(fingerprint, "Blocked").await;
+
+        for peer_id in &removed {
+            let event = P2PEvent::PeerDisconnected {
+                peer_id: peer_id.clone(),
+                reason: "Blocked".to_string(),
+            };
+            if let Err(e) = self.event_tx.send(event).await {
+                warn!("Failed to send peer disconnected event: {}", e);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Handshake settings shared by inbound and outbound connections
+    fn admission(&self) -> Admission {
+        Admission {
+            local_peer_id: self.peer_id.clone(),
+            local_hello: self.message_router.create_handshake(
+                self.config.fingerprint.clone().unwrap_or_default(),
+                self.config.heartbeat_interval_secs,
+            ),
+            connection_timeout: Duration::from_secs(self.config.connection_timeout_secs),
+            max_frame_length: self.config.max_frame_length,
+            room_key: self.config.room_key.clone(),
+            peer_manager: self.peer_manager.clone(),
+            event_tx: self.event_tx.clone(),
+            stats: self.stats.clone(),
+            known_peers: self.known_peers.clone(),
+        }
+    }
+
     /// Start listening for incoming connections
-    async fn start_listener(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    async fn start_listener(&self) -> Result<JoinHandle<()>, Box<dyn std::error::Error + Send + Sync>> {
         let listener = if let Some(tls_context) = &self.tls_context {
             TlsListener::bind_tls(self.config.listen_addr, tls_context.server_config.clone()).await?
         } else {
@@ -239,27 +698,25 @@ impl P2PNode {
             *addr_lock = Some(actual_addr);
         }
 
-        let peer_manager = self.peer_manager.clone();
-        let event_tx = self.event_tx.clone();
-        let running = self.running.clone();
+        let admission = self.admission();
+        let shutdown = self.shutdown.clone();
 
-        tokio::spawn(async move {
-            while *running.read().await {
-                match listener.accept().await {
+        let task = tokio::spawn(async move {
+            loop {
+                let accepted = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    accepted = listener.accept() => accepted,
+                };
+                
+                match accepted {
                     Ok((connection, peer_addr)) => {
                         info!("Accepted connection from {}", peer_addr);
                         
                         // Handle the connection in a separate task
-                        let peer_manager_clone = peer_manager.clone();
-                        let event_tx_clone = event_tx.clone();
+                        let admission = admission.clone();
                         
                         tokio::spawn(async move {
-                            if let Err(e) = Self::handle_incoming_connection(
-                                connection,
-                                peer_addr,
-                                peer_manager_clone,
-                                event_tx_clone,
-                            ).await {
+                            if let Err(e) = Self::admit_peer(connection, peer_addr, admission).await {
                                 error!("Failed to handle incoming connection from {}: {}", peer_addr, e);
                             }
                         });
@@ -269,57 +726,129 @@ impl P2PNode {
                     }
                 }
             }
+            
+            debug!("Listener task stopped");
         });
 
-        Ok(())
+        Ok(task)
     }
 
-    /// Handle an incoming connection
-    async fn handle_incoming_connection(
+    /// Handshake with a newly connected peer and add it unless it is blocked
+    /// or, in allowlist mode, not listed
+    ///
+    /// Returns the peer's username, or `None` if it was refused
+    async fn admit_peer(
         connection: TlsConnection,
         peer_addr: SocketAddr,
-        peer_manager: PeerManager,
-        event_tx: mpsc::Sender<P2PEvent>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // For now, we'll create a temporary peer ID
-        // In a real implementation, you'd perform a handshake to get the actual peer ID
-        let temp_peer_id = Uuid::new_v4().to_string();
-        let temp_username = format!("Peer@{}", peer_addr);
-
-        peer_manager.add_peer(
-            connection,
-            temp_peer_id.clone(),
-            peer_addr,
-            temp_username.clone(),
-            "1.0".to_string(),
-        ).await?;
+        admission: Admission,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut framed = frame_connection(connection, admission.max_frame_length);
+        let binding = room_binding(framed.get_ref());
+        let tls_identity = framed.get_ref().peer_identity();
+        let local_hello = prove_room_key(&admission.local_hello, admission.room_key.as_ref(), &binding);
+        let hello = exchange_handshake(&mut framed, &local_hello, admission.connection_timeout).await?;
+
+        // Refuse incompatible or keyless peers before they are ever added
+        let checked = check_protocol_version(&hello.protocol_version)
+            .and_then(|()| check_peer_id(&hello.peer_id))
+            .and_then(|()| check_room_proof(&hello, admission.room_key.as_ref(), &binding))
+            .and_then(|()| check_tls_identity(&hello, tls_identity.as_ref()))
+            .and_then(|()| {
+                // Peer IDs follow the identity, so this is our own identity running elsewhere (or ourselves)
+                if hello.peer_id == admission.local_peer_id {
+                    Err("This identity is already connected".to_string())
+                } else {
+                    Ok(())
+                }
+            });
+        if let Err(reason) = &checked {
+            refuse(&mut framed, &admission.local_peer_id, reason).await;
+        }
+
+        // Blocked, unlisted, surplus and duplicate peers are refused by the peer manager,
+        // which settles the username under the same lock as the insert
+        let peer_id = hello.peer_id.clone();
+        let outcome = match checked {
+            Err(reason) => AddPeerOutcome::Refused(reason),
+            Ok(()) => {
+                let verified = tls_identity.is_some();
+                admission.peer_manager.add_peer(framed, hello.clone(), peer_addr, verified).await?
+            }
+        };
+        let added = match outcome {
+            AddPeerOutcome::Added(added) => added,
+            AddPeerOutcome::Refused(reason) => {
+                let event = P2PEvent::Error {
+                    error: format!("Refused connection from {} ({}) at {}: {}", hello.username, hello.fingerprint, peer_addr, reason),
+                    peer_id: Some(peer_id),
+                };
+                if let Err(e) = admission.event_tx.send(event).await {
+                    warn!("Failed to send error event: {}", e);
+                }
+                return Ok(None);
+            }
+        };
+
+        if added.replaced {
+            let event = P2PEvent::PeerDisconnected {
+                peer_id: peer_id.clone(),
+                reason: REPLACED_REASON.to_string(),
+            };
+            if let Err(e) = admission.event_tx.send(event).await {
+                warn!("Failed to send peer disconnected event: {}", e);
+            }
+        }
 
         // Send peer connected event
         let event = P2PEvent::PeerConnected {
-            peer_id: temp_peer_id,
+            peer_id: peer_id.clone(),
             addr: peer_addr,
-            username: temp_username,
+            username: added.username.clone(),
         };
 
-        if let Err(e) = event_tx.send(event).await {
+        if let Err(e) = admission.event_tx.send(event).await {
             warn!("Failed to send peer connected event: {}", e);
         }
 
-        Ok(())
+        if let Some(requested) = added.renamed_from {
+            let event = P2PEvent::UsernameCollision {
+                peer_id,
+                requested,
+                assigned: added.username.clone(),
+            };
+            if let Err(e) = admission.event_tx.send(event).await {
+                warn!("Failed to send username collision event: {}", e);
+            }
+        }
+
+        Ok(Some(added.username))
     }
 
     /// Start peer discovery
-    async fn start_discovery(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    async fn start_discovery(&mut self) -> Result<JoinHandle<()>, Box<dyn std::error::Error + Send + Sync>> {
         let mut discovery_rx = self.peer_discovery.start().await?;
         let event_tx = self.event_tx.clone();
-        let running = self.running.clone();
+        let shutdown = self.shutdown.clone();
+        let tls_context = self.tls_context.clone();
+        let admission = self.admission();
+        let stats = self.stats.clone();
 
-        tokio::spawn(async move {
-            while *running.read().await {
-                match discovery_rx.recv().await {
-                    Some(discovered_peer) => {
+        let task = tokio::spawn(async move {
+            loop {
+                let discovered = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    discovered = discovery_rx.recv() => discovered,
+                };
+
+                // Every peer a discovery method turns up counts, whether or not we dial it
+                if matches!(discovered, Some(DiscoveryEvent::Discovered(_)) | Some(DiscoveryEvent::Listed(_))) {
+                    stats.write().await.discovery_attempts += 1;
+                }
+
+                match discovered {
+                    Some(DiscoveryEvent::Discovered(discovered_peer)) => {
                         debug!("Discovered peer: {:?}", discovered_peer);
-                        
+
                         let event = P2PEvent::PeersDiscovered {
                             peers: vec![discovered_peer.addr],
                         };
@@ -328,6 +857,44 @@ impl P2PNode {
                             warn!("Failed to send peers discovered event: {}", e);
                         }
                     }
+                    Some(DiscoveryEvent::Listed(addr)) => {
+                        let peer_manager = &admission.peer_manager;
+                        if peer_manager.get_connected_peers().await.iter().any(|peer| peer.addr == addr) {
+                            continue;
+                        }
+
+                        let connect = Self::connect_to_peer(addr, tls_context.clone(), admission.clone());
+                        let shutdown = shutdown.clone();
+                        let event_tx = event_tx.clone();
+                        tokio::spawn(async move {
+                            let result = tokio::select! {
+                                _ = shutdown.cancelled() => return,
+                                result = connect => result,
+                            };
+                            match result {
+                                Ok(_) => info!("Connected to listed peer {}", addr),
+                                Err(e) => {
+                                    warn!("Failed to connect to listed peer {}: {}", addr, e);
+                                    Self::report_dial_failure(&event_tx, addr, &e.to_string()).await;
+                                }
+                            }
+                        });
+                    }
+                    Some(DiscoveryEvent::Unlisted(addr)) => {
+                        let reason = "Removed from peers file".to_string();
+                        for peer in admission.peer_manager.get_connected_peers().await {
+                            if peer.addr != addr || !admission.peer_manager.remove_peer(&peer.peer_id, reason.clone()).await {
+                                continue;
+                            }
+                            let event = P2PEvent::PeerDisconnected {
+                                peer_id: peer.peer_id,
+                                reason: reason.clone(),
+                            };
+                            if let Err(e) = event_tx.send(event).await {
+                                warn!("Failed to send peer disconnected event: {}", e);
+                            }
+                        }
+                    }
                     None => {
                         debug!("Discovery channel closed");
                         break;
@@ -336,7 +903,7 @@ impl P2PNode {
             }
         });
 
-        Ok(())
+        Ok(task)
     }
 
     /// Start message processing
@@ -344,15 +911,30 @@ impl P2PNode {
         &self,
         mut message_rx: mpsc::Receiver<(P2PMessage, String)>,
         mut disconnect_rx: mpsc::Receiver<String>,
-    ) {
+    ) -> JoinHandle<()> {
         let message_router = self.message_router.clone();
         let peer_manager = self.peer_manager.clone();
         let event_tx = self.event_tx.clone();
-        let running = self.running.clone();
+        let shutdown = self.shutdown.clone();
+        let pending_pings = self.pending_pings.clone();
+        let stats = self.stats.clone();
+        let mut reorder = ReorderBuffer::new(Duration::from_millis(self.config.reorder_window_ms));
+        let mut flush = tokio::time::interval((reorder.window() / 2).max(Duration::from_millis(10)));
 
         tokio::spawn(async move {
-            while *running.read().await {
+            loop {
                 tokio::select! {
+                    _ = shutdown.cancelled() => break,
+
+                    // Release messages that gave up waiting for a lost predecessor
+                    _ = flush.tick(), if !reorder.window().is_zero() => {
+                        for event in reorder.flush_expired(Instant::now()) {
+                            if let Err(e) = event_tx.send(event).await {
+                                warn!("Failed to send message received event: {}", e);
+                            }
+                        }
+                    }
+
                     // Handle incoming messages
                     message = message_rx.recv() => {
                         if let Some((p2p_message, from_peer)) = message {
@@ -360,26 +942,68 @@ impl P2PNode {
                                 crate::p2p::routing::RoutingAction::Drop => {
                                     debug!("Dropped message from {}", from_peer);
                                 }
+                                // Disconnects are never forwarded, so this is the sending connection saying goodbye
+                                crate::p2p::routing::RoutingAction::Deliver {
+                                    message: P2PMessage::Disconnect { reason, .. },
+                                } => {
+                                    if peer_manager.remove_peer(&from_peer, "Goodbye".to_string()).await {
+                                        for event in reorder.forget_sender(&from_peer) {
+                                            if let Err(e) = event_tx.send(event).await {
+                                                warn!("Failed to send message received event: {}", e);
+                                            }
+                                        }
+                                        let event = P2PEvent::PeerDisconnected { peer_id: from_peer, reason };
+                                        if let Err(e) = event_tx.send(event).await {
+                                            warn!("Failed to send peer disconnected event: {}", e);
+                                        }
+                                    }
+                                }
+                                // Only the peer we pinged may answer, late or unknown replies are ignored
+                                crate::p2p::routing::RoutingAction::Deliver {
+                                    message: P2PMessage::Pong { nonce, .. },
+                                } => {
+                                    let mut pending = pending_pings.write().await;
+                                    if pending.get(&nonce).is_some_and(|(peer_id, _)| *peer_id == from_peer) {
+                                        let (peer_id, sent_at) = pending.remove(&nonce).unwrap();
+                                        let rtt_micros = sent_at.elapsed().as_micros() as u64;
+                                        if let Err(e) = event_tx.send(P2PEvent::PingReply { peer_id, rtt_micros }).await {
+                                            warn!("Failed to send ping reply event: {}", e);
+                                        }
+                                    }
+                                }
                                 crate::p2p::routing::RoutingAction::Deliver { message } => {
+                                    if matches!(message, P2PMessage::ChatMessage { .. }) {
+                                        stats.write().await.total_messages_received += 1;
+                                    }
+                                    let security = peer_manager.message_security(&from_peer).await;
                                     let event = P2PEvent::MessageReceived {
                                         message,
                                         from_peer,
+                                        security,
                                     };
-                                    if let Err(e) = event_tx.send(event).await {
-                                        warn!("Failed to send message received event: {}", e);
+                                    for event in order_chat_event(&mut reorder, event) {
+                                        if let Err(e) = event_tx.send(event).await {
+                                            warn!("Failed to send message received event: {}", e);
+                                        }
                                     }
                                 }
                                 crate::p2p::routing::RoutingAction::ForwardAndDeliver { original_message, forward_message, forward_to } => {
-                                    // Deliver locally
+                                    // Deliver locally, in the sender's order
+                                    if matches!(original_message, P2PMessage::ChatMessage { .. }) {
+                                        stats.write().await.total_messages_received += 1;
+                                    }
                                     let event = P2PEvent::MessageReceived {
                                         message: original_message,
                                         from_peer: from_peer.clone(),
+                                        security: peer_manager.message_security(&from_peer).await,
                                     };
-                                    if let Err(e) = event_tx.send(event).await {
-                                        warn!("Failed to send message received event: {}", e);
+                                    for event in order_chat_event(&mut reorder, event) {
+                                        if let Err(e) = event_tx.send(event).await {
+                                            warn!("Failed to send message received event: {}", e);
+                                        }
                                     }
 
-                                    // Forward to other peers
+                                    // Forward to other peers right away, each of them orders for itself
                                     for peer_id in forward_to {
                                         if let Err(e) = peer_manager.send_to_peer(&peer_id, forward_message.clone()).await {
                                             debug!("Failed to forward message to {}: {}", peer_id, e);
@@ -400,9 +1024,18 @@ impl P2PNode {
 
                     // Handle peer disconnections
                     disconnected_peer = disconnect_rx.recv() => {
+                        // Peers we already removed, e.g. after their Disconnect, are not reported twice
                         if let Some(peer_id) = disconnected_peer {
-                            peer_manager.remove_peer(&peer_id, "Connection lost".to_string()).await;
-                            
+                            if !peer_manager.remove_peer(&peer_id, "Connection lost".to_string()).await {
+                                continue;
+                            }
+
+                            for event in reorder.forget_sender(&peer_id) {
+                                if let Err(e) = event_tx.send(event).await {
+                                    warn!("Failed to send message received event: {}", e);
+                                }
+                            }
+
                             let event = P2PEvent::PeerDisconnected {
                                 peer_id,
                                 reason: "Connection lost".to_string(),
@@ -414,24 +1047,52 @@ impl P2PNode {
                     }
                 }
             }
-        });
+        })
     }
 
     /// Start background tasks
-    async fn start_background_tasks(&self) {
+    async fn start_background_tasks(&self) -> Vec<JoinHandle<()>> {
         let peer_manager = self.peer_manager.clone();
         let stats = self.stats.clone();
-        let running = self.running.clone();
+        let shutdown = self.shutdown.clone();
+        let event_tx = self.event_tx.clone();
+        let idle_timeout_secs = self.config.idle_timeout_secs;
+        let cleanup_secs = self.config.effective_cleanup_interval_secs();
+        let dead_peer_secs = self.config.effective_dead_peer_timeout_secs();
+        if self.config.dead_peer_timeout_secs != 0 && dead_peer_secs != self.config.dead_peer_timeout_secs {
+            warn!(
+                "Dead peer timeout of {}s is under {} heartbeats, using {}s",
+                self.config.dead_peer_timeout_secs, MIN_DEAD_PEER_HEARTBEATS, dead_peer_secs
+            );
+        }
+        debug!("Sweeping every {}s for peers silent over {}s", cleanup_secs, dead_peer_secs);
+        let timeout_config = self.config.clone();
 
         // Cleanup task
-        tokio::spawn(async move {
-            let mut cleanup_interval = interval(Duration::from_secs(60));
+        let cleanup_task = tokio::spawn(async move {
+            let mut cleanup_interval = interval(Duration::from_secs(cleanup_secs));
             
-            while *running.read().await {
-                cleanup_interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = cleanup_interval.tick() => {}
+                }
                 
                 // Cleanup dead connections
-                peer_manager.cleanup_dead_connections(120).await; // 2 minutes timeout
+                peer_manager.cleanup_dead_connections(|heartbeat| timeout_config.dead_peer_timeout_for(heartbeat)).await;
+                
+                // Drop peers that are alive but have gone quiet
+                if idle_timeout_secs > 0 {
+                    for peer_id in peer_manager.remove_idle_peers(idle_timeout_secs).await {
+                        let event = P2PEvent::PeerDisconnected {
+                            peer_id,
+                            reason: "idle timeout".to_string(),
+                        };
+                        if let Err(e) = event_tx.send(event).await {
+                            warn!("Failed to send peer disconnected event: {}", e);
+                        }
+                    }
+                }
                 
                 debug!("Performed cleanup tasks");
             }
@@ -439,80 +1100,319 @@ impl P2PNode {
 
         // Statistics update task
         let stats_clone = stats.clone();
-        let running_clone = self.running.clone();
+        let shutdown = self.shutdown.clone();
         
-        tokio::spawn(async move {
+        let stats_task = tokio::spawn(async move {
             let mut stats_interval = interval(Duration::from_secs(10));
             let start_time = SystemTime::now();
             
-            while *running_clone.read().await {
-                stats_interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = stats_interval.tick() => {}
+                }
                 
                 let mut stats = stats_clone.write().await;
                 stats.uptime_secs = start_time.elapsed().unwrap_or_default().as_secs();
             }
         });
+
+        let mut tasks = vec![cleanup_task, stats_task];
+        if let Some(tls_context) = self.tls_context.clone() {
+            tasks.push(self.spawn_cert_renewal(tls_context));
+        }
+        if let Some(path) = self.config.known_peers_path.clone() {
+            tasks.push(self.spawn_known_peers_saver(path));
+        }
+        tasks
+    }
+
+    /// Save the known peers file periodically
+    ///
+    /// `stop` saves it too, this keeps it current when the process is killed
+    /// or interrupted before it gets there.
+    fn spawn_known_peers_saver(&self, path: PathBuf) -> JoinHandle<()> {
+        let known_peers = self.known_peers.clone();
+        let peer_manager = self.peer_manager.clone();
+        let max_age_secs = self.config.known_peers_max_age_secs;
+        let shutdown = self.shutdown.clone();
+
+        tokio::spawn(async move {
+            let mut save_interval = interval(Duration::from_secs(KNOWN_PEERS_SAVE_INTERVAL_SECS));
+            // The first tick is immediate and there is nothing new to save yet
+            save_interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = save_interval.tick() => {}
+                }
+                Self::write_known_peers(&path, &known_peers, &peer_manager, max_age_secs).await;
+            }
+        })
+    }
+
+    /// Renew our certificate before it expires, for nodes that run for a long time
+    ///
+    /// A fresh certificate is swapped in once three quarters of the validity
+    /// has passed. Only new connections see it, open ones are left alone.
+    fn spawn_cert_renewal(&self, tls_context: TlsContext) -> JoinHandle<()> {
+        let peer_id = self.peer_id.clone();
+        let config = self.config.clone();
+        let validity = config.cert_validity();
+        let shutdown = self.shutdown.clone();
+        let event_tx = self.event_tx.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let renew_at = tls_context.certificate_expires_at() - validity / 4;
+                let wait = renew_at.duration_since(SystemTime::now()).unwrap_or_default();
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(wait) => {}
+                }
+
+                match Self::renew_certificate(config.certificate_manager(&peer_id), &tls_context).await {
+                    Ok(event) => {
+                        if let Err(e) = event_tx.send(event).await {
+                            warn!("Failed to send certificate rotated event: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Failed to renew TLS certificate, retrying in {}s: {}", CERT_RENEW_RETRY_SECS, e);
+                        tokio::select! {
+                            _ = shutdown.cancelled() => break,
+                            _ = tokio::time::sleep(Duration::from_secs(CERT_RENEW_RETRY_SECS)) => {}
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Generate a new certificate and serve it from the next handshake on
+    async fn renew_certificate(
+        mut cert_manager: CertificateManager,
+        tls_context: &TlsContext,
+    ) -> Result<P2PEvent, Box<dyn std::error::Error + Send + Sync>> {
+        let cert = cert_manager.generate_self_signed_cert().await?.clone();
+        tls_context.rotate_certificate(&cert_manager)?;
+
+        let expires_at = cert
+            .not_after
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        info!("🔄 Rotated TLS certificate, new fingerprint {} expires at {}", cert.fingerprint, expires_at);
+        Ok(P2PEvent::CertificateRotated {
+            fingerprint: cert.fingerprint,
+            expires_at,
+        })
     }
 
     /// Connect to bootstrap peers
     async fn connect_to_bootstrap_peers(&self) {
         for bootstrap_addr in &self.config.bootstrap_peers {
-            let peer_manager = self.peer_manager.clone();
-            let tls_context = self.tls_context.clone();
-            let bootstrap_addr = *bootstrap_addr;
-            let event_tx = self.event_tx.clone();
+            self.dial_bootstrap_peer(*bootstrap_addr);
+        }
+    }
+
+    /// Dial the bootstrap peers we are not connected to right now
+    ///
+    /// Lets the user retry straight away after an outage. Results arrive as
+    /// the usual connect and error events; returns how many dials started.
+    pub async fn reconnect_bootstrap_peers(&self) -> usize {
+        let connected: Vec<SocketAddr> = self
+            .peer_manager
+            .get_connected_peers()
+            .await
+            .into_iter()
+            .map(|peer| peer.addr)
+            .collect();
+
+        let mut dialed = 0;
+        for bootstrap_addr in &self.config.bootstrap_peers {
+            if !connected.contains(bootstrap_addr) {
+                self.dial_bootstrap_peer(*bootstrap_addr);
+                dialed += 1;
+            }
+        }
+        dialed
+    }
+
+    /// Connect to one bootstrap peer in the background
+    fn dial_bootstrap_peer(&self, bootstrap_addr: SocketAddr) {
+        let tls_context = self.tls_context.clone();
+        let admission = self.admission();
+        let shutdown = self.shutdown.clone();
+        let event_tx = self.event_tx.clone();
+
+        tokio::spawn(async move {
+            let connect = Self::connect_to_peer(bootstrap_addr, tls_context, admission);
+            let result = tokio::select! {
+                _ = shutdown.cancelled() => return,
+                result = connect => result,
+            };
+            
+            match result {
+                Ok(_) => {
+                    info!("Successfully connected to bootstrap peer: {}", bootstrap_addr);
+                }
+                Err(e) => {
+                    warn!("Failed to connect to bootstrap peer {}: {}", bootstrap_addr, e);
+                    Self::report_dial_failure(&event_tx, bootstrap_addr, &e.to_string()).await;
+                }
+            }
+        });
+    }
 
+    /// Dial the peers in the known peers file, retrying each with growing delays
+    ///
+    /// These may well be offline, so failures are only logged. Only
+    /// [`KNOWN_PEER_DIAL_CONCURRENCY`] dials are in flight at a time.
+    async fn connect_to_known_peers(&self) {
+        let Some(path) = &self.config.known_peers_path else {
+            return;
+        };
+        let loaded = match load_known_peers(path, self.config.known_peers_max_age_secs, unix_now()) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                warn!("Failed to load known peers from {}: {}", path.display(), e);
+                return;
+            }
+        };
+        info!("Loaded {} known peers from {}", loaded.len(), path.display());
+
+        let listen_addr = self.listen_addr().await;
+        let dial_slots = Arc::new(Semaphore::new(KNOWN_PEER_DIAL_CONCURRENCY));
+        let mut known_peers = self.known_peers.write().await;
+        for (&addr, &last_seen) in &loaded {
+            remember_known_peer(&mut known_peers, addr, last_seen);
+
+            if addr == listen_addr || self.config.bootstrap_peers.contains(&addr) {
+                continue;
+            }
+
+            let tls_context = self.tls_context.clone();
+            let admission = self.admission();
+            let shutdown = self.shutdown.clone();
+            let dial_slots = dial_slots.clone();
             tokio::spawn(async move {
-                match Self::connect_to_peer(bootstrap_addr, tls_context, peer_manager, event_tx).await {
-                    Ok(_) => {
-                        info!("Successfully connected to bootstrap peer: {}", bootstrap_addr);
+                for attempt in 1..=KNOWN_PEER_DIAL_ATTEMPTS {
+                    // Hold a slot for the dial only, not for the backoff
+                    let Ok(slot) = dial_slots.acquire().await else {
+                        return;
+                    };
+                    if admission.peer_manager.get_connected_peers().await.iter().any(|peer| peer.addr == addr) {
+                        return;
                     }
-                    Err(e) => {
-                        warn!("Failed to connect to bootstrap peer {}: {}", bootstrap_addr, e);
+
+                    let connect = Self::connect_to_peer(addr, tls_context.clone(), admission.clone());
+                    let result = tokio::select! {
+                        _ = shutdown.cancelled() => return,
+                        result = connect => result,
+                    };
+                    drop(slot);
+                    match result {
+                        Ok(_) => {
+                            info!("Reconnected to known peer {}", addr);
+                            return;
+                        }
+                        Err(e) => debug!("Known peer {} not reachable (attempt {}): {}", addr, attempt, e),
+                    }
+
+                    if attempt < KNOWN_PEER_DIAL_ATTEMPTS {
+                        let backoff = Duration::from_secs(1 << attempt);
+                        tokio::select! {
+                            _ = shutdown.cancelled() => return,
+                            _ = tokio::time::sleep(backoff) => {}
+                        }
                     }
                 }
+                info!("Giving up on known peer {}", addr);
             });
         }
     }
 
+    /// Write the known peers file, counting peers still connected as seen now
+    async fn save_known_peers(&self) {
+        if let Some(path) = &self.config.known_peers_path {
+            Self::write_known_peers(path, &self.known_peers, &self.peer_manager, self.config.known_peers_max_age_secs).await;
+        }
+    }
+
+    async fn write_known_peers(path: &Path, known_peers: &RwLock<KnownPeers>, peer_manager: &PeerManager, max_age_secs: u64) {
+        let now = unix_now();
+        let mut known_peers = known_peers.write().await;
+        for peer in peer_manager.get_connected_peers().await {
+            if let Some(last_seen) = known_peers.get_mut(&peer.addr) {
+                *last_seen = now;
+            }
+        }
+
+        match save_known_peers(path, &known_peers, max_age_secs, now) {
+            Ok(()) => info!("Saved {} known peers to {}", known_peers.len(), path.display()),
+            Err(e) => warn!("Failed to save known peers to {}: {}", path.display(), e),
+        }
+    }
+
+    /// Dial a peer while running, e.g. one learned about mid-session
+    ///
+    /// Returns the peer's username once it has been admitted. Dialing and
+    /// the handshake are each limited to `connection_timeout_secs`.
+    pub async fn connect_to(&self, addr: SocketAddr) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        match Self::connect_to_peer(addr, self.tls_context.clone(), self.admission()).await? {
+            Some(username) => Ok(username),
+            None => Err(format!("Peer at {} was refused", addr).into()),
+        }
+    }
+
     /// Connect to a specific peer
+    ///
+    /// Dialing is limited to the connection timeout, so an unreachable
+    /// address fails in time instead of waiting for the operating system's
+    /// own connect timeout. Failed dials and handshakes count in the stats.
     async fn connect_to_peer(
         addr: SocketAddr,
         tls_context: Option<TlsContext>,
-        peer_manager: PeerManager,
-        event_tx: mpsc::Sender<P2PEvent>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let connection = if let Some(tls_context) = tls_context {
-            TlsConnection::connect_tls(addr, tls_context.client_config).await?
-        } else {
-            TlsConnection::connect_plain(addr).await?
+        admission: Admission,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let dial = async {
+            match tls_context {
+                Some(tls_context) => TlsConnection::connect_tls(addr, tls_context.client_config).await,
+                None => TlsConnection::connect_plain(addr).await,
+            }
         };
 
-        // For now, create a temporary peer ID
-        // In a real implementation, you'd perform a handshake
-        let temp_peer_id = Uuid::new_v4().to_string();
-        let temp_username = format!("Peer@{}", addr);
+        let stats = admission.stats.clone();
+        let known_peers = admission.known_peers.clone();
+        let timeout = admission.connection_timeout;
+        let result = match tokio::time::timeout(timeout, dial).await {
+            Ok(Ok(connection)) => Self::admit_peer(connection, addr, admission).await,
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(format!("Timed out after {}s", timeout.as_secs()).into()),
+        };
 
-        peer_manager.add_peer(
-            connection,
-            temp_peer_id.clone(),
-            addr,
-            temp_username.clone(),
-            "1.0".to_string(),
-        ).await?;
+        // Refused peers were reached, so only errors count as failures
+        match &result {
+            Ok(Some(_)) => {
+                stats.write().await.successful_connections += 1;
+                remember_known_peer(&mut *known_peers.write().await, addr, unix_now());
+            }
+            Ok(None) => {}
+            Err(_) => stats.write().await.failed_connections += 1,
+        }
+        result
+    }
 
-        // Send peer connected event
-        let event = P2PEvent::PeerConnected {
-            peer_id: temp_peer_id,
-            addr,
-            username: temp_username,
+    /// Tell the application that a peer it did not dial itself could not be reached
+    async fn report_dial_failure(event_tx: &mpsc::Sender<P2PEvent>, addr: SocketAddr, error: &str) {
+        let event = P2PEvent::Error {
+            error: format!("Could not connect to {}: {}", addr, error),
+            peer_id: None,
         };
-
         if let Err(e) = event_tx.send(event).await {
-            warn!("Failed to send peer connected event: {}", e);
+            warn!("Failed to send connection error event: {}", e);
         }
-
-        Ok(())
     }
 
     /// Get the local peer ID
@@ -525,9 +1425,755 @@ impl P2PNode {
         &self.config.username
     }
 
+    /// Get the local identity fingerprint, `None` when running without an identity
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.config.fingerprint.as_deref().filter(|fingerprint| !fingerprint.is_empty())
+    }
+
     /// Get the listening address
     pub async fn listen_addr(&self) -> SocketAddr {
         let addr_lock = self.actual_listen_addr.read().await;
         addr_lock.unwrap_or(self.config.listen_addr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BUILD_VERSION, PROTOCOL_VERSION};
+    use identity_gen::Identity;
+    use tokio_util::codec::{Framed, LinesCodec};
+
+    #[tokio::test]
+    async fn test_stop_releases_listen_port() {
+        let config = P2PNodeConfig {
+            listen_addr: "127.0.0.1:0".parse().unwrap(),
+            username: "alice".to_string(),
+            enable_tls: false,
+            discovery_methods: vec![],
+            ..Default::default()
+        };
+
+        let (mut node, _event_rx) = P2PNode::new(config).await.unwrap();
+        node.start().await.unwrap();
+        let addr = node.listen_addr().await;
+
+        node.stop().await;
+
+        assert!(node.tasks.is_empty());
+        std::net::TcpListener::bind(addr).expect("listen port should be free after stop");
+    }
+
+    #[test]
+    fn test_cleanup_timings_follow_heartbeat() {
+        let config = P2PNodeConfig {
+            heartbeat_interval_secs: 10,
+            ..Default::default()
+        };
+        assert_eq!(config.effective_cleanup_interval_secs(), 10);
+        assert_eq!(config.effective_dead_peer_timeout_secs(), 10 * DEAD_PEER_HEARTBEATS);
+
+        let config = P2PNodeConfig {
+            heartbeat_interval_secs: 10,
+            cleanup_interval_secs: 3,
+            dead_peer_timeout_secs: 45,
+            ..Default::default()
+        };
+        assert_eq!(config.effective_cleanup_interval_secs(), 3);
+        assert_eq!(config.effective_dead_peer_timeout_secs(), 45);
+
+        // A timeout under two heartbeats would reap healthy peers
+        let config = P2PNodeConfig {
+            heartbeat_interval_secs: 10,
+            dead_peer_timeout_secs: 5,
+            ..Default::default()
+        };
+        assert_eq!(config.effective_dead_peer_timeout_secs(), 10 * MIN_DEAD_PEER_HEARTBEATS);
+
+        // A peer heartbeating slower than us gets counted in its own heartbeats
+        let config = P2PNodeConfig {
+            heartbeat_interval_secs: 10,
+            ..Default::default()
+        };
+        assert_eq!(config.dead_peer_timeout_for(60), 60 * DEAD_PEER_HEARTBEATS);
+        assert_eq!(config.dead_peer_timeout_for(5), 10 * DEAD_PEER_HEARTBEATS);
+
+        // A huge heartbeat interval must not overflow the timeout
+        let config = P2PNodeConfig {
+            heartbeat_interval_secs: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(config.effective_dead_peer_timeout_secs(), u64::MAX);
+    }
+
+    fn plain_config(username: &str, fingerprint: &str) -> P2PNodeConfig {
+        P2PNodeConfig {
+            listen_addr: "127.0.0.1:0".parse().unwrap(),
+            username: username.to_string(),
+            enable_tls: false,
+            discovery_methods: vec![],
+            fingerprint: Some(fingerprint.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// A node proving a fresh identity with mutual TLS
+    fn mutual_tls_config(username: &str) -> P2PNodeConfig {
+        let identity = DilithiumKeypair::generate();
+        let fingerprint = Identity::generate_fingerprint(identity.public_key_bytes()).unwrap();
+        P2PNodeConfig {
+            enable_tls: true,
+            tls_identity: Some(identity),
+            ..plain_config(username, &fingerprint)
+        }
+    }
+
+    async fn next_event(events: &mut mpsc::Receiver<P2PEvent>) -> P2PEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for event")
+            .expect("event channel closed")
+    }
+
+    #[tokio::test]
+    async fn test_stopped_node_cannot_be_restarted() {
+        let (mut node, _events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        node.start().await.unwrap();
+        node.stop().await;
+        assert!(node.start().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_identifies_peers() {
+        let (mut bob, mut bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+
+        let mut config = plain_config("alice", "aa:aa");
+        config.bootstrap_peers = vec![bob.listen_addr().await];
+        let (mut alice, _alice_events) = P2PNode::new(config).await.unwrap();
+        alice.start().await.unwrap();
+
+        match next_event(&mut bob_events).await {
+            P2PEvent::PeerConnected { peer_id, username, .. } => {
+                assert_eq!(peer_id, alice.peer_id());
+                assert_eq!(username, "alice");
+                assert_eq!(bob.peer_fingerprint(&peer_id).await.as_deref(), Some("aa:aa"));
+                assert_eq!(
+                    bob.peer_version(&peer_id).await,
+                    Some((PROTOCOL_VERSION.to_string(), BUILD_VERSION.to_string()))
+                );
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_dials_only_missing_bootstrap_peers() {
+        let (mut bob, _bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+        let gone = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let mut config = plain_config("alice", "aa:aa");
+        config.bootstrap_peers = vec![bob.listen_addr().await, gone];
+        let (mut alice, mut alice_events) = P2PNode::new(config).await.unwrap();
+        alice.start().await.unwrap();
+
+        loop {
+            if let P2PEvent::PeerConnected { username, .. } = next_event(&mut alice_events).await {
+                assert_eq!(username, "bob");
+                break;
+            }
+        }
+        assert_eq!(alice.reconnect_bootstrap_peers().await, 1);
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_listener_keeps_accepting_across_cert_rotation() {
+        let bob_config = P2PNodeConfig { enable_tls: true, cert_validity_secs: 2, ..plain_config("bob", "bb:bb") };
+        let (mut bob, mut bob_events) = P2PNode::new(bob_config).await.unwrap();
+        bob.start().await.unwrap();
+        let tls_peer = |username: &str, fingerprint: &str, bob_addr| P2PNodeConfig {
+            enable_tls: true,
+            bootstrap_peers: vec![bob_addr],
+            ..plain_config(username, fingerprint)
+        };
+
+        let (mut alice, _alice_events) = P2PNode::new(tls_peer("alice", "aa:aa", bob.listen_addr().await)).await.unwrap();
+        alice.start().await.unwrap();
+        let mut connected = Vec::new();
+        let mut rotated = false;
+        while !rotated {
+            match next_event(&mut bob_events).await {
+                P2PEvent::PeerConnected { username, .. } => connected.push(username),
+                P2PEvent::CertificateRotated { .. } => rotated = true,
+                _ => {}
+            }
+        }
+        assert_eq!(connected, vec!["alice".to_string()]);
+
+        // A peer arriving after the swap gets the new certificate
+        let (mut carol, _carol_events) = P2PNode::new(tls_peer("carol", "cc:cc", bob.listen_addr().await)).await.unwrap();
+        carol.start().await.unwrap();
+        loop {
+            if let P2PEvent::PeerConnected { username, .. } = next_event(&mut bob_events).await {
+                assert_eq!(username, "carol");
+                break;
+            }
+        }
+
+        carol.stop().await;
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls_binds_peers_to_their_identity() {
+        let fingerprint_of = |identity: &DilithiumKeypair| Identity::generate_fingerprint(identity.public_key_bytes()).unwrap();
+        let bound = |username: &str, identity: DilithiumKeypair, fingerprint: &str| P2PNodeConfig {
+            enable_tls: true,
+            tls_identity: Some(identity),
+            ..plain_config(username, fingerprint)
+        };
+        let (alice_identity, bob_identity) = (DilithiumKeypair::generate(), DilithiumKeypair::generate());
+        let alice_fingerprint = fingerprint_of(&alice_identity);
+        let bob_config = bound("bob", bob_identity.clone(), &fingerprint_of(&bob_identity));
+        let (mut bob, mut bob_events) = P2PNode::new(bob_config).await.unwrap();
+        bob.start().await.unwrap();
+        let bob_addr = bob.listen_addr().await;
+
+        // Without a bound certificate the TLS handshake itself fails
+        let carol_config = P2PNodeConfig { enable_tls: true, bootstrap_peers: vec![bob_addr], ..plain_config("carol", "cc:cc") };
+        let (mut carol, mut carol_events) = P2PNode::new(carol_config).await.unwrap();
+        carol.start().await.unwrap();
+        while !matches!(next_event(&mut carol_events).await, P2PEvent::Error { .. }) {}
+
+        let alice_config = P2PNodeConfig { bootstrap_peers: vec![bob_addr], ..bound("alice", alice_identity, &alice_fingerprint) };
+        let (mut alice, _alice_events) = P2PNode::new(alice_config).await.unwrap();
+        alice.start().await.unwrap();
+        loop {
+            if let P2PEvent::PeerConnected { peer_id, .. } = next_event(&mut bob_events).await {
+                assert_eq!(bob.peer_fingerprint(&peer_id).await, Some(alice_fingerprint.clone()));
+                assert!(bob.is_peer_verified(&peer_id).await);
+                break;
+            }
+        }
+
+        // A bound certificate doesn't let a peer claim someone else's fingerprint
+        let mallory_config = P2PNodeConfig {
+            bootstrap_peers: vec![bob_addr],
+            ..bound("mallory", DilithiumKeypair::generate(), &alice_fingerprint)
+        };
+        let (mut mallory, _mallory_events) = P2PNode::new(mallory_config).await.unwrap();
+        mallory.start().await.unwrap();
+        loop {
+            match next_event(&mut bob_events).await {
+                P2PEvent::Error { error, .. } => {
+                    assert!(error.contains("Handshake fingerprint does not match the TLS certificate"), "{}", error);
+                    break;
+                }
+                P2PEvent::PeerConnected { username, .. } => panic!("{} was admitted", username),
+                _ => {}
+            }
+        }
+
+        mallory.stop().await;
+        alice.stop().await;
+        carol.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_blocked_peer_is_refused() {
+        // Fingerprints are only worth blocking once mutual TLS proves them
+        let path = std::env::temp_dir().join(format!("dpq-blocklist-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "aa:aa\n").unwrap();
+        let unbound = P2PNodeConfig { blocklist_path: Some(path.clone()), ..plain_config("bob", "bb:bb") };
+        assert!(P2PNode::new(unbound).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+        let (plain, _plain_events) = P2PNode::new(plain_config("dave", "dd:dd")).await.unwrap();
+        assert!(plain.block_peer("aa:aa").await.is_err());
+
+        let (mut bob, mut bob_events) = P2PNode::new(mutual_tls_config("bob")).await.unwrap();
+        bob.start().await.unwrap();
+        let alice_config = P2PNodeConfig { bootstrap_peers: vec![bob.listen_addr().await], ..mutual_tls_config("alice") };
+        bob.block_peer(alice_config.fingerprint.as_deref().unwrap()).await.unwrap();
+
+        let (mut alice, _alice_events) = P2PNode::new(alice_config).await.unwrap();
+        alice.start().await.unwrap();
+
+        match next_event(&mut bob_events).await {
+            P2PEvent::Error { error, .. } => assert!(error.contains("Blocked")),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(bob.get_connected_peers().await.is_empty());
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_protocol_version_is_refused() {
+        use futures::StreamExt;
+
+        let (mut bob, mut bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+
+        let connection = TlsConnection::connect_plain(bob.listen_addr().await).await.unwrap();
+        let mut framed = Framed::new(connection, LinesCodec::new());
+        let future_hello = P2PMessage::Handshake {
+            peer_id: "future".to_string(),
+            username: "future".to_string(),
+            protocol_version: "2.0".to_string(),
+            fingerprint: String::new(),
+            build_version: String::new(),
+            heartbeat_interval_secs: 0,
+            room_proof: String::new(),
+        };
+        exchange_handshake(&mut framed, &future_hello, Duration::from_secs(5)).await.unwrap();
+
+        let line = framed.next().await.unwrap().unwrap();
+        match serde_json::from_str::<P2PMessage>(&line).unwrap() {
+            P2PMessage::Disconnect { reason, .. } => assert!(reason.contains("Incompatible protocol version 2.0")),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(matches!(next_event(&mut bob_events).await, P2PEvent::Error { .. }));
+        assert!(bob.get_connected_peers().await.is_empty());
+
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_room_key_must_match() {
+        let keyed = |username: &str, fingerprint: &str, room_key: Option<&str>| P2PNodeConfig {
+            enable_tls: true,
+            room_key: room_key.map(RoomKey::from_passphrase),
+            ..plain_config(username, fingerprint)
+        };
+        let (mut bob, mut bob_events) = P2PNode::new(keyed("bob", "bb:bb", Some("blue room"))).await.unwrap();
+        bob.start().await.unwrap();
+
+        let (mut alice, _alice_events) = P2PNode::new(keyed("alice", "aa:aa", Some("blue room"))).await.unwrap();
+        alice.start().await.unwrap();
+        assert_eq!(alice.connect_to(bob.listen_addr().await).await.unwrap(), "bob");
+        assert!(matches!(next_event(&mut bob_events).await, P2PEvent::PeerConnected { .. }));
+
+        // Wrong key and no key are both refused
+        for (username, room_key) in [("mallory", Some("red room")), ("carol", None)] {
+            let (mut other, _other_events) = P2PNode::new(keyed(username, "cc:cc", room_key)).await.unwrap();
+            other.start().await.unwrap();
+            other.connect_to(bob.listen_addr().await).await.ok();
+            match next_event(&mut bob_events).await {
+                P2PEvent::Error { error, .. } => assert!(error.contains("room key"), "{}", error),
+                event => panic!("unexpected event: {:?}", event),
+            }
+            other.stop().await;
+        }
+        assert_eq!(bob.get_connected_peers().await.len(), 1);
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_connect_to_at_runtime() {
+        let (mut bob, _bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut alice, mut alice_events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        alice.start().await.unwrap();
+
+        assert_eq!(alice.connect_to(bob.listen_addr().await).await.unwrap(), "bob");
+        assert!(matches!(next_event(&mut alice_events).await, P2PEvent::PeerConnected { .. }));
+
+        bob.stop().await;
+        assert!(alice.connect_to(bob.listen_addr().await).await.is_err());
+        alice.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_send_to_fingerprint_reaches_only_that_peer() {
+        let (mut alice, _alice_events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        alice.start().await.unwrap();
+        let (mut bob, mut bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut carol, mut carol_events) = P2PNode::new(plain_config("carol", "cc:cc")).await.unwrap();
+        carol.start().await.unwrap();
+
+        alice.connect_to(bob.listen_addr().await).await.unwrap();
+        alice.connect_to(carol.listen_addr().await).await.unwrap();
+        assert!(matches!(next_event(&mut bob_events).await, P2PEvent::PeerConnected { .. }));
+        assert!(matches!(next_event(&mut carol_events).await, P2PEvent::PeerConnected { .. }));
+
+        // A single hop, so bob does not pass it on
+        let message = P2PMessage::ChatMessage {
+            message_id: "only-bob".to_string(),
+            sender_id: alice.peer_id().to_string(),
+            username: "alice".to_string(),
+            content: "just for you".to_string(),
+            ttl: 1,
+            seen_by: vec![],
+            kind: Default::default(),
+            sequence: 0,
+        };
+        let peer_id = alice.send_to_fingerprint("bb:bb", message).await.unwrap();
+        assert_eq!(peer_id, bob.peer_id());
+
+        match next_event(&mut bob_events).await {
+            P2PEvent::MessageReceived { message, .. } => assert_eq!(message.to_string(), "alice: just for you"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(300), carol_events.recv()).await.is_err());
+        assert!(alice.send_to_fingerprint("dd:dd", P2PMessage::PeerListRequest { peer_id: String::new() }).await.is_err());
+
+        alice.stop().await;
+        bob.stop().await;
+        carol.stop().await;
+    }
+
+    #[test]
+    fn test_peer_id_follows_identity_key() {
+        let alice = derive_peer_id(b"alice public key");
+        assert_eq!(alice, derive_peer_id(b"alice public key"));
+        assert_ne!(alice, derive_peer_id(b"bob public key"));
+        assert!(Uuid::parse_str(&alice).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_peers_file_entries_are_dialed() {
+        let (mut bob, mut bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("dpq-peers-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, format!("# trusted\nnot an address\n{}\n", bob.listen_addr().await)).unwrap();
+
+        let mut config = plain_config("alice", "aa:aa");
+        config.discovery_methods = vec![DiscoveryMethod::StaticFile {
+            path: path.clone(),
+            disconnect_removed: true,
+        }];
+        let (mut alice, _alice_events) = P2PNode::new(config).await.unwrap();
+        alice.start().await.unwrap();
+
+        match next_event(&mut bob_events).await {
+            P2PEvent::PeerConnected { username, .. } => assert_eq!(username, "alice"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        alice.stop().await;
+        bob.stop().await;
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_presence_reaches_direct_peers() {
+        let (mut bob, mut bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut alice, _alice_events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        alice.start().await.unwrap();
+
+        alice.connect_to(bob.listen_addr().await).await.unwrap();
+        assert!(matches!(next_event(&mut bob_events).await, P2PEvent::PeerConnected { .. }));
+
+        alice.send_presence(PresenceStatus::Away, "lunch".to_string()).await;
+        match next_event(&mut bob_events).await {
+            P2PEvent::MessageReceived { message: P2PMessage::Presence { status, message, .. }, from_peer, .. } => {
+                assert_eq!(from_peer, alice.peer_id());
+                assert_eq!(status, PresenceStatus::Away);
+                assert_eq!(message, "lunch");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_admits_only_listed_peers() {
+        let alice_config = mutual_tls_config("alice");
+        let path = std::env::temp_dir().join(format!("dpq-allowlist-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, format!("# trusted\n{}\n", alice_config.fingerprint.as_deref().unwrap())).unwrap();
+
+        // Fingerprints are only trusted once mutual TLS proves them
+        let unbound = P2PNodeConfig { allowlist_path: Some(path.clone()), ..plain_config("bob", "bb:bb") };
+        assert!(P2PNode::new(unbound).await.is_err());
+
+        let config = P2PNodeConfig { allowlist_path: Some(path.clone()), ..mutual_tls_config("bob") };
+        let (mut bob, mut bob_events) = P2PNode::new(config).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut carol, _carol_events) = P2PNode::new(mutual_tls_config("carol")).await.unwrap();
+        carol.start().await.unwrap();
+        let (mut alice, _alice_events) = P2PNode::new(alice_config).await.unwrap();
+        alice.start().await.unwrap();
+
+        // Carol only learns she was refused from bob's Disconnect
+        carol.connect_to(bob.listen_addr().await).await.ok();
+        match next_event(&mut bob_events).await {
+            P2PEvent::Error { error, .. } => assert!(error.contains("Not on the allowlist"), "{}", error),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        assert_eq!(alice.connect_to(bob.listen_addr().await).await.unwrap(), "bob");
+        match next_event(&mut bob_events).await {
+            P2PEvent::PeerConnected { username, .. } => assert_eq!(username, "alice"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(bob.get_connected_peers().await.len(), 1);
+
+        alice.stop().await;
+        carol.stop().await;
+        bob.stop().await;
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_known_peers_are_redialed_after_restart() {
+        let path = std::env::temp_dir().join(format!("dpq-known-peers-{}", uuid::Uuid::new_v4()));
+        let (mut bob, mut bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+
+        let mut config = plain_config("alice", "aa:aa");
+        config.known_peers_path = Some(path.clone());
+        let (mut alice, _alice_events) = P2PNode::new(config.clone()).await.unwrap();
+        alice.start().await.unwrap();
+        alice.connect_to(bob.listen_addr().await).await.unwrap();
+        alice.stop().await;
+
+        let saved = load_known_peers(&path, DEFAULT_KNOWN_PEER_MAX_AGE_SECS, unix_now()).unwrap();
+        assert!(saved.contains_key(&bob.listen_addr().await));
+        while !matches!(next_event(&mut bob_events).await, P2PEvent::PeerDisconnected { .. }) {}
+
+        // Started again without bootstrap peers, alice finds bob on her own
+        let (mut alice, _alice_events) = P2PNode::new(config).await.unwrap();
+        alice.start().await.unwrap();
+        loop {
+            if let P2PEvent::PeerConnected { username, .. } = next_event(&mut bob_events).await {
+                assert_eq!(username, "alice");
+                break;
+            }
+        }
+
+        alice.stop().await;
+        bob.stop().await;
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_connection_stats_count_dials() {
+        let (mut bob, _bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut alice, _alice_events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        alice.start().await.unwrap();
+
+        // A port nothing listens on any more
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        alice.connect_to(bob.listen_addr().await).await.unwrap();
+        assert!(alice.connect_to(closed).await.is_err());
+
+        let stats = alice.get_stats().await;
+        assert_eq!(stats.successful_connections, 1);
+        assert_eq!(stats.failed_connections, 1);
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_bootstrap_peer_times_out() {
+        // Non-routable address: the dial times out, or fails at once without a network
+        let black_hole: SocketAddr = "10.255.255.1:40000".parse().unwrap();
+        let mut config = plain_config("alice", "aa:aa");
+        config.connection_timeout_secs = 1;
+        config.bootstrap_peers = vec![black_hole];
+        let (mut alice, mut alice_events) = P2PNode::new(config).await.unwrap();
+
+        let started = Instant::now();
+        alice.start().await.unwrap();
+        match next_event(&mut alice_events).await {
+            P2PEvent::Error { error, .. } => assert!(error.contains("10.255.255.1:40000"), "{}", error),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert_eq!(alice.get_stats().await.failed_connections, 1);
+
+        alice.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_follow_configured_interval() {
+        use futures::StreamExt;
+
+        let mut config = plain_config("bob", "bb:bb");
+        config.heartbeat_interval_secs = 1;
+        let (mut bob, _bob_events) = P2PNode::new(config).await.unwrap();
+        bob.start().await.unwrap();
+
+        let connection = TlsConnection::connect_plain(bob.listen_addr().await).await.unwrap();
+        let mut framed = Framed::new(connection, LinesCodec::new());
+        let hello = P2PMessage::Handshake {
+            peer_id: Uuid::new_v4().to_string(),
+            username: "carol".to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            fingerprint: String::new(),
+            build_version: BUILD_VERSION.to_string(),
+            heartbeat_interval_secs: 0,
+            room_proof: String::new(),
+        };
+        exchange_handshake(&mut framed, &hello, Duration::from_secs(5)).await.unwrap();
+
+        // The first heartbeat goes out right away, then one per second
+        let started = Instant::now();
+        let mut heartbeats = 0;
+        while heartbeats < 3 {
+            let line = tokio::time::timeout(Duration::from_secs(5), framed.next())
+                .await
+                .expect("timed out waiting for a heartbeat")
+                .unwrap()
+                .unwrap();
+            if matches!(serde_json::from_str(&line).unwrap(), P2PMessage::Heartbeat { .. }) {
+                heartbeats += 1;
+            }
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(1500) && elapsed < Duration::from_secs(4), "{:?}", elapsed);
+
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_ping_reports_round_trip() {
+        let (mut bob, _bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut alice, mut alice_events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        alice.start().await.unwrap();
+
+        alice.connect_to(bob.listen_addr().await).await.unwrap();
+        assert!(matches!(next_event(&mut alice_events).await, P2PEvent::PeerConnected { .. }));
+
+        alice.ping(bob.peer_id()).await.unwrap();
+        match next_event(&mut alice_events).await {
+            P2PEvent::PingReply { peer_id, .. } => assert_eq!(peer_id, bob.peer_id()),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(alice.ping("nobody").await.is_err());
+        assert!(alice.pending_pings.read().await.is_empty());
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_peer_receives_reason() {
+        let (mut bob, mut bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut alice, mut alice_events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        alice.start().await.unwrap();
+
+        alice.connect_to(bob.listen_addr().await).await.unwrap();
+        assert!(matches!(next_event(&mut alice_events).await, P2PEvent::PeerConnected { .. }));
+        assert!(matches!(next_event(&mut bob_events).await, P2PEvent::PeerConnected { .. }));
+
+        let bob_id = bob.peer_id().to_string();
+        assert!(alice.disconnect_peer(&bob_id).await);
+        assert!(!alice.disconnect_peer(&bob_id).await);
+        assert!(matches!(next_event(&mut alice_events).await, P2PEvent::PeerDisconnected { .. }));
+
+        loop {
+            match next_event(&mut bob_events).await {
+                P2PEvent::PeerDisconnected { peer_id, reason } => {
+                    assert_eq!(peer_id, alice.peer_id());
+                    assert_eq!(reason, "Disconnected by user");
+                    break;
+                }
+                P2PEvent::MessageReceived { .. } => {}
+                other => panic!("unexpected event: {:?}", other),
+            }
+        }
+        assert!(bob.get_connected_peers().await.is_empty());
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_username_policy() {
+        let (mut bob, mut bob_events) = P2PNode::new(plain_config("bob", "bb:bb")).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut alice, _alice_events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        alice.start().await.unwrap();
+        alice.connect_to(bob.listen_addr().await).await.unwrap();
+        assert!(matches!(next_event(&mut bob_events).await, P2PEvent::PeerConnected { .. }));
+
+        // A second "alice" with another identity is renamed
+        let (mut impostor, _impostor_events) = P2PNode::new(plain_config("alice", "ee:ee")).await.unwrap();
+        impostor.start().await.unwrap();
+        assert_eq!(impostor.connect_to(bob.listen_addr().await).await.unwrap(), "bob");
+        match next_event(&mut bob_events).await {
+            P2PEvent::PeerConnected { username, .. } => assert_eq!(username, "alice#2"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match next_event(&mut bob_events).await {
+            P2PEvent::UsernameCollision { requested, assigned, .. } => {
+                assert_eq!(requested, "alice");
+                assert_eq!(assigned, "alice#2");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        // With the strict policy a third one is refused
+        let mut config = plain_config("carol", "cc:cc");
+        config.username_policy = UsernamePolicy::Reject;
+        let (mut carol, _carol_events) = P2PNode::new(config).await.unwrap();
+        carol.start().await.unwrap();
+        let (mut copycat, _copycat_events) = P2PNode::new(plain_config("carol", "dd:dd")).await.unwrap();
+        copycat.start().await.unwrap();
+        copycat.connect_to(carol.listen_addr().await).await.unwrap();
+        assert!(carol.get_connected_peers().await.is_empty());
+
+        for node in [&mut alice, &mut bob, &mut impostor, &mut carol, &mut copycat] {
+            node.stop().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peer_over_limit_is_told_why() {
+        use futures::StreamExt;
+
+        let mut config = plain_config("bob", "bb:bb");
+        config.max_connections = 1;
+        let (mut bob, _bob_events) = P2PNode::new(config).await.unwrap();
+        bob.start().await.unwrap();
+        let (mut alice, _alice_events) = P2PNode::new(plain_config("alice", "aa:aa")).await.unwrap();
+        alice.start().await.unwrap();
+        alice.connect_to(bob.listen_addr().await).await.unwrap();
+
+        let connection = TlsConnection::connect_plain(bob.listen_addr().await).await.unwrap();
+        let mut framed = Framed::new(connection, LinesCodec::new());
+        let hello = P2PMessage::Handshake {
+            peer_id: Uuid::new_v4().to_string(),
+            username: "carol".to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            fingerprint: String::new(),
+            build_version: BUILD_VERSION.to_string(),
+            heartbeat_interval_secs: 0,
+            room_proof: String::new(),
+        };
+        exchange_handshake(&mut framed, &hello, Duration::from_secs(5)).await.unwrap();
+
+        let line = framed.next().await.unwrap().unwrap();
+        match serde_json::from_str::<P2PMessage>(&line).unwrap() {
+            P2PMessage::Disconnect { reason, .. } => assert_eq!(reason, "Peer limit reached"),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert_eq!(bob.get_connected_peers().await.len(), 1);
+
+        alice.stop().await;
+        bob.stop().await;
+    }
+}
@@ -0,0 +1,212 @@
+/// Per-sender ordering of flooded chat messages
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Furthest a sequence number may skip ahead and still be waited for
+///
+/// A larger jump is not a few lost messages, so the message is passed on
+/// without moving the expected number rather than holding everything after it.
+const MAX_SEQUENCE_GAP: u64 = 1024;
+
+/// Senders with nothing pending are forgotten after this long without a message
+const IDLE_SENDER_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Holds back a sender's messages that overtook an earlier one
+///
+/// Flooding sends each message along several paths, so a sender's messages
+/// can arrive out of order. Messages carry the sender's sequence number and
+/// are released in that order. When one goes missing, the messages after it
+/// wait at most `window` before they are released anyway; a longer window
+/// fixes more reordering but delays everything behind a lost message by as
+/// much. Sequence 0 means the sender does not number its messages, these
+/// are never held back, and neither are numbers too far ahead to be a gap.
+#[derive(Debug)]
+pub struct ReorderBuffer<T> {
+    window: Duration,
+    senders: HashMap<String, SenderQueue<T>>,
+}
+
+#[derive(Debug)]
+struct SenderQueue<T> {
+    /// Sequence number expected next
+    next: u64,
+    /// Messages waiting for an earlier one, with when they arrived
+    pending: BTreeMap<u64, (Instant, T)>,
+    /// When the last message from this sender arrived
+    last_seen: Instant,
+}
+
+impl<T> ReorderBuffer<T> {
+    /// Hold out-of-order messages for up to `window`, a zero window disables ordering
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            senders: HashMap::new(),
+        }
+    }
+
+    /// Accept a message, returning the messages now ready in delivery order
+    pub fn push(&mut self, sender_id: &str, sequence: u64, item: T, now: Instant) -> Vec<T> {
+        if sequence == 0 || self.window.is_zero() {
+            return vec![item];
+        }
+
+        let Some(queue) = self.senders.get_mut(sender_id) else {
+            // The first message we see from a sender sets where its sequence starts
+            let queue = SenderQueue {
+                next: sequence.saturating_add(1),
+                pending: BTreeMap::new(),
+                last_seen: now,
+            };
+            self.senders.insert(sender_id.to_string(), queue);
+            return vec![item];
+        };
+        queue.last_seen = now;
+
+        if sequence == 1 && queue.next > 1 {
+            // The sender restarted and counts from the beginning again
+            debug!("Sender {} restarted its sequence", sender_id);
+            let mut ready: Vec<T> = std::mem::take(&mut queue.pending).into_values().map(|(_, item)| item).collect();
+            ready.push(item);
+            queue.next = 2;
+            return ready;
+        }
+
+        if sequence < queue.next {
+            // Released past already after its window ran out, late is better than never
+            return vec![item];
+        }
+
+        if sequence - queue.next > MAX_SEQUENCE_GAP {
+            debug!("Sender {} skipped from {} to {}, not holding it back", sender_id, queue.next, sequence);
+            return vec![item];
+        }
+
+        queue.pending.insert(sequence, (now, item));
+        queue.drain_ready()
+    }
+
+    /// Release messages that waited longer than the window for a missing one
+    pub fn flush_expired(&mut self, now: Instant) -> Vec<T> {
+        let mut ready = Vec::new();
+        for (sender_id, queue) in &mut self.senders {
+            let Some((&first, (arrived, _))) = queue.pending.first_key_value() else {
+                continue;
+            };
+            if now.duration_since(*arrived) < self.window {
+                continue;
+            }
+
+            debug!("Giving up on messages {}..{} from {}", queue.next, first, sender_id);
+            queue.next = first;
+            ready.extend(queue.drain_ready());
+        }
+
+        self.senders
+            .retain(|_, queue| !queue.pending.is_empty() || now.duration_since(queue.last_seen) < IDLE_SENDER_TIMEOUT);
+        ready
+    }
+
+    /// Forget a sender, e.g. once it disconnected, releasing what it had pending
+    pub fn forget_sender(&mut self, sender_id: &str) -> Vec<T> {
+        self.senders
+            .remove(sender_id)
+            .map(|queue| queue.pending.into_values().map(|(_, item)| item).collect())
+            .unwrap_or_default()
+    }
+
+    /// How long messages may be held back
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+impl<T> SenderQueue<T> {
+    /// Take the messages that continue the sequence without a gap
+    fn drain_ready(&mut self) -> Vec<T> {
+        let mut ready = Vec::new();
+        while let Some((_, item)) = self.pending.remove(&self.next) {
+            ready.push(item);
+            // u64::MAX is the last number a sender can use, nothing follows it
+            let Some(next) = self.next.checked_add(1) else {
+                break;
+            };
+            self.next = next;
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overtaking_messages_wait_for_earlier_ones() {
+        let mut buffer = ReorderBuffer::new(Duration::from_millis(300));
+        let now = Instant::now();
+
+        assert_eq!(buffer.push("alice", 1, "a1", now), vec!["a1"]);
+        assert!(buffer.push("alice", 3, "a3", now).is_empty());
+        assert!(buffer.push("alice", 4, "a4", now).is_empty());
+        // Other senders are not held up
+        assert_eq!(buffer.push("bob", 7, "b7", now), vec!["b7"]);
+        assert_eq!(buffer.push("alice", 2, "a2", now), vec!["a2", "a3", "a4"]);
+        // Unnumbered messages pass straight through
+        assert_eq!(buffer.push("carol", 0, "c", now), vec!["c"]);
+    }
+
+    #[test]
+    fn test_lost_message_is_skipped_after_window() {
+        let window = Duration::from_millis(300);
+        let mut buffer = ReorderBuffer::new(window);
+        let now = Instant::now();
+
+        buffer.push("alice", 1, "a1", now);
+        assert!(buffer.push("alice", 3, "a3", now).is_empty());
+        assert!(buffer.flush_expired(now + window / 2).is_empty());
+        assert_eq!(buffer.flush_expired(now + window), vec!["a3"]);
+
+        // The missing message still shows up if it arrives late
+        assert_eq!(buffer.push("alice", 2, "a2", now + window * 2), vec!["a2"]);
+        assert_eq!(buffer.push("alice", 4, "a4", now + window * 2), vec!["a4"]);
+    }
+
+    #[test]
+    fn test_far_jumps_and_the_last_sequence_number_pass_through() {
+        let mut buffer = ReorderBuffer::new(Duration::from_millis(300));
+        let now = Instant::now();
+
+        buffer.push("alice", 1, "a1", now);
+        assert_eq!(buffer.push("alice", u64::MAX, "far", now), vec!["far"]);
+        // The jump didn't move alice's expected sequence
+        assert_eq!(buffer.push("alice", 2, "a2", now), vec!["a2"]);
+
+        assert_eq!(buffer.push("bob", u64::MAX - 1, "b1", now), vec!["b1"]);
+        assert_eq!(buffer.push("bob", u64::MAX, "b2", now), vec!["b2"]);
+    }
+
+    #[test]
+    fn test_departed_and_idle_senders_are_forgotten() {
+        let mut buffer = ReorderBuffer::new(Duration::from_millis(300));
+        let now = Instant::now();
+
+        buffer.push("alice", 1, "a1", now);
+        buffer.push("alice", 3, "a3", now);
+        assert_eq!(buffer.forget_sender("alice"), vec!["a3"]);
+        assert!(buffer.forget_sender("alice").is_empty());
+
+        buffer.push("bob", 1, "b1", now);
+        buffer.flush_expired(now + IDLE_SENDER_TIMEOUT);
+        assert!(buffer.senders.is_empty());
+    }
+
+    #[test]
+    fn test_zero_window_disables_ordering() {
+        let mut buffer = ReorderBuffer::new(Duration::ZERO);
+        let now = Instant::now();
+        buffer.push("alice", 1, "a1", now);
+        assert_eq!(buffer.push("alice", 3, "a3", now), vec!["a3"]);
+    }
+}
@@ -1,16 +1,65 @@
 /// Peer management for P2P networking
-use crate::message::{P2PMessage, PeerInfo};
-use crate::tls::TlsConnection;
+use crate::config::{HEARTBEAT_INTERVAL, MAX_HEARTBEAT_INTERVAL_SECS};
+use crate::message::{MessageSecurity, P2PMessage, PeerInfo};
+use crate::p2p::blocklist::{Allowlist, Blocklist};
+use crate::p2p::handshake::{refuse, PeerFramed, PeerHello};
+use crate::p2p::node::UsernamePolicy;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::{mpsc, RwLock};
 use tokio::time::{interval, Duration};
-use tokio_util::codec::{FramedRead, FramedWrite, LinesCodec};
 use futures::{SinkExt, StreamExt};
+use tokio_util::codec::LinesCodecError;
 use tracing::{info, warn, error, debug};
 
+/// Disconnect reason given to a connection displaced by a newer one
+pub const REPLACED_REASON: &str = "Replaced by a newer connection";
+
+/// Traffic exchanged with a single peer
+///
+/// Every protocol message counts, including heartbeats, and byte counts
+/// are the serialized lines with their newline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerStats {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Counters updated by the connection task without taking a lock
+#[derive(Debug, Default)]
+struct TrafficCounters {
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl TrafficCounters {
+    fn record_sent(&self, line: &str) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(line.len() as u64 + 1, Ordering::Relaxed);
+    }
+
+    fn record_received(&self, line: &str) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(line.len() as u64 + 1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> PeerStats {
+        PeerStats {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
 /// Represents a connected peer
 #[derive(Debug)]
 pub struct Peer {
@@ -18,8 +67,27 @@ pub struct Peer {
     pub addr: SocketAddr,
     pub username: String,
     pub connected_at: u64,
-    pub last_heartbeat: u64,
+    /// Unix timestamp (secs) of the last traffic seen from this peer.
+    /// Shared with the connection task so it can be bumped without a write lock.
+    last_heartbeat: Arc<AtomicU64>,
+    /// Unix timestamp (secs) of the last chat message from this peer.
+    /// Heartbeats and other control traffic do not move it.
+    last_chat: Arc<AtomicU64>,
+    /// Traffic counters shared with the connection task
+    traffic: Arc<TrafficCounters>,
     pub protocol_version: String,
+    /// Release of the peer's build from the handshake, empty for older builds
+    pub build_version: String,
+    /// Seconds between the peer's heartbeats from the handshake, capped at
+    /// [`MAX_HEARTBEAT_INTERVAL_SECS`], 0 for older builds that don't say
+    pub heartbeat_interval_secs: u64,
+    /// Identity fingerprint from the handshake, empty if the peer sent none
+    pub fingerprint: String,
+    /// Whether the connection to this peer is TLS-encrypted
+    pub tls: bool,
+    /// Whether mutual TLS proved the peer's identity and peer ID,
+    /// otherwise both are only what the peer claimed
+    pub verified: bool,
 }
 
 impl Peer {
@@ -29,6 +97,7 @@ impl Peer {
         addr: SocketAddr,
         username: String,
         protocol_version: String,
+        fingerprint: String,
     ) -> Self {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -40,17 +109,30 @@ impl Peer {
             addr,
             username,
             connected_at: now,
-            last_heartbeat: now,
+            last_heartbeat: Arc::new(AtomicU64::new(now)),
+            last_chat: Arc::new(AtomicU64::new(now)),
+            traffic: Arc::new(TrafficCounters::default()),
             protocol_version,
+            build_version: String::new(),
+            heartbeat_interval_secs: 0,
+            fingerprint,
+            tls: false,
+            verified: false,
         }
     }
 
     /// Update the last heartbeat time
-    pub fn update_heartbeat(&mut self) {
-        self.last_heartbeat = SystemTime::now()
+    pub fn update_heartbeat(&self) {
+        let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
+        self.last_heartbeat.store(now, Ordering::Relaxed);
+    }
+
+    /// Get the last heartbeat time
+    pub fn last_heartbeat(&self) -> u64 {
+        self.last_heartbeat.load(Ordering::Relaxed)
     }
 
     /// Check if the peer is considered alive
@@ -60,7 +142,27 @@ impl Peer {
             .unwrap()
             .as_secs();
         
-        now - self.last_heartbeat < timeout_secs
+        now.saturating_sub(self.last_heartbeat()) < timeout_secs
+    }
+
+    /// Get the time of the last chat message, or of connecting if none was sent
+    pub fn last_chat(&self) -> u64 {
+        self.last_chat.load(Ordering::Relaxed)
+    }
+
+    /// Check if the peer has sent no chat messages for `idle_secs`
+    pub fn is_idle(&self, idle_secs: u64) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        now.saturating_sub(self.last_chat()) >= idle_secs
+    }
+
+    /// Traffic exchanged with this peer so far
+    pub fn stats(&self) -> PeerStats {
+        self.traffic.snapshot()
     }
 
     /// Convert to PeerInfo
@@ -69,7 +171,7 @@ impl Peer {
             peer_id: self.peer_id.clone(),
             addr: self.addr,
             username: self.username.clone(),
-            last_seen: self.last_heartbeat,
+            last_seen: self.last_heartbeat(),
         }
     }
 }
@@ -84,8 +186,10 @@ pub struct PeerConnection {
 impl PeerConnection {
     /// Create a new peer connection
     pub async fn new(
-        connection: TlsConnection,
+        framed: PeerFramed,
+        local_peer_id: &str,
         peer: Peer,
+        heartbeat_interval: Duration,
         message_tx: mpsc::Sender<(P2PMessage, String)>,
         disconnect_tx: mpsc::Sender<String>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
@@ -95,15 +199,20 @@ impl PeerConnection {
         let peer_id_clone = peer_id.clone();
         let message_tx_clone = message_tx.clone();
         let disconnect_tx_clone = disconnect_tx.clone();
+        let last_heartbeat = peer.last_heartbeat.clone();
+        let last_chat = peer.last_chat.clone();
+        let traffic = peer.traffic.clone();
 
-        // Split the connection for reading and writing
-        let (read_half, write_half) = tokio::io::split(connection);
-        let mut reader = FramedRead::new(read_half, LinesCodec::new());
-        let mut writer = FramedWrite::new(write_half, LinesCodec::new());
+        // Split the connection for reading and writing, keeping anything
+        // already buffered while the handshake was read
+        let max_frame_length = framed.codec().max_length();
+        let (mut writer, mut reader) = framed.split();
+        let local_peer_id = local_peer_id.to_string();
 
         // Spawn connection handler
         let connection_handle = tokio::spawn(async move {
-            let mut heartbeat_interval = interval(Duration::from_secs(30));
+            let mut heartbeat_interval = interval(heartbeat_interval);
+            let mut said_goodbye = false;
             
             loop {
                 tokio::select! {
@@ -111,21 +220,49 @@ impl PeerConnection {
                     frame = reader.next() => {
                         match frame {
                             Some(Ok(line)) => {
+                                traffic.record_received(&line);
                                 match serde_json::from_str::<P2PMessage>(&line) {
                                     Ok(message) => {
                                         debug!("Received message from {}: {:?}", peer_id, message);
                                         
-                                        // Update heartbeat for any received message
+                                        // Update heartbeat for any received message,
+                                        // but only chat traffic counts as activity
+                                        let now = SystemTime::now()
+                                            .duration_since(UNIX_EPOCH)
+                                            .unwrap()
+                                            .as_secs();
+                                        last_heartbeat.store(now, Ordering::Relaxed);
+                                        if matches!(message, P2PMessage::ChatMessage { .. }) {
+                                            last_chat.store(now, Ordering::Relaxed);
+                                        }
+
+                                        // A goodbye carries its own reason, so it is not reported as a lost connection
+                                        let goodbye = matches!(message, P2PMessage::Disconnect { .. });
                                         if let Err(e) = message_tx_clone.send((message, peer_id.clone())).await {
                                             error!("Failed to forward message from {}: {}", peer_id, e);
                                             break;
                                         }
+                                        if goodbye {
+                                            said_goodbye = true;
+                                            break;
+                                        }
                                     }
                                     Err(e) => {
                                         warn!("Failed to parse message from {}: {}", peer_id, e);
                                     }
                                 }
                             }
+                            Some(Err(LinesCodecError::MaxLineLengthExceeded)) => {
+                                // The rest of the line is unread, so the stream can't be trusted any more
+                                let reason = format!("Message exceeds {} bytes", max_frame_length);
+                                warn!("Disconnecting {}: {}", peer_id, reason);
+                                let disconnect = P2PMessage::Disconnect { peer_id: local_peer_id.clone(), reason };
+                                if let Ok(line) = serde_json::to_string(&disconnect) {
+                                    writer.send(line).await.ok();
+                                }
+                                writer.close().await.ok();
+                                break;
+                            }
                             Some(Err(e)) => {
                                 error!("Connection error with {}: {}", peer_id, e);
                                 break;
@@ -143,6 +280,7 @@ impl PeerConnection {
                             Some(msg) => {
                                 match serde_json::to_string(&msg) {
                                     Ok(line) => {
+                                        traffic.record_sent(&line);
                                         if let Err(e) = writer.send(line).await {
                                             error!("Failed to send message to {}: {}", peer_id, e);
                                             break;
@@ -153,6 +291,13 @@ impl PeerConnection {
                                         error!("Failed to serialize message for {}: {}", peer_id, e);
                                     }
                                 }
+
+                                // We said goodbye, close our side once it is flushed
+                                if matches!(msg, P2PMessage::Disconnect { .. }) {
+                                    writer.close().await.ok();
+                                    said_goodbye = true;
+                                    break;
+                                }
                             }
                             None => {
                                 info!("Message channel closed for peer {}", peer_id);
@@ -173,6 +318,7 @@ impl PeerConnection {
                         
                         match serde_json::to_string(&heartbeat) {
                             Ok(line) => {
+                                traffic.record_sent(&line);
                                 if let Err(e) = writer.send(line).await {
                                     error!("Failed to send heartbeat to {}: {}", peer_id, e);
                                     break;
@@ -187,9 +333,11 @@ impl PeerConnection {
                 }
             }
 
-            // Notify about disconnection
-            if let Err(e) = disconnect_tx_clone.send(peer_id_clone).await {
-                error!("Failed to notify about disconnection: {}", e);
+            // Notify about connections that ended without a goodbye from either side
+            if !said_goodbye {
+                if let Err(e) = disconnect_tx_clone.send(peer_id_clone).await {
+                    error!("Failed to notify about disconnection: {}", e);
+                }
             }
         });
 
@@ -207,31 +355,93 @@ impl PeerConnection {
     }
 
     /// Disconnect from this peer
-    pub async fn disconnect(&self, reason: String) {
+    ///
+    /// The connection task sends the `Disconnect` and closes the socket;
+    /// it is aborted if that takes longer than a second.
+    pub async fn disconnect(self, local_peer_id: &str, reason: String) {
         let disconnect_msg = P2PMessage::Disconnect {
-            peer_id: self.peer.peer_id.clone(),
+            peer_id: local_peer_id.to_string(),
             reason,
         };
         
-        if let Err(e) = self.sender.send(disconnect_msg).await {
-            warn!("Failed to send disconnect message to {}: {}", self.peer.peer_id, e);
+        let mut handle = self.connection_handle;
+        if let Err(e) = self.sender.try_send(disconnect_msg) {
+            // Usually the connection already closed, e.g. after a broadcast goodbye
+            debug!("Could not send disconnect message to {}: {}", self.peer.peer_id, e);
+            handle.abort();
+            return;
         }
         
-        self.connection_handle.abort();
+        tokio::spawn(async move {
+            if tokio::time::timeout(Duration::from_secs(1), &mut handle).await.is_err() {
+                handle.abort();
+            }
+        });
+    }
+}
+
+/// A peer connection that was added
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddedPeer {
+    /// Name the peer is known by, suffixed if its requested name was taken
+    pub username: String,
+    /// The requested username, if it was taken and the peer got another one
+    pub renamed_from: Option<String>,
+    /// Whether an unverified connection with the same peer ID was dropped for it
+    pub replaced: bool,
+}
+
+/// Outcome of adding a peer connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddPeerOutcome {
+    Added(AddedPeer),
+    /// Refused for the given reason, which the peer has been sent
+    Refused(String),
+}
+
+/// Free display name for a new peer whose username is already taken
+///
+/// A name is taken by us or by another connected peer with a different
+/// identity. Only a fingerprint proven by mutual TLS on both connections
+/// shows it is the same person again, who then keeps the name.
+/// Returns `None` if the requested username is free.
+fn unique_username(
+    connections: &HashMap<String, PeerConnection>,
+    local_username: &str,
+    hello: &PeerHello,
+    verified: bool,
+) -> Option<String> {
+    let taken = |name: &str| {
+        name == local_username
+            || connections.values().any(|conn| {
+                conn.peer.username == name
+                    && conn.peer.peer_id != hello.peer_id
+                    && !(verified && conn.peer.verified && conn.peer.fingerprint == hello.fingerprint)
+            })
+    };
+
+    if !taken(&hello.username) {
+        return None;
     }
+    (2..).map(|n| format!("{}#{}", hello.username, n)).find(|name| !taken(name))
 }
 
 /// Manages all peer connections
 #[derive(Clone)]
 pub struct PeerManager {
-    #[allow(dead_code)]
     local_peer_id: String,
-    #[allow(dead_code)]
     local_username: String,
     connections: Arc<RwLock<HashMap<String, PeerConnection>>>,
     message_tx: mpsc::Sender<(P2PMessage, String)>,
     disconnect_tx: mpsc::Sender<String>,
     max_connections: usize,
+    blocklist: Blocklist,
+    /// How often each connection sends a heartbeat
+    heartbeat_interval: Duration,
+    /// Only peers on this list are accepted, anyone not blocked if `None`
+    allowlist: Option<Allowlist>,
+    /// What to do with a peer whose username is already in use
+    username_policy: UsernamePolicy,
 }
 
 impl PeerManager {
@@ -240,6 +450,7 @@ impl PeerManager {
         local_peer_id: String,
         local_username: String,
         max_connections: usize,
+        blocklist: Blocklist,
     ) -> (Self, mpsc::Receiver<(P2PMessage, String)>, mpsc::Receiver<String>) {
         let (message_tx, message_rx) = mpsc::channel(1000);
         let (disconnect_tx, disconnect_rx) = mpsc::channel(100);
@@ -251,58 +462,201 @@ impl PeerManager {
             message_tx,
             disconnect_tx,
             max_connections,
+            blocklist,
+            heartbeat_interval: Duration::from_secs(HEARTBEAT_INTERVAL),
+            allowlist: None,
+            username_policy: UsernamePolicy::default(),
         };
 
         (manager, message_rx, disconnect_rx)
     }
 
-    /// Add a new peer connection
+    /// Set how often connections added from now on send heartbeats, at least every second
+    pub fn with_heartbeat_interval(mut self, secs: u64) -> Self {
+        self.heartbeat_interval = Duration::from_secs(secs.max(1));
+        self
+    }
+
+    /// Accept only peers on an allowlist from now on
+    pub fn with_allowlist(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist = Some(allowlist);
+        self
+    }
+
+    /// Set how peers with a username already in use are admitted
+    pub fn with_username_policy(mut self, policy: UsernamePolicy) -> Self {
+        self.username_policy = policy;
+        self
+    }
+
+    /// Whether a peer may connect, always true without an allowlist
+    pub fn is_allowed(&self, fingerprint: &str, addr: SocketAddr) -> bool {
+        self.allowlist.as_ref().is_none_or(|allowlist| allowlist.matches(fingerprint, addr))
+    }
+
+    /// Identity block list checked when peers are admitted
+    pub fn blocklist(&self) -> &Blocklist {
+        &self.blocklist
+    }
+
+    /// Add a new peer connection after its handshake, unless it must be refused
+    ///
+    /// `verified` says whether mutual TLS proved the peer's identity and peer ID.
+    /// An existing connection with the same peer ID is replaced unless it was
+    /// verified. The username is settled under the same lock as the insert, so
+    /// two peers can't both take a free name. A refused peer is told why.
     pub async fn add_peer(
         &self,
-        connection: TlsConnection,
-        peer_id: String,
+        mut framed: PeerFramed,
+        hello: PeerHello,
         addr: SocketAddr,
-        username: String,
-        protocol_version: String,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        verified: bool,
+    ) -> Result<AddPeerOutcome, Box<dyn std::error::Error + Send + Sync>> {
         let mut connections = self.connections.write().await;
-        
-        // Check if we already have this peer
-        if connections.contains_key(&peer_id) {
-            warn!("Peer {} already connected", peer_id);
-            return Ok(());
-        }
+        let unique = unique_username(&connections, &self.local_username, &hello, verified);
 
-        // Check connection limit
-        if connections.len() >= self.max_connections {
-            warn!("Maximum connections reached, rejecting peer {}", peer_id);
-            return Err("Maximum connections reached".into());
+        let refusal = if self.blocklist.is_blocked(&hello.fingerprint) {
+            Some("Blocked".to_string())
+        } else if !self.is_allowed(&hello.fingerprint, addr) {
+            Some("Not on the allowlist".to_string())
+        } else if connections.get(&hello.peer_id).is_some_and(|existing| existing.peer.verified) {
+            // A proven identity is never displaced by a later connection
+            Some("This identity is already connected".to_string())
+        } else if !connections.contains_key(&hello.peer_id) && connections.len() >= self.max_connections {
+            // A connection being replaced frees its own slot
+            Some("Peer limit reached".to_string())
+        } else if unique.is_some() && self.username_policy == UsernamePolicy::Reject {
+            Some(format!("Username {} is already in use", hello.username))
+        } else {
+            None
+        };
+
+        if let Some(reason) = refusal {
+            drop(connections);
+            warn!("Refusing peer {} ({}) at {}: {}", hello.username, hello.peer_id, addr, reason);
+            refuse(&mut framed, &self.local_peer_id, &reason).await;
+            return Ok(AddPeerOutcome::Refused(reason));
         }
 
-        let peer = Peer::new(peer_id.clone(), addr, username.clone(), protocol_version);
+        let PeerHello { peer_id, username: requested, protocol_version, fingerprint, build_version, heartbeat_interval_secs, .. } = hello;
+        let (username, renamed_from) = match unique {
+            Some(unique) => (unique, Some(requested)),
+            None => (requested, None),
+        };
+
+        let mut peer = Peer::new(peer_id.clone(), addr, username.clone(), protocol_version, fingerprint);
+        peer.build_version = build_version;
+        peer.heartbeat_interval_secs = heartbeat_interval_secs.min(MAX_HEARTBEAT_INTERVAL_SECS);
+        peer.tls = framed.get_ref().is_tls();
+        peer.verified = verified;
         let peer_connection = PeerConnection::new(
-            connection,
+            framed,
+            &self.local_peer_id,
             peer,
+            self.heartbeat_interval,
             self.message_tx.clone(),
             self.disconnect_tx.clone(),
         ).await?;
 
-        connections.insert(peer_id.clone(), peer_connection);
+        // An unproven peer ID is only a claim, so an existing connection under it
+        // is either stale or not who it said and gives way to the newcomer
+        let replaced = match connections.insert(peer_id.clone(), peer_connection) {
+            Some(stale) => {
+                stale.disconnect(&self.local_peer_id, REPLACED_REASON.to_string()).await;
+                info!("Replaced stale connection for peer {}", peer_id);
+                true
+            }
+            None => false,
+        };
         info!("Added peer connection: {} ({})", username, peer_id);
 
-        Ok(())
+        Ok(AddPeerOutcome::Added(AddedPeer { username, renamed_from, replaced }))
     }
 
-    /// Remove a peer connection
-    pub async fn remove_peer(&self, peer_id: &str, reason: String) {
+    /// Remove a peer connection, returns false if it was not connected
+    pub async fn remove_peer(&self, peer_id: &str, reason: String) -> bool {
         let mut connections = self.connections.write().await;
         
-        if let Some(connection) = connections.remove(peer_id) {
-            connection.disconnect(reason).await;
-            info!("Removed peer connection: {}", peer_id);
+        match connections.remove(peer_id) {
+            Some(connection) => {
+                connection.disconnect(&self.local_peer_id, reason).await;
+                info!("Removed peer connection: {}", peer_id);
+                true
+            }
+            None => false,
         }
     }
     
+    /// Disconnect every peer using a fingerprint, returns their peer IDs
+    pub async fn remove_peers_with_fingerprint(&self, fingerprint: &str, reason: &str) -> Vec<String> {
+        let mut connections = self.connections.write().await;
+        let peer_ids: Vec<String> = connections
+            .iter()
+            .filter(|(_, connection)| connection.peer.fingerprint == fingerprint)
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+
+        for peer_id in &peer_ids {
+            if let Some(connection) = connections.remove(peer_id) {
+                connection.disconnect(&self.local_peer_id, reason.to_string()).await;
+                info!("Removed peer connection: {}", peer_id);
+            }
+        }
+
+        peer_ids
+    }
+
+    /// How messages arriving from a connected peer are protected
+    ///
+    /// Chat messages are not end-to-end encrypted yet, so this reflects the
+    /// connection they arrived over.
+    pub async fn message_security(&self, peer_id: &str) -> MessageSecurity {
+        let connections = self.connections.read().await;
+        match connections.get(peer_id) {
+            Some(conn) if conn.peer.tls => MessageSecurity::Transport,
+            _ => MessageSecurity::Plain,
+        }
+    }
+
+    /// Fingerprint a connected peer presented in its handshake
+    pub async fn peer_fingerprint(&self, peer_id: &str) -> Option<String> {
+        let connections = self.connections.read().await;
+        connections.get(peer_id).map(|conn| conn.peer.fingerprint.clone())
+    }
+
+    /// Whether mutual TLS proved a connected peer's fingerprint, false if it is only claimed
+    pub async fn is_peer_verified(&self, peer_id: &str) -> bool {
+        let connections = self.connections.read().await;
+        connections.get(peer_id).is_some_and(|conn| conn.peer.verified)
+    }
+
+    /// Peer ID of the connection presenting a fingerprint, if any
+    pub async fn peer_id_for_fingerprint(&self, fingerprint: &str) -> Option<String> {
+        if fingerprint.is_empty() {
+            return None;
+        }
+
+        let connections = self.connections.read().await;
+        connections
+            .iter()
+            .find(|(_, conn)| conn.peer.fingerprint == fingerprint)
+            .map(|(peer_id, _)| peer_id.clone())
+    }
+
+    /// Traffic exchanged with a connected peer
+    pub async fn get_peer_stats(&self, peer_id: &str) -> Option<PeerStats> {
+        let connections = self.connections.read().await;
+        connections.get(peer_id).map(|conn| conn.peer.stats())
+    }
+
+    /// Protocol and build version a connected peer sent in its handshake
+    pub async fn peer_version(&self, peer_id: &str) -> Option<(String, String)> {
+        let connections = self.connections.read().await;
+        connections
+            .get(peer_id)
+            .map(|conn| (conn.peer.protocol_version.clone(), conn.peer.build_version.clone()))
+    }
+
     /// Disconnect all peers
     pub async fn disconnect_all_peers(&self) {
         let mut connections = self.connections.write().await;
@@ -310,10 +664,12 @@ impl PeerManager {
         info!("Disconnecting all {} peers", connections.len());
         
         for (peer_id, connection) in connections.drain() {
-            connection.disconnect("Node shutting down".to_string()).await;
+            connection.disconnect(&self.local_peer_id, "Node shutting down".to_string()).await;
             info!("Disconnected peer: {}", peer_id);
         }
     }
+
+    /// Send a message to a single connected peer
     pub async fn send_to_peer(
         &self,
         peer_id: &str,
@@ -353,6 +709,11 @@ impl PeerManager {
         connections.len()
     }
 
+    /// Whether no more peers can be added
+    pub async fn is_full(&self) -> bool {
+        self.connection_count().await >= self.max_connections
+    }
+
     /// Check if a peer is connected
     pub async fn is_peer_connected(&self, peer_id: &str) -> bool {
         let connections = self.connections.read().await;
@@ -360,32 +721,328 @@ impl PeerManager {
     }
 
     /// Cleanup dead connections
-    pub async fn cleanup_dead_connections(&self, timeout_secs: u64) {
+    ///
+    /// `timeout_for` maps a peer's advertised heartbeat interval to how long
+    /// it may stay silent, so peers that heartbeat slower than us survive.
+    pub async fn cleanup_dead_connections(&self, timeout_for: impl Fn(u64) -> u64) {
         let mut connections = self.connections.write().await;
         let mut dead_peers = Vec::new();
 
         for (peer_id, connection) in connections.iter() {
-            if !connection.peer.is_alive(timeout_secs) {
+            if !connection.peer.is_alive(timeout_for(connection.peer.heartbeat_interval_secs)) {
                 dead_peers.push(peer_id.clone());
             }
         }
 
         for peer_id in dead_peers {
             if let Some(connection) = connections.remove(&peer_id) {
-                connection.disconnect("Connection timeout".to_string()).await;
+                connection.disconnect(&self.local_peer_id, "Connection timeout".to_string()).await;
                 warn!("Removed dead peer connection: {}", peer_id);
             }
         }
     }
 
+    /// Disconnect peers that sent no chat messages for `idle_secs`
+    ///
+    /// Returns the peer IDs that were disconnected
+    pub async fn remove_idle_peers(&self, idle_secs: u64) -> Vec<String> {
+        let mut connections = self.connections.write().await;
+        let idle_peers: Vec<String> = connections
+            .iter()
+            .filter(|(_, connection)| connection.peer.is_idle(idle_secs))
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+
+        for peer_id in &idle_peers {
+            if let Some(connection) = connections.remove(peer_id) {
+                connection.disconnect(&self.local_peer_id, "idle timeout".to_string()).await;
+                info!("Removed idle peer connection: {}", peer_id);
+            }
+        }
+
+        idle_peers
+    }
+
     /// Update peer heartbeat
     pub async fn update_peer_heartbeat(&self, peer_id: &str) {
-        let mut connections = self.connections.write().await;
+        let connections = self.connections.read().await;
         
-        if let Some(_connection) = connections.get_mut(peer_id) {
-            // Note: This is a simplified approach. In a real implementation,
-            // you might want to use Arc<Mutex<Peer>> for interior mutability
+        if let Some(connection) = connections.get(peer_id) {
+            connection.peer.update_heartbeat();
             debug!("Updated heartbeat for peer {}", peer_id);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::{TlsConnection, TlsListener};
+    use crate::p2p::handshake::frame_connection;
+    use tokio_util::codec::{Framed, LinesCodec};
+
+    fn hello(peer_id: &str, fingerprint: &str) -> PeerHello {
+        PeerHello {
+            peer_id: peer_id.to_string(),
+            username: peer_id.to_string(),
+            protocol_version: "1.0".to_string(),
+            fingerprint: fingerprint.to_string(),
+            build_version: String::new(),
+            heartbeat_interval_secs: 0,
+            room_proof: String::new(),
+        }
+    }
+
+    fn named_hello(peer_id: &str, username: &str, fingerprint: &str) -> PeerHello {
+        PeerHello {
+            username: username.to_string(),
+            ..hello(peer_id, fingerprint)
+        }
+    }
+
+    /// Connect a plain peer and return the remote end so it stays open
+    async fn try_connect_peer(
+        manager: &PeerManager,
+        hello: PeerHello,
+        listener: &TlsListener,
+    ) -> (AddPeerOutcome, TlsConnection) {
+        try_connect_verified_peer(manager, hello, listener, false).await
+    }
+
+    async fn try_connect_verified_peer(
+        manager: &PeerManager,
+        hello: PeerHello,
+        listener: &TlsListener,
+        verified: bool,
+    ) -> (AddPeerOutcome, TlsConnection) {
+        let addr = listener.local_addr().unwrap();
+        let connection = TlsConnection::connect_plain(addr).await.unwrap();
+        let (remote, _) = listener.accept().await.unwrap();
+        let framed = frame_connection(connection, 1024);
+        (manager.add_peer(framed, hello, addr, verified).await.unwrap(), remote)
+    }
+
+    fn added(outcome: AddPeerOutcome) -> AddedPeer {
+        match outcome {
+            AddPeerOutcome::Added(added) => added,
+            AddPeerOutcome::Refused(reason) => panic!("peer was refused: {}", reason),
+        }
+    }
+
+    async fn connect_peer(manager: &PeerManager, peer_id: &str, listener: &TlsListener) -> TlsConnection {
+        let (outcome, remote) = try_connect_peer(manager, hello(peer_id, ""), listener).await;
+        added(outcome);
+        remote
+    }
+
+    async fn backdate(manager: &PeerManager, peer_id: &str, secs: u64) {
+        let connections = manager.connections.read().await;
+        let peer = &connections.get(peer_id).unwrap().peer;
+        peer.last_heartbeat.store(peer.last_heartbeat() - secs, Ordering::Relaxed);
+        peer.last_chat.store(peer.last_chat() - secs, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_peer_heartbeat_updates() {
+        let peer = Peer::new(
+            "peer".to_string(),
+            "127.0.0.1:40000".parse().unwrap(),
+            "alice".to_string(),
+            "1.0".to_string(),
+            String::new(),
+        );
+        peer.last_heartbeat.store(0, Ordering::Relaxed);
+        assert!(!peer.is_alive(120));
+
+        peer.update_heartbeat();
+        assert!(peer.is_alive(120));
+        assert_eq!(peer.to_peer_info().last_seen, peer.last_heartbeat());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_keeps_heartbeating_peer() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, _msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "local".to_string(), 10, Blocklist::new());
+
+        let _alive_remote = connect_peer(&manager, "alive", &listener).await;
+        let _silent_remote = connect_peer(&manager, "silent", &listener).await;
+
+        backdate(&manager, "alive", 300).await;
+        backdate(&manager, "silent", 300).await;
+        manager.update_peer_heartbeat("alive").await;
+
+        manager.cleanup_dead_connections(|_| 120).await;
+
+        assert!(manager.is_peer_connected("alive").await);
+        assert!(!manager.is_peer_connected("silent").await);
+        assert_eq!(manager.connection_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_waits_on_slow_heartbeating_peer() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, _msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "local".to_string(), 10, Blocklist::new());
+
+        let slow = PeerHello { heartbeat_interval_secs: 100, ..hello("slow", "") };
+        let (outcome, _slow_remote) = try_connect_peer(&manager, slow, &listener).await;
+        added(outcome);
+        let _legacy_remote = connect_peer(&manager, "legacy", &listener).await;
+
+        backdate(&manager, "slow", 300).await;
+        backdate(&manager, "legacy", 300).await;
+
+        manager.cleanup_dead_connections(|heartbeat| heartbeat.max(30) * 4).await;
+
+        assert!(manager.is_peer_connected("slow").await);
+        assert!(!manager.is_peer_connected("legacy").await);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_fingerprint_is_refused() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let blocklist = Blocklist::new();
+        blocklist.block("bad:fp").unwrap();
+        let (manager, _msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "local".to_string(), 10, blocklist);
+
+        let (outcome, _remote) = try_connect_peer(&manager, hello("mallory", "bad:fp"), &listener).await;
+        assert_eq!(outcome, AddPeerOutcome::Refused("Blocked".to_string()));
+        assert!(!manager.is_peer_connected("mallory").await);
+
+        let (outcome, _remote) = try_connect_peer(&manager, hello("carol", "carol:fp"), &listener).await;
+        added(outcome);
+        let removed = manager.remove_peers_with_fingerprint("carol:fp", "Blocked").await;
+        assert_eq!(removed, vec!["carol".to_string()]);
+        assert_eq!(manager.connection_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_unverified_duplicate_replaces_stale_connection() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, _msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "local".to_string(), 1, Blocklist::new());
+
+        let (first, _first_remote) = try_connect_peer(&manager, hello("bob", ""), &listener).await;
+        assert!(!added(first).replaced);
+
+        // A claimed peer ID cannot lock anyone out, even with no slots left
+        let (second, _second_remote) = try_connect_peer(&manager, hello("bob", ""), &listener).await;
+        assert!(added(second).replaced);
+        assert_eq!(manager.connection_count().await, 1);
+
+        // A proven peer ID can't be taken over
+        let (third, _third_remote) = try_connect_verified_peer(&manager, hello("bob", ""), &listener, true).await;
+        assert!(added(third).replaced);
+        assert!(manager.connections.read().await["bob"].peer.verified);
+        let (fourth, _fourth_remote) = try_connect_verified_peer(&manager, hello("bob", ""), &listener, true).await;
+        assert!(matches!(fourth, AddPeerOutcome::Refused(_)));
+        let (fifth, _fifth_remote) = try_connect_peer(&manager, hello("bob", ""), &listener).await;
+        assert!(matches!(fifth, AddPeerOutcome::Refused(_)));
+        assert!(manager.connections.read().await["bob"].peer.verified);
+    }
+
+    #[tokio::test]
+    async fn test_plain_connection_security() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, _msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "local".to_string(), 10, Blocklist::new());
+
+        let _remote = connect_peer(&manager, "bob", &listener).await;
+        assert_eq!(manager.message_security("bob").await, MessageSecurity::Plain);
+        assert_eq!(manager.message_security("unknown").await, MessageSecurity::Plain);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_do_not_reset_idle_timer() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, _msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "local".to_string(), 10, Blocklist::new());
+
+        let _idle_remote = connect_peer(&manager, "idle", &listener).await;
+        let _fresh_remote = connect_peer(&manager, "fresh", &listener).await;
+
+        backdate(&manager, "idle", 600).await;
+        manager.update_peer_heartbeat("idle").await;
+
+        assert_eq!(manager.remove_idle_peers(300).await, vec!["idle".to_string()]);
+        assert!(manager.is_peer_connected("fresh").await);
+        assert_eq!(manager.connection_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_peer_stats_count_received_lines() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, mut msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "local".to_string(), 10, Blocklist::new());
+
+        let remote = connect_peer(&manager, "bob", &listener).await;
+        let mut remote = Framed::new(remote, LinesCodec::new());
+        let line = serde_json::to_string(&P2PMessage::Heartbeat { peer_id: "bob".to_string(), timestamp: 0 }).unwrap();
+        remote.send(line.clone()).await.unwrap();
+        msg_rx.recv().await.unwrap();
+
+        let stats = manager.get_peer_stats("bob").await.unwrap();
+        assert_eq!(stats.messages_received, 1);
+        assert_eq!(stats.bytes_received, line.len() as u64 + 1);
+        assert!(manager.get_peer_stats("nobody").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_disconnects_peer() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, _msg_rx, mut disc_rx) =
+            PeerManager::new("local".to_string(), "local".to_string(), 10, Blocklist::new());
+
+        let remote = connect_peer(&manager, "bob", &listener).await;
+        let mut remote = Framed::new(remote, LinesCodec::new());
+        remote.send("x".repeat(2048)).await.unwrap();
+
+        // Skip heartbeats until the goodbye arrives
+        let reason = loop {
+            let line = remote.next().await.unwrap().unwrap();
+            if let P2PMessage::Disconnect { reason, .. } = serde_json::from_str(&line).unwrap() {
+                break reason;
+            }
+        };
+        assert_eq!(reason, "Message exceeds 1024 bytes");
+        assert_eq!(disc_rx.recv().await.unwrap(), "bob");
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_usernames_get_suffix() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, _msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "carol".to_string(), 10, Blocklist::new());
+        let cases = [
+            (named_hello("a1", "alice", "aa:01"), true, "alice"),
+            (named_hello("b1", "bob", "bb:01"), false, "bob"),
+            (named_hello("a2", "alice", "aa:02"), true, "alice#2"),
+            (named_hello("c1", "carol", ""), false, "carol#2"),
+            // Only a proven fingerprint shows it is the same person on a second connection
+            (named_hello("a3", "alice", "aa:01"), false, "alice#3"),
+            (named_hello("a4", "alice", "aa:01"), true, "alice"),
+        ];
+
+        let mut remotes = Vec::new();
+        for (hello, verified, expected) in cases {
+            let (outcome, remote) = try_connect_verified_peer(&manager, hello, &listener, verified).await;
+            assert_eq!(added(outcome).username, expected);
+            remotes.push(remote);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_username_policy_reject() {
+        let listener = TlsListener::bind_plain("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let (manager, _msg_rx, _disc_rx) =
+            PeerManager::new("local".to_string(), "carol".to_string(), 10, Blocklist::new());
+        let manager = manager.with_username_policy(UsernamePolicy::Reject);
+
+        let (outcome, _alice_remote) = try_connect_peer(&manager, named_hello("a1", "alice", ""), &listener).await;
+        added(outcome);
+        let (outcome, _other_remote) = try_connect_peer(&manager, named_hello("a2", "alice", ""), &listener).await;
+        assert_eq!(outcome, AddPeerOutcome::Refused("Username alice is already in use".to_string()));
+    }
+}
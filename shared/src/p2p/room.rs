@@ -0,0 +1,93 @@
+/// Room key: a shared passphrase peers must know to be admitted
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Label for the TLS exporter value a room proof is bound to
+pub const ROOM_BINDING_LABEL: &[u8] = b"EXPORTER-dpq-chat-room";
+
+/// Key derived from a room passphrase
+///
+/// Each side of a handshake sends an HMAC of its peer ID and the TLS
+/// connection's exporter value keyed with this, so a proof seen on one
+/// connection is useless on another. Over plain TCP there is no exporter
+/// and a proof could be replayed by anyone who saw it.
+///
+/// The passphrase is only hashed, so anyone who connects can try to guess
+/// it offline from a proof; use a long one.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RoomKey([u8; 32]);
+
+impl RoomKey {
+    /// Derive the key from a passphrase
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"dpq-chat-room-key");
+        hasher.update(passphrase.as_bytes());
+        Self(hasher.finalize().into())
+    }
+
+    /// Proof that the peer `peer_id` knows this key, as lowercase hex
+    pub fn proof(&self, binding: &[u8], peer_id: &str) -> String {
+        self.mac(binding, peer_id)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Check a proof sent by `peer_id`, in constant time
+    pub fn verify(&self, proof: &str, binding: &[u8], peer_id: &str) -> bool {
+        match decode_hex(proof) {
+            Some(bytes) => self.mac(binding, peer_id).verify_slice(&bytes).is_ok(),
+            None => false,
+        }
+    }
+
+    fn mac(&self, binding: &[u8], peer_id: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(&(binding.len() as u64).to_be_bytes());
+        mac.update(binding);
+        mac.update(peer_id.as_bytes());
+        mac
+    }
+}
+
+impl fmt::Debug for RoomKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RoomKey(<redacted>)")
+    }
+}
+
+/// Decode lowercase or uppercase hex, `None` if malformed
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_proof_is_bound_to_key_connection_and_peer() {
+        let key = RoomKey::from_passphrase("correct horse battery staple");
+        let proof = key.proof(b"connection-1", "alice");
+
+        assert!(key.verify(&proof, b"connection-1", "alice"));
+        assert!(!RoomKey::from_passphrase("wrong").verify(&proof, b"connection-1", "alice"));
+        assert!(!key.verify(&proof, b"connection-2", "alice"));
+        assert!(!key.verify(&proof, b"connection-1", "mallory"));
+        assert!(!key.verify("", b"connection-1", "alice"));
+        assert!(!key.verify("not hex", b"connection-1", "alice"));
+        assert_eq!(format!("{:?}", key), "RoomKey(<redacted>)");
+    }
+}
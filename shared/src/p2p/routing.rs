@@ -1,10 +1,13 @@
 /// Message routing and flooding for P2P networks
-use crate::message::{P2PMessage, PeerInfo};
-use std::collections::HashMap;
+use crate::config::{BUILD_VERSION, DEFAULT_MESSAGE_TTL, DEFAULT_RATE_LIMIT, LINK_RATE_LIMIT_FACTOR, PROTOCOL_VERSION, RATE_LIMIT_WINDOW_SECS};
+use crate::crypto::message_crypto::MessageSequenceManager;
+use crate::message::{ChatKind, P2PMessage, PeerInfo};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
-use tracing::{info, debug};
+use crate::utils::is_valid_message_content;
+use tracing::{info, warn, debug};
 use uuid::Uuid;
 
 /// Routing table for P2P network
@@ -16,7 +19,7 @@ pub struct RoutingTable {
     /// Connected peers
     peers: Arc<RwLock<HashMap<String, PeerInfo>>>,
     /// Message cache to prevent loops
-    message_cache: Arc<RwLock<HashMap<String, u64>>>,
+    message_cache: Arc<RwLock<SeenMessages>>,
     /// Maximum cache size
     max_cache_size: usize,
     /// Cache TTL in seconds
@@ -29,7 +32,7 @@ impl RoutingTable {
         Self {
             local_peer_id,
             peers: Arc::new(RwLock::new(HashMap::new())),
-            message_cache: Arc::new(RwLock::new(HashMap::new())),
+            message_cache: Arc::new(RwLock::new(SeenMessages::default())),
             max_cache_size: 10000,
             cache_ttl_secs: 300, // 5 minutes
         }
@@ -59,24 +62,32 @@ impl RoutingTable {
     /// Check if we have seen this message before
     pub async fn has_seen_message(&self, message_id: &str) -> bool {
         let cache = self.message_cache.read().await;
-        cache.contains_key(message_id)
+        cache.ids.contains(message_id)
     }
 
     /// Mark a message as seen
-    pub async fn mark_message_seen(&self, message_id: String) {
+    ///
+    /// Checking and marking happen under one lock, so when the same message
+    /// arrives over two paths at once only one caller gets `true`.
+    /// Returns false if the message was already seen.
+    pub async fn mark_message_seen(&self, message_id: String) -> bool {
         let mut cache = self.message_cache.write().await;
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
         
-        cache.insert(message_id, now);
+        if !cache.ids.insert(message_id.clone()) {
+            return false;
+        }
+        cache.order.push_back((message_id, now));
 
-        // Clean up old entries if cache is too large
-        if cache.len() > self.max_cache_size {
-            let cutoff_time = now - self.cache_ttl_secs;
-            cache.retain(|_, &mut timestamp| timestamp > cutoff_time);
+        // Entries are in insertion order, so the oldest are always at the front
+        while cache.ids.len() > self.max_cache_size {
+            cache.evict_oldest();
         }
+
+        true
     }
 
     /// Clean up old message cache entries
@@ -86,11 +97,13 @@ impl RoutingTable {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        let cutoff_time = now - self.cache_ttl_secs;
+        let cutoff_time = now.saturating_sub(self.cache_ttl_secs);
 
-        let old_size = cache.len();
-        cache.retain(|_, &mut timestamp| timestamp > cutoff_time);
-        let new_size = cache.len();
+        let old_size = cache.ids.len();
+        while cache.order.front().is_some_and(|(_, timestamp)| *timestamp <= cutoff_time) {
+            cache.evict_oldest();
+        }
+        let new_size = cache.ids.len();
 
         if old_size != new_size {
             debug!("Cleaned up message cache: {} -> {} entries", old_size, new_size);
@@ -104,12 +117,92 @@ impl RoutingTable {
     }
 }
 
+/// IDs of messages already seen, with the order they were first seen in
+#[derive(Debug, Default)]
+struct SeenMessages {
+    ids: HashSet<String>,
+    /// Message IDs and when they were seen, oldest first
+    order: VecDeque<(String, u64)>,
+}
+
+impl SeenMessages {
+    fn evict_oldest(&mut self) {
+        if let Some((message_id, _)) = self.order.pop_front() {
+            self.ids.remove(&message_id);
+        }
+    }
+}
+
+/// Per-source limit on chat messages entering the mesh through this node
+///
+/// Counts messages per source, a `sender_id` or the direct peer that passed
+/// them on, in fixed windows of [`RATE_LIMIT_WINDOW_SECS`]. Dropping excess messages here, before they are
+/// forwarded, keeps a flooding peer from being amplified by every node.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    /// Messages allowed per source and window, 0 disables the limit
+    limit: u32,
+    window: Duration,
+    /// sender_id -> (window start, messages seen in it)
+    counts: Arc<RwLock<HashMap<String, (Instant, u32)>>>,
+}
+
+impl RateLimiter {
+    /// Allow `limit` messages per source and window, 0 for no limit
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            window: Duration::from_secs(RATE_LIMIT_WINDOW_SECS),
+            counts: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Count a message from `sender_id`, returning false if it is over the limit
+    pub async fn allow(&self, sender_id: &str) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut counts = self.counts.write().await;
+
+        // Forget sources whose window has ended, so spoofed IDs can't grow the map forever
+        if counts.len() > 1000 {
+            counts.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+        }
+
+        let (start, count) = counts.entry(sender_id.to_string()).or_insert((now, 0));
+        if now.duration_since(*start) >= self.window {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+
+        // Warn once per window rather than for every dropped message
+        if *count == self.limit + 1 {
+            warn!(
+                "Source {} sent more than {} messages in {}s, dropping the rest",
+                sender_id, self.limit, self.window.as_secs()
+            );
+        }
+        *count <= self.limit
+    }
+}
+
 /// Message router for handling P2P message propagation
 #[derive(Clone)]
 pub struct MessageRouter {
     routing_table: RoutingTable,
     local_peer_id: String,
     local_username: String,
+    /// Hops our own chat messages may travel
+    message_ttl: u8,
+    /// Limit on chat messages relayed per source
+    rate_limiter: RateLimiter,
+    /// Limit on chat messages per direct peer, whatever sources they claim
+    link_rate_limiter: RateLimiter,
+    /// Numbers our chat messages so receivers can put them in order
+    sequences: Arc<std::sync::Mutex<MessageSequenceManager>>,
 }
 
 impl MessageRouter {
@@ -121,9 +214,29 @@ impl MessageRouter {
             routing_table,
             local_peer_id,
             local_username,
+            message_ttl: DEFAULT_MESSAGE_TTL,
+            rate_limiter: RateLimiter::new(DEFAULT_RATE_LIMIT),
+            link_rate_limiter: RateLimiter::new(DEFAULT_RATE_LIMIT * LINK_RATE_LIMIT_FACTOR),
+            sequences: Arc::new(std::sync::Mutex::new(MessageSequenceManager::new())),
         }
     }
 
+    /// Set how many hops our own chat messages may travel, at least one
+    pub fn with_message_ttl(mut self, message_ttl: u8) -> Self {
+        self.message_ttl = message_ttl.max(1);
+        self
+    }
+
+    /// Set how many chat messages a source may send per rate window, 0 for no limit
+    ///
+    /// A direct peer may pass on [`LINK_RATE_LIMIT_FACTOR`] times as many,
+    /// since it relays for others too.
+    pub fn with_rate_limit(mut self, limit: u32) -> Self {
+        self.rate_limiter = RateLimiter::new(limit);
+        self.link_rate_limiter = RateLimiter::new(limit.saturating_mul(LINK_RATE_LIMIT_FACTOR));
+        self
+    }
+
     /// Get the routing table
     pub fn routing_table(&self) -> &RoutingTable {
         &self.routing_table
@@ -143,31 +256,67 @@ impl MessageRouter {
                 content,
                 ttl,
                 mut seen_by,
+                kind,
+                sequence,
             } => {
-                // Check if we've seen this message before
-                if self.routing_table.has_seen_message(&message_id).await {
+                // Check and mark in one step so parallel paths can't both deliver
+                if !self.routing_table.mark_message_seen(message_id.clone()).await {
                     debug!("Ignoring duplicate message: {}", message_id);
                     return RoutingAction::Drop;
                 }
 
+                // The sender ID is whatever the message claims, so the link it came
+                // over is limited too, or rotating IDs would get past the limit
+                if !self.link_rate_limiter.allow(&from_peer_id).await {
+                    debug!("Dropping rate-limited message {} relayed by {}", message_id, from_peer_id);
+                    return RoutingAction::Drop;
+                }
+
+                // Neither deliver nor re-flood messages from a source over its rate
+                if !self.rate_limiter.allow(&sender_id).await {
+                    debug!("Dropping rate-limited message {} from {}", message_id, sender_id);
+                    return RoutingAction::Drop;
+                }
+
                 // Check if TTL is expired
                 if ttl == 0 {
                     debug!("Dropping message with expired TTL: {}", message_id);
                     return RoutingAction::Drop;
                 }
 
+                // Never deliver or re-flood empty or over-long content
+                if !is_valid_message_content(&content) {
+                    warn!("Dropping invalid message {} ({} bytes) from {}", message_id, content.len(), from_peer_id);
+                    return RoutingAction::Drop;
+                }
+
                 // Check if we're in the seen_by list
                 if seen_by.contains(&self.local_peer_id) {
                     debug!("Ignoring message already seen by us: {}", message_id);
                     return RoutingAction::Drop;
                 }
 
-                // Mark message as seen
-                self.routing_table.mark_message_seen(message_id.clone()).await;
-
-                // Add ourselves to seen_by list
+                // Add ourselves to seen_by before picking peers, so the copy
+                // we forward tells them we already have it
                 seen_by.push(self.local_peer_id.clone());
 
+                // This was the last hop, deliver without flooding further
+                if ttl == 1 {
+                    debug!("Not forwarding message {} on its last hop", message_id);
+                    return RoutingAction::Deliver {
+                        message: P2PMessage::ChatMessage {
+                            message_id,
+                            sender_id,
+                            username,
+                            content,
+                            ttl,
+                            seen_by,
+                            kind,
+                            sequence,
+                        },
+                    };
+                }
+
                 // Create modified message for forwarding
                 let forward_message = P2PMessage::ChatMessage {
                     message_id: message_id.clone(),
@@ -176,6 +325,8 @@ impl MessageRouter {
                     content: content.clone(),
                     ttl: ttl - 1,
                     seen_by: seen_by.clone(),
+                    kind,
+                    sequence,
                 };
 
                 // Determine which peers to forward to
@@ -198,6 +349,8 @@ impl MessageRouter {
                         content,
                         ttl,
                         seen_by,
+                        kind,
+                        sequence,
                     },
                     forward_message,
                     forward_to,
@@ -245,10 +398,10 @@ impl MessageRouter {
                 }
             }
 
-            P2PMessage::Handshake { peer_id, username, protocol_version } => {
-                RoutingAction::Deliver {
-                    message: P2PMessage::Handshake { peer_id, username, protocol_version },
-                }
+            P2PMessage::Handshake { .. } => {
+                // Handshakes are exchanged before a peer is admitted, a late one is noise
+                debug!("Ignoring unexpected handshake from {}", from_peer_id);
+                RoutingAction::Drop
             }
 
             P2PMessage::Heartbeat { peer_id, timestamp: _ } => {
@@ -257,6 +410,37 @@ impl MessageRouter {
                 RoutingAction::UpdateHeartbeat { peer_id }
             }
 
+            P2PMessage::Presence { peer_id, status, message } => {
+                // Only meaningful for the direct peer that sent it, never forwarded
+                RoutingAction::Deliver {
+                    message: P2PMessage::Presence { peer_id, status, message },
+                }
+            }
+
+            P2PMessage::Typing { peer_id, username } => {
+                // Like presence, only shown for direct peers
+                RoutingAction::Deliver {
+                    message: P2PMessage::Typing { peer_id, username },
+                }
+            }
+
+            P2PMessage::Ping { nonce, .. } => {
+                // Echo straight back over the connection it came in on
+                RoutingAction::Respond {
+                    to_peer: from_peer_id,
+                    message: P2PMessage::Pong {
+                        peer_id: self.local_peer_id.clone(),
+                        nonce,
+                    },
+                }
+            }
+
+            P2PMessage::Pong { peer_id, nonce } => {
+                RoutingAction::Deliver {
+                    message: P2PMessage::Pong { peer_id, nonce },
+                }
+            }
+
             P2PMessage::Disconnect { peer_id, reason } => {
                 // Remove peer from routing table
                 self.routing_table.remove_peer(&peer_id).await;
@@ -270,6 +454,15 @@ impl MessageRouter {
 
     /// Create a new chat message for broadcasting
     pub fn create_chat_message(&self, content: String) -> P2PMessage {
+        self.create_message_of_kind(content, ChatKind::Text)
+    }
+
+    /// Create a new `/me` action message for broadcasting
+    pub fn create_action_message(&self, content: String) -> P2PMessage {
+        self.create_message_of_kind(content, ChatKind::Action)
+    }
+
+    fn create_message_of_kind(&self, content: String, kind: ChatKind) -> P2PMessage {
         let message_id = Uuid::new_v4().to_string();
         
         P2PMessage::ChatMessage {
@@ -277,8 +470,10 @@ impl MessageRouter {
             sender_id: self.local_peer_id.clone(),
             username: self.local_username.clone(),
             content,
-            ttl: 7, // Default TTL
+            ttl: self.message_ttl,
             seen_by: vec![self.local_peer_id.clone()],
+            kind,
+            sequence: self.sequences.lock().unwrap().next_sequence(),
         }
     }
 
@@ -291,12 +486,16 @@ impl MessageRouter {
         }
     }
 
-    /// Create a handshake message
-    pub fn create_handshake(&self) -> P2PMessage {
+    /// Create a handshake message advertising our heartbeat interval
+    pub fn create_handshake(&self, fingerprint: String, heartbeat_interval_secs: u64) -> P2PMessage {
         P2PMessage::Handshake {
             peer_id: self.local_peer_id.clone(),
             username: self.local_username.clone(),
-            protocol_version: "1.0".to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            fingerprint,
+            build_version: BUILD_VERSION.to_string(),
+            heartbeat_interval_secs,
+            room_proof: String::new(),
         }
     }
 
@@ -306,7 +505,7 @@ impl MessageRouter {
             connected_peers: self.routing_table.peer_count().await,
             cached_messages: {
                 let cache = self.routing_table.message_cache.read().await;
-                cache.len()
+                cache.ids.len()
             },
         }
     }
@@ -344,3 +543,191 @@ pub struct NetworkStats {
     pub connected_peers: usize,
     pub cached_messages: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MAX_MESSAGE_LENGTH;
+
+    #[tokio::test]
+    async fn test_router_drops_oversized_messages() {
+        let sender = MessageRouter::new("sender".to_string(), "alice".to_string());
+        let receiver = MessageRouter::new("receiver".to_string(), "bob".to_string());
+
+        let message = sender.create_chat_message("a".repeat(MAX_MESSAGE_LENGTH + 1));
+        let action = receiver.process_message(message, "sender".to_string()).await;
+        assert!(matches!(action, RoutingAction::Drop));
+
+        let message = sender.create_chat_message("a".repeat(MAX_MESSAGE_LENGTH));
+        let action = receiver.process_message(message, "sender".to_string()).await;
+        assert!(matches!(action, RoutingAction::ForwardAndDeliver { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_chat_messages_are_numbered_in_order() {
+        let sender = MessageRouter::new("sender".to_string(), "alice".to_string());
+        let sequences: Vec<u64> = [
+            sender.create_chat_message("one".to_string()),
+            sender.create_action_message("waves".to_string()),
+            sender.create_chat_message("two".to_string()),
+        ]
+        .into_iter()
+        .map(|message| match message {
+            P2PMessage::ChatMessage { sequence, .. } => sequence,
+            other => panic!("unexpected message: {:?}", other),
+        })
+        .collect();
+        assert_eq!(sequences, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_flooding_source_is_throttled() {
+        let spammer = MessageRouter::new("spammer".to_string(), "mallory".to_string());
+        let sender = MessageRouter::new("sender".to_string(), "alice".to_string());
+        let receiver = MessageRouter::new("receiver".to_string(), "bob".to_string()).with_rate_limit(5);
+
+        let mut delivered = 0;
+        for i in 0..20 {
+            let message = spammer.create_chat_message(format!("spam {}", i));
+            if !matches!(receiver.process_message(message, "spammer".to_string()).await, RoutingAction::Drop) {
+                delivered += 1;
+            }
+        }
+        assert_eq!(delivered, 5);
+
+        // Other sources are unaffected
+        let message = sender.create_chat_message("hello".to_string());
+        let action = receiver.process_message(message, "sender".to_string()).await;
+        assert!(matches!(action, RoutingAction::ForwardAndDeliver { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_flooding_link_is_throttled_whatever_the_sender_id() {
+        let receiver = MessageRouter::new("receiver".to_string(), "bob".to_string()).with_rate_limit(5);
+
+        let mut delivered = 0;
+        for i in 0..50 {
+            let spoofed = MessageRouter::new(format!("spoofed-{}", i), "mallory".to_string());
+            let message = spoofed.create_chat_message("spam".to_string());
+            if !matches!(receiver.process_message(message, "spammer".to_string()).await, RoutingAction::Drop) {
+                delivered += 1;
+            }
+        }
+        assert_eq!(delivered, 5 * LINK_RATE_LIMIT_FACTOR);
+    }
+
+    #[tokio::test]
+    async fn test_message_dies_after_ttl_hops() {
+        // r0 - r1 - r2 - r3 - r4, each router only knows its neighbours
+        let routers: Vec<MessageRouter> = (0..5)
+            .map(|i| MessageRouter::new(format!("r{}", i), format!("user{}", i)).with_message_ttl(3))
+            .collect();
+        for (i, router) in routers.iter().enumerate() {
+            for neighbour in [i.wrapping_sub(1), i + 1] {
+                if neighbour < routers.len() {
+                    router.routing_table().add_peer(PeerInfo {
+                        peer_id: format!("r{}", neighbour),
+                        addr: "127.0.0.1:40000".parse().unwrap(),
+                        username: format!("user{}", neighbour),
+                        last_seen: 0,
+                    }).await;
+                }
+            }
+        }
+
+        let mut message = routers[0].create_chat_message("hello".to_string());
+        let mut delivered_at = Vec::new();
+        for (hop, router) in routers.iter().enumerate().skip(1) {
+            match router.process_message(message.clone(), format!("r{}", hop - 1)).await {
+                RoutingAction::ForwardAndDeliver { forward_message, forward_to, .. } => {
+                    assert_eq!(forward_to, vec![format!("r{}", hop + 1)]);
+                    delivered_at.push(hop);
+                    message = forward_message;
+                }
+                RoutingAction::Deliver { .. } => {
+                    delivered_at.push(hop);
+                    break;
+                }
+                other => panic!("unexpected action at hop {}: {:?}", hop, other),
+            }
+        }
+
+        assert_eq!(delivered_at, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_router_drops_expired_ttl() {
+        let receiver = MessageRouter::new("receiver".to_string(), "bob".to_string());
+        let message = P2PMessage::ChatMessage {
+            message_id: "expired".to_string(),
+            sender_id: "sender".to_string(),
+            username: "alice".to_string(),
+            content: "hello".to_string(),
+            ttl: 0,
+            seen_by: vec!["sender".to_string()],
+            kind: ChatKind::Text,
+            sequence: 1,
+        };
+        let action = receiver.process_message(message, "sender".to_string()).await;
+        assert!(matches!(action, RoutingAction::Drop));
+    }
+
+    #[tokio::test]
+    async fn test_redundant_paths_deliver_once() {
+        let ids = ["a", "b", "c"];
+        let routers: Vec<MessageRouter> = ids
+            .iter()
+            .map(|id| MessageRouter::new(id.to_string(), id.to_string()))
+            .collect();
+        for router in &routers {
+            for id in ids {
+                if id != router.local_peer_id {
+                    router.routing_table().add_peer(PeerInfo {
+                        peer_id: id.to_string(),
+                        addr: "127.0.0.1:40000".parse().unwrap(),
+                        username: id.to_string(),
+                        last_seen: 0,
+                    }).await;
+                }
+            }
+        }
+
+        // "a" floods its message to both neighbours, then every forward is
+        // delivered in turn as (to, from, message)
+        let message = routers[0].create_chat_message("hello".to_string());
+        let mut in_flight: std::collections::VecDeque<(usize, String, P2PMessage)> =
+            [(1, "a".to_string(), message.clone()), (2, "a".to_string(), message)].into();
+        let mut deliveries = [0; 3];
+
+        while let Some((to, from, message)) = in_flight.pop_front() {
+            match routers[to].process_message(message, from).await {
+                RoutingAction::ForwardAndDeliver { forward_message, forward_to, .. } => {
+                    deliveries[to] += 1;
+                    for peer_id in forward_to {
+                        let next = ids.iter().position(|id| *id == peer_id).unwrap();
+                        in_flight.push_back((next, ids[to].to_string(), forward_message.clone()));
+                    }
+                }
+                RoutingAction::Deliver { .. } => deliveries[to] += 1,
+                RoutingAction::Drop => {}
+                other => panic!("unexpected action: {:?}", other),
+            }
+        }
+
+        assert_eq!(deliveries, [0, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_message_cache_is_bounded() {
+        let mut table = RoutingTable::new("local".to_string());
+        table.max_cache_size = 3;
+
+        for i in 0..5 {
+            assert!(table.mark_message_seen(format!("msg-{}", i)).await);
+        }
+        let kept: Vec<String> = table.message_cache.read().await.order.iter().map(|(id, _)| id.clone()).collect();
+        assert_eq!(kept, ["msg-2", "msg-3", "msg-4"]);
+        assert!(!table.mark_message_seen(kept[0].clone()).await);
+        assert!(table.mark_message_seen("msg-0".to_string()).await);
+    }
+}
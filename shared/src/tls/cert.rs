@@ -1,23 +1,35 @@
 /// Certificate management for TLS connections
+use crate::config::DEFAULT_CERT_VALIDITY_SECS;
+use crate::crypto::DilithiumKeypair;
+use crate::tls::identity::{identity_extension, IdentityVerifier};
 use rcgen::{Certificate, CertificateParams, DistinguishedName, DnType, KeyPair, PKCS_ECDSA_P256_SHA256};
-use rustls::{ClientConfig, ServerConfig};
+use rustls::client::ResolvesClientCert;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{ClientConfig, ServerConfig, SignatureScheme};
 use rustls_pki_types::{CertificateDer, PrivateKeyDer, ServerName};
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use std::io::Cursor;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
 use tracing::info;
+
 /// TLS Certificate wrapper
 #[derive(Debug, Clone)]
 pub struct TlsCertificate {
     pub cert_pem: String,
     pub key_pem: String,
     pub fingerprint: String,
+    /// When the certificate stops being valid
+    pub not_after: SystemTime,
 }
 
 /// Certificate manager for generating and managing TLS certificates
 pub struct CertificateManager {
     certificate: Option<TlsCertificate>,
     peer_id: String,
+    validity: Duration,
+    identity: Option<DilithiumKeypair>,
 }
 
 impl CertificateManager {
@@ -26,9 +38,32 @@ impl CertificateManager {
         Self {
             certificate: None,
             peer_id,
+            validity: Duration::from_secs(DEFAULT_CERT_VALIDITY_SECS),
+            identity: None,
         }
     }
 
+    /// Set how long generated certificates stay valid
+    pub fn with_validity(mut self, validity: Duration) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    /// Bind generated certificates to a Dilithium identity and use mutual TLS
+    ///
+    /// Configurations created afterwards present our certificate in both
+    /// directions and only complete handshakes with peers whose certificate
+    /// is bound to an identity too.
+    pub fn with_identity(mut self, identity: DilithiumKeypair) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Whether peers must present a certificate bound to their identity
+    pub fn mutual_tls(&self) -> bool {
+        self.identity.is_some()
+    }
+
     /// Generate a self-signed certificate for this peer
     pub async fn generate_self_signed_cert(&mut self) -> Result<&TlsCertificate, Box<dyn std::error::Error + Send + Sync>> {
         info!("Generating self-signed certificate for peer: {}", self.peer_id);
@@ -40,9 +75,15 @@ impl CertificateManager {
         ]);
 
         // Set certificate validity (rcgen uses time crate, not std::time)
-        let now = time::OffsetDateTime::now_utc();
+        let issued_at = SystemTime::now();
+        let now = time::OffsetDateTime::from(issued_at);
         params.not_before = now - time::Duration::seconds(60);
-        params.not_after = now + time::Duration::days(365); // 1 year
+        let too_long = || format!("Certificate validity of {}s is too long", self.validity.as_secs());
+        let not_after = issued_at.checked_add(self.validity).ok_or_else(too_long)?;
+        params.not_after = time::Duration::try_from(self.validity)
+            .ok()
+            .and_then(|validity| now.checked_add(validity))
+            .ok_or_else(too_long)?;
 
         // Set distinguished name
         let mut distinguished_name = DistinguishedName::new();
@@ -53,6 +94,9 @@ impl CertificateManager {
 
         // Generate key pair
         let key_pair = KeyPair::generate(&PKCS_ECDSA_P256_SHA256)?;
+        if let Some(identity) = &self.identity {
+            params.custom_extensions.push(identity_extension(identity, &key_pair.public_key_der()));
+        }
         params.key_pair = Some(key_pair);
 
         // Generate certificate
@@ -67,6 +111,7 @@ impl CertificateManager {
             cert_pem,
             key_pem,
             fingerprint,
+            not_after,
         };
 
         info!("Generated certificate with fingerprint: {}", tls_cert.fingerprint);
@@ -115,12 +160,71 @@ impl CertificateManager {
         Ok(config)
     }
 
+    /// Create a client TLS configuration that presents certificates from `resolver`
+    /// when mutual TLS is on
+    pub async fn create_client_config_with(
+        &self,
+        resolver: Arc<RotatingCertResolver>,
+    ) -> Result<ClientConfig, Box<dyn std::error::Error + Send + Sync>> {
+        if !self.mutual_tls() {
+            return self.create_client_config().await;
+        }
+
+        let pq_provider = rustls_post_quantum::provider();
+        let _ = pq_provider.install_default();
+
+        let config = ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(IdentityVerifier::new()))
+            .with_client_cert_resolver(resolver);
+
+        info!("🔐 Client TLS configuration created with mutual TLS and HYBRID X25519+ML-KEM support");
+        Ok(config)
+    }
+
+    /// Create a server TLS configuration serving certificates from `resolver`,
+    /// so the certificate can be swapped while the server runs
+    pub async fn create_server_config_with(
+        &self,
+        resolver: Arc<RotatingCertResolver>,
+    ) -> Result<ServerConfig, Box<dyn std::error::Error + Send + Sync>> {
+        let pq_provider = rustls_post_quantum::provider();
+        let _ = pq_provider.install_default();
+
+        let config = if self.mutual_tls() {
+            ServerConfig::builder()
+                .with_client_cert_verifier(Arc::new(IdentityVerifier::new()))
+                .with_cert_resolver(resolver)
+        } else {
+            ServerConfig::builder()
+                .with_no_client_auth()
+                .with_cert_resolver(resolver)
+        };
+
+        info!("🔐 Server TLS configuration created with HYBRID X25519+ML-KEM support");
+        Ok(config)
+    }
+
+    /// Current certificate and key in the form rustls serves them
+    pub fn certified_key(&self) -> Result<Arc<CertifiedKey>, Box<dyn std::error::Error + Send + Sync>> {
+        let cert = self.certificate.as_ref()
+            .ok_or("No certificate available. Call generate_self_signed_cert first.")?;
+
+        let cert_chain = self.parse_certificates(&cert.cert_pem)?;
+        let private_key = self.parse_private_key(&cert.key_pem)?;
+        let signing_key = rustls_post_quantum::provider()
+            .key_provider
+            .load_private_key(private_key)?;
+
+        Ok(Arc::new(CertifiedKey::new(cert_chain, signing_key)))
+    }
+
     /// Parse PEM certificates
     fn parse_certificates(&self, pem: &str) -> Result<Vec<CertificateDer<'static>>, Box<dyn std::error::Error + Send + Sync>> {
         let mut cursor = Cursor::new(pem.as_bytes());
         let certs = certs(&mut cursor)?
             .into_iter()
-            .map(|cert| CertificateDer::from(cert))
+            .map(CertificateDer::from)
             .collect();
         Ok(certs)
     }
@@ -150,6 +254,48 @@ impl CertificateManager {
     }
 }
 
+/// Serves the current certificate, which can be replaced while the server runs
+///
+/// New handshakes pick up the replacement, established sessions keep the
+/// certificate they were opened with.
+#[derive(Debug)]
+pub struct RotatingCertResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl RotatingCertResolver {
+    /// Create a resolver serving `key`
+    pub fn new(key: Arc<CertifiedKey>) -> Self {
+        Self { current: RwLock::new(key) }
+    }
+
+    /// Serve `key` from the next handshake on
+    pub fn replace(&self, key: Arc<CertifiedKey>) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = key;
+    }
+
+    /// Certificate currently being served
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ResolvesServerCert for RotatingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+impl ResolvesClientCert for RotatingCertResolver {
+    fn resolve(&self, _root_hint_subjects: &[&[u8]], _sigschemes: &[SignatureScheme]) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
 /// Custom certificate verifier for P2P connections
 #[derive(Debug)]
 struct P2PVerifier;
@@ -252,3 +398,14 @@ impl rustls::server::danger::ClientCertVerifier for P2PVerifier {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_overlong_validity_is_an_error() {
+        let mut cert_manager = CertificateManager::new("test".to_string()).with_validity(Duration::from_secs(u64::MAX));
+        assert!(cert_manager.generate_self_signed_cert().await.is_err());
+    }
+}
@@ -164,7 +164,7 @@ impl Default for P2PConfig {
             bootstrap_peers: vec![],
             max_connections: 50,
             connection_timeout_secs: 30,
-            heartbeat_interval_secs: 30,
+            heartbeat_interval_secs: crate::config::HEARTBEAT_INTERVAL,
             message_ttl: 7,
             max_message_size: 1024 * 1024, // 1MB
         }
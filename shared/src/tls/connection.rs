@@ -6,13 +6,14 @@ use tokio_rustls::{TlsConnector, TlsAcceptor, TlsStream};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tracing::{info, debug};
+use crate::tls::identity::{verify_identity_binding, BoundIdentity};
 
 /// TLS connection wrapper
 pub enum TlsConnection {
     /// Plain TCP connection (when TLS is disabled)
     Plain(TcpStream),
     /// TLS-secured connection
-    Tls(TlsStream<TcpStream>),
+    Tls(Box<TlsStream<TcpStream>>),
 }
 
 impl TlsConnection {
@@ -32,7 +33,7 @@ impl TlsConnection {
         let tls_stream = connector.connect(server_name, tcp_stream).await?;
         
         info!("Established TLS 1.3 connection to {}", addr);
-        Ok(TlsConnection::Tls(TlsStream::Client(tls_stream)))
+        Ok(TlsConnection::Tls(Box::new(TlsStream::Client(tls_stream))))
     }
 
     /// Create a new plain TCP connection
@@ -66,6 +67,35 @@ impl TlsConnection {
         matches!(self, TlsConnection::Tls(_))
     }
 
+    /// Value unique to this TLS session that both ends can compute
+    ///
+    /// Used to tie proofs sent inside the connection to it, so they can't be
+    /// replayed on another. `None` for plain TCP.
+    pub fn channel_binding(&self, label: &[u8]) -> Option<[u8; 32]> {
+        let output = [0u8; 32];
+        match self {
+            TlsConnection::Plain(_) => None,
+            TlsConnection::Tls(stream) => match stream.as_ref() {
+                TlsStream::Client(client) => client.get_ref().1.export_keying_material(output, label, None).ok(),
+                TlsStream::Server(server) => server.get_ref().1.export_keying_material(output, label, None).ok(),
+            },
+        }
+    }
+
+    /// Identity the peer's certificate is bound to, with mutual TLS
+    ///
+    /// `None` for plain TCP and for certificates without a valid binding.
+    pub fn peer_identity(&self) -> Option<BoundIdentity> {
+        let certificates = match self {
+            TlsConnection::Plain(_) => None,
+            TlsConnection::Tls(stream) => match stream.as_ref() {
+                TlsStream::Client(client) => client.get_ref().1.peer_certificates(),
+                TlsStream::Server(server) => server.get_ref().1.peer_certificates(),
+            },
+        }?;
+        verify_identity_binding(certificates.first()?.as_ref()).ok()
+    }
+
     /// Get TLS protocol version information (if available)
     pub fn get_tls_info(&self) -> Option<String> {
         match self {
@@ -182,7 +212,7 @@ impl TlsListener {
                 debug!("Accepting TLS connection from {}", peer_addr);
                 let tls_stream = acceptor.accept(tcp_stream).await?;
                 info!("Accepted TLS 1.3 connection from {}", peer_addr);
-                Ok((TlsConnection::Tls(TlsStream::Server(tls_stream)), peer_addr))
+                Ok((TlsConnection::Tls(Box::new(TlsStream::Server(tls_stream))), peer_addr))
             }
             None => {
                 debug!("Accepting plain TCP connection from {}", peer_addr);
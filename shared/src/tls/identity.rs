@@ -0,0 +1,268 @@
+/// Identity binding in TLS certificates, for mutual TLS
+///
+/// A certificate is bound to a Dilithium identity by an extension holding the
+/// identity's public key and its signature over the certificate's public key.
+/// Copying the extension into another certificate breaks the signature, and a
+/// copied certificate is useless without its private key, so a peer can only
+/// present a bound certificate for an identity it holds the secret key of.
+use crate::crypto::{DilithiumKeypair, DilithiumVerifier};
+use identity_gen::Identity;
+use rcgen::CustomExtension;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+use tracing::{debug, warn};
+use yasna::models::ObjectIdentifier;
+use yasna::Tag;
+
+/// Certificate extension carrying the binding, under the experimental arc (RFC 1155)
+const IDENTITY_EXTENSION_OID: &[u64] = &[1, 3, 6, 1, 3, 6_582_385, 1];
+
+/// Prefix of the signed data, so the signature can't be reused elsewhere
+const BINDING_CONTEXT: &[u8] = b"dpq-chat tls identity binding v1";
+
+/// Certificate extension binding the key in `spki_der` to `identity`
+///
+/// `spki_der` is the certificate's SubjectPublicKeyInfo in DER.
+pub fn identity_extension(identity: &DilithiumKeypair, spki_der: &[u8]) -> CustomExtension {
+    let signature = identity.sign(&binding_message(spki_der));
+    let content = yasna::construct_der(|writer| {
+        writer.write_sequence(|writer| {
+            writer.next().write_bytes(identity.public_key_bytes());
+            writer.next().write_bytes(&signature);
+        })
+    });
+    CustomExtension::from_oid_content(IDENTITY_EXTENSION_OID, content)
+}
+
+/// Identity a certificate was proven to be bound to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundIdentity {
+    /// Fingerprint of the identity's public key
+    pub fingerprint: String,
+    /// The identity's Dilithium public key
+    pub public_key: Vec<u8>,
+}
+
+/// Identity a DER certificate is bound to
+///
+/// Fails if the certificate has no binding or its signature doesn't match.
+pub fn verify_identity_binding(cert_der: &[u8]) -> Result<BoundIdentity, String> {
+    let (spki, extension) = parse_certificate(cert_der).map_err(|e| format!("Malformed certificate: {}", e))?;
+    let extension = extension.ok_or("Certificate is not bound to an identity")?;
+    let (public_key, signature) = yasna::parse_der(&extension, |reader| {
+        reader.read_sequence(|reader| Ok((reader.next().read_bytes()?, reader.next().read_bytes()?)))
+    })
+    .map_err(|e| format!("Malformed identity binding: {}", e))?;
+
+    match DilithiumVerifier::verify(&binding_message(&spki), &signature, &public_key) {
+        Ok(true) => {
+            let fingerprint = Identity::generate_fingerprint(&public_key).map_err(|e| e.to_string())?;
+            Ok(BoundIdentity { fingerprint, public_key })
+        }
+        Ok(false) => Err("Identity signature does not match the certificate".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn binding_message(spki_der: &[u8]) -> Vec<u8> {
+    [BINDING_CONTEXT, spki_der].concat()
+}
+
+/// SubjectPublicKeyInfo and identity extension value of a DER certificate
+fn parse_certificate(cert_der: &[u8]) -> yasna::ASN1Result<(Vec<u8>, Option<Vec<u8>>)> {
+    let binding_oid = ObjectIdentifier::from_slice(IDENTITY_EXTENSION_OID);
+    yasna::parse_der(cert_der, |reader| {
+        reader.read_sequence(|reader| {
+            let parsed = reader.next().read_sequence(|reader| {
+                // Version, then serial number, signature algorithm, issuer, validity and subject
+                reader.read_optional(|reader| reader.read_tagged(Tag::context(0), |reader| reader.read_der()))?;
+                for _ in 0..5 {
+                    reader.next().read_der()?;
+                }
+                let spki = reader.next().read_der()?;
+                let extensions = reader
+                    .read_optional(|reader| {
+                        reader.read_tagged(Tag::context(3), |reader| {
+                            reader.collect_sequence_of(|reader| {
+                                reader.read_sequence(|reader| {
+                                    let oid = reader.next().read_oid()?;
+                                    reader.read_default(false, |reader| reader.read_bool())?;
+                                    Ok((oid, reader.next().read_bytes()?))
+                                })
+                            })
+                        })
+                    })?
+                    .unwrap_or_default();
+                let binding = extensions.into_iter().find(|(oid, _)| *oid == binding_oid).map(|(_, value)| value);
+                Ok((spki, binding))
+            })?;
+            // Signature algorithm and value, the handshake proves key possession instead
+            reader.next().read_der()?;
+            reader.next().read_der()?;
+            Ok(parsed)
+        })
+    })
+}
+
+/// Certificate verifier for mutual TLS
+///
+/// Accepts only certificates bound to an identity, in both directions, and
+/// checks handshake signatures for real so a copied certificate can't be used.
+#[derive(Debug)]
+pub(crate) struct IdentityVerifier {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl IdentityVerifier {
+    pub(crate) fn new() -> Self {
+        Self {
+            algorithms: rustls_post_quantum::provider().signature_verification_algorithms,
+        }
+    }
+
+    fn verify_binding(&self, end_entity: &CertificateDer<'_>) -> Result<String, rustls::Error> {
+        verify_identity_binding(end_entity.as_ref()).map(|identity| identity.fingerprint).map_err(|reason| {
+            warn!("Rejecting TLS certificate: {}", reason);
+            rustls::Error::General(reason)
+        })
+    }
+}
+
+impl ServerCertVerifier for IdentityVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = self.verify_binding(end_entity)?;
+        debug!("P2P: Server certificate bound to identity {}", fingerprint);
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+impl ClientCertVerifier for IdentityVerifier {
+    fn offer_client_auth(&self) -> bool {
+        true
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        true
+    }
+
+    fn root_hint_subjects(&self) -> &[rustls::DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        let fingerprint = self.verify_binding(end_entity)?;
+        debug!("P2P: Client certificate bound to identity {}", fingerprint);
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::CertificateManager;
+
+    async fn bound_cert_der(identity: Option<&DilithiumKeypair>) -> Vec<u8> {
+        let mut cert_manager = CertificateManager::new("test".to_string());
+        if let Some(identity) = identity {
+            cert_manager = cert_manager.with_identity(identity.clone());
+        }
+        cert_manager.generate_self_signed_cert().await.unwrap();
+        cert_manager.certified_key().unwrap().cert[0].to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_binding_names_the_identity() {
+        let identity = DilithiumKeypair::generate();
+        let cert = bound_cert_der(Some(&identity)).await;
+        assert_eq!(
+            verify_identity_binding(&cert),
+            Ok(BoundIdentity {
+                fingerprint: Identity::generate_fingerprint(identity.public_key_bytes()).unwrap(),
+                public_key: identity.public_key_bytes().to_vec(),
+            })
+        );
+
+        assert_eq!(
+            verify_identity_binding(&bound_cert_der(None).await),
+            Err("Certificate is not bound to an identity".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_binding_fails_for_another_key() {
+        let identity = DilithiumKeypair::generate();
+        let cert = bound_cert_der(Some(&identity)).await;
+        let other = bound_cert_der(Some(&identity)).await;
+
+        // Graft the other certificate's key into this one, keeping our binding
+        let (spki, _) = parse_certificate(&cert).unwrap();
+        let (other_spki, _) = parse_certificate(&other).unwrap();
+        let start = cert.windows(spki.len()).position(|window| window == spki).unwrap();
+        let mut grafted = cert.clone();
+        grafted[start..start + spki.len()].copy_from_slice(&other_spki);
+
+        assert_eq!(
+            verify_identity_binding(&grafted),
+            Err("Identity signature does not match the certificate".to_string())
+        );
+    }
+}
@@ -5,15 +5,18 @@
 pub mod cert;
 pub mod config;
 pub mod connection;
+pub mod identity;
 // pub mod hybrid_config;  // Disabled temporarily due to API compatibility
 
 // Re-export main types for convenience
-pub use cert::{CertificateManager, TlsCertificate};
+pub use cert::{CertificateManager, RotatingCertResolver, TlsCertificate};
 pub use config::TlsConfig;
 pub use connection::{TlsConnection, TlsListener};
+pub use identity::{verify_identity_binding, BoundIdentity};
 // pub use hybrid_config::{HybridTlsConfig, create_hybrid_tls_context};
 
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
 use rustls::{ClientConfig, ServerConfig};
 
 /// TLS context that holds both client and server configurations
@@ -21,19 +24,48 @@ use rustls::{ClientConfig, ServerConfig};
 pub struct TlsContext {
     pub client_config: Arc<ClientConfig>,
     pub server_config: Arc<ServerConfig>,
+    cert_resolver: Arc<RotatingCertResolver>,
+    cert_expires_at: Arc<RwLock<SystemTime>>,
 }
 
 impl TlsContext {
     /// Create a new TLS context with the given certificate manager
     pub async fn new(cert_manager: &CertificateManager) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let client_config = cert_manager.create_client_config().await?;
-        let server_config = cert_manager.create_server_config().await?;
-        
+        let cert_resolver = Arc::new(RotatingCertResolver::new(cert_manager.certified_key()?));
+        let client_config = cert_manager.create_client_config_with(cert_resolver.clone()).await?;
+        let server_config = cert_manager.create_server_config_with(cert_resolver.clone()).await?;
+        let cert_expires_at = Self::expiry_of(cert_manager)?;
+
         Ok(TlsContext {
             client_config: Arc::new(client_config),
             server_config: Arc::new(server_config),
+            cert_resolver,
+            cert_expires_at: Arc::new(RwLock::new(cert_expires_at)),
         })
     }
+
+    /// When the certificate being served expires
+    pub fn certificate_expires_at(&self) -> SystemTime {
+        *self.cert_expires_at.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Serve the manager's current certificate to new connections
+    ///
+    /// Shared by every clone of this context, so a running listener picks
+    /// it up without being rebound.
+    pub fn rotate_certificate(&self, cert_manager: &CertificateManager) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let expires_at = Self::expiry_of(cert_manager)?;
+        self.cert_resolver.replace(cert_manager.certified_key()?);
+        *self.cert_expires_at.write().unwrap_or_else(|e| e.into_inner()) = expires_at;
+        Ok(())
+    }
+
+    fn expiry_of(cert_manager: &CertificateManager) -> Result<SystemTime, Box<dyn std::error::Error + Send + Sync>> {
+        cert_manager
+            .get_certificate()
+            .map(|cert| cert.not_after)
+            .ok_or_else(|| "No certificate available. Call generate_self_signed_cert first.".into())
+    }
     
     // /// Create a new hybrid TLS context with post-quantum support
     // pub async fn new_hybrid(cert_manager: &CertificateManager) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
@@ -1,13 +1,232 @@
 use crate::config;
+use crate::p2p::RoomKey;
+use std::net::{Ipv4Addr, SocketAddr};
+
+pub use identity_gen::{safety_number, short_fingerprint, validate_username};
 
 /// validate username for P2P chat
+///
+/// same rule as identity usernames, see [`validate_username`]
 pub fn is_valid_username(username: &str) -> bool {
-    !username.is_empty() 
-        && username.len() <= config::MAX_USERNAME_LENGTH
-        && username.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    validate_username(username).is_ok()
 }
 
 /// validate message content for P2P chat
+///
+/// content is checked after sanitizing, so a message made only of
+/// control characters or escape sequences is rejected
 pub fn is_valid_message_content(content: &str) -> bool {
-    !content.trim().is_empty() && content.len() <= config::MAX_MESSAGE_LENGTH
+    content.len() <= config::MAX_MESSAGE_LENGTH && !sanitize_text(content).trim().is_empty()
+}
+
+/// check that a peer address has the form `host:port`
+///
+/// host may be an IP address or a DNS name, it is only looked up when
+/// connecting. returns the address unchanged so it can be used as a clap
+/// value parser.
+pub fn parse_peer_target(target: &str) -> Result<String, String> {
+    if target.parse::<SocketAddr>().is_ok() {
+        return Ok(target.to_string());
+    }
+    match target.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && !host.contains(':') && port.parse::<u16>().is_ok() => Ok(target.to_string()),
+        _ => Err(format!("'{}' is not a valid peer address, expected host:port (e.g. 192.168.1.100:40000 or chat.example.com:40000)", target)),
+    }
+}
+
+/// check that a discovery group is an IPv4 multicast address
+///
+/// only IPv4 groups are joined. usable as a clap value parser.
+pub fn parse_multicast_group(group: &str) -> Result<Ipv4Addr, String> {
+    let ip: Ipv4Addr = group
+        .parse()
+        .map_err(|_| format!("'{}' is not an IPv4 address, expected a multicast group such as 239.255.42.99", group))?;
+    if !ip.is_multicast() {
+        return Err(format!("{} is not a multicast address, expected one in 224.0.0.0-239.255.255.255 (239.x.x.x is meant for private groups)", ip));
+    }
+    Ok(ip)
+}
+
+/// turn a room passphrase into a room key, refusing a blank one
+///
+/// usable as a clap value parser.
+pub fn parse_room_key(passphrase: &str) -> Result<RoomKey, String> {
+    if passphrase.trim().is_empty() {
+        return Err("room key must not be empty".to_string());
+    }
+    Ok(RoomKey::from_passphrase(passphrase))
+}
+
+/// resolve `host:port` peer addresses to socket addresses
+///
+/// `ip:port` is used as is, anything else goes through DNS. every address a
+/// name resolves to is kept so each one gets dialed; the node keeps a single
+/// connection per peer, so a host reachable on several addresses is only
+/// joined once.
+pub async fn resolve_peer_addrs(targets: &[String]) -> Result<Vec<SocketAddr>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut addrs = Vec::new();
+    for target in targets {
+        if let Ok(addr) = target.parse::<SocketAddr>() {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+            continue;
+        }
+
+        let resolved: Vec<SocketAddr> = tokio::net::lookup_host(target.as_str())
+            .await
+            .map_err(|e| format!("Could not resolve peer address '{}': {}", target, e))?
+            .collect();
+        if resolved.is_empty() {
+            return Err(format!("Could not resolve peer address '{}': no addresses found", target).into());
+        }
+        for addr in resolved {
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+    }
+    Ok(addrs)
+}
+
+/// strip terminal control sequences from text received over the network
+///
+/// removes ANSI CSI/OSC escape sequences and C0/C1 control characters so a
+/// peer cannot move the cursor, clear the screen or retitle the terminal.
+/// unicode bidi overrides and isolates are dropped too, so a name or link
+/// cannot be displayed reversed. tabs and newlines become spaces since chat
+/// lines are drawn on one row.
+pub fn sanitize_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => match chars.peek() {
+                // CSI: ESC [ params... final byte in 0x40..=0x7e
+                Some('[') => {
+                    chars.next();
+                    skip_csi(&mut chars);
+                }
+                // OSC/DCS/PM/APC/SOS: terminated by BEL or ESC \
+                Some(']') | Some('P') | Some('^') | Some('_') | Some('X') => {
+                    chars.next();
+                    skip_string_sequence(&mut chars);
+                }
+                // Two-character escape such as ESC c (full reset)
+                Some(_) => {
+                    chars.next();
+                }
+                None => {}
+            },
+            // 8-bit CSI
+            '\u{9b}' => skip_csi(&mut chars),
+            // 8-bit OSC/DCS/PM/APC/SOS
+            '\u{9d}' | '\u{90}' | '\u{9e}' | '\u{9f}' | '\u{98}' => skip_string_sequence(&mut chars),
+            '\t' | '\n' | '\r' => result.push(' '),
+            // Bidi embeddings/overrides and isolates
+            '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => {}
+            c if c.is_control() => {}
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+fn skip_csi(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    for c in chars.by_ref() {
+        if ('\x40'..='\x7e').contains(&c) {
+            break;
+        }
+    }
+}
+
+fn skip_string_sequence(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\x07' | '\u{9c}' => break,
+            '\x1b' if chars.peek() == Some(&'\\') => {
+                chars.next();
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_keeps_plain_text() {
+        assert_eq!(sanitize_text("hello, wörld 👋"), "hello, wörld 👋");
+    }
+
+    #[test]
+    fn test_sanitize_strips_escape_sequences() {
+        assert_eq!(sanitize_text("\x1b[2J\x1b[1;1Hhi"), "hi");
+        assert_eq!(sanitize_text("\x1b]0;pwned\x07title"), "title");
+        assert_eq!(sanitize_text("\x1b]0;pwned\x1b\\title"), "title");
+        assert_eq!(sanitize_text("\x1b[31mred\x1b[0m"), "red");
+        assert_eq!(sanitize_text("a\x1bcb"), "ab");
+        assert_eq!(sanitize_text("\u{9b}2Jok"), "ok");
+    }
+
+    #[test]
+    fn test_sanitize_control_characters() {
+        assert_eq!(sanitize_text("bell\x07\x08\x7f"), "bell");
+        assert_eq!(sanitize_text("line\none\ttab"), "line one tab");
+    }
+
+    #[test]
+    fn test_sanitize_strips_bidi_overrides() {
+        assert_eq!(sanitize_text("invoice\u{202e}fdp.exe"), "invoicefdp.exe");
+        assert_eq!(sanitize_text("\u{202a}a\u{202b}b\u{202c}c\u{202d}d"), "abcd");
+        assert_eq!(sanitize_text("\u{2066}e\u{2067}f\u{2068}g\u{2069}"), "efg");
+        assert_eq!(sanitize_text("עברית"), "עברית");
+    }
+
+    #[tokio::test]
+    async fn test_peer_addresses_accept_hostnames() {
+        assert!(parse_peer_target("192.168.1.100:40000").is_ok());
+        assert!(parse_peer_target("[::1]:40000").is_ok());
+        assert!(parse_peer_target("chat.example.com:40000").is_ok());
+        assert!(parse_peer_target("chat.example.com").is_err());
+        assert!(parse_peer_target(":40000").is_err());
+        assert!(parse_peer_target("chat.example.com:99999").is_err());
+
+        let targets = vec!["127.0.0.1:40000".to_string(), "localhost:40000".to_string()];
+        let resolved = resolve_peer_addrs(&targets).await.unwrap();
+        assert_eq!(resolved[0], "127.0.0.1:40000".parse::<SocketAddr>().unwrap());
+        assert!(resolved.iter().all(|addr| addr.port() == 40000 && addr.ip().is_loopback()));
+
+        let error = resolve_peer_addrs(&["no-such-host.invalid:40000".to_string()]).await.unwrap_err();
+        assert!(error.to_string().contains("no-such-host.invalid:40000"));
+    }
+
+    #[test]
+    fn test_multicast_group_must_be_multicast() {
+        assert_eq!(parse_multicast_group("239.255.1.2"), Ok(Ipv4Addr::new(239, 255, 1, 2)));
+        assert!(parse_multicast_group("224.0.0.251").is_ok());
+        assert!(parse_multicast_group("192.168.1.10").unwrap_err().contains("not a multicast address"));
+        assert!(parse_multicast_group("ff02::1").unwrap_err().contains("not an IPv4 address"));
+        assert!(parse_multicast_group("239.255.1.2:8899").is_err());
+    }
+
+    #[test]
+    fn test_room_key_must_not_be_blank() {
+        assert_eq!(parse_room_key("blue room"), Ok(RoomKey::from_passphrase("blue room")));
+        assert!(parse_room_key("").is_err());
+        assert!(parse_room_key("   ").is_err());
+    }
+
+    #[test]
+    fn test_message_content_validation() {
+        assert!(is_valid_message_content("hello"));
+        assert!(!is_valid_message_content("   "));
+        assert!(!is_valid_message_content("\x1b[2J\x07"));
+        assert!(!is_valid_message_content(&"a".repeat(config::MAX_MESSAGE_LENGTH + 1)));
+    }
 }